@@ -0,0 +1,78 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Lookups of a VRL `get_enrichment_table_record` in a 100k rows enrichment
+//! table, with and without an index.
+//!
+//! Run with `cargo bench --bench enrichment_table`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use openobserve::service::enrichment::StreamTable;
+use vector_enrichment::{Case, Condition, Table};
+use vrl::value::{ObjectMap, Value};
+
+const ROWS: usize = 100_000;
+
+fn table() -> StreamTable {
+    let data = (0..ROWS)
+        .map(|i| {
+            Value::Object(ObjectMap::from([
+                (
+                    "ip".into(),
+                    Value::from(format!("10.{}.{}.{}", i >> 16, (i >> 8) & 0xff, i & 0xff)),
+                ),
+                ("host".into(), Value::from(format!("host-{i}"))),
+                ("zone".into(), Value::from(format!("zone-{}", i % 8))),
+            ]))
+        })
+        .collect();
+    StreamTable::new("default", "hosts", data)
+}
+
+fn find_table_row(c: &mut Criterion) {
+    let mut table = table();
+    let index = table.add_index(Case::Sensitive, &["ip"]).unwrap();
+    let mut group = c.benchmark_group("find_table_row");
+    for (name, index) in [("scan", None), ("index", Some(index))] {
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            let mut i = 0;
+            b.iter(|| {
+                // spread the lookups over the table
+                i = (i + 7919) % ROWS;
+                let conditions = [Condition::Equals {
+                    field: "ip",
+                    value: Value::from(format!("10.{}.{}.{}", i >> 16, (i >> 8) & 0xff, i & 0xff)),
+                }];
+                black_box(
+                    table
+                        .find_table_row(Case::Sensitive, &conditions, None, index)
+                        .unwrap(),
+                )
+            })
+        });
+    }
+    group.finish();
+}
+
+fn add_index(c: &mut Criterion) {
+    c.bench_function("add_index", |b| {
+        b.iter_with_setup(table, |mut table| {
+            black_box(table.add_index(Case::Insensitive, &["host"]).unwrap())
+        })
+    });
+}
+
+criterion_group!(benches, find_table_row, add_index);
+criterion_main!(benches);
@@ -114,6 +114,8 @@ fn main() -> Result<()> {
         &["proto"],
     )?;
 
+    prost_build::Config::new().compile_protos(&["proto/loki/push.proto"], &["proto"])?;
+
     // build information
     let output = Command::new("git")
         .args(["describe", "--tags", "--abbrev=0"])
@@ -21,6 +21,14 @@ use datafusion::arrow::datatypes::Schema;
 use dotenv_config::EnvConfig;
 use dotenvy::dotenv;
 use itertools::chain;
+use lettre::{
+    transport::smtp::{
+        self,
+        authentication::Credentials,
+        client::{Tls, TlsParameters},
+    },
+    AsyncSmtpTransport, Tokio1Executor,
+};
 use once_cell::sync::Lazy;
 use parking_lot::RwLock;
 use reqwest::Client;
@@ -32,10 +40,12 @@ use crate::{
     common::{
         meta::{
             alerts,
+            enrichment_table::ScheduledEnrichmentTable,
             functions::{StreamFunctionsList, Transform},
             maxmind::MaxmindClient,
             organization::OrganizationSetting,
             prom::ClusterLeader,
+            rules::RuleGroup,
             syslog::SyslogRoute,
             user::User,
         },
@@ -169,9 +179,14 @@ pub static ALERTS_TEMPLATES: Lazy<RwHashMap<String, alerts::templates::Template>
     Lazy::new(Default::default);
 pub static ALERTS_DESTINATIONS: Lazy<RwHashMap<String, alerts::destinations::Destination>> =
     Lazy::new(Default::default);
+pub static MAINTENANCE_WINDOWS: Lazy<RwHashMap<String, alerts::maintenance::MaintenanceWindow>> =
+    Lazy::new(Default::default);
+pub static RULE_GROUPS: Lazy<RwHashMap<String, RuleGroup>> = Lazy::new(Default::default);
 pub static SYSLOG_ROUTES: Lazy<RwHashMap<String, SyslogRoute>> = Lazy::new(Default::default);
 pub static SYSLOG_ENABLED: Lazy<Arc<RwLock<bool>>> = Lazy::new(|| Arc::new(RwLock::new(false)));
 pub static ENRICHMENT_TABLES: Lazy<RwHashMap<String, StreamTable>> = Lazy::new(Default::default);
+pub static ENRICHMENT_TABLE_SCHEDULES: Lazy<RwHashMap<String, ScheduledEnrichmentTable>> =
+    Lazy::new(Default::default);
 pub static ENRICHMENT_REGISTRY: Lazy<Arc<TableRegistry>> =
     Lazy::new(|| Arc::new(TableRegistry::default()));
 pub static LOCAL_SCHEMA_LOCKER: Lazy<Arc<RwAHashMap<String, tokio::sync::RwLock<bool>>>> =
@@ -183,6 +198,38 @@ pub static MAXMIND_DB_CLIENT: Lazy<Arc<TRwLock<Option<MaxmindClient>>>> =
 pub static GEOIP_TABLE: Lazy<Arc<RwLock<Option<Geoip>>>> =
     Lazy::new(|| Arc::new(RwLock::new(None)));
 
+/// The SMTP client of the email destinations, an error when the SMTP settings
+/// are invalid, which is reported by the notifications.
+pub static SMTP_CLIENT: Lazy<Option<Result<AsyncSmtpTransport<Tokio1Executor>, String>>> =
+    Lazy::new(|| {
+        if !CONFIG.smtp.smtp_enabled {
+            return None;
+        }
+        Some(build_smtp_client().map_err(|e| e.to_string()))
+    });
+
+fn build_smtp_client() -> Result<AsyncSmtpTransport<Tokio1Executor>, smtp::Error> {
+    let mut transport_builder =
+        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&CONFIG.smtp.smtp_host)
+            .port(CONFIG.smtp.smtp_port);
+    transport_builder = match CONFIG.smtp.smtp_encryption.as_str() {
+        "ssl" => transport_builder.tls(Tls::Wrapper(TlsParameters::new(
+            CONFIG.smtp.smtp_host.clone(),
+        )?)),
+        "starttls" => transport_builder.tls(Tls::Required(TlsParameters::new(
+            CONFIG.smtp.smtp_host.clone(),
+        )?)),
+        _ => transport_builder.tls(Tls::None),
+    };
+    if !CONFIG.smtp.smtp_username.is_empty() && !CONFIG.smtp.smtp_password.is_empty() {
+        transport_builder = transport_builder.credentials(Credentials::new(
+            CONFIG.smtp.smtp_username.clone(),
+            CONFIG.smtp.smtp_password.clone(),
+        ));
+    }
+    Ok(transport_builder.build())
+}
+
 #[derive(EnvConfig)]
 pub struct Config {
     pub auth: Auth,
@@ -202,6 +249,7 @@ pub struct Config {
     pub tcp: TCP,
     pub prom: Prometheus,
     pub profiling: Pyroscope,
+    pub smtp: Smtp,
 }
 
 #[derive(EnvConfig)]
@@ -215,6 +263,27 @@ pub struct Pyroscope {
     pub pyroscope_project_name: String,
 }
 
+#[derive(EnvConfig)]
+pub struct Smtp {
+    #[env_config(name = "ZO_SMTP_ENABLED", default = false)]
+    pub smtp_enabled: bool,
+    #[env_config(name = "ZO_SMTP_HOST", default = "localhost")]
+    pub smtp_host: String,
+    #[env_config(name = "ZO_SMTP_PORT", default = 25)]
+    pub smtp_port: u16,
+    #[env_config(name = "ZO_SMTP_USER_NAME", default = "")]
+    pub smtp_username: String,
+    #[env_config(name = "ZO_SMTP_PASSWORD", default = "")]
+    pub smtp_password: String,
+    #[env_config(name = "ZO_SMTP_REPLY_TO", default = "")]
+    pub smtp_reply_to: String,
+    #[env_config(name = "ZO_SMTP_FROM_EMAIL", default = "")]
+    pub smtp_from_email: String,
+    // none, ssl or starttls
+    #[env_config(name = "ZO_SMTP_ENCRYPTION", default = "")]
+    pub smtp_encryption: String,
+}
+
 #[derive(EnvConfig)]
 pub struct Auth {
     #[env_config(name = "ZO_ROOT_USER_EMAIL")]
@@ -330,6 +399,8 @@ pub struct Common {
     pub ui_sql_base64_enabled: bool,
     #[env_config(name = "ZO_METRICS_DEDUP_ENABLED", default = true)]
     pub metrics_dedup_enabled: bool,
+    #[env_config(name = "ZO_METRICS_RESULT_CACHE_ENABLED", default = true)]
+    pub metrics_result_cache_enabled: bool,
     #[env_config(name = "ZO_BLOOM_FILTER_ENABLED", default = true)]
     pub bloom_filter_enabled: bool,
     #[env_config(name = "ZO_BLOOM_FILTER_DEFAULT_FIELDS", default = "")]
@@ -351,6 +422,31 @@ pub struct Common {
     pub telemetry_url: String,
     #[env_config(name = "ZO_PROMETHEUS_ENABLED", default = true)]
     pub prometheus_enabled: bool,
+    #[env_config(name = "ZO_TRACES_SPAN_METRICS_ENABLED", default = false)]
+    pub traces_span_metrics_enabled: bool,
+    // comma separated span or resource attributes, eg: http.method,k8s.namespace.name
+    #[env_config(name = "ZO_TRACES_SPAN_METRICS_DIMENSIONS", default = "")]
+    pub traces_span_metrics_dimensions: String,
+    // comma separated upper bounds of the duration buckets, in seconds
+    #[env_config(
+        name = "ZO_TRACES_SPAN_METRICS_BUCKETS",
+        default = "0.002,0.004,0.006,0.008,0.01,0.05,0.1,0.2,0.4,0.8,1,1.4,2,5,10,15"
+    )]
+    pub traces_span_metrics_buckets: String,
+    #[env_config(name = "ZO_TRACES_TAIL_SAMPLING_ENABLED", default = false)]
+    pub traces_tail_sampling_enabled: bool,
+    #[env_config(name = "ZO_TRACES_TAIL_SAMPLING_KEEP_ERRORS", default = true)]
+    pub traces_tail_sampling_keep_errors: bool,
+    // keep the traces lasting longer, in milliseconds, 0 disables it
+    #[env_config(name = "ZO_TRACES_TAIL_SAMPLING_MIN_DURATION", default = 0)]
+    pub traces_tail_sampling_min_duration: i64,
+    // keep the traces having a span or resource attribute matching one of the
+    // comma separated key=value pairs, eg: http.status_code=500,tenant=acme
+    #[env_config(name = "ZO_TRACES_TAIL_SAMPLING_ATTRIBUTES", default = "")]
+    pub traces_tail_sampling_attributes: String,
+    // keep this percentage, from 0 to 100, of the other traces
+    #[env_config(name = "ZO_TRACES_TAIL_SAMPLING_PERCENTAGE", default = 0)]
+    pub traces_tail_sampling_percentage: u64,
     #[env_config(name = "ZO_PRINT_KEY_CONFIG", default = false)]
     pub print_key_config: bool,
     #[env_config(name = "ZO_PRINT_KEY_EVENT", default = false)]
@@ -432,6 +528,8 @@ pub struct Limit {
     pub metrics_leader_push_interval: u64,
     #[env_config(name = "ZO_METRICS_LEADER_ELECTION_INTERVAL", default = 30)]
     pub metrics_leader_election_interval: i64,
+    #[env_config(name = "ZO_METRICS_RESULT_CACHE_MAX_ENTRIES", default = 1000)]
+    pub metrics_result_cache_max_entries: usize,
     #[env_config(name = "ZO_HEARTBEAT_INTERVAL", default = 30)] // in minutes
     pub hb_interval: i64,
     #[env_config(name = "ZO_COLS_PER_RECORD_LIMIT", default = 1000)]
@@ -442,8 +540,20 @@ pub struct Limit {
     pub http_worker_max_blocking: usize,
     #[env_config(name = "ZO_CALCULATE_STATS_INTERVAL", default = 600)] // in seconds
     pub calculate_stats_interval: u64,
+    #[env_config(name = "ZO_SERVICE_GRAPH_INTERVAL", default = 300)] // in seconds, 0 disables it
+    pub service_graph_interval: i64,
+    #[env_config(name = "ZO_TRACES_SPAN_METRICS_FLUSH_INTERVAL", default = 15)] // in seconds
+    pub traces_span_metrics_flush_interval: u64,
+    #[env_config(name = "ZO_TRACES_TAIL_SAMPLING_DECISION_WAIT", default = 10)] // in seconds
+    pub traces_tail_sampling_decision_wait: i64,
+    #[env_config(name = "ZO_TRACES_TAIL_SAMPLING_MAX_TRACES", default = 100000)]
+    pub traces_tail_sampling_max_traces: usize,
     #[env_config(name = "ZO_ENRICHMENT_TABLE_LIMIT", default = 10)] // size in mb
     pub enrichment_table_limit: usize,
+    #[env_config(name = "ZO_ALERT_NOTIFICATION_MAX_ATTEMPTS", default = 5)]
+    pub alert_notification_max_attempts: u32,
+    #[env_config(name = "ZO_ALERT_NOTIFICATION_RETRY_INTERVAL", default = 30)] // in seconds
+    pub alert_notification_retry_interval: i64,
     #[env_config(name = "ZO_ACTIX_REQ_TIMEOUT", default = 30)] // in second
     pub request_timeout: u64,
     #[env_config(name = "ZO_ACTIX_KEEP_ALIVE", default = 30)] // in second
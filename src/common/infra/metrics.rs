@@ -162,6 +162,44 @@ pub static INGEST_WAL_READ_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
     .expect("Metric created")
 });
 
+// ingester tail sampling stats
+pub static TRACES_TAIL_SAMPLING_TRACES: Lazy<IntCounterVec> = Lazy::new(|| {
+    IntCounterVec::new(
+        Opts::new(
+            "traces_tail_sampling_traces",
+            "Traces sampled, by the policy which kept them or dropped. ".to_owned() + HELP_SUFFIX,
+        )
+        .namespace(NAMESPACE)
+        .const_labels(create_const_labels()),
+        &["organization", "stream", "decision"],
+    )
+    .expect("Metric created")
+});
+pub static TRACES_TAIL_SAMPLING_SPANS: Lazy<IntCounterVec> = Lazy::new(|| {
+    IntCounterVec::new(
+        Opts::new(
+            "traces_tail_sampling_spans",
+            "Spans sampled, kept or dropped. ".to_owned() + HELP_SUFFIX,
+        )
+        .namespace(NAMESPACE)
+        .const_labels(create_const_labels()),
+        &["organization", "stream", "decision"],
+    )
+    .expect("Metric created")
+});
+pub static TRACES_TAIL_SAMPLING_BUFFERED_TRACES: Lazy<IntGaugeVec> = Lazy::new(|| {
+    IntGaugeVec::new(
+        Opts::new(
+            "traces_tail_sampling_buffered_traces",
+            "Traces waiting for a sampling decision. ".to_owned() + HELP_SUFFIX,
+        )
+        .namespace(NAMESPACE)
+        .const_labels(create_const_labels()),
+        &[],
+    )
+    .expect("Metric created")
+});
+
 // querier memory cache stats
 pub static QUERY_MEMORY_CACHE_LIMIT_BYTES: Lazy<IntGaugeVec> = Lazy::new(|| {
     IntGaugeVec::new(
@@ -537,6 +575,15 @@ fn register_metrics(registry: &Registry) {
     registry
         .register(Box::new(INGEST_WAL_READ_BYTES.clone()))
         .expect("Metric registered");
+    registry
+        .register(Box::new(TRACES_TAIL_SAMPLING_TRACES.clone()))
+        .expect("Metric registered");
+    registry
+        .register(Box::new(TRACES_TAIL_SAMPLING_SPANS.clone()))
+        .expect("Metric registered");
+    registry
+        .register(Box::new(TRACES_TAIL_SAMPLING_BUFFERED_TRACES.clone()))
+        .expect("Metric registered");
 
     // querier stats
     registry
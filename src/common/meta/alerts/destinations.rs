@@ -25,12 +25,23 @@ use super::templates::Template;
 pub struct Destination {
     #[serde(default)]
     pub name: String,
+    #[serde(default)]
+    pub destination_type: DestinationType,
+    #[serde(default)]
     pub url: String,
+    #[serde(default)]
     pub method: HTTPType,
     #[serde(default)]
     pub skip_tls_verify: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub headers: Option<HashMap<String, String>>,
+    /// PagerDuty integration key or Opsgenie API key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    /// Recipients of the email destination
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub emails: Vec<String>,
     pub template: String,
 }
 
@@ -38,10 +49,13 @@ impl Destination {
     pub fn with_template(&self, template: Template) -> DestinationWithTemplate {
         DestinationWithTemplate {
             name: self.name.clone(),
+            destination_type: self.destination_type,
             url: self.url.clone(),
             method: self.method.clone(),
             skip_tls_verify: self.skip_tls_verify,
             headers: self.headers.clone(),
+            api_key: self.api_key.clone(),
+            emails: self.emails.clone(),
             template,
         }
     }
@@ -50,15 +64,49 @@ impl Destination {
 #[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
 pub struct DestinationWithTemplate {
     pub name: String,
+    #[serde(default)]
+    pub destination_type: DestinationType,
     pub url: String,
     pub method: HTTPType,
     #[serde(default)]
     pub skip_tls_verify: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub headers: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub emails: Vec<String>,
     pub template: Template,
 }
 
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize, ToSchema)]
+pub enum DestinationType {
+    #[default]
+    #[serde(rename = "webhook")]
+    Webhook,
+    #[serde(rename = "slack")]
+    Slack,
+    #[serde(rename = "pagerduty_events_v2")]
+    PagerDutyEventsV2,
+    #[serde(rename = "opsgenie")]
+    Opsgenie,
+    #[serde(rename = "email")]
+    Email,
+}
+
+impl fmt::Display for DestinationType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DestinationType::Webhook => write!(f, "webhook"),
+            DestinationType::Slack => write!(f, "slack"),
+            DestinationType::PagerDutyEventsV2 => write!(f, "pagerduty_events_v2"),
+            DestinationType::Opsgenie => write!(f, "opsgenie"),
+            DestinationType::Email => write!(f, "email"),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize, ToSchema)]
 pub enum HTTPType {
     #[default]
@@ -0,0 +1,143 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::{triggers::AlertState, Alert};
+use crate::common::meta::StreamType;
+
+pub const ALERT_HISTORY_STREAM: &str = "_alert_history";
+
+/// One evaluation of an alert, written to the `_alert_history` stream of the
+/// organization the alert belongs to.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct AlertHistory {
+    pub org_id: String,
+    pub stream_type: StreamType,
+    pub stream_name: String,
+    pub alert_name: String,
+    pub is_realtime: bool,
+    pub matched_rows: usize,
+    pub outcome: EvaluationOutcome,
+    pub destinations: String,
+    pub delivery_status: DeliveryStatus,
+    #[serde(default)]
+    pub delivery_error: String,
+    #[serde(default)]
+    pub error: String,
+    #[serde(default)]
+    pub maintenance_window: String, // the window which muted the notification
+    pub took: usize, // in milliseconds
+}
+
+impl AlertHistory {
+    pub fn new(alert: &Alert) -> Self {
+        Self {
+            org_id: alert.org_id.clone(),
+            stream_type: alert.stream_type,
+            stream_name: alert.stream_name.clone(),
+            alert_name: alert.name.clone(),
+            is_realtime: alert.is_real_time,
+            destinations: alert.destinations.join(","),
+            ..Default::default()
+        }
+    }
+
+    /// Records the delivery result from the destinations which failed.
+    pub fn set_delivery(&mut self, failed: &[(String, String)]) {
+        let total = self
+            .destinations
+            .split(',')
+            .filter(|v| !v.is_empty())
+            .count();
+        self.delivery_status = if failed.is_empty() {
+            DeliveryStatus::Sent
+        } else if failed.len() < total {
+            DeliveryStatus::Partial
+        } else {
+            DeliveryStatus::Failed
+        };
+        self.delivery_error = failed
+            .iter()
+            .map(|(dest, e)| format!("{dest}: {e}"))
+            .collect::<Vec<_>>()
+            .join("; ");
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum EvaluationOutcome {
+    #[default]
+    #[serde(rename = "ok")]
+    Ok,
+    #[serde(rename = "pending")]
+    Pending,
+    #[serde(rename = "firing")]
+    Firing,
+    #[serde(rename = "resolved")]
+    Resolved,
+    #[serde(rename = "error")]
+    Error,
+}
+
+impl From<AlertState> for EvaluationOutcome {
+    fn from(state: AlertState) -> Self {
+        match state {
+            AlertState::Ok => EvaluationOutcome::Ok,
+            AlertState::Pending => EvaluationOutcome::Pending,
+            AlertState::Firing => EvaluationOutcome::Firing,
+            AlertState::Resolved => EvaluationOutcome::Resolved,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum DeliveryStatus {
+    #[default]
+    #[serde(rename = "skipped")]
+    Skipped,
+    #[serde(rename = "sent")]
+    Sent,
+    #[serde(rename = "partial")]
+    Partial,
+    #[serde(rename = "failed")]
+    Failed,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_delivery() {
+        let alert = Alert {
+            destinations: vec!["slack".to_string(), "email".to_string()],
+            ..Default::default()
+        };
+        let mut history = AlertHistory::new(&alert);
+        assert_eq!(history.delivery_status, DeliveryStatus::Skipped);
+        history.set_delivery(&[]);
+        assert_eq!(history.delivery_status, DeliveryStatus::Sent);
+        history.set_delivery(&[("slack".to_string(), "timeout".to_string())]);
+        assert_eq!(history.delivery_status, DeliveryStatus::Partial);
+        assert_eq!(history.delivery_error, "slack: timeout");
+        history.set_delivery(&[
+            ("slack".to_string(), "timeout".to_string()),
+            ("email".to_string(), "refused".to_string()),
+        ]);
+        assert_eq!(history.delivery_status, DeliveryStatus::Failed);
+    }
+}
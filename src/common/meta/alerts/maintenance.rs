@@ -0,0 +1,88 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use ahash::HashMap;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::common::meta::StreamType;
+
+/// Suppresses the notifications of the matching alerts while it is active,
+/// the alerts are still evaluated and recorded in the history.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct MaintenanceWindow {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub start_time: i64, // in microseconds, the window starts at it when not recurring
+    #[serde(default)]
+    pub end_time: i64, // in microseconds, the window ends at it when not recurring
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cron: Option<String>, // recurring windows start on the cron schedule
+    #[serde(default)]
+    pub duration: i64, // in minutes, how long a recurring window lasts
+    #[serde(default = "default_timezone")]
+    pub timezone: String, // timezone of the cron schedule, such as Asia/Shanghai
+    #[serde(default)]
+    pub matchers: MaintenanceMatchers,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+fn default_timezone() -> String {
+    "UTC".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Selects the alerts of a maintenance window, all of the given matchers must
+/// match and an empty matcher matches every alert of the organization.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct MaintenanceMatchers {
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alert_name: Option<String>, // glob pattern, such as `api_*`
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_type: Option<StreamType>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_name: Option<String>,
+    #[serde(default)]
+    pub labels: HashMap<String, String>, // matched against the context attributes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::utils::json;
+
+    #[test]
+    fn test_maintenance_window_defaults() {
+        let window: MaintenanceWindow =
+            json::from_str(r#"{"name":"upgrade","start_time":1,"end_time":2}"#).unwrap();
+        assert!(window.enabled);
+        assert_eq!(window.timezone, "UTC");
+
+        let window: MaintenanceWindow =
+            json::from_str(r#"{"name":"upgrade","enabled":false}"#).unwrap();
+        assert!(!window.enabled);
+    }
+}
@@ -21,6 +21,10 @@ use super::StreamType;
 use crate::common::utils::json::Value;
 
 pub mod destinations;
+pub mod history;
+pub mod maintenance;
+pub mod preview;
+pub mod retries;
 pub mod templates;
 pub mod triggers;
 
@@ -86,6 +90,10 @@ pub struct TriggerCondition {
     pub frequency: i64, // 1 minute
     #[serde(default)]
     pub silence: i64, // silence for 10 minutes after fire an alert
+    #[serde(default)]
+    pub pending_evaluations: i64, // condition should match 2 evaluations before fire
+    #[serde(default)]
+    pub notify_on_resolved: bool, // send a notification when the condition clears
 }
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
@@ -94,9 +102,14 @@ pub struct QueryCondition {
     #[serde(rename = "type")]
     pub query_type: QueryType,
     pub conditions: Option<Vec<Condition>>,
+    #[serde(default)]
+    pub logical_operator: LogicalOperator, // how the conditions are combined
     pub sql: Option<String>,
     pub promql: Option<String>,
     pub aggregation: Option<Aggregation>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub anomaly: Option<AnomalyDetection>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
@@ -106,6 +119,58 @@ pub struct Aggregation {
     pub having: Condition,
 }
 
+/// Compares the aggregate of the current period against a baseline built from
+/// the previous periods of the same stream.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct AnomalyDetection {
+    pub function: AggFunction,
+    pub column: String,
+    #[serde(default)]
+    pub baseline: BaselineMethod,
+    #[serde(default)]
+    pub periods: i64, // number of past periods in the baseline, 0 for the default
+    #[serde(default)]
+    pub seasonal: bool, // use the same time of the day in the past weeks
+    #[serde(default = "default_anomaly_sigma")]
+    pub sigma: f64, // fire when the deviation exceeds it
+}
+
+fn default_anomaly_sigma() -> f64 {
+    3.0
+}
+
+impl AnomalyDetection {
+    /// Returns the number of past periods in the baseline. The seasonal default
+    /// is kept short as every period of it goes back one more week.
+    pub fn baseline_periods(&self) -> i64 {
+        if self.periods > 0 {
+            self.periods
+        } else if self.seasonal {
+            4
+        } else {
+            24
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub enum BaselineMethod {
+    #[default]
+    #[serde(rename = "mean_stddev")]
+    MeanStddev,
+    #[serde(rename = "median_mad")]
+    MedianMad,
+}
+
+impl ToString for BaselineMethod {
+    fn to_string(&self) -> String {
+        match self {
+            BaselineMethod::MeanStddev => "mean_stddev".to_string(),
+            BaselineMethod::MedianMad => "median_mad".to_string(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
 pub enum AggFunction {
     #[serde(rename = "avg")]
@@ -114,6 +179,18 @@ pub enum AggFunction {
     Min,
     #[serde(rename = "max")]
     Max,
+    #[serde(rename = "sum")]
+    Sum,
+    #[serde(rename = "count")]
+    Count,
+    #[serde(rename = "median")]
+    Median,
+    #[serde(rename = "p50")]
+    P50,
+    #[serde(rename = "p95")]
+    P95,
+    #[serde(rename = "p99")]
+    P99,
 }
 
 impl ToString for AggFunction {
@@ -122,6 +199,12 @@ impl ToString for AggFunction {
             AggFunction::Avg => "avg".to_string(),
             AggFunction::Min => "min".to_string(),
             AggFunction::Max => "max".to_string(),
+            AggFunction::Sum => "sum".to_string(),
+            AggFunction::Count => "count".to_string(),
+            AggFunction::Median => "median".to_string(),
+            AggFunction::P50 => "p50".to_string(),
+            AggFunction::P95 => "p95".to_string(),
+            AggFunction::P99 => "p99".to_string(),
         }
     }
 }
@@ -133,11 +216,47 @@ impl TryFrom<&str> for AggFunction {
             "avg" => AggFunction::Avg,
             "min" => AggFunction::Min,
             "max" => AggFunction::Max,
+            "sum" => AggFunction::Sum,
+            "count" => AggFunction::Count,
+            "median" => AggFunction::Median,
+            "p50" => AggFunction::P50,
+            "p95" => AggFunction::P95,
+            "p99" => AggFunction::P99,
             _ => return Err("invalid aggregation function"),
         })
     }
 }
 
+impl AggFunction {
+    /// Returns the SQL expression which aggregates the column.
+    pub fn to_sql(&self, column: &str) -> String {
+        match self {
+            AggFunction::P50 => format!("approx_percentile_cont(\"{column}\", 0.5)"),
+            AggFunction::P95 => format!("approx_percentile_cont(\"{column}\", 0.95)"),
+            AggFunction::P99 => format!("approx_percentile_cont(\"{column}\", 0.99)"),
+            _ => format!("{}(\"{column}\")", self.to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub enum LogicalOperator {
+    #[default]
+    #[serde(rename = "and")]
+    And,
+    #[serde(rename = "or")]
+    Or,
+}
+
+impl ToString for LogicalOperator {
+    fn to_string(&self) -> String {
+        match self {
+            LogicalOperator::And => "AND".to_string(),
+            LogicalOperator::Or => "OR".to_string(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
 pub enum QueryType {
     #[default]
@@ -147,6 +266,8 @@ pub enum QueryType {
     SQL,
     #[serde(rename = "promql")]
     PromQL,
+    #[serde(rename = "anomaly")]
+    Anomaly,
 }
 
 impl ToString for QueryType {
@@ -155,6 +276,7 @@ impl ToString for QueryType {
             QueryType::Custom => "custom".to_string(),
             QueryType::SQL => "sql".to_string(),
             QueryType::PromQL => "promql".to_string(),
+            QueryType::Anomaly => "anomaly".to_string(),
         }
     }
 }
@@ -165,6 +287,7 @@ impl From<&str> for QueryType {
             "custom" => QueryType::Custom,
             "sql" => QueryType::SQL,
             "promql" => QueryType::PromQL,
+            "anomaly" => QueryType::Anomaly,
             _ => QueryType::Custom,
         }
     }
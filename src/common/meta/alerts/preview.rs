@@ -0,0 +1,48 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::triggers::AlertState;
+use crate::common::utils::json::Value;
+
+/// What an alert would have done over a historical time range.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct AlertPreview {
+    pub query: String,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub intervals: Vec<PreviewInterval>,
+    #[serde(default)]
+    pub template: String,
+    /// The notification body for the first interval which notifies.
+    #[schema(value_type = Object)]
+    pub notification: Option<Value>,
+}
+
+/// The trigger decision for one evaluation of the alert.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PreviewInterval {
+    pub start_time: i64,
+    pub end_time: i64,
+    pub matched_rows: usize,
+    pub state: AlertState,
+    pub notify: bool,
+    /// The maintenance window which muted the notification.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub maintenance_window: String,
+}
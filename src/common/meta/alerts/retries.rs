@@ -0,0 +1,40 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::triggers::AlertState;
+use crate::common::{meta::StreamType, utils::json::Value};
+
+/// A notification which failed to deliver, it is retried with backoff and
+/// moved to the dead letters once it runs out of attempts.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct FailedNotification {
+    pub id: String,
+    pub org_id: String,
+    pub stream_type: StreamType,
+    pub stream_name: String,
+    pub alert_name: String,
+    pub destination: String,
+    #[schema(value_type = Object)]
+    pub payload: Value,
+    pub state: AlertState,
+    pub attempts: u32,
+    pub next_retry_at: i64,
+    pub created_at: i64,
+    #[serde(default)]
+    pub last_error: String,
+}
@@ -13,11 +13,47 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::fmt;
+
+use ahash::HashMap;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Trigger {
     pub next_run_at: i64,
     pub is_realtime: bool,
     pub is_silenced: bool,
+    #[serde(default)]
+    pub state: AlertState,
+    #[serde(default)]
+    pub pending_count: i64, // consecutive evaluations which matched the condition
+    #[serde(default)]
+    pub fired_at: i64, // when the alert moved into firing state, in microseconds
+    #[serde(default)]
+    pub silenced_groups: HashMap<String, i64>, // group key -> silenced until, in microseconds
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum AlertState {
+    #[default]
+    #[serde(rename = "ok")]
+    Ok,
+    #[serde(rename = "pending")]
+    Pending,
+    #[serde(rename = "firing")]
+    Firing,
+    #[serde(rename = "resolved")]
+    Resolved,
+}
+
+impl fmt::Display for AlertState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AlertState::Ok => write!(f, "ok"),
+            AlertState::Pending => write!(f, "pending"),
+            AlertState::Firing => write!(f, "firing"),
+            AlertState::Resolved => write!(f, "resolved"),
+        }
+    }
 }
@@ -0,0 +1,68 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::StreamType;
+
+/// An enrichment table refreshed on a schedule from its source. A refresh
+/// replaces the rows of the table, a failed refresh keeps the previous ones.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct ScheduledEnrichmentTable {
+    #[serde(default)]
+    pub name: String,
+    pub source: EnrichmentTableSource,
+    #[serde(default = "default_frequency")]
+    pub frequency: String, // such as `30m`, `1h`
+    /// Maintained by the refreshes, ignored when the table is saved.
+    #[serde(default)]
+    pub status: RefreshStatus,
+}
+
+fn default_frequency() -> String {
+    "1h".to_string()
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum EnrichmentTableSource {
+    /// The rows returned by the SQL query over a stream, for the last `period`.
+    Query {
+        #[serde(default)]
+        stream_type: StreamType,
+        sql: String,
+        #[serde(default = "default_period")]
+        period: String, // such as `15m`, `1d`
+    },
+    /// A CSV file in the object storage, the path is relative to the
+    /// `enrichment_sources/{org_id}/` folder of its bucket.
+    File { path: String },
+}
+
+fn default_period() -> String {
+    "1d".to_string()
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct RefreshStatus {
+    /// Incremented by every successful refresh.
+    pub version: u64,
+    pub last_refreshed_at: i64, // in microseconds
+    pub last_attempt_at: i64,   // in microseconds
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub last_error: String,
+}
@@ -15,7 +15,7 @@
 
 use std::io;
 
-use actix_web::web;
+use actix_web::{http, web};
 use ahash::AHashMap as HashMap;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
@@ -69,6 +69,20 @@ impl IngestionResponse {
             error: None,
         }
     }
+
+    /// Returns the number of the records which failed in all the streams, and
+    /// the last error reported.
+    pub fn failed(&self) -> (u32, Option<&str>) {
+        let failed = self.status.iter().map(|s| s.status.failed).sum();
+        let error = self
+            .status
+            .iter()
+            .rev()
+            .map(|s| s.status.error.as_str())
+            .find(|e| !e.is_empty())
+            .or(self.error.as_deref());
+        (failed, error)
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -79,7 +93,7 @@ pub struct StreamSchemaChk {
     pub has_metadata: bool,
 }
 
-pub const INGESTION_EP: [&str; 14] = [
+pub const INGESTION_EP: [&str; 15] = [
     "_bulk",
     "_json",
     "_multi",
@@ -94,6 +108,7 @@ pub const INGESTION_EP: [&str; 14] = [
     "logs",
     "metrics",
     "_json_arrow",
+    "push",
 ];
 
 #[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
@@ -285,11 +300,114 @@ pub struct GCPIngestionResponse {
     pub timestamp: String,
 }
 
+/// An event of the Splunk HTTP Event Collector.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct HecEvent {
+    /// The epoch time in seconds, with an optional fraction.
+    pub time: Option<json::Value>,
+    pub host: Option<String>,
+    pub source: Option<String>,
+    pub sourcetype: Option<String>,
+    /// The index, written to the stream of the same name.
+    pub index: Option<String>,
+    pub event: Option<json::Value>,
+    #[serde(default)]
+    pub fields: json::Map<String, json::Value>,
+}
+
+/// The query parameters of the Splunk HTTP Event Collector, the defaults of
+/// the metadata of the events.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct HecParams {
+    pub index: Option<String>,
+    pub host: Option<String>,
+    pub source: Option<String>,
+    pub sourcetype: Option<String>,
+}
+
+/// The acknowledgement of the Splunk HTTP Event Collector.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct HecResponse {
+    pub text: String,
+    pub code: u16,
+    #[serde(rename = "invalid-event-number")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub invalid_event_number: Option<usize>,
+}
+
+impl HecResponse {
+    fn new(text: &str, code: u16, invalid_event_number: Option<usize>) -> Self {
+        HecResponse {
+            text: text.to_string(),
+            code,
+            invalid_event_number,
+        }
+    }
+
+    pub fn success() -> Self {
+        Self::new("Success", 0, None)
+    }
+
+    pub fn token_required() -> Self {
+        Self::new("Token is required", 2, None)
+    }
+
+    pub fn invalid_authorization() -> Self {
+        Self::new("Invalid authorization", 3, None)
+    }
+
+    pub fn invalid_token() -> Self {
+        Self::new("Invalid token", 4, None)
+    }
+
+    pub fn no_data() -> Self {
+        Self::new("No data", 5, None)
+    }
+
+    pub fn invalid_data_format(event: usize) -> Self {
+        Self::new("Invalid data format", 6, Some(event))
+    }
+
+    /// Some events were parsed but could not be indexed, such as the events
+    /// older than the allowed ingestion time.
+    pub fn events_failed() -> Self {
+        Self::new("Invalid data format", 6, None)
+    }
+
+    pub fn internal_error() -> Self {
+        Self::new("Internal server error", 8, None)
+    }
+
+    pub fn event_required(event: usize) -> Self {
+        Self::new("Event field is required", 12, Some(event))
+    }
+
+    pub fn event_blank(event: usize) -> Self {
+        Self::new("Event field cannot be blank", 13, Some(event))
+    }
+
+    pub fn healthy() -> Self {
+        Self::new("HEC is healthy", 17, None)
+    }
+
+    /// The HTTP status of the acknowledgement, as the collector returns it.
+    pub fn status_code(&self) -> http::StatusCode {
+        match self.code {
+            0 | 17 => http::StatusCode::OK,
+            2 | 3 => http::StatusCode::UNAUTHORIZED,
+            4 => http::StatusCode::FORBIDDEN,
+            8 => http::StatusCode::INTERNAL_SERVER_ERROR,
+            _ => http::StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
 pub enum IngestionRequest<'a> {
     JSON(&'a web::Bytes),
     Multi(&'a web::Bytes),
     KinesisFH(&'a KinesisFHRequest),
     GCP(&'a GCPIngestionRequest),
+    Loki(&'a Vec<json::Value>),
 }
 
 pub enum IngestionData<'a> {
@@ -331,3 +449,35 @@ pub enum IngestionDataIter<'a> {
         Option<KinesisFHIngestionResponse>,
     ),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ingestion_response_failed() {
+        let mut ok = StreamStatus::new("default");
+        ok.status.successful = 2;
+        let resp = IngestionResponse::new(200, vec![ok.clone()]);
+        assert_eq!(resp.failed(), (0, None));
+
+        let mut too_old = StreamStatus::new("app");
+        too_old.status.failed = 3;
+        too_old.status.error = "too old data".to_string();
+        let resp = IngestionResponse::new(200, vec![too_old, ok]);
+        assert_eq!(resp.failed(), (3, Some("too old data")));
+        assert_eq!(
+            HecResponse::events_failed().status_code(),
+            http::StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[test]
+    fn test_hec_response_status_code() {
+        assert_eq!(HecResponse::healthy().status_code(), http::StatusCode::OK);
+        assert_eq!(
+            HecResponse::invalid_token().status_code(),
+            http::StatusCode::FORBIDDEN
+        );
+    }
+}
@@ -0,0 +1,80 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::utils::json;
+
+/// The push request in the JSON format, such as
+/// `{"streams":[{"stream":{"app":"cart"},"values":[["1700000000000000000","
+/// line"]]}]}`.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct PushRequest {
+    #[serde(default)]
+    pub streams: Vec<PushStream>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct PushStream {
+    /// The labels of the stream.
+    #[serde(default)]
+    pub stream: HashMap<String, String>,
+    /// The entries, the timestamp in nanoseconds as a string, the line and an
+    /// optional object of structured metadata.
+    #[serde(default)]
+    pub values: Vec<Vec<json::Value>>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct RequestQueryRange {
+    pub query: Option<String>,
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub step: Option<String>,
+    pub limit: Option<usize>,
+    pub direction: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct RequestLabels {
+    pub start: Option<String>,
+    pub end: Option<String>,
+    /// The stream selector of the label values.
+    pub query: Option<String>,
+}
+
+/// The result of the `query_range` API.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "resultType", content = "result", rename_all = "lowercase")]
+pub enum QueryResult {
+    Streams(Vec<Stream>),
+    Matrix(Vec<Series>),
+}
+
+/// The lines of a stream, as the timestamp in nanoseconds and the line.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Stream {
+    pub stream: BTreeMap<String, String>,
+    pub values: Vec<[String; 2]>,
+}
+
+/// The samples of a metric query, as the timestamp in seconds and the value.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Series {
+    pub metric: BTreeMap<String, String>,
+    pub values: Vec<(f64, String)>,
+}
@@ -18,15 +18,18 @@ use utoipa::ToSchema;
 pub mod alerts;
 pub mod common;
 pub mod dashboards;
+pub mod enrichment_table;
 pub mod functions;
 pub mod http;
 pub mod ingestion;
+pub mod loki;
 pub mod maxmind;
 pub mod meta_store;
 pub mod middleware_data;
 pub mod organization;
 pub mod prom;
 pub mod proxy;
+pub mod rules;
 pub mod saved_view;
 pub mod search;
 pub mod service;
@@ -13,6 +13,8 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::BTreeMap;
+
 use ahash::HashMap;
 use serde::{Deserialize, Serialize};
 use strum::Display;
@@ -27,6 +29,8 @@ pub const VALUE_LABEL: &str = "value";
 pub const LE_LABEL: &str = "le";
 pub const QUANTILE_LABEL: &str = "quantile";
 pub const METADATA_LABEL: &str = "prom_metadata"; // for schema metadata key
+pub const EXEMPLARS_LABEL: &str = "exemplars";
+pub const HISTOGRAM_LABEL: &str = "histogram"; // native histogram, JSON encoded
 
 #[derive(Debug, Clone, Serialize)]
 pub struct Metric<'a> {
@@ -35,6 +39,66 @@ pub struct Metric<'a> {
     pub value: f64,
 }
 
+/// A native (exponential) histogram, stored JSON encoded in the
+/// [`HISTOGRAM_LABEL`] column of its metric stream.
+///
+/// Buckets follow the OpenTelemetry convention: with `base = 2^(2^-schema)`,
+/// the positive bucket of index `i` covers `(base^i, base^(i+1)]` and the
+/// negative bucket of index `i` covers `[-base^(i+1), -base^i)`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NativeHistogram {
+    pub schema: i32,
+    pub count: f64,
+    pub sum: f64,
+    #[serde(default)]
+    pub zero_threshold: f64,
+    #[serde(default)]
+    pub zero_count: f64,
+    #[serde(default)]
+    pub positive: HistogramBuckets,
+    #[serde(default)]
+    pub negative: HistogramBuckets,
+}
+
+/// Consecutive buckets of a [`NativeHistogram`], starting at index `offset`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HistogramBuckets {
+    pub offset: i32,
+    pub counts: Vec<f64>,
+}
+
+impl NativeHistogram {
+    /// Returns the upper bound of the positive bucket `index`, which is also
+    /// the absolute value of the lower bound of the negative bucket `index`.
+    pub fn bucket_bound(&self, index: i32) -> f64 {
+        2_f64.powf(f64::from(index + 1) * 2_f64.powi(-self.schema))
+    }
+
+    /// Returns the `(upper bound, cumulative count)` buckets of the equivalent
+    /// classic histogram, from the lowest negative bucket to the `+Inf` one.
+    pub fn cumulative_buckets(&self) -> Vec<(f64, f64)> {
+        let mut buckets =
+            Vec::with_capacity(self.negative.counts.len() + self.positive.counts.len() + 2);
+        let mut total = 0.0;
+        for (i, count) in self.negative.counts.iter().enumerate().rev() {
+            total += count;
+            // the negative bucket `index` ends at `-base^index`
+            let index = self.negative.offset + i as i32;
+            buckets.push((-self.bucket_bound(index - 1), total));
+        }
+        if self.zero_count > 0.0 {
+            total += self.zero_count;
+            buckets.push((self.zero_threshold, total));
+        }
+        for (i, count) in self.positive.counts.iter().enumerate() {
+            total += count;
+            buckets.push((self.bucket_bound(self.positive.offset + i as i32), total));
+        }
+        buckets.push((f64::INFINITY, self.count));
+        buckets
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Eq, PartialEq, Deserialize)]
 pub struct ClusterLeader {
     pub name: String,
@@ -217,6 +281,34 @@ pub struct RequestFormatQuery {
     pub query: String,
 }
 
+/// Request the exemplars of the series selected by a query.
+#[derive(Debug, Deserialize)]
+pub struct RequestQueryExemplars {
+    /// Prometheus expression query string.
+    pub query: Option<String>,
+    /// Start timestamp.
+    pub start: Option<String>,
+    /// End timestamp.
+    pub end: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExemplarSeries {
+    pub series_labels: BTreeMap<String, String>,
+    pub exemplars: Vec<ExemplarData>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExemplarData {
+    /// Labels of the exemplar, typically the `trace_id` and `span_id` of the
+    /// trace it was recorded in.
+    pub labels: BTreeMap<String, String>,
+    pub value: String,
+    /// Time in seconds.
+    pub timestamp: f64,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -233,4 +325,54 @@ mod tests {
         assert_eq!(format!("{}", MetricType::Unknown), "unknown");
         assert_eq!(MetricType::Unknown.to_string(), "unknown");
     }
+
+    #[test]
+    fn test_native_histogram_bucket_bound() {
+        let hist = NativeHistogram {
+            schema: 0,
+            ..Default::default()
+        };
+        assert_eq!(hist.bucket_bound(-1), 1.0);
+        assert_eq!(hist.bucket_bound(0), 2.0);
+        assert_eq!(hist.bucket_bound(2), 8.0);
+
+        let hist = NativeHistogram {
+            schema: 1,
+            ..Default::default()
+        };
+        assert_eq!(hist.bucket_bound(1), 2.0);
+        assert!((hist.bucket_bound(0) - std::f64::consts::SQRT_2).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_native_histogram_cumulative_buckets() {
+        let hist = NativeHistogram {
+            schema: 0,
+            count: 10.0,
+            zero_threshold: 0.5,
+            zero_count: 1.0,
+            // buckets [-2, -1) and [-4, -2)
+            negative: HistogramBuckets {
+                offset: 0,
+                counts: vec![2.0, 1.0],
+            },
+            // buckets (2, 4] and (4, 8]
+            positive: HistogramBuckets {
+                offset: 1,
+                counts: vec![4.0, 2.0],
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            hist.cumulative_buckets(),
+            vec![
+                (-2.0, 1.0),
+                (-1.0, 3.0),
+                (0.5, 4.0),
+                (4.0, 8.0),
+                (8.0, 10.0),
+                (f64::INFINITY, 10.0)
+            ]
+        );
+    }
 }
@@ -0,0 +1,124 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use ahash::HashMap;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// The content of a Prometheus rules file.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct RuleGroups {
+    #[serde(default)]
+    pub groups: Vec<RuleGroup>,
+}
+
+/// A named group of rules which are evaluated in order at the same interval.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct RuleGroup {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default = "default_interval")]
+    pub interval: String, // such as `30s`, `1m`
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+}
+
+fn default_interval() -> String {
+    "1m".to_string()
+}
+
+/// A recording rule writes the result of the expression as the metric named by
+/// `record`, an alerting rule named by `alert` fires when the expression
+/// returns any series for the `for` duration. The labels are added to the
+/// results.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct Rule {
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub record: String,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub alert: String,
+    #[serde(default)]
+    pub expr: String,
+    #[serde(default)]
+    #[serde(rename = "for")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub for_duration: Option<String>, // such as `5m`, only for alerting rules
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub annotations: HashMap<String, String>, // only for alerting rules
+}
+
+impl Rule {
+    pub fn is_alerting(&self) -> bool {
+        !self.alert.is_empty()
+    }
+}
+
+/// Response of the Prometheus `/api/v1/rules` API.
+#[derive(Debug, Default, Serialize)]
+pub struct RuleDiscovery {
+    pub groups: Vec<RuleGroupStatus>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleGroupStatus {
+    pub name: String,
+    pub file: String,
+    pub rules: Vec<RuleStatus>,
+    pub interval: f64, // in seconds
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleStatus {
+    #[serde(rename = "type")]
+    pub rule_type: String, // alerting, recording
+    pub name: String,
+    pub query: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>, // in seconds, only for alerting rules
+    pub labels: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alerts: Option<Vec<ActiveAlert>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>, // inactive, pending, firing
+    pub health: String, // ok, err, unknown
+    pub last_error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_evaluation: Option<String>, // RFC3339
+}
+
+/// Response of the Prometheus `/api/v1/alerts` API.
+#[derive(Debug, Default, Serialize)]
+pub struct AlertDiscovery {
+    pub alerts: Vec<ActiveAlert>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActiveAlert {
+    pub labels: HashMap<String, String>,
+    pub annotations: HashMap<String, String>,
+    pub state: String, // pending, firing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_at: Option<String>, // RFC3339
+}
@@ -52,6 +52,8 @@ pub enum SpanRefType {
     ChildOf,
     #[serde(rename = "PARENT_OF")]
     ParentOf,
+    #[serde(rename = "FOLLOWS_FROM")]
+    FollowsFrom,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -100,3 +102,243 @@ pub struct ExportTracePartialSuccess {
     // is equivalent to it not being set.
     pub error_message: String,
 }
+
+/// Criteria of a trace search. A trace matches when one of its spans matches
+/// all of the criteria.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TraceSearchRequest {
+    pub service_name: Option<String>,
+    pub operation_name: Option<String>,
+    /// Minimum span duration, in microseconds.
+    pub min_duration: Option<i64>,
+    /// Maximum span duration, in microseconds.
+    pub max_duration: Option<i64>,
+    /// Span status: `OK`, `ERROR` or `UNSET`.
+    pub span_status: Option<String>,
+    /// Span attributes, and resource attributes prefixed with `service.`.
+    pub attributes: Vec<(String, String)>,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub from: usize,
+    pub size: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TraceSummary {
+    pub trace_id: String,
+    /// Start time of the first span, in nanoseconds.
+    pub start_time: i64,
+    /// End time of the last span, in nanoseconds.
+    pub end_time: i64,
+    /// Duration of the trace, in microseconds.
+    pub duration: i64,
+    pub root_span: Option<RootSpan>,
+    pub span_count: usize,
+    pub error_count: usize,
+    pub services: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct RootSpan {
+    pub span_id: String,
+    pub service_name: String,
+    pub operation_name: String,
+    /// Duration of the span, in microseconds.
+    pub duration: i64,
+}
+
+/// A trace with its spans assembled into trees, from their parent span ids.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TraceDetail {
+    #[serde(flatten)]
+    pub summary: TraceSummary,
+    pub spans: Vec<SpanNode>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SpanNode {
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    pub span: json::Map<String, json::Value>,
+    pub children: Vec<SpanNode>,
+}
+
+pub const SERVICE_GRAPH_STREAM: &str = "_service_graph";
+
+/// The calls from a service to another one, aggregated over a time window and
+/// written to the `_service_graph` stream of the organization.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ServiceGraphEdge {
+    /// Start of the window, in microseconds.
+    pub _timestamp: i64,
+    pub caller: String,
+    pub callee: String,
+    pub call_count: u64,
+    pub error_count: u64,
+    pub error_rate: f64,
+    /// Median duration of the calls, in microseconds.
+    pub p50_latency: i64,
+    /// 95th percentile duration of the calls, in microseconds.
+    pub p95_latency: i64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ServiceGraph {
+    pub nodes: Vec<String>,
+    pub edges: Vec<ServiceGraphEdge>,
+}
+
+/// A span of the Zipkin v2 JSON API.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ZipkinSpan {
+    pub trace_id: String,
+    pub id: String,
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+    /// `CLIENT`, `SERVER`, `PRODUCER` or `CONSUMER`.
+    #[serde(default)]
+    pub kind: Option<String>,
+    /// Start time, in microseconds.
+    #[serde(default)]
+    pub timestamp: u64,
+    /// In microseconds.
+    #[serde(default)]
+    pub duration: u64,
+    #[serde(default)]
+    pub local_endpoint: Option<ZipkinEndpoint>,
+    #[serde(default)]
+    pub remote_endpoint: Option<ZipkinEndpoint>,
+    #[serde(default)]
+    pub annotations: Vec<ZipkinAnnotation>,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ZipkinEndpoint {
+    #[serde(default)]
+    pub service_name: Option<String>,
+    #[serde(default)]
+    pub ipv4: Option<String>,
+    #[serde(default)]
+    pub ipv6: Option<String>,
+    #[serde(default)]
+    pub port: Option<u16>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ZipkinAnnotation {
+    /// In microseconds.
+    pub timestamp: u64,
+    pub value: String,
+}
+
+/// The envelope of the responses of the Jaeger query API.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct JaegerResponse<T> {
+    pub data: Option<T>,
+    pub total: usize,
+    pub limit: usize,
+    pub offset: usize,
+    pub errors: Option<Vec<JaegerError>>,
+}
+
+impl<T> JaegerResponse<T> {
+    pub fn data(data: T, total: usize) -> Self {
+        Self {
+            data: Some(data),
+            total,
+            limit: 0,
+            offset: 0,
+            errors: None,
+        }
+    }
+
+    pub fn error(code: u16, msg: impl ToString) -> Self {
+        Self {
+            data: None,
+            total: 0,
+            limit: 0,
+            offset: 0,
+            errors: Some(vec![JaegerError {
+                code,
+                msg: msg.to_string(),
+            }]),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct JaegerError {
+    pub code: u16,
+    pub msg: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JaegerTrace {
+    #[serde(rename = "traceID")]
+    pub trace_id: String,
+    pub spans: Vec<JaegerSpan>,
+    pub processes: HashMap<String, JaegerProcess>,
+    pub warnings: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JaegerSpan {
+    #[serde(rename = "traceID")]
+    pub trace_id: String,
+    #[serde(rename = "spanID")]
+    pub span_id: String,
+    pub operation_name: String,
+    pub references: Vec<JaegerReference>,
+    pub flags: u32,
+    /// In microseconds.
+    pub start_time: i64,
+    /// In microseconds.
+    pub duration: i64,
+    pub tags: Vec<JaegerKeyValue>,
+    pub logs: Vec<JaegerLog>,
+    #[serde(rename = "processID")]
+    pub process_id: String,
+    pub warnings: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JaegerReference {
+    /// `CHILD_OF` or `FOLLOWS_FROM`.
+    pub ref_type: String,
+    #[serde(rename = "traceID")]
+    pub trace_id: String,
+    #[serde(rename = "spanID")]
+    pub span_id: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct JaegerKeyValue {
+    pub key: String,
+    /// `string`, `bool`, `int64` or `float64`.
+    #[serde(rename = "type")]
+    pub value_type: String,
+    pub value: json::Value,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct JaegerLog {
+    /// In microseconds.
+    pub timestamp: i64,
+    pub fields: Vec<JaegerKeyValue>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JaegerProcess {
+    pub service_name: String,
+    pub tags: Vec<JaegerKeyValue>,
+}
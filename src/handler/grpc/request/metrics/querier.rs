@@ -32,13 +32,15 @@ use crate::{
     },
     handler::grpc::{
         cluster_rpc::{
-            metrics_server::Metrics, MetricsQueryRequest, MetricsQueryResponse, MetricsWalFile,
-            MetricsWalFileRequest, MetricsWalFileResponse,
+            metrics_server::Metrics, EmptyResponse, MetricsQueryRequest, MetricsQueryResponse,
+            MetricsRemoteWriteRequest, MetricsWalFile, MetricsWalFileRequest,
+            MetricsWalFileResponse,
         },
         request::MetadataMap,
     },
     service::{
         db,
+        metrics::prom,
         promql::search as SearchService,
         stream::{stream_settings, unwrap_partition_time_level},
     },
@@ -306,4 +308,16 @@ impl Metrics for Querier {
 
         Ok(Response::new(resp))
     }
+
+    #[tracing::instrument(name = "grpc:metrics:remote_write", skip_all, fields(org_id = req.get_ref().org_id))]
+    async fn remote_write(
+        &self,
+        req: Request<MetricsRemoteWriteRequest>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        let req = req.into_inner();
+        prom::remote_write(&req.org_id, 0, req.data.into())
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(EmptyResponse {}))
+    }
 }
@@ -15,9 +15,9 @@
 
 use actix_web::{
     dev::ServiceRequest,
-    error::{ErrorForbidden, ErrorUnauthorized},
+    error::{ErrorForbidden, ErrorUnauthorized, InternalError},
     http::{header, Method},
-    web, Error,
+    web, Error, HttpMessage, HttpResponse,
 };
 use actix_web_httpauth::extractors::basic::BasicAuth;
 #[cfg(feature = "enterprise")]
@@ -27,7 +27,7 @@ use crate::{
     common::{
         infra::config::CONFIG,
         meta::{
-            ingestion::INGESTION_EP,
+            ingestion::{HecResponse, INGESTION_EP},
             proxy::QueryParamProxyURL,
             user::{DBUser, UserRole},
         },
@@ -401,6 +401,41 @@ pub async fn validator_rum(
     }
 }
 
+/// `validator_splunk` validates the Splunk HTTP Event Collector requests, the
+/// token of the `Authorization: Splunk <token>` header is the ingestion
+/// passcode of the org. The org is the one of the path under `/splunk`, or the
+/// one of the passcode for the collector paths, and the user of the passcode
+/// is added to the request. The errors are acknowledgements of the collector.
+pub async fn validator_splunk(
+    req: ServiceRequest,
+    _credentials: Option<BasicAuth>,
+) -> Result<ServiceRequest, (Error, ServiceRequest)> {
+    let org_id = req
+        .request()
+        .path()
+        .strip_prefix(format!("{}/splunk/", CONFIG.common.base_uri).as_str())
+        .map(|path| path.split('/').next().unwrap_or_default().to_string());
+
+    let resp = match req.headers().get(header::AUTHORIZATION) {
+        None => HecResponse::token_required(),
+        Some(val) => match val.to_str().ok().and_then(|v| v.strip_prefix("Splunk ")) {
+            None => HecResponse::invalid_authorization(),
+            Some(token) => match users::get_user_by_passcode(org_id.as_deref(), token.trim()) {
+                Some(user) => {
+                    req.extensions_mut().insert(user);
+                    return Ok(req);
+                }
+                None => HecResponse::invalid_token(),
+            },
+        },
+    };
+    let err = InternalError::from_response(
+        resp.text.clone(),
+        HttpResponse::build(resp.status_code()).json(resp),
+    );
+    Err((err.into(), req))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
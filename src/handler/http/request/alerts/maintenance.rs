@@ -0,0 +1,138 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::io::Error;
+
+use actix_web::{delete, get, http, post, web, HttpResponse};
+
+use crate::{
+    common::meta::{
+        alerts::maintenance::MaintenanceWindow, http::HttpResponse as MetaHttpResponse,
+    },
+    service::alerts::maintenance,
+};
+
+/// CreateMaintenanceWindow
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Alerts",
+    operation_id = "SaveMaintenanceWindow",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("window_name" = String, Path, description = "Maintenance window name"),
+      ),
+    request_body(content = MaintenanceWindow, description = "Maintenance window data", content_type = "application/json"),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = HttpResponse),
+        (status = 400, description = "Error",   content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/alerts/maintenance_windows/{window_name}")]
+pub async fn save_maintenance_window(
+    path: web::Path<(String, String)>,
+    window: web::Json<MaintenanceWindow>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, name) = path.into_inner();
+    match maintenance::save(&org_id, &name, window.into_inner()).await {
+        Ok(_) => Ok(MetaHttpResponse::ok("Maintenance window saved")),
+        Err(e) => Ok(MetaHttpResponse::bad_request(e)),
+    }
+}
+
+/// GetMaintenanceWindow
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Alerts",
+    operation_id = "GetMaintenanceWindow",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("window_name" = String, Path, description = "Maintenance window name"),
+      ),
+    responses(
+        (status = 200, description = "Success",  content_type = "application/json", body = MaintenanceWindow),
+        (status = 404, description = "NotFound", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/alerts/maintenance_windows/{window_name}")]
+async fn get_maintenance_window(path: web::Path<(String, String)>) -> Result<HttpResponse, Error> {
+    let (org_id, name) = path.into_inner();
+    match maintenance::get(&org_id, &name).await {
+        Ok(data) => Ok(MetaHttpResponse::json(data)),
+        Err(e) => Ok(MetaHttpResponse::not_found(e)),
+    }
+}
+
+/// ListMaintenanceWindows
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Alerts",
+    operation_id = "ListMaintenanceWindows",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+      ),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = Vec<MaintenanceWindow>),
+        (status = 400, description = "Error",   content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/alerts/maintenance_windows")]
+async fn list_maintenance_windows(path: web::Path<String>) -> Result<HttpResponse, Error> {
+    let org_id = path.into_inner();
+    match maintenance::list(&org_id).await {
+        Ok(data) => Ok(MetaHttpResponse::json(data)),
+        Err(e) => Ok(MetaHttpResponse::bad_request(e)),
+    }
+}
+
+/// DeleteMaintenanceWindow
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Alerts",
+    operation_id = "DeleteMaintenanceWindow",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("window_name" = String, Path, description = "Maintenance window name"),
+    ),
+    responses(
+        (status = 200, description = "Success",  content_type = "application/json", body = HttpResponse),
+        (status = 404, description = "NotFound", content_type = "application/json", body = HttpResponse),
+        (status = 500, description = "Failure",  content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[delete("/{org_id}/alerts/maintenance_windows/{window_name}")]
+async fn delete_maintenance_window(
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, name) = path.into_inner();
+    match maintenance::delete(&org_id, &name).await {
+        Ok(_) => Ok(MetaHttpResponse::ok("Maintenance window deleted")),
+        Err(e) => match e {
+            (http::StatusCode::NOT_FOUND, e) => Ok(MetaHttpResponse::not_found(e)),
+            (_, e) => Ok(MetaHttpResponse::internal_error(e)),
+        },
+    }
+}
@@ -17,16 +17,22 @@ use std::io::Error;
 
 use actix_web::{delete, get, http, post, put, web, HttpRequest, HttpResponse};
 use ahash::AHashMap as HashMap;
+use chrono::{Duration, Utc};
 
 use crate::{
     common::{
-        meta::{alerts::Alert, http::HttpResponse as MetaHttpResponse},
+        meta::{
+            alerts::{preview::AlertPreview, Alert},
+            http::HttpResponse as MetaHttpResponse,
+        },
         utils::http::get_stream_type_from_request,
     },
     service::alerts,
 };
 
+pub mod dead_letters;
 pub mod destinations;
+pub mod maintenance;
 pub mod templates;
 
 /// CreateAlert
@@ -303,3 +309,129 @@ async fn trigger_alert(
         },
     }
 }
+
+/// PreviewAlert
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Alerts",
+    operation_id = "PreviewAlert",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("stream_name" = String, Path, description = "Stream name"),
+        ("start_time" = Option<i64>, Query, description = "Start time in microseconds, default is 1 hour ago"),
+        ("end_time" = Option<i64>, Query, description = "End time in microseconds, default is now"),
+        ("template" = Option<String>, Query, description = "Template to render, default is the template of the first destination"),
+    ),
+    request_body(content = Alert, description = "Alert data", content_type = "application/json"),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = AlertPreview),
+        (status = 400, description = "Error",   content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/{stream_name}/alerts/_preview")]
+pub async fn preview_alert(
+    path: web::Path<(String, String)>,
+    alert: web::Json<Alert>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let (org_id, stream_name) = path.into_inner();
+    let query = web::Query::<HashMap<String, String>>::from_query(req.query_string()).unwrap();
+    let stream_type = match get_stream_type_from_request(&query) {
+        Ok(v) => v.unwrap_or_default(),
+        Err(e) => {
+            return Ok(MetaHttpResponse::bad_request(e));
+        }
+    };
+    let end_time = query
+        .get("end_time")
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or_else(|| Utc::now().timestamp_micros());
+    let start_time = query
+        .get("start_time")
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or_else(|| end_time - Duration::hours(1).num_microseconds().unwrap());
+    let template = query.get("template").map(|v| v.as_str());
+    match alerts::preview::preview(
+        &org_id,
+        stream_type,
+        &stream_name,
+        alert.into_inner(),
+        (start_time, end_time),
+        template,
+    )
+    .await
+    {
+        Ok(data) => Ok(MetaHttpResponse::json(data)),
+        Err(e) => Ok(MetaHttpResponse::bad_request(e)),
+    }
+}
+
+/// GetAlertHistory
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Alerts",
+    operation_id = "GetAlertHistory",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("stream_name" = String, Path, description = "Stream name"),
+        ("alert_name" = String, Path, description = "Alert name"),
+        ("start_time" = Option<i64>, Query, description = "Start time in microseconds, default is 24 hours ago"),
+        ("end_time" = Option<i64>, Query, description = "End time in microseconds, default is now"),
+        ("from" = Option<usize>, Query, description = "Offset of the first record"),
+        ("size" = Option<usize>, Query, description = "Number of records to return"),
+    ),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = SearchResponse),
+        (status = 400, description = "Error",   content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/{stream_name}/alerts/{alert_name}/history")]
+async fn get_alert_history(
+    path: web::Path<(String, String, String)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let (org_id, stream_name, name) = path.into_inner();
+    let query = web::Query::<HashMap<String, String>>::from_query(req.query_string()).unwrap();
+    let stream_type = match get_stream_type_from_request(&query) {
+        Ok(v) => v.unwrap_or_default(),
+        Err(e) => {
+            return Ok(MetaHttpResponse::bad_request(e));
+        }
+    };
+    let end_time = query
+        .get("end_time")
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or_else(|| Utc::now().timestamp_micros());
+    let start_time = query
+        .get("start_time")
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or_else(|| end_time - Duration::hours(24).num_microseconds().unwrap());
+    let from = query
+        .get("from")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or_default();
+    let size = query
+        .get("size")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(100);
+    match alerts::history::list(
+        &org_id,
+        stream_type,
+        &stream_name,
+        &name,
+        (start_time, end_time),
+        from,
+        size,
+    )
+    .await
+    {
+        Ok(data) => Ok(MetaHttpResponse::json(data)),
+        Err(e) => Ok(MetaHttpResponse::bad_request(e)),
+    }
+}
@@ -16,15 +16,17 @@
 use std::io::Error;
 
 use actix_multipart::Multipart;
-use actix_web::{post, web, HttpRequest, HttpResponse};
+use actix_web::{delete, get, http, post, web, HttpRequest, HttpResponse};
 use ahash::AHashMap;
 
 use crate::{
     common::{
         infra::config::{CONFIG, SIZE_IN_MB},
-        meta::http::HttpResponse as MetaHttpResponse,
+        meta::{
+            enrichment_table::ScheduledEnrichmentTable, http::HttpResponse as MetaHttpResponse,
+        },
     },
-    service::enrichment_table::save_enrichment_data,
+    service::enrichment_table::{save_enrichment_data, schedule},
 };
 
 /// CreateEnrichmentTable
@@ -95,3 +97,116 @@ pub async fn save_enrichment_table(
         )),
     }
 }
+
+/// SaveScheduledEnrichmentTable
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Functions",
+    operation_id = "SaveScheduledEnrichmentTable",
+    security(
+        ("Authorization" = [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("table_name" = String, Path, description = "Table name"),
+    ),
+    request_body(content = ScheduledEnrichmentTable, description = "Scheduled enrichment table data", content_type = "application/json"),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = HttpResponse),
+        (status = 400, description = "Error",   content_type = "application/json", body = HttpResponse),
+    ),
+)]
+#[post("/{org_id}/enrichment_tables/{table_name}/schedule")]
+pub async fn save_scheduled_table(
+    path: web::Path<(String, String)>,
+    table: web::Json<ScheduledEnrichmentTable>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, table_name) = path.into_inner();
+    match schedule::save(&org_id, &table_name, table.into_inner()).await {
+        Ok(_) => Ok(MetaHttpResponse::ok("Scheduled enrichment table saved")),
+        Err(e) => Ok(MetaHttpResponse::bad_request(e)),
+    }
+}
+
+/// GetScheduledEnrichmentTable
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Functions",
+    operation_id = "GetScheduledEnrichmentTable",
+    security(
+        ("Authorization" = [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("table_name" = String, Path, description = "Table name"),
+    ),
+    responses(
+        (status = 200, description = "Success",  content_type = "application/json", body = ScheduledEnrichmentTable),
+        (status = 404, description = "NotFound", content_type = "application/json", body = HttpResponse),
+    ),
+)]
+#[get("/{org_id}/enrichment_tables/{table_name}/schedule")]
+pub async fn get_scheduled_table(path: web::Path<(String, String)>) -> Result<HttpResponse, Error> {
+    let (org_id, table_name) = path.into_inner();
+    match schedule::get(&org_id, &table_name).await {
+        Ok(data) => Ok(MetaHttpResponse::json(data)),
+        Err(e) => Ok(MetaHttpResponse::not_found(e)),
+    }
+}
+
+/// ListScheduledEnrichmentTables
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Functions",
+    operation_id = "ListScheduledEnrichmentTables",
+    security(
+        ("Authorization" = [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+    ),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = Vec<ScheduledEnrichmentTable>),
+        (status = 400, description = "Error",   content_type = "application/json", body = HttpResponse),
+    ),
+)]
+#[get("/{org_id}/enrichment_tables/schedules")]
+pub async fn list_scheduled_tables(path: web::Path<String>) -> Result<HttpResponse, Error> {
+    let org_id = path.into_inner();
+    match schedule::list(&org_id).await {
+        Ok(data) => Ok(MetaHttpResponse::json(data)),
+        Err(e) => Ok(MetaHttpResponse::bad_request(e)),
+    }
+}
+
+/// DeleteScheduledEnrichmentTable
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Functions",
+    operation_id = "DeleteScheduledEnrichmentTable",
+    security(
+        ("Authorization" = [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("table_name" = String, Path, description = "Table name"),
+    ),
+    responses(
+        (status = 200, description = "Success",  content_type = "application/json", body = HttpResponse),
+        (status = 404, description = "NotFound", content_type = "application/json", body = HttpResponse),
+        (status = 500, description = "Failure",  content_type = "application/json", body = HttpResponse),
+    ),
+)]
+#[delete("/{org_id}/enrichment_tables/{table_name}/schedule")]
+pub async fn delete_scheduled_table(
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, table_name) = path.into_inner();
+    match schedule::delete(&org_id, &table_name).await {
+        Ok(_) => Ok(MetaHttpResponse::ok("Scheduled enrichment table deleted")),
+        Err(e) => match e {
+            (http::StatusCode::NOT_FOUND, e) => Ok(MetaHttpResponse::not_found(e)),
+            (_, e) => Ok(MetaHttpResponse::internal_error(e)),
+        },
+    }
+}
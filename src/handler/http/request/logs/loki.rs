@@ -0,0 +1,277 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::io::Error;
+
+use actix_web::{get, http, post, web, HttpRequest, HttpResponse};
+
+use crate::{
+    common::{
+        infra::config::CONFIG,
+        meta::{
+            http::HttpResponse as MetaHttpResponse,
+            loki::{RequestLabels, RequestQueryRange},
+        },
+    },
+    service::{
+        logs::loki::{self, logql, QueryRangeRequest},
+        promql::ApiFuncResponse,
+    },
+};
+
+/// The number of lines returned by default, as Loki does.
+const DEFAULT_LIMIT: usize = 100;
+
+/// LokiPush
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Logs",
+    operation_id = "LokiPush",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+    ),
+    request_body(content = String, description = "Loki push request, snappy compressed protobuf or JSON", content_type = "application/x-protobuf"),
+    responses(
+        (status = 204, description = "Success"),
+        (status = 400, description = "Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/loki/api/v1/push")]
+pub async fn push(
+    org_id: web::Path<String>,
+    thread_id: web::Data<usize>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let org_id = org_id.into_inner();
+    let in_stream_name = req
+        .headers()
+        .get(&CONFIG.grpc.stream_header_key)
+        .and_then(|header| header.to_str().ok());
+    let content_type = req
+        .headers()
+        .get("Content-Type")
+        .and_then(|header| header.to_str().ok())
+        .unwrap_or_default();
+    Ok(
+        match loki::push(&org_id, in_stream_name, content_type, &body, **thread_id).await {
+            Ok(_) => HttpResponse::NoContent().finish(),
+            Err(e) => {
+                log::error!("Error processing loki push request: {:?}", e);
+                HttpResponse::BadRequest().json(MetaHttpResponse::error(
+                    http::StatusCode::BAD_REQUEST.into(),
+                    e.to_string(),
+                ))
+            }
+        },
+    )
+}
+
+/// LokiQueryRange
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Logs",
+    operation_id = "LokiQueryRange",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("query" = String, Query, description = "LogQL query, eg: {app=\"cart\"} |= \"error\""),
+        ("start" = Option<String>, Query, description = "start time, nanoseconds or RFC3339, an hour before the end by default"),
+        ("end" = Option<String>, Query, description = "end time, nanoseconds or RFC3339, now by default"),
+        ("step" = Option<String>, Query, description = "step of the metric queries, eg: 15s"),
+        ("limit" = Option<usize>, Query, description = "maximum number of lines, 100 by default"),
+        ("direction" = Option<String>, Query, description = "forward or backward, backward by default"),
+    ),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = Object, example = json!({
+            "status": "success",
+            "data": {
+                "resultType": "streams",
+                "result": [{
+                    "stream": {"app": "cart"},
+                    "values": [["1700000000000000000", "connection refused"]]
+                }]
+            }
+        })),
+        (status = 400, description = "Failure", content_type = "application/json", body = Object),
+        (status = 500, description = "Failure", content_type = "application/json", body = Object),
+    )
+)]
+#[get("/{org_id}/loki/api/v1/query_range")]
+pub async fn query_range(
+    org_id: web::Path<String>,
+    req: web::Query<RequestQueryRange>,
+    in_req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let org_id = org_id.into_inner();
+    let req = req.into_inner();
+    let expr = match logql::parse(&req.query.unwrap_or_default()) {
+        Ok(expr) => expr,
+        Err(e) => {
+            return Ok(
+                HttpResponse::BadRequest().json(ApiFuncResponse::<()>::err_bad_data(format!(
+                    "parse logql error: {e}"
+                ))),
+            );
+        }
+    };
+    let range =
+        loki::time_range(req.start.as_deref(), req.end.as_deref()).and_then(|(start, end)| {
+            Ok((
+                start,
+                end,
+                loki::parse_step(req.step.as_deref(), start, end)?,
+            ))
+        });
+    let (start, end, step) = match range {
+        Ok(v) => v,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(ApiFuncResponse::<()>::err_bad_data(e)));
+        }
+    };
+    let query = QueryRangeRequest {
+        start,
+        end,
+        step,
+        limit: req.limit.unwrap_or(DEFAULT_LIMIT),
+        forward: req.direction.as_deref() == Some("forward"),
+    };
+    let stream_name = loki::stream_name(stream_header(&in_req));
+    Ok(
+        match loki::query_range(&org_id, &stream_name, &expr, &query).await {
+            Ok(resp) => HttpResponse::Ok().json(ApiFuncResponse::ok(resp)),
+            Err(e) => {
+                log::error!("loki query_range failed: {e}");
+                HttpResponse::InternalServerError()
+                    .json(ApiFuncResponse::<()>::err_internal(e.to_string()))
+            }
+        },
+    )
+}
+
+/// LokiLabels
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Logs",
+    operation_id = "LokiLabels",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+    ),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = Object, example = json!({
+            "status": "success",
+            "data": ["app", "env"]
+        })),
+    )
+)]
+#[get("/{org_id}/loki/api/v1/labels")]
+pub async fn labels(org_id: web::Path<String>, in_req: HttpRequest) -> Result<HttpResponse, Error> {
+    let org_id = org_id.into_inner();
+    let stream_name = loki::stream_name(stream_header(&in_req));
+    let labels = loki::labels(&org_id, &stream_name).await;
+    Ok(HttpResponse::Ok().json(ApiFuncResponse::ok(labels)))
+}
+
+/// LokiLabelValues
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Logs",
+    operation_id = "LokiLabelValues",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("label_name" = String, Path, description = "Label name"),
+        ("start" = Option<String>, Query, description = "start time, nanoseconds or RFC3339, an hour before the end by default"),
+        ("end" = Option<String>, Query, description = "end time, nanoseconds or RFC3339, now by default"),
+        ("query" = Option<String>, Query, description = "stream selector, eg: {env=\"prod\"}"),
+    ),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = Object, example = json!({
+            "status": "success",
+            "data": ["cart", "frontend"]
+        })),
+        (status = 400, description = "Failure", content_type = "application/json", body = Object),
+        (status = 500, description = "Failure", content_type = "application/json", body = Object),
+    )
+)]
+#[get("/{org_id}/loki/api/v1/label/{label_name}/values")]
+pub async fn label_values(
+    path: web::Path<(String, String)>,
+    req: web::Query<RequestLabels>,
+    in_req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let (org_id, label_name) = path.into_inner();
+    let req = req.into_inner();
+    let selector = match req.query.as_deref().map(logql::parse).transpose() {
+        Ok(None) => None,
+        Ok(Some(logql::Expr::Log(log))) => Some(log),
+        Ok(Some(_)) => {
+            return Ok(
+                HttpResponse::BadRequest().json(ApiFuncResponse::<()>::err_bad_data(
+                    "the query should be a stream selector",
+                )),
+            );
+        }
+        Err(e) => {
+            return Ok(
+                HttpResponse::BadRequest().json(ApiFuncResponse::<()>::err_bad_data(format!(
+                    "parse logql error: {e}"
+                ))),
+            );
+        }
+    };
+    let (start, end) = match loki::time_range(req.start.as_deref(), req.end.as_deref()) {
+        Ok(v) => v,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(ApiFuncResponse::<()>::err_bad_data(e)));
+        }
+    };
+    let stream_name = loki::stream_name(stream_header(&in_req));
+    Ok(
+        match loki::label_values(
+            &org_id,
+            &stream_name,
+            &label_name,
+            selector.as_ref(),
+            start,
+            end,
+        )
+        .await
+        {
+            Ok(values) => HttpResponse::Ok().json(ApiFuncResponse::ok(values)),
+            Err(e) => {
+                log::error!("loki label_values failed: {e}");
+                HttpResponse::InternalServerError()
+                    .json(ApiFuncResponse::<()>::err_internal(e.to_string()))
+            }
+        },
+    )
+}
+
+fn stream_header(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(&CONFIG.grpc.stream_header_key)
+        .and_then(|header| header.to_str().ok())
+}
@@ -14,3 +14,5 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 pub mod ingest;
+pub mod loki;
+pub mod splunk;
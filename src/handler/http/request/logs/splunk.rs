@@ -0,0 +1,249 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::io::Error;
+
+use actix_web::{get, post, web, HttpResponse};
+
+use crate::{
+    common::meta::{
+        ingestion::{HecParams, HecResponse},
+        user::User,
+    },
+    service::logs::splunk,
+};
+
+/// Splunk HEC compatible ingestion API, the events are written to the stream
+/// named by their index
+#[utoipa::path(
+    context_path = "/splunk",
+    tag = "Logs",
+    operation_id = "SplunkHecEvent",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("index" = Option<String>, Query, description = "default index of the events, the stream written"),
+        ("host" = Option<String>, Query, description = "default host of the events"),
+        ("source" = Option<String>, Query, description = "default source of the events"),
+        ("sourcetype" = Option<String>, Query, description = "default sourcetype of the events"),
+    ),
+    request_body(content = String, description = "Concatenated JSON events, authorized by the header `Authorization: Splunk <passcode>`", content_type = "application/json"),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = HecResponse, example = json!({"text": "Success", "code": 0})),
+        (status = 400, description = "Failure", content_type = "application/json", body = HecResponse, example = json!({"text": "Event field is required", "code": 12, "invalid-event-number": 1})),
+        (status = 401, description = "Unauthorized", content_type = "application/json", body = HecResponse, example = json!({"text": "Token is required", "code": 2})),
+        (status = 500, description = "Failure", content_type = "application/json", body = HecResponse),
+    )
+)]
+#[post("/{org_id}/services/collector/event")]
+pub async fn event(
+    org_id: web::Path<String>,
+    params: web::Query<HecParams>,
+    thread_id: web::Data<usize>,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let org_id = org_id.into_inner();
+    let resp = splunk::ingest_events(&org_id, &body, &params, **thread_id).await;
+    Ok(HttpResponse::build(resp.status_code()).json(resp))
+}
+
+/// Splunk HEC compatible ingestion API, the same as the `event` endpoint
+#[utoipa::path(
+    context_path = "/splunk",
+    tag = "Logs",
+    operation_id = "SplunkHecCollector",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("index" = Option<String>, Query, description = "default index of the events, the stream written"),
+        ("host" = Option<String>, Query, description = "default host of the events"),
+        ("source" = Option<String>, Query, description = "default source of the events"),
+        ("sourcetype" = Option<String>, Query, description = "default sourcetype of the events"),
+    ),
+    request_body(content = String, description = "Concatenated JSON events, authorized by the header `Authorization: Splunk <passcode>`", content_type = "application/json"),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = HecResponse, example = json!({"text": "Success", "code": 0})),
+        (status = 400, description = "Failure", content_type = "application/json", body = HecResponse, example = json!({"text": "Event field is required", "code": 12, "invalid-event-number": 1})),
+        (status = 401, description = "Unauthorized", content_type = "application/json", body = HecResponse, example = json!({"text": "Token is required", "code": 2})),
+        (status = 500, description = "Failure", content_type = "application/json", body = HecResponse),
+    )
+)]
+#[post("/{org_id}/services/collector")]
+pub async fn collector(
+    org_id: web::Path<String>,
+    params: web::Query<HecParams>,
+    thread_id: web::Data<usize>,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let org_id = org_id.into_inner();
+    let resp = splunk::ingest_events(&org_id, &body, &params, **thread_id).await;
+    Ok(HttpResponse::build(resp.status_code()).json(resp))
+}
+
+/// Splunk HEC compatible raw ingestion API, one event per line
+#[utoipa::path(
+    context_path = "/splunk",
+    tag = "Logs",
+    operation_id = "SplunkHecRaw",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("index" = Option<String>, Query, description = "index of the events, the stream written"),
+        ("host" = Option<String>, Query, description = "host of the events"),
+        ("source" = Option<String>, Query, description = "source of the events"),
+        ("sourcetype" = Option<String>, Query, description = "sourcetype of the events"),
+    ),
+    request_body(content = String, description = "Lines of text, authorized by the header `Authorization: Splunk <passcode>`", content_type = "text/plain"),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = HecResponse, example = json!({"text": "Success", "code": 0})),
+        (status = 400, description = "Failure", content_type = "application/json", body = HecResponse, example = json!({"text": "No data", "code": 5})),
+        (status = 401, description = "Unauthorized", content_type = "application/json", body = HecResponse, example = json!({"text": "Token is required", "code": 2})),
+        (status = 500, description = "Failure", content_type = "application/json", body = HecResponse),
+    )
+)]
+#[post("/{org_id}/services/collector/raw")]
+pub async fn raw(
+    org_id: web::Path<String>,
+    params: web::Query<HecParams>,
+    thread_id: web::Data<usize>,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let org_id = org_id.into_inner();
+    let resp = splunk::ingest_raw(&org_id, &body, &params, **thread_id).await;
+    Ok(HttpResponse::build(resp.status_code()).json(resp))
+}
+
+/// Splunk HEC compatible ingestion API for the forwarders configured with a
+/// host and a port only, the events are written to the org of the passcode
+#[utoipa::path(
+    context_path = "/services/collector",
+    tag = "Logs",
+    operation_id = "SplunkHecTokenEvent",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("index" = Option<String>, Query, description = "default index of the events, the stream written"),
+        ("host" = Option<String>, Query, description = "default host of the events"),
+        ("source" = Option<String>, Query, description = "default source of the events"),
+        ("sourcetype" = Option<String>, Query, description = "default sourcetype of the events"),
+    ),
+    request_body(content = String, description = "Concatenated JSON events, authorized by the header `Authorization: Splunk <passcode>`", content_type = "application/json"),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = HecResponse, example = json!({"text": "Success", "code": 0})),
+        (status = 400, description = "Failure", content_type = "application/json", body = HecResponse, example = json!({"text": "Event field is required", "code": 12, "invalid-event-number": 1})),
+        (status = 401, description = "Unauthorized", content_type = "application/json", body = HecResponse, example = json!({"text": "Token is required", "code": 2})),
+        (status = 500, description = "Failure", content_type = "application/json", body = HecResponse),
+    )
+)]
+#[post("/event")]
+pub async fn token_event(
+    user: web::ReqData<User>,
+    params: web::Query<HecParams>,
+    thread_id: web::Data<usize>,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let resp = splunk::ingest_events(&user.org, &body, &params, **thread_id).await;
+    Ok(HttpResponse::build(resp.status_code()).json(resp))
+}
+
+/// Splunk HEC compatible ingestion API for the forwarders configured with a
+/// host and a port only, the same as the `event` endpoint
+#[utoipa::path(
+    context_path = "/services/collector",
+    tag = "Logs",
+    operation_id = "SplunkHecTokenCollector",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("index" = Option<String>, Query, description = "default index of the events, the stream written"),
+        ("host" = Option<String>, Query, description = "default host of the events"),
+        ("source" = Option<String>, Query, description = "default source of the events"),
+        ("sourcetype" = Option<String>, Query, description = "default sourcetype of the events"),
+    ),
+    request_body(content = String, description = "Concatenated JSON events, authorized by the header `Authorization: Splunk <passcode>`", content_type = "application/json"),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = HecResponse, example = json!({"text": "Success", "code": 0})),
+        (status = 400, description = "Failure", content_type = "application/json", body = HecResponse, example = json!({"text": "Event field is required", "code": 12, "invalid-event-number": 1})),
+        (status = 401, description = "Unauthorized", content_type = "application/json", body = HecResponse, example = json!({"text": "Token is required", "code": 2})),
+        (status = 500, description = "Failure", content_type = "application/json", body = HecResponse),
+    )
+)]
+#[post("")]
+pub async fn token_collector(
+    user: web::ReqData<User>,
+    params: web::Query<HecParams>,
+    thread_id: web::Data<usize>,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let resp = splunk::ingest_events(&user.org, &body, &params, **thread_id).await;
+    Ok(HttpResponse::build(resp.status_code()).json(resp))
+}
+
+/// Splunk HEC compatible raw ingestion API for the forwarders configured with
+/// a host and a port only, the events are written to the org of the passcode
+#[utoipa::path(
+    context_path = "/services/collector",
+    tag = "Logs",
+    operation_id = "SplunkHecTokenRaw",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("index" = Option<String>, Query, description = "index of the events, the stream written"),
+        ("host" = Option<String>, Query, description = "host of the events"),
+        ("source" = Option<String>, Query, description = "source of the events"),
+        ("sourcetype" = Option<String>, Query, description = "sourcetype of the events"),
+    ),
+    request_body(content = String, description = "Lines of text, authorized by the header `Authorization: Splunk <passcode>`", content_type = "text/plain"),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = HecResponse, example = json!({"text": "Success", "code": 0})),
+        (status = 400, description = "Failure", content_type = "application/json", body = HecResponse, example = json!({"text": "No data", "code": 5})),
+        (status = 401, description = "Unauthorized", content_type = "application/json", body = HecResponse, example = json!({"text": "Token is required", "code": 2})),
+        (status = 500, description = "Failure", content_type = "application/json", body = HecResponse),
+    )
+)]
+#[post("/raw")]
+pub async fn token_raw(
+    user: web::ReqData<User>,
+    params: web::Query<HecParams>,
+    thread_id: web::Data<usize>,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let resp = splunk::ingest_raw(&user.org, &body, &params, **thread_id).await;
+    Ok(HttpResponse::build(resp.status_code()).json(resp))
+}
+
+/// Splunk HEC compatible health check, used by the forwarders before sending
+/// the events
+#[utoipa::path(
+    tag = "Logs",
+    operation_id = "SplunkHecHealth",
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = HecResponse, example = json!({"text": "HEC is healthy", "code": 17})),
+    )
+)]
+#[get("/services/collector/health")]
+pub async fn health() -> Result<HttpResponse, Error> {
+    let resp = HecResponse::healthy();
+    Ok(HttpResponse::build(resp.status_code()).json(resp))
+}
@@ -15,7 +15,12 @@
 
 use std::io::Error;
 
-use actix_web::{get, http, put, route, web, HttpRequest, HttpResponse, Result};
+use actix_web::{get, http, post, put, route, web, HttpRequest, HttpResponse, Result};
+
+use crate::{
+    common::utils::json,
+    service::search::es::{self, EsError},
+};
 
 #[route("/{org_id}/", method = "GET", method = "HEAD")]
 async fn org_index(_org_id: web::Path<String>, req: HttpRequest) -> Result<HttpResponse, Error> {
@@ -109,3 +114,55 @@ async fn org_data_stream_create(
         .insert_header(("X-Elastic-Product", "Elasticsearch"))
         .body(es_info))
 }
+
+#[route("/{org_id}/{index}/_search", method = "GET", method = "POST")]
+async fn org_search(
+    path: web::Path<(String, String)>,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let (org_id, index) = path.into_inner();
+    let body = if body.is_empty() {
+        json::json!({})
+    } else {
+        match json::from_slice::<json::Value>(&body) {
+            Ok(v) => v,
+            Err(e) => return Ok(es_error(EsError::parsing(e))),
+        }
+    };
+    match es::search(&org_id, &index, &body).await {
+        Ok(resp) => Ok(es_json(resp)),
+        Err(e) => Ok(es_error(e)),
+    }
+}
+
+#[post("/{org_id}/_msearch")]
+async fn org_msearch(org_id: web::Path<String>, body: web::Bytes) -> Result<HttpResponse, Error> {
+    match es::msearch(&org_id, None, &body).await {
+        Ok(resp) => Ok(es_json(resp)),
+        Err(e) => Ok(es_error(e)),
+    }
+}
+
+#[post("/{org_id}/{index}/_msearch")]
+async fn org_index_msearch(
+    path: web::Path<(String, String)>,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let (org_id, index) = path.into_inner();
+    match es::msearch(&org_id, Some(&index), &body).await {
+        Ok(resp) => Ok(es_json(resp)),
+        Err(e) => Ok(es_error(e)),
+    }
+}
+
+fn es_json(resp: json::Value) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(("X-Elastic-Product", "Elasticsearch"))
+        .json(resp)
+}
+
+fn es_error(e: EsError) -> HttpResponse {
+    HttpResponse::build(e.status)
+        .insert_header(("X-Elastic-Product", "Elasticsearch"))
+        .json(e.to_json())
+}
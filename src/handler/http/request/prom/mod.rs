@@ -28,6 +28,8 @@ use crate::{
 };
 use crate::service::promql::MetricsQueryRequest;
 
+pub mod rules;
+
 /// prometheus remote-write endpoint for metrics
 #[utoipa::path(
     context_path = "/api",
@@ -72,6 +74,46 @@ pub async fn remote_write(
     }
 }
 
+/// prometheus remote-read endpoint for metrics
+// refer: https://prometheus.io/docs/prometheus/latest/querying/remote_read_api/
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "PrometheusRemoteRead",
+        security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+    ),
+    request_body(content = String, description = "prometheus ReadRequest", content_type = "application/x-protobuf"),
+    responses(
+        (status = 200, description = "Success", content_type = "application/x-protobuf", body = String),
+        (status = 400, description = "Failure", content_type = "application/json", body = HttpResponse),
+        (status = 500, description = "Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/prometheus/api/v1/read")]
+pub async fn remote_read(
+    org_id: web::Path<String>,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let org_id = org_id.into_inner();
+    Ok(match metrics::prom::remote_read(&org_id, body).await {
+        Ok(metrics::prom::RemoteReadResponse::Samples(body)) => HttpResponse::Ok()
+            .content_type("application/x-protobuf")
+            .insert_header((http::header::CONTENT_ENCODING, "snappy"))
+            .body(body),
+        Ok(metrics::prom::RemoteReadResponse::Chunks(frames)) => HttpResponse::Ok()
+            .content_type("application/x-streamed-protobuf; proto=prometheus.ChunkedReadResponse")
+            .insert_header(http::header::ContentEncoding::Identity)
+            .streaming(frames),
+        Err((code, e)) => {
+            HttpResponse::build(code).json(MetaHttpResponse::error(code.into(), e.to_string()))
+        }
+    })
+}
+
 /// prometheus instant queries
 // refer: https://prometheus.io/docs/prometheus/latest/querying/api/#instant-queries
 #[utoipa::path(
@@ -368,6 +410,101 @@ pub async fn metadata(
     )
 }
 
+/// prometheus querying exemplars
+// refer: https://prometheus.io/docs/prometheus/latest/querying/api/#querying-exemplars
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "PrometheusQueryExemplars",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("query" = String, Query, description = "Prometheus expression query string"),
+        ("start" = Option<String>, Query, description = "<rfc3339 | unix_timestamp>: Start timestamp"),
+        ("end" = Option<String>, Query, description = "<rfc3339 | unix_timestamp>: End timestamp"),
+    ),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = HttpResponse, example = json!({
+            "status": "success",
+            "data": [
+                {
+                    "seriesLabels": {
+                        "__name__": "http_request_duration_seconds_bucket",
+                        "service": "bar",
+                        "le": "0.5"
+                    },
+                    "exemplars": [
+                        {
+                            "labels": {
+                                "trace_id": "EpTxMJ40fUus7aGY",
+                                "span_id": "6f5a5f3c2d1e0b9a"
+                            },
+                            "value": "0.46",
+                            "timestamp": 1600096945.479
+                        }
+                    ]
+                }
+            ]
+        })),
+        (status = 500, description = "Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/prometheus/api/v1/query_exemplars")]
+pub async fn query_exemplars_get(
+    org_id: web::Path<String>,
+    req: web::Query<meta::prom::RequestQueryExemplars>,
+) -> Result<HttpResponse, Error> {
+    query_exemplars(&org_id, req.into_inner()).await
+}
+
+#[post("/{org_id}/prometheus/api/v1/query_exemplars")]
+pub async fn query_exemplars_post(
+    org_id: web::Path<String>,
+    req: web::Query<meta::prom::RequestQueryExemplars>,
+    web::Form(form): web::Form<meta::prom::RequestQueryExemplars>,
+) -> Result<HttpResponse, Error> {
+    let req = if form.query.is_some() || form.start.is_some() || form.end.is_some() {
+        form
+    } else {
+        req.into_inner()
+    };
+    query_exemplars(&org_id, req).await
+}
+
+async fn query_exemplars(
+    org_id: &str,
+    req: meta::prom::RequestQueryExemplars,
+) -> Result<HttpResponse, Error> {
+    let expr = match parser::parse(&req.query.unwrap_or_default()) {
+        Ok(expr) => expr,
+        Err(err) => {
+            return Ok(HttpResponse::BadRequest().json(
+                promql::ApiFuncResponse::<()>::err_bad_data(format!("parse promql error: {err}")),
+            ));
+        }
+    };
+    let (_, start, end) = match validate_metadata_params(None, req.start, req.end) {
+        Ok(v) => v,
+        Err(e) => {
+            return Ok(
+                HttpResponse::BadRequest().json(promql::ApiFuncResponse::<()>::err_bad_data(e))
+            );
+        }
+    };
+    Ok(
+        match metrics::prom::get_exemplars(org_id, &expr, start, end).await {
+            Ok(resp) => HttpResponse::Ok().json(promql::ApiFuncResponse::ok(resp)),
+            Err(err) => {
+                log::error!("get_exemplars failed: {err}");
+                HttpResponse::InternalServerError()
+                    .json(promql::ApiFuncResponse::<()>::err_internal(err.to_string()))
+            }
+        },
+    )
+}
+
 /// prometheus finding series by label matchers
 // refer: https://prometheus.io/docs/prometheus/latest/querying/api/#finding-series-by-label-matchers
 #[utoipa::path(
@@ -0,0 +1,277 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::io::Error;
+
+use actix_web::{delete, get, http, post, web, HttpRequest, HttpResponse};
+use ahash::AHashMap as HashMap;
+
+use crate::{
+    common::meta::{http::HttpResponse as MetaHttpResponse, rules::RuleGroup},
+    service::{metrics::rules, promql},
+};
+
+/// ImportPrometheusRules
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "ImportPrometheusRules",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("destinations" = Option<String>, Query, description = "Comma separated alert destinations, required by alerting rules"),
+    ),
+    request_body(content = String, description = "Prometheus rules file", content_type = "application/yaml"),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = HttpResponse),
+        (status = 400, description = "Error",   content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/prometheus/rules")]
+pub async fn import_rules(
+    path: web::Path<String>,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let org_id = path.into_inner();
+    match rules::import(&org_id, &body).await {
+        Ok(names) => Ok(MetaHttpResponse::ok(format!(
+            "Rule groups imported: {}",
+            names.join(", ")
+        ))),
+        Err(e) => Ok(MetaHttpResponse::bad_request(e)),
+    }
+}
+
+/// SaveRuleGroup
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "SaveRuleGroup",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("group_name" = String, Path, description = "Rule group name"),
+        ("destinations" = Option<String>, Query, description = "Comma separated alert destinations, required by alerting rules"),
+      ),
+    request_body(content = RuleGroup, description = "Rule group data", content_type = "application/json"),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = HttpResponse),
+        (status = 400, description = "Error",   content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/prometheus/rules/{group_name}")]
+pub async fn save_rule_group(
+    path: web::Path<(String, String)>,
+    req: HttpRequest,
+    group: web::Json<RuleGroup>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, name) = path.into_inner();
+    match rules::save(&org_id, &name, group.into_inner(), &get_destinations(&req)).await {
+        Ok(_) => Ok(MetaHttpResponse::ok("Rule group saved")),
+        Err(e) => Ok(MetaHttpResponse::bad_request(e)),
+    }
+}
+
+/// GetRuleGroup
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "GetRuleGroup",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("group_name" = String, Path, description = "Rule group name"),
+      ),
+    responses(
+        (status = 200, description = "Success",  content_type = "application/json", body = RuleGroup),
+        (status = 404, description = "NotFound", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/prometheus/rules/{group_name}")]
+async fn get_rule_group(path: web::Path<(String, String)>) -> Result<HttpResponse, Error> {
+    let (org_id, name) = path.into_inner();
+    match rules::get(&org_id, &name).await {
+        Ok(data) => Ok(MetaHttpResponse::json(data)),
+        Err(e) => Ok(MetaHttpResponse::not_found(e)),
+    }
+}
+
+/// ListRuleGroups
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "ListRuleGroups",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+      ),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = Vec<RuleGroup>),
+        (status = 400, description = "Error",   content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/prometheus/rules")]
+async fn list_rule_groups(path: web::Path<String>) -> Result<HttpResponse, Error> {
+    let org_id = path.into_inner();
+    match rules::list(&org_id).await {
+        Ok(data) => Ok(MetaHttpResponse::json(data)),
+        Err(e) => Ok(MetaHttpResponse::bad_request(e)),
+    }
+}
+
+/// DeleteRuleGroup
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "DeleteRuleGroup",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("group_name" = String, Path, description = "Rule group name"),
+    ),
+    responses(
+        (status = 200, description = "Success",  content_type = "application/json", body = HttpResponse),
+        (status = 404, description = "NotFound", content_type = "application/json", body = HttpResponse),
+        (status = 500, description = "Failure",  content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[delete("/{org_id}/prometheus/rules/{group_name}")]
+async fn delete_rule_group(path: web::Path<(String, String)>) -> Result<HttpResponse, Error> {
+    let (org_id, name) = path.into_inner();
+    match rules::delete(&org_id, &name).await {
+        Ok(_) => Ok(MetaHttpResponse::ok("Rule group deleted")),
+        Err(e) => match e {
+            (http::StatusCode::NOT_FOUND, e) => Ok(MetaHttpResponse::not_found(e)),
+            (_, e) => Ok(MetaHttpResponse::internal_error(e)),
+        },
+    }
+}
+
+/// prometheus rules
+// refer: https://prometheus.io/docs/prometheus/latest/querying/api/#rules
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "PrometheusRules",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("type" = Option<String>, Query, description = "Return only the alerting rules (alert) or the recording rules (record)"),
+    ),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = HttpResponse, example = json!({
+            "status": "success",
+            "data": {
+                "groups": [{
+                    "name": "example",
+                    "file": "",
+                    "interval": 60.0,
+                    "rules": [{
+                        "type": "alerting",
+                        "name": "HighRequestLatency",
+                        "query": "job:request_latency_seconds:mean5m{job=\"myjob\"} > 0.5",
+                        "duration": 600.0,
+                        "labels": {"severity": "page"},
+                        "annotations": {"summary": "High request latency"},
+                        "alerts": [],
+                        "state": "inactive",
+                        "health": "ok",
+                        "lastError": ""
+                    }]
+                }]
+            }
+        })),
+        (status = 500, description = "Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/prometheus/api/v1/rules")]
+pub async fn rules_get(path: web::Path<String>, req: HttpRequest) -> Result<HttpResponse, Error> {
+    let org_id = path.into_inner();
+    let query = web::Query::<HashMap<String, String>>::from_query(req.query_string()).unwrap();
+    Ok(
+        match rules::list_rules_status(&org_id, query.get("type").map(|v| v.as_str())).await {
+            Ok(resp) => HttpResponse::Ok().json(promql::ApiFuncResponse::ok(resp)),
+            Err(err) => {
+                log::error!("list rules failed: {err}");
+                HttpResponse::InternalServerError()
+                    .json(promql::ApiFuncResponse::<()>::err_internal(err.to_string()))
+            }
+        },
+    )
+}
+
+/// prometheus alerts
+// refer: https://prometheus.io/docs/prometheus/latest/querying/api/#alerts
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "PrometheusAlerts",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+    ),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = HttpResponse, example = json!({
+            "status": "success",
+            "data": {
+                "alerts": [{
+                    "labels": {"alertname": "HighRequestLatency", "severity": "page"},
+                    "annotations": {"summary": "High request latency"},
+                    "state": "firing",
+                    "activeAt": "2023-11-20T08:00:00.000Z"
+                }]
+            }
+        })),
+        (status = 500, description = "Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/prometheus/api/v1/alerts")]
+pub async fn alerts_get(path: web::Path<String>) -> Result<HttpResponse, Error> {
+    let org_id = path.into_inner();
+    Ok(match rules::list_active_alerts(&org_id).await {
+        Ok(resp) => HttpResponse::Ok().json(promql::ApiFuncResponse::ok(resp)),
+        Err(err) => {
+            log::error!("list alerts failed: {err}");
+            HttpResponse::InternalServerError()
+                .json(promql::ApiFuncResponse::<()>::err_internal(err.to_string()))
+        }
+    })
+}
+
+fn get_destinations(req: &HttpRequest) -> Vec<String> {
+    let query = web::Query::<HashMap<String, String>>::from_query(req.query_string()).unwrap();
+    match query.get("destinations") {
+        Some(v) => v
+            .split(',')
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+            .collect(),
+        None => vec![],
+    }
+}
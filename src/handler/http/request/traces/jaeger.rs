@@ -0,0 +1,233 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::io::Error;
+
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use ahash::AHashMap;
+
+use crate::{
+    common::{
+        infra::{config::CONFIG, errors},
+        meta::traces::{JaegerResponse, JaegerTrace},
+    },
+    service::traces::jaeger,
+};
+
+/// JaegerTraces
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Traces",
+    operation_id = "PostJaegerTraces",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+    ),
+    request_body(content = String, description = "Jaeger Batch in the Thrift binary protocol", content_type = "application/x-thrift"),
+    responses(
+        (status = 202, description = "Accepted"),
+        (status = 400, description = "Failure", content_type = "application/json", body = HttpResponse),
+        (status = 500, description = "Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/jaeger/api/traces")]
+pub async fn traces_write(
+    org_id: web::Path<String>,
+    thread_id: web::Data<usize>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let org_id = org_id.into_inner();
+    let in_stream_name = req
+        .headers()
+        .get(&CONFIG.grpc.stream_header_key)
+        .and_then(|header| header.to_str().ok());
+    jaeger::ingest(&org_id, **thread_id, body, in_stream_name).await
+}
+
+/// JaegerServices
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Traces",
+    operation_id = "GetJaegerServices",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+    ),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = Object, example = json!({
+            "data": ["cart", "frontend"],
+            "total": 2,
+            "limit": 0,
+            "offset": 0,
+            "errors": null
+        })),
+        (status = 500, description = "Failure", content_type = "application/json", body = Object),
+    )
+)]
+#[get("/{org_id}/jaeger/api/services")]
+pub async fn get_services(org_id: web::Path<String>) -> Result<HttpResponse, Error> {
+    let org_id = org_id.into_inner();
+    Ok(names_response(jaeger::get_services(&org_id).await))
+}
+
+/// JaegerOperations
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Traces",
+    operation_id = "GetJaegerOperations",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("service" = String, Path, description = "Service name"),
+    ),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = Object, example = json!({
+            "data": ["GET /cart", "POST /cart"],
+            "total": 2,
+            "limit": 0,
+            "offset": 0,
+            "errors": null
+        })),
+        (status = 500, description = "Failure", content_type = "application/json", body = Object),
+    )
+)]
+#[get("/{org_id}/jaeger/api/services/{service}/operations")]
+pub async fn get_operations(path: web::Path<(String, String)>) -> Result<HttpResponse, Error> {
+    let (org_id, service) = path.into_inner();
+    Ok(names_response(
+        jaeger::get_operations(&org_id, &service).await,
+    ))
+}
+
+/// JaegerFindTraces
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Traces",
+    operation_id = "FindJaegerTraces",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("service" = Option<String>, Query, description = "service name"),
+        ("operation" = Option<String>, Query, description = "operation name"),
+        ("tags" = Option<String>, Query, description = "span tags as a JSON object, eg: {\"http.method\":\"GET\"}"),
+        ("minDuration" = Option<String>, Query, description = "minimum span duration, eg: 100ms"),
+        ("maxDuration" = Option<String>, Query, description = "maximum span duration, eg: 1.5s"),
+        ("limit" = Option<usize>, Query, description = "maximum number of traces, 20 by default"),
+        ("start" = Option<i64>, Query, description = "start time, microseconds"),
+        ("end" = Option<i64>, Query, description = "end time, microseconds"),
+        ("lookback" = Option<String>, Query, description = "period before the end time when start is missing, 1h by default"),
+    ),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = Object),
+        (status = 400, description = "Failure", content_type = "application/json", body = Object),
+        (status = 500, description = "Failure", content_type = "application/json", body = Object),
+    )
+)]
+#[get("/{org_id}/jaeger/api/traces")]
+pub async fn find_traces(
+    org_id: web::Path<String>,
+    in_req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let org_id = org_id.into_inner();
+    // the tag parameter can be repeated
+    let params = web::Query::<Vec<(String, String)>>::from_query(in_req.query_string())
+        .map(|v| v.into_inner())
+        .unwrap_or_default();
+    let req = match jaeger::search_request(&params) {
+        Ok(v) => v,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(JaegerResponse::<()>::error(400, e)));
+        }
+    };
+    match jaeger::find_traces(&org_id, &req).await {
+        Ok(traces) => {
+            let total = traces.len();
+            Ok(HttpResponse::Ok().json(JaegerResponse::data(traces, total)))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+/// JaegerGetTrace
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Traces",
+    operation_id = "GetJaegerTrace",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("trace_id" = String, Path, description = "Trace ID"),
+        ("start" = Option<i64>, Query, description = "start time, microseconds"),
+        ("end" = Option<i64>, Query, description = "end time, microseconds"),
+    ),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = Object),
+        (status = 404, description = "NotFound", content_type = "application/json", body = Object),
+        (status = 500, description = "Failure", content_type = "application/json", body = Object),
+    )
+)]
+#[get("/{org_id}/jaeger/api/traces/{trace_id}")]
+pub async fn get_trace(
+    path: web::Path<(String, String)>,
+    in_req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let (org_id, trace_id) = path.into_inner();
+    let query = web::Query::<AHashMap<String, String>>::from_query(in_req.query_string()).unwrap();
+    let start_time = query
+        .get("start")
+        .map_or(0, |v| v.parse::<i64>().unwrap_or(0));
+    let mut end_time = query
+        .get("end")
+        .map_or(0, |v| v.parse::<i64>().unwrap_or(0));
+    if end_time == 0 {
+        end_time = chrono::Utc::now().timestamp_micros();
+    }
+
+    match jaeger::get_trace(&org_id, &trace_id, start_time, end_time).await {
+        Ok(Some(trace)) => Ok(HttpResponse::Ok().json(JaegerResponse::data(vec![trace], 1))),
+        Ok(None) => Ok(
+            HttpResponse::NotFound().json(JaegerResponse::<Vec<JaegerTrace>>::error(
+                404,
+                "trace not found",
+            )),
+        ),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+fn names_response(names: errors::Result<Vec<String>>) -> HttpResponse {
+    match names {
+        Ok(names) => {
+            let total = names.len();
+            HttpResponse::Ok().json(JaegerResponse::data(names, total))
+        }
+        Err(e) => error_response(e),
+    }
+}
+
+fn error_response(err: errors::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(JaegerResponse::<()>::error(500, err))
+}
@@ -22,13 +22,21 @@ use serde::Serialize;
 use crate::{
     common::{
         infra::{config::CONFIG, errors, metrics},
-        meta::{self, http::HttpResponse as MetaHttpResponse, StreamType},
+        meta::{
+            self, http::HttpResponse as MetaHttpResponse, traces::TraceSearchRequest, StreamType,
+        },
         utils::json,
     },
     handler::http::request::{CONTENT_TYPE_JSON, CONTENT_TYPE_PROTO},
-    service::{search as SearchService, traces::otlp_http},
+    service::{
+        search as SearchService,
+        traces::{self, otlp_http},
+    },
 };
 
+pub mod jaeger;
+pub mod zipkin;
+
 /// TracesIngest
 #[utoipa::path(
     context_path = "/api",
@@ -416,3 +424,238 @@ struct TraceServiceNameItem {
     service_name: String,
     count: u16,
 }
+
+/// SearchTraces
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Traces",
+    operation_id = "SearchTraces",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("service_name" = Option<String>, Query, description = "service name of a span"),
+        ("operation_name" = Option<String>, Query, description = "span name"),
+        ("min_duration" = Option<i64>, Query, description = "minimum span duration, microseconds"),
+        ("max_duration" = Option<i64>, Query, description = "maximum span duration, microseconds"),
+        ("status" = Option<String>, Query, description = "span status: OK, ERROR or UNSET"),
+        ("attributes" = Option<String>, Query, description = "span attributes as a JSON object, resource attributes prefixed with `service.`, eg: {\"http.method\":\"GET\"}"),
+        ("from" = Option<i64>, Query, description = "from"),
+        ("size" = Option<i64>, Query, description = "size"),
+        ("start_time" = i64, Query, description = "start time"),
+        ("end_time" = Option<i64>, Query, description = "end time"),
+    ),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = Vec<TraceSummary>, example = json!({
+            "took": 155,
+            "hits": [
+                {
+                    "trace_id": "4bf92f3577b34da6a3ce929d0e0e4736",
+                    "start_time": 1696324382031000000_i64,
+                    "end_time": 1696324382080000000_i64,
+                    "duration": 49000,
+                    "root_span": {
+                        "span_id": "00f067aa0ba902b7",
+                        "service_name": "frontend",
+                        "operation_name": "GET /cart",
+                        "duration": 49000
+                    },
+                    "span_count": 12,
+                    "error_count": 1,
+                    "services": ["cart", "frontend"]
+                }
+            ]
+        })),
+        (status = 400, description = "Failure", content_type = "application/json", body = HttpResponse),
+        (status = 500, description = "Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/traces/search")]
+pub async fn search_traces(
+    org_id: web::Path<String>,
+    in_req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let start = std::time::Instant::now();
+    let org_id = org_id.into_inner();
+    let query = web::Query::<AHashMap<String, String>>::from_query(in_req.query_string()).unwrap();
+
+    let non_empty = |key: &str| query.get(key).filter(|v| !v.is_empty()).cloned();
+    let mut req = TraceSearchRequest {
+        service_name: non_empty("service_name"),
+        operation_name: non_empty("operation_name"),
+        span_status: non_empty("status"),
+        from: query
+            .get("from")
+            .map_or(0, |v| v.parse::<usize>().unwrap_or(0)),
+        size: query
+            .get("size")
+            .map_or(10, |v| v.parse::<usize>().unwrap_or(10)),
+        ..Default::default()
+    };
+    for (key, duration) in [
+        ("min_duration", &mut req.min_duration),
+        ("max_duration", &mut req.max_duration),
+    ] {
+        if let Some(v) = non_empty(key) {
+            match v.parse::<i64>() {
+                Ok(v) => *duration = Some(v),
+                Err(_) => return Ok(MetaHttpResponse::bad_request(format!("invalid {key}"))),
+            }
+        }
+    }
+    if let Some(v) = non_empty("attributes") {
+        match json::from_str::<HashMap<String, json::Value>>(&v) {
+            Ok(attributes) => {
+                req.attributes = attributes
+                    .into_iter()
+                    .map(|(k, v)| match v {
+                        json::Value::String(v) => (k, v),
+                        v => (k, v.to_string()),
+                    })
+                    .collect();
+            }
+            Err(_) => return Ok(MetaHttpResponse::bad_request("invalid attributes")),
+        }
+    }
+    req.start_time = query
+        .get("start_time")
+        .map_or(0, |v| v.parse::<i64>().unwrap_or(0));
+    if req.start_time == 0 {
+        return Ok(MetaHttpResponse::bad_request("start_time is empty"));
+    }
+    req.end_time = query
+        .get("end_time")
+        .map_or(0, |v| v.parse::<i64>().unwrap_or(0));
+    if req.end_time == 0 {
+        req.end_time = chrono::Utc::now().timestamp_micros();
+    }
+
+    match traces::search::search_traces(&org_id, &req).await {
+        Ok(hits) => {
+            let mut resp: HashMap<&str, json::Value> = HashMap::new();
+            resp.insert(
+                "took",
+                json::Value::from(start.elapsed().as_millis() as usize),
+            );
+            resp.insert("total", json::Value::from(hits.len()));
+            resp.insert("from", json::Value::from(req.from));
+            resp.insert("size", json::Value::from(req.size));
+            resp.insert("hits", json::to_value(hits).unwrap());
+            Ok(HttpResponse::Ok().json(resp))
+        }
+        Err(err) => Ok(search_error_response(err)),
+    }
+}
+
+/// GetServiceGraph
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Traces",
+    operation_id = "GetServiceGraph",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("start_time" = i64, Query, description = "start time"),
+        ("end_time" = Option<i64>, Query, description = "end time"),
+    ),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = ServiceGraph, example = json!({
+            "nodes": ["cart", "frontend"],
+            "edges": [
+                {
+                    "_timestamp": 1696324200000000_i64,
+                    "caller": "frontend",
+                    "callee": "cart",
+                    "call_count": 120,
+                    "error_count": 3,
+                    "error_rate": 0.025,
+                    "p50_latency": 1200,
+                    "p95_latency": 8300
+                }
+            ]
+        })),
+        (status = 400, description = "Failure", content_type = "application/json", body = HttpResponse),
+        (status = 500, description = "Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/traces/service_graph")]
+pub async fn get_service_graph(
+    org_id: web::Path<String>,
+    in_req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let org_id = org_id.into_inner();
+    let query = web::Query::<AHashMap<String, String>>::from_query(in_req.query_string()).unwrap();
+    let start_time = query
+        .get("start_time")
+        .map_or(0, |v| v.parse::<i64>().unwrap_or(0));
+    if start_time == 0 {
+        return Ok(MetaHttpResponse::bad_request("start_time is empty"));
+    }
+    let mut end_time = query
+        .get("end_time")
+        .map_or(0, |v| v.parse::<i64>().unwrap_or(0));
+    if end_time == 0 {
+        end_time = chrono::Utc::now().timestamp_micros();
+    }
+
+    match traces::service_graph::get(&org_id, start_time, end_time).await {
+        Ok(graph) => Ok(HttpResponse::Ok().json(graph)),
+        Err(err) => Ok(MetaHttpResponse::internal_error(err)),
+    }
+}
+
+/// GetTrace
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Traces",
+    operation_id = "GetTrace",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("trace_id" = String, Path, description = "Trace ID"),
+        ("start_time" = Option<i64>, Query, description = "start time"),
+        ("end_time" = Option<i64>, Query, description = "end time"),
+    ),
+    responses(
+        (status = 200, description = "Success", content_type = "application/json", body = TraceDetail),
+        (status = 404, description = "NotFound", content_type = "application/json", body = HttpResponse),
+        (status = 500, description = "Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/traces/{trace_id}")]
+pub async fn get_trace(
+    path: web::Path<(String, String)>,
+    in_req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let (org_id, trace_id) = path.into_inner();
+    let query = web::Query::<AHashMap<String, String>>::from_query(in_req.query_string()).unwrap();
+    let start_time = query
+        .get("start_time")
+        .map_or(0, |v| v.parse::<i64>().unwrap_or(0));
+    let mut end_time = query
+        .get("end_time")
+        .map_or(0, |v| v.parse::<i64>().unwrap_or(0));
+    if end_time == 0 {
+        end_time = chrono::Utc::now().timestamp_micros();
+    }
+
+    match traces::search::get_trace(&org_id, &trace_id, start_time, end_time).await {
+        Ok(Some(trace)) => Ok(HttpResponse::Ok().json(trace)),
+        Ok(None) => Ok(MetaHttpResponse::not_found("Trace not found")),
+        Err(err) => Ok(search_error_response(err)),
+    }
+}
+
+fn search_error_response(err: errors::Error) -> HttpResponse {
+    match err {
+        errors::Error::ErrorCode(code) => {
+            HttpResponse::InternalServerError().json(meta::http::HttpResponse::error_code(code))
+        }
+        _ => MetaHttpResponse::internal_error(err),
+    }
+}
@@ -29,7 +29,10 @@ use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
 use super::{
-    auth::{validator, validator_aws, validator_gcp, validator_proxy_url, validator_rum},
+    auth::{
+        validator, validator_aws, validator_gcp, validator_proxy_url, validator_rum,
+        validator_splunk,
+    },
     request::{
         dashboards::{folders::*, *},
         enrichment_table, functions, kv, logs, metrics, organization, prom, rum, search, status,
@@ -191,6 +194,9 @@ pub fn get_service_routes(cfg: &mut web::ServiceConfig) {
             .service(organization::es::org_index_template_create)
             .service(organization::es::org_data_stream)
             .service(organization::es::org_data_stream_create)
+            .service(organization::es::org_search)
+            .service(organization::es::org_msearch)
+            .service(organization::es::org_index_msearch)
             .service(stream::schema)
             .service(stream::settings)
             .service(stream::delete_fields)
@@ -200,17 +206,33 @@ pub fn get_service_routes(cfg: &mut web::ServiceConfig) {
             .service(logs::ingest::multi)
             .service(logs::ingest::json)
             .service(logs::ingest::otlp_logs_write)
+            .service(logs::loki::push)
+            .service(logs::loki::query_range)
+            .service(logs::loki::labels)
+            .service(logs::loki::label_values)
             .service(traces::traces_write)
             .service(traces::otlp_traces_write)
             .service(traces::get_latest_traces)
+            .service(traces::search_traces)
+            .service(traces::get_service_graph)
+            .service(traces::get_trace)
+            .service(traces::zipkin::spans_write)
+            .service(traces::jaeger::traces_write)
+            .service(traces::jaeger::get_services)
+            .service(traces::jaeger::get_operations)
+            .service(traces::jaeger::find_traces)
+            .service(traces::jaeger::get_trace)
             .service(metrics::ingest::json)
             .service(metrics::ingest::otlp_metrics_write)
             .service(prom::remote_write)
+            .service(prom::remote_read)
             .service(prom::query_get)
             .service(prom::query_post)
             .service(prom::query_range_get)
             .service(prom::query_range_post)
             .service(prom::metadata)
+            .service(prom::query_exemplars_get)
+            .service(prom::query_exemplars_post)
             .service(prom::series_get)
             .service(prom::series_post)
             .service(prom::labels_get)
@@ -218,7 +240,18 @@ pub fn get_service_routes(cfg: &mut web::ServiceConfig) {
             .service(prom::label_values)
             .service(prom::format_query_get)
             .service(prom::format_query_post)
+            .service(prom::rules::rules_get)
+            .service(prom::rules::alerts_get)
+            .service(prom::rules::import_rules)
+            .service(prom::rules::save_rule_group)
+            .service(prom::rules::get_rule_group)
+            .service(prom::rules::list_rule_groups)
+            .service(prom::rules::delete_rule_group)
             .service(enrichment_table::save_enrichment_table)
+            .service(enrichment_table::save_scheduled_table)
+            .service(enrichment_table::get_scheduled_table)
+            .service(enrichment_table::list_scheduled_tables)
+            .service(enrichment_table::delete_scheduled_table)
             .service(search::search)
             .service(search::around)
             .service(search::values)
@@ -245,6 +278,7 @@ pub fn get_service_routes(cfg: &mut web::ServiceConfig) {
             .service(dashboards::folders::update_folder)
             .service(dashboards::folders::get_folder)
             .service(dashboards::folders::delete_folder)
+            .service(alerts::preview_alert)
             .service(alerts::save_alert)
             .service(alerts::get_alert)
             .service(alerts::list_alerts)
@@ -252,6 +286,7 @@ pub fn get_service_routes(cfg: &mut web::ServiceConfig) {
             .service(alerts::list_stream_alerts)
             .service(alerts::delete_alert)
             .service(alerts::enable_alert)
+            .service(alerts::get_alert_history)
             .service(alerts::templates::save_template)
             .service(alerts::templates::get_template)
             .service(alerts::templates::delete_template)
@@ -260,6 +295,13 @@ pub fn get_service_routes(cfg: &mut web::ServiceConfig) {
             .service(alerts::destinations::get_destination)
             .service(alerts::destinations::list_destinations)
             .service(alerts::destinations::delete_destination)
+            .service(alerts::dead_letters::list_dead_letters)
+            .service(alerts::dead_letters::replay_dead_letter)
+            .service(alerts::dead_letters::delete_dead_letter)
+            .service(alerts::maintenance::save_maintenance_window)
+            .service(alerts::maintenance::get_maintenance_window)
+            .service(alerts::maintenance::list_maintenance_windows)
+            .service(alerts::maintenance::delete_maintenance_window)
             .service(kv::get)
             .service(kv::set)
             .service(kv::delete)
@@ -280,6 +322,9 @@ pub fn get_service_routes(cfg: &mut web::ServiceConfig) {
             .service(delete_folder)
             .service(move_dashboard)
             .service(traces::get_latest_traces)
+            .service(traces::search_traces)
+            .service(traces::get_service_graph)
+            .service(traces::get_trace)
             .service(logs::ingest::multi)
             .service(logs::ingest::json)
             .service(logs::ingest::handle_kinesis_request)
@@ -305,6 +350,29 @@ pub fn get_other_service_routes(cfg: &mut web::ServiceConfig) {
             .service(logs::ingest::handle_gcp_request),
     );
 
+    let splunk_auth = HttpAuthentication::with_fn(validator_splunk);
+    cfg.service(
+        web::scope("/splunk")
+            .wrap(cors.clone())
+            .wrap(splunk_auth)
+            .service(logs::splunk::event)
+            .service(logs::splunk::collector)
+            .service(logs::splunk::raw),
+    );
+
+    // NOTE: the health check is registered before the collector scope, which
+    // would match it by prefix and ask for a token.
+    cfg.service(logs::splunk::health);
+    let hec_auth = HttpAuthentication::with_fn(validator_splunk);
+    cfg.service(
+        web::scope("/services/collector")
+            .wrap(cors.clone())
+            .wrap(hec_auth)
+            .service(logs::splunk::token_collector)
+            .service(logs::splunk::token_event)
+            .service(logs::splunk::token_raw),
+    );
+
     // NOTE: Here the order of middlewares matter. Once we consume the api-token in
     // `rum_auth`, we drop it in the RumExtraData data.
     // https://docs.rs/actix-web/latest/actix_web/middleware/index.html#ordering
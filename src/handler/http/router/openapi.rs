@@ -50,18 +50,51 @@ use crate::{
         request::logs::ingest::bulk,
         request::logs::ingest::multi,
         request::logs::ingest::json,
+        request::logs::loki::push,
+        request::logs::loki::query_range,
+        request::logs::loki::labels,
+        request::logs::loki::label_values,
+        request::logs::splunk::event,
+        request::logs::splunk::collector,
+        request::logs::splunk::raw,
+        request::logs::splunk::token_event,
+        request::logs::splunk::token_collector,
+        request::logs::splunk::token_raw,
+        request::logs::splunk::health,
         request::traces::traces_write,
         request::traces::get_latest_traces,
+        request::traces::search_traces,
+        request::traces::get_service_graph,
+        request::traces::get_trace,
+        request::traces::zipkin::spans_write,
+        request::traces::jaeger::traces_write,
+        request::traces::jaeger::get_services,
+        request::traces::jaeger::get_operations,
+        request::traces::jaeger::find_traces,
+        request::traces::jaeger::get_trace,
         request::metrics::ingest::json,
         request::prom::remote_write,
+        request::prom::remote_read,
         request::prom::query_get,
         request::prom::query_range_get,
         request::prom::metadata,
+        request::prom::query_exemplars_get,
         request::prom::series_get,
         request::prom::labels_get,
         request::prom::label_values,
         request::prom::format_query_get,
+        request::prom::rules::rules_get,
+        request::prom::rules::alerts_get,
+        request::prom::rules::import_rules,
+        request::prom::rules::save_rule_group,
+        request::prom::rules::get_rule_group,
+        request::prom::rules::list_rule_groups,
+        request::prom::rules::delete_rule_group,
         request::enrichment_table::save_enrichment_table,
+        request::enrichment_table::save_scheduled_table,
+        request::enrichment_table::get_scheduled_table,
+        request::enrichment_table::list_scheduled_tables,
+        request::enrichment_table::delete_scheduled_table,
         request::rum::ingest::log,
         request::rum::ingest::data,
         request::rum::ingest::sessionreplay,
@@ -98,6 +131,8 @@ use crate::{
         request::alerts::delete_alert,
         request::alerts::enable_alert,
         request::alerts::trigger_alert,
+        request::alerts::get_alert_history,
+        request::alerts::preview_alert,
         request::alerts::templates::list_templates,
         request::alerts::templates::get_template,
         request::alerts::templates::save_template,
@@ -106,6 +141,13 @@ use crate::{
         request::alerts::destinations::get_destination,
         request::alerts::destinations::save_destination,
         request::alerts::destinations::delete_destination,
+        request::alerts::dead_letters::list_dead_letters,
+        request::alerts::dead_letters::replay_dead_letter,
+        request::alerts::dead_letters::delete_dead_letter,
+        request::alerts::maintenance::save_maintenance_window,
+        request::alerts::maintenance::get_maintenance_window,
+        request::alerts::maintenance::list_maintenance_windows,
+        request::alerts::maintenance::delete_maintenance_window,
         request::kv::get,
         request::kv::set,
         request::kv::delete,
@@ -129,6 +171,7 @@ use crate::{
             meta::ingestion::RecordStatus,
             meta::ingestion::StreamStatus,
             meta::ingestion::IngestionResponse,
+            meta::ingestion::HecResponse,
             meta::dashboards::Dashboard,
             meta::dashboards::Dashboards,
             meta::dashboards::v1::AxisItem,
@@ -162,11 +205,21 @@ use crate::{
             meta::alerts::Condition,
             meta::alerts::Operator,
             meta::alerts::Aggregation,
+            meta::alerts::AggFunction,
+            meta::alerts::LogicalOperator,
+            meta::alerts::AnomalyDetection,
+            meta::alerts::BaselineMethod,
             meta::alerts::TriggerCondition,
             meta::alerts::QueryCondition,
             meta::alerts::destinations::Destination,
             meta::alerts::destinations::DestinationWithTemplate,
+            meta::alerts::retries::FailedNotification,
+            meta::alerts::maintenance::MaintenanceWindow,
+            meta::alerts::maintenance::MaintenanceMatchers,
+            meta::alerts::preview::AlertPreview,
+            meta::alerts::preview::PreviewInterval,
             meta::alerts::destinations::HTTPType,
+            meta::alerts::destinations::DestinationType,
             meta::alerts::templates::Template,
             meta::functions::Transform,
             meta::functions::FunctionList,
@@ -201,6 +254,17 @@ use crate::{
             meta::syslog::SyslogRoutes,
             meta::prom::Metadata,
             meta::prom::MetricType,
+            meta::rules::RuleGroup,
+            meta::rules::Rule,
+            meta::enrichment_table::ScheduledEnrichmentTable,
+            meta::enrichment_table::EnrichmentTableSource,
+            meta::enrichment_table::RefreshStatus,
+            meta::traces::TraceSummary,
+            meta::traces::RootSpan,
+            meta::traces::TraceDetail,
+            meta::traces::SpanNode,
+            meta::traces::ServiceGraph,
+            meta::traces::ServiceGraphEdge,
          ),
     ),
     modifiers(&SecurityAddon),
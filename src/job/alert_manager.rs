@@ -21,6 +21,16 @@ pub async fn run() -> Result<(), anyhow::Error> {
     if !is_alert_manager(&super::cluster::LOCAL_NODE_ROLE) {
         return Ok(());
     }
+
+    tokio::task::spawn(async move { run_evaluate().await });
+    tokio::task::spawn(async move { run_retry().await });
+    tokio::task::spawn(async move { run_rules().await });
+
+    Ok(())
+}
+
+/// Evaluate the scheduled alerts
+async fn run_evaluate() -> Result<(), anyhow::Error> {
     // should run it every 10 seconds
     let mut interval = time::interval(time::Duration::from_secs(30));
     interval.tick().await; // trigger the first run
@@ -32,3 +42,35 @@ pub async fn run() -> Result<(), anyhow::Error> {
         }
     }
 }
+
+/// Retry the failed notifications
+async fn run_retry() -> Result<(), anyhow::Error> {
+    let mut interval = time::interval(time::Duration::from_secs(10));
+    interval.tick().await; // trigger the first run
+    loop {
+        interval.tick().await;
+        let ret = service::alerts::retries::run().await;
+        if ret.is_err() {
+            log::error!(
+                "[ALERT MANAGER] retry notifications error: {}",
+                ret.err().unwrap()
+            );
+        }
+    }
+}
+
+/// Evaluate the prometheus recording rules
+async fn run_rules() -> Result<(), anyhow::Error> {
+    let mut interval = time::interval(time::Duration::from_secs(10));
+    interval.tick().await; // trigger the first run
+    loop {
+        interval.tick().await;
+        let ret = service::metrics::rules::recording::run().await;
+        if ret.is_err() {
+            log::error!(
+                "[ALERT MANAGER] evaluate rules error: {}",
+                ret.err().unwrap()
+            );
+        }
+    }
+}
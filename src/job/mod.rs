@@ -30,13 +30,17 @@ use crate::{
 
 mod alert_manager;
 mod compact;
+mod enrichment_table;
 pub(crate) mod file_list;
 pub(crate) mod files;
 mod metrics;
 mod mmdb_downloader;
 mod prom;
+mod service_graph;
+mod span_metrics;
 mod stats;
 pub(crate) mod syslog_server;
+mod tail_sampling;
 mod telemetry;
 
 pub async fn init() -> Result<(), anyhow::Error> {
@@ -112,9 +116,12 @@ pub async fn init() -> Result<(), anyhow::Error> {
     tokio::task::spawn(async move { db::metrics::watch_prom_cluster_leader().await });
     tokio::task::spawn(async move { db::alerts::templates::watch().await });
     tokio::task::spawn(async move { db::alerts::destinations::watch().await });
+    tokio::task::spawn(async move { db::alerts::maintenance::watch().await });
     tokio::task::spawn(async move { db::alerts::watch().await });
     tokio::task::spawn(async move { db::alerts::triggers::watch().await });
     tokio::task::spawn(async move { db::organization::watch().await });
+    tokio::task::spawn(async move { db::rules::watch().await });
+    tokio::task::spawn(async move { db::enrichment_table::watch().await });
     tokio::task::yield_now().await; // yield let other tasks run
 
     // cache core metadata
@@ -136,10 +143,17 @@ pub async fn init() -> Result<(), anyhow::Error> {
     db::alerts::destinations::cache()
         .await
         .expect("alerts destinations cache failed");
+    db::alerts::maintenance::cache()
+        .await
+        .expect("maintenance windows cache failed");
     db::alerts::cache().await.expect("alerts cache failed");
     db::alerts::triggers::cache()
         .await
         .expect("alerts triggers cache failed");
+    db::rules::cache().await.expect("rule groups cache failed");
+    db::enrichment_table::cache()
+        .await
+        .expect("scheduled enrichment tables cache failed");
     db::syslog::cache().await.expect("syslog cache failed");
     db::syslog::cache_syslog_settings()
         .await
@@ -194,6 +208,10 @@ pub async fn init() -> Result<(), anyhow::Error> {
     tokio::task::spawn(async move { metrics::run().await });
     tokio::task::spawn(async move { prom::run().await });
     tokio::task::spawn(async move { alert_manager::run().await });
+    tokio::task::spawn(async move { service_graph::run().await });
+    tokio::task::spawn(async move { span_metrics::run().await });
+    tokio::task::spawn(async move { tail_sampling::run().await });
+    tokio::task::spawn(async move { enrichment_table::run().await });
 
     // Shouldn't serve request until initialization finishes
     log::info!("Job initialization complete");
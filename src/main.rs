@@ -78,7 +78,7 @@ use openobserve::{
         http::router::*,
     },
     job, router,
-    service::{db, distinct_values},
+    service::{db, distinct_values, traces},
 };
 use openobserve::cli::basic::cli;
 
@@ -216,6 +216,8 @@ async fn main() -> Result<(), anyhow::Error> {
         .await;
     // leave the cluster
     _ = cluster::leave().await;
+    // write the spans of the traces buffered by the tail sampling
+    _ = traces::tail_sampling::close().await;
     // flush WAL cache to disk
     infra::wal::flush_all_to_disk().await;
     // flush compact offset cache to disk disk
@@ -22,7 +22,15 @@ use crate::{
             config::{CONFIG, TRIGGERS},
             dist_lock,
         },
-        meta::{alerts::triggers::Trigger, StreamType},
+        meta::{
+            alerts::{
+                history::{AlertHistory, EvaluationOutcome},
+                triggers::{AlertState, Trigger},
+                Alert,
+            },
+            StreamType,
+        },
+        utils::json::{Map, Value},
     },
     service::db,
 };
@@ -62,14 +70,13 @@ pub async fn run() -> Result<(), anyhow::Error> {
         if trigger.next_run_at > now {
             continue;
         }
-        let is_realtime = trigger.is_realtime;
-        let is_silenced = trigger.is_silenced;
-        if is_realtime && !is_silenced {
+        if trigger.is_realtime && !trigger.is_silenced {
             continue; // realtime trigger and not silenced, no need to schedule
         }
         let key = key.to_string();
+        let trigger = trigger.clone();
         tokio::task::spawn(async move {
-            if let Err(e) = handle_triggers(&key, is_realtime, is_silenced).await {
+            if let Err(e) = handle_triggers(&key, trigger).await {
                 log::error!("[ALERT_MANAGER] Error handling trigger: {}", e);
             }
         });
@@ -77,11 +84,7 @@ pub async fn run() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
-pub async fn handle_triggers(
-    key: &str,
-    is_realtime: bool,
-    is_silenced: bool,
-) -> Result<(), anyhow::Error> {
+pub async fn handle_triggers(key: &str, trigger: Trigger) -> Result<(), anyhow::Error> {
     let columns = key.split('/').collect::<Vec<&str>>();
     assert_eq!(columns.len(), 4);
     let org_id = columns[0];
@@ -89,12 +92,13 @@ pub async fn handle_triggers(
     let stream_name = columns[2];
     let alert_name = columns[3];
 
-    if is_realtime && is_silenced {
-        // wakeup the trigger
+    if trigger.is_realtime && trigger.is_silenced {
+        // wakeup the trigger, the alert state carries on with the next records
         let new_trigger = Trigger {
             next_run_at: Utc::now().timestamp_micros(),
             is_realtime: true,
             is_silenced: false,
+            ..trigger
         };
         super::triggers::save(org_id, stream_type, stream_name, alert_name, &new_trigger).await?;
         return Ok(());
@@ -113,40 +117,295 @@ pub async fn handle_triggers(
         }
     };
 
+    let now = Utc::now().timestamp_micros();
     let mut new_trigger = Trigger {
-        next_run_at: Utc::now().timestamp_micros(),
+        next_run_at: now,
         is_realtime: false,
         is_silenced: false,
+        ..trigger
     };
 
     if !alert.enabled {
         // update trigger, check on next week
         new_trigger.next_run_at += Duration::days(7).num_microseconds().unwrap();
         new_trigger.is_silenced = true;
+        new_trigger.state = AlertState::Ok;
+        new_trigger.pending_count = 0;
+        new_trigger.fired_at = 0;
         super::triggers::save(org_id, stream_type, stream_name, alert_name, &new_trigger).await?;
         return Ok(());
     }
 
     // evaluate alert
-    let ret = alert.evaluate(None).await?;
-    if ret.is_some() && alert.trigger_condition.silence > 0 {
-        new_trigger.next_run_at += Duration::minutes(alert.trigger_condition.silence)
+    let start = std::time::Instant::now();
+    let mut history = AlertHistory::new(&alert);
+    let ret = match alert.evaluate(None).await {
+        Ok(ret) => ret,
+        Err(e) => {
+            history.outcome = EvaluationOutcome::Error;
+            history.error = e.to_string();
+            history.took = start.elapsed().as_millis() as usize;
+            if let Err(e) = super::history::save(history).await {
+                log::error!("[ALERT_MANAGER] Error saving alert history: {}", e);
+            }
+            return Err(e);
+        }
+    };
+    history.matched_rows = ret.as_ref().map(|v| v.len()).unwrap_or_default();
+    let Decision {
+        state,
+        notify: should_notify,
+        rows,
+        maintenance_window,
+    } = decide(&alert, &mut new_trigger, ret, now);
+    history.outcome = state.into();
+    history.maintenance_window = maintenance_window;
+
+    // send notification
+    if should_notify {
+        let failed = alert
+            .send_notification(&rows, state, new_trigger.fired_at)
+            .await;
+        history.set_delivery(&failed);
+    }
+    history.took = start.elapsed().as_millis() as usize;
+    if let Err(e) = super::history::save(history).await {
+        log::error!("[ALERT_MANAGER] Error saving alert history: {}", e);
+    }
+
+    // update trigger
+    super::triggers::save(org_id, stream_type, stream_name, alert_name, &new_trigger).await?;
+
+    Ok(())
+}
+
+/// What to do after an evaluation of a scheduled alert.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Decision {
+    pub state: AlertState,
+    pub notify: bool,
+    /// The rows to notify, without those of the groups still silenced.
+    pub rows: Vec<Map<String, Value>>,
+    /// The maintenance window which muted the notification.
+    pub maintenance_window: String,
+}
+
+/// Moves the trigger forward with the result of the evaluation at `now`: the
+/// alert state, the groups silenced, the maintenance windows muting the
+/// notification and the next run of the trigger.
+pub(crate) fn decide(
+    alert: &Alert,
+    trigger: &mut Trigger,
+    ret: Option<Vec<Map<String, Value>>>,
+    now: i64,
+) -> Decision {
+    let (state, mut notify) = next_state(alert, trigger, ret.is_some(), now);
+    trigger.state = state;
+    trigger.next_run_at = now;
+    trigger.is_silenced = false;
+    let mut maintenance_window = String::new();
+    if notify {
+        // keep evaluating but don't notify during the maintenance
+        if let Some(window) = super::maintenance::active_window(alert, now) {
+            maintenance_window = window;
+            notify = false;
+        }
+    }
+    let mut rows = ret.unwrap_or_default();
+    if alert.has_group_by() {
+        // group-by alerts are silenced per group, keep evaluating the others
+        if state == AlertState::Firing && notify {
+            rows = silence_groups(alert, trigger, rows, now);
+            notify = !rows.is_empty();
+        } else if matches!(state, AlertState::Ok | AlertState::Resolved) {
+            trigger.silenced_groups.clear();
+        }
+        trigger.next_run_at += Duration::minutes(alert.trigger_condition.frequency)
             .num_microseconds()
             .unwrap();
-        new_trigger.is_silenced = true;
+    } else if state == AlertState::Firing && notify && alert.trigger_condition.silence > 0 {
+        trigger.next_run_at += Duration::minutes(alert.trigger_condition.silence)
+            .num_microseconds()
+            .unwrap();
+        trigger.is_silenced = true;
     } else {
-        new_trigger.next_run_at += Duration::minutes(alert.trigger_condition.frequency)
+        trigger.next_run_at += Duration::minutes(alert.trigger_condition.frequency)
             .num_microseconds()
             .unwrap();
     }
+    Decision {
+        state,
+        notify,
+        rows,
+        maintenance_window,
+    }
+}
 
-    // send notification
-    if let Some(ret) = ret {
-        alert.send_notification(&ret).await?;
+/// Moves the alert state forward based on the result of the latest evaluation,
+/// returns the new state and whether a notification should be sent for it.
+pub(crate) fn next_state(
+    alert: &Alert,
+    trigger: &mut Trigger,
+    matched: bool,
+    now: i64,
+) -> (AlertState, bool) {
+    if !matched {
+        trigger.pending_count = 0;
+        return match trigger.state {
+            AlertState::Firing => (
+                AlertState::Resolved,
+                alert.trigger_condition.notify_on_resolved,
+            ),
+            _ => {
+                trigger.fired_at = 0;
+                (AlertState::Ok, false)
+            }
+        };
     }
 
-    // update trigger
-    super::triggers::save(org_id, stream_type, stream_name, alert_name, &new_trigger).await?;
+    if trigger.state == AlertState::Firing {
+        // still firing, notify again once the silence period is over
+        return (AlertState::Firing, true);
+    }
+    trigger.pending_count += 1;
+    if trigger.pending_count < alert.trigger_condition.pending_evaluations {
+        return (AlertState::Pending, false);
+    }
+    trigger.pending_count = 0;
+    trigger.fired_at = now;
+    (AlertState::Firing, true)
+}
 
-    Ok(())
+/// Drops the rows of the groups which are still silenced, the groups of the
+/// remaining rows are silenced from now on.
+pub(crate) fn silence_groups(
+    alert: &Alert,
+    trigger: &mut Trigger,
+    rows: Vec<Map<String, Value>>,
+    now: i64,
+) -> Vec<Map<String, Value>> {
+    trigger.silenced_groups.retain(|_, until| *until > now);
+    let until = now
+        + Duration::minutes(alert.trigger_condition.silence)
+            .num_microseconds()
+            .unwrap();
+    let mut notify_rows = Vec::with_capacity(rows.len());
+    for row in rows {
+        let key = alert.group_key(&row).unwrap_or_default();
+        if trigger.silenced_groups.contains_key(&key) {
+            continue;
+        }
+        if alert.trigger_condition.silence > 0 {
+            trigger.silenced_groups.insert(key, until);
+        }
+        notify_rows.push(row);
+    }
+    notify_rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::meta::alerts::{AggFunction, Aggregation, Condition, Operator};
+
+    #[test]
+    fn test_next_state() {
+        let mut alert = Alert::default();
+        alert.trigger_condition.pending_evaluations = 2;
+        alert.trigger_condition.notify_on_resolved = true;
+        let mut trigger = Trigger::default();
+
+        let (state, notify) = next_state(&alert, &mut trigger, true, 1);
+        assert_eq!((state, notify), (AlertState::Pending, false));
+        trigger.state = state;
+        let (state, notify) = next_state(&alert, &mut trigger, true, 2);
+        assert_eq!((state, notify), (AlertState::Firing, true));
+        assert_eq!(trigger.fired_at, 2);
+        trigger.state = state;
+        let (state, notify) = next_state(&alert, &mut trigger, true, 3);
+        assert_eq!((state, notify), (AlertState::Firing, true));
+        assert_eq!(trigger.fired_at, 2);
+        trigger.state = state;
+        let (state, notify) = next_state(&alert, &mut trigger, false, 4);
+        assert_eq!((state, notify), (AlertState::Resolved, true));
+        trigger.state = state;
+        let (state, notify) = next_state(&alert, &mut trigger, false, 5);
+        assert_eq!((state, notify), (AlertState::Ok, false));
+        assert_eq!(trigger.fired_at, 0);
+    }
+
+    #[test]
+    fn test_decide() {
+        let mut alert = Alert::default();
+        alert.trigger_condition.frequency = 1;
+        alert.trigger_condition.silence = 10;
+        let minute = Duration::minutes(1).num_microseconds().unwrap();
+        let mut trigger = Trigger::default();
+
+        let decision = decide(&alert, &mut trigger, Some(vec![Map::new()]), 0);
+        assert_eq!(
+            (decision.state, decision.notify),
+            (AlertState::Firing, true)
+        );
+        assert_eq!(decision.rows.len(), 1);
+        assert!(trigger.is_silenced);
+        assert_eq!(trigger.next_run_at, 10 * minute);
+
+        let decision = decide(&alert, &mut trigger, None, 10 * minute);
+        assert_eq!(decision.state, AlertState::Resolved);
+        assert!(!trigger.is_silenced);
+        assert_eq!(trigger.next_run_at, 11 * minute);
+    }
+
+    #[test]
+    fn test_silence_groups() {
+        let mut alert = Alert::default();
+        alert.query_condition.aggregation = Some(Aggregation {
+            group_by: Some(vec!["host".to_string()]),
+            function: AggFunction::Count,
+            having: Condition {
+                column: "host".to_string(),
+                operator: Operator::GreaterThan,
+                value: Value::from(100),
+                ignore_case: false,
+            },
+        });
+        alert.trigger_condition.silence = 10;
+        let mut trigger = Trigger::default();
+        let row = |host: &str| {
+            let mut row = Map::new();
+            row.insert("host".to_string(), Value::String(host.to_string()));
+            row
+        };
+        let minute = Duration::minutes(1).num_microseconds().unwrap();
+
+        let rows = silence_groups(&alert, &mut trigger, vec![row("a"), row("b")], 0);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(trigger.silenced_groups.len(), 2);
+
+        // a noisy host keeps firing while a new one still notifies
+        let rows = silence_groups(&alert, &mut trigger, vec![row("a"), row("c")], minute);
+        assert_eq!(rows, vec![row("c")]);
+
+        // the silence of the first groups is over
+        let rows = silence_groups(&alert, &mut trigger, vec![row("a")], 10 * minute);
+        assert_eq!(rows, vec![row("a")]);
+        assert_eq!(trigger.silenced_groups.len(), 2);
+    }
+
+    #[test]
+    fn test_next_state_pending_reset() {
+        let mut alert = Alert::default();
+        alert.trigger_condition.pending_evaluations = 3;
+        let mut trigger = Trigger::default();
+
+        let (state, _) = next_state(&alert, &mut trigger, true, 1);
+        trigger.state = state;
+        let (state, _) = next_state(&alert, &mut trigger, true, 2);
+        assert_eq!(state, AlertState::Pending);
+        trigger.state = state;
+        let (state, _) = next_state(&alert, &mut trigger, false, 3);
+        assert_eq!(state, AlertState::Ok);
+        assert_eq!(trigger.pending_count, 0);
+    }
 }
@@ -0,0 +1,240 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use ahash::HashMap;
+use chrono::Duration;
+
+use super::{build_where, search_request};
+use crate::{
+    common::{
+        infra::config::CONFIG,
+        meta::alerts::{AggFunction, Alert, AnomalyDetection, BaselineMethod},
+        utils::json::{Map, Value},
+    },
+    service::{db, search as SearchService},
+};
+
+/// Scale factor which makes the MAD comparable to the standard deviation.
+const MAD_SCALE: f64 = 1.4826;
+
+/// Builds the SQL which aggregates the current period as bucket `0` and the
+/// baseline periods as buckets `1..=periods`, counting back from `end_time`.
+pub async fn build_sql(
+    alert: &Alert,
+    anomaly: &AnomalyDetection,
+    end_time: i64,
+) -> Result<String, anyhow::Error> {
+    let schema = db::schema::get(&alert.org_id, &alert.stream_name, alert.stream_type).await?;
+    if schema.field_with_name(&anomaly.column).is_err() {
+        return Err(anyhow::anyhow!(
+            "Anomaly column {} not found on stream {}",
+            &anomaly.column,
+            &alert.stream_name
+        ));
+    }
+    let conditions = alert
+        .query_condition
+        .conditions
+        .as_deref()
+        .unwrap_or_default();
+    let where_sql = build_where(alert, &schema, conditions)?;
+    let (period, stride) = window(alert, anomaly);
+    let ts = &CONFIG.common.column_timestamp;
+    let where_sql = if anomaly.seasonal {
+        // only keep the same time of the day in the past weeks
+        let seasonal = format!("({end_time} - {ts}) % {stride} < {period}");
+        if where_sql.is_empty() {
+            format!("WHERE {seasonal}")
+        } else {
+            format!("{where_sql} AND {seasonal}")
+        }
+    } else {
+        where_sql
+    };
+    Ok(format!(
+        "SELECT ({end_time} - {ts}) / {stride} AS zo_sql_key, {} AS alert_agg_value, MIN({ts}) AS zo_sql_min_time, MAX({ts}) AS zo_sql_max_time FROM \"{}\" {} GROUP BY zo_sql_key",
+        anomaly.function.to_sql(&anomaly.column),
+        alert.stream_name,
+        where_sql
+    ))
+}
+
+/// Fires when the aggregate of the period ending at `end_time` deviates from
+/// the baseline by more than the configured sigma.
+pub async fn evaluate(
+    alert: &Alert,
+    anomaly: &AnomalyDetection,
+    end_time: i64,
+) -> Result<Option<Vec<Map<String, Value>>>, anyhow::Error> {
+    let sql = build_sql(alert, anomaly, end_time).await?;
+    let (period, stride) = window(alert, anomaly);
+    let periods = anomaly.baseline_periods();
+    let start_time = end_time - periods * stride - period;
+    let req = search_request(sql, start_time, end_time, periods as usize + 1);
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let resp = SearchService::search(&session_id, &alert.org_id, alert.stream_type, &req).await?;
+
+    let mut buckets = HashMap::default();
+    for hit in resp.hits.iter() {
+        let key = match hit.get("zo_sql_key").and_then(|v| v.as_i64()) {
+            Some(key) => key,
+            None => continue,
+        };
+        if let Some(hit) = hit.as_object() {
+            buckets.insert(key, hit);
+        }
+    }
+    let value = |key: i64| -> Option<f64> {
+        match buckets
+            .get(&key)
+            .and_then(|hit| hit.get("alert_agg_value"))
+            .and_then(|v| v.as_f64())
+        {
+            Some(v) => Some(v),
+            // no data in the period means zero for counters
+            None if matches!(anomaly.function, AggFunction::Count | AggFunction::Sum) => Some(0.0),
+            None => None,
+        }
+    };
+    let current = match value(0) {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    let baseline = (1..=periods).filter_map(value).collect::<Vec<_>>();
+    let (center, sigma) = match deviation(anomaly.baseline, &baseline, current) {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    if sigma <= anomaly.sigma {
+        return Ok(None);
+    }
+
+    let mut row = buckets
+        .get(&0)
+        .map(|hit| (*hit).clone())
+        .unwrap_or_default();
+    row.remove("zo_sql_key");
+    row.insert("alert_agg_value".to_string(), Value::from(current));
+    row.insert("alert_baseline".to_string(), Value::from(center));
+    row.insert("alert_deviation".to_string(), Value::from(sigma));
+    Ok(Some(vec![row]))
+}
+
+/// Returns the length of a period and the distance between two periods of
+/// the baseline, in microseconds.
+fn window(alert: &Alert, anomaly: &AnomalyDetection) -> (i64, i64) {
+    let period = Duration::minutes(std::cmp::max(1, alert.trigger_condition.period))
+        .num_microseconds()
+        .unwrap();
+    let stride = if anomaly.seasonal {
+        Duration::weeks(1).num_microseconds().unwrap()
+    } else {
+        period
+    };
+    (period, stride)
+}
+
+/// Returns the center of the baseline and how far the current value is from
+/// it, in units of the baseline spread.
+fn deviation(method: BaselineMethod, baseline: &[f64], current: f64) -> Option<(f64, f64)> {
+    if baseline.len() < 2 {
+        return None;
+    }
+    let (center, spread) = match method {
+        BaselineMethod::MeanStddev => {
+            let mean = baseline.iter().sum::<f64>() / baseline.len() as f64;
+            let variance =
+                baseline.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / baseline.len() as f64;
+            (mean, variance.sqrt())
+        }
+        BaselineMethod::MedianMad => {
+            let median = median(baseline.to_vec());
+            let mad = median(baseline.iter().map(|v| (v - median).abs()).collect());
+            (median, mad * MAD_SCALE)
+        }
+    };
+    let distance = (current - center).abs();
+    let deviation = if spread > 0.0 {
+        distance / spread
+    } else if distance > 0.0 {
+        f64::INFINITY
+    } else {
+        0.0
+    };
+    Some((center, deviation))
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    if values.len() % 2 == 0 {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deviation_mean_stddev() {
+        let baseline = [10.0, 12.0, 8.0, 10.0];
+        let (center, dev) = deviation(BaselineMethod::MeanStddev, &baseline, 10.0).unwrap();
+        assert_eq!(center, 10.0);
+        assert_eq!(dev, 0.0);
+        let (_, dev) = deviation(BaselineMethod::MeanStddev, &baseline, 20.0).unwrap();
+        assert!(dev > 3.0);
+        assert!(deviation(BaselineMethod::MeanStddev, &[10.0], 20.0).is_none());
+    }
+
+    #[test]
+    fn test_deviation_median_mad() {
+        // a single outlier in the baseline does not move the median
+        let baseline = [10.0, 11.0, 9.0, 10.0, 1000.0];
+        let (center, dev) = deviation(BaselineMethod::MedianMad, &baseline, 11.0).unwrap();
+        assert_eq!(center, 10.0);
+        assert!(dev < 1.0);
+        let (_, dev) = deviation(BaselineMethod::MedianMad, &baseline, 50.0).unwrap();
+        assert!(dev > 3.0);
+    }
+
+    #[test]
+    fn test_deviation_flat_baseline() {
+        let baseline = [5.0, 5.0, 5.0];
+        let (_, dev) = deviation(BaselineMethod::MeanStddev, &baseline, 5.0).unwrap();
+        assert_eq!(dev, 0.0);
+        let (_, dev) = deviation(BaselineMethod::MedianMad, &baseline, 6.0).unwrap();
+        assert!(dev.is_infinite());
+    }
+
+    #[test]
+    fn test_baseline_periods() {
+        let mut anomaly: AnomalyDetection =
+            serde_json::from_str(r#"{"function":"count","column":"code"}"#).unwrap();
+        assert_eq!(anomaly.baseline_periods(), 24);
+        anomaly.seasonal = true;
+        assert_eq!(anomaly.baseline_periods(), 4);
+        anomaly.periods = 8;
+        assert_eq!(anomaly.baseline_periods(), 8);
+    }
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median(vec![3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(median(vec![4.0, 1.0, 2.0, 3.0]), 2.5);
+    }
+}
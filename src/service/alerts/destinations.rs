@@ -14,11 +14,12 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use actix_web::http;
+use lettre::message::Mailbox;
 
 use crate::{
     common::{
-        infra::config::STREAM_ALERTS,
-        meta::alerts::destinations::{Destination, DestinationWithTemplate},
+        infra::config::{CONFIG, STREAM_ALERTS},
+        meta::alerts::destinations::{Destination, DestinationType, DestinationWithTemplate},
     },
     service::db,
 };
@@ -41,9 +42,57 @@ pub async fn save(
     if destination.name.is_empty() {
         return Err(anyhow::anyhow!("Alert destination name is required"));
     }
+    validate(&destination)?;
     db::alerts::destinations::set(org_id, name, destination).await
 }
 
+fn validate(destination: &Destination) -> Result<(), anyhow::Error> {
+    let api_key_required = match destination.destination_type {
+        DestinationType::Webhook | DestinationType::Slack => {
+            if destination.url.is_empty() {
+                return Err(anyhow::anyhow!("Alert destination url is required"));
+            }
+            false
+        }
+        DestinationType::PagerDutyEventsV2 | DestinationType::Opsgenie => true,
+        DestinationType::Email => {
+            if !CONFIG.smtp.smtp_enabled {
+                return Err(anyhow::anyhow!(
+                    "SMTP is not configured, email destination is not available"
+                ));
+            }
+            if destination.emails.is_empty() {
+                return Err(anyhow::anyhow!("Alert destination emails is required"));
+            }
+            for email in destination.emails.iter() {
+                if email.parse::<Mailbox>().is_err() {
+                    return Err(anyhow::anyhow!(
+                        "Alert destination email {email} is invalid"
+                    ));
+                }
+            }
+            false
+        }
+    };
+    if api_key_required
+        && destination
+            .api_key
+            .as_ref()
+            .map_or(true, |v| v.trim().is_empty())
+    {
+        return Err(anyhow::anyhow!(
+            "Alert destination api_key is required for {}",
+            destination.destination_type
+        ));
+    }
+    if !destination.url.is_empty() {
+        if let Err(e) = url::Url::parse(&destination.url) {
+            return Err(anyhow::anyhow!("Alert destination url is invalid: {e}"));
+        }
+    }
+    Ok(())
+}
+
 pub async fn get(org_id: &str, name: &str) -> Result<Destination, anyhow::Error> {
     db::alerts::destinations::get(org_id, name)
         .await
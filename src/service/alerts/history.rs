@@ -0,0 +1,99 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+
+use crate::{
+    common::{
+        infra::config::CONFIG,
+        meta::{
+            alerts::history::{AlertHistory, ALERT_HISTORY_STREAM},
+            search, StreamType,
+        },
+        utils::json,
+    },
+    handler::grpc::cluster_rpc,
+    service::{db, search as SearchService, usage::ingestion_service},
+};
+
+/// Writes the evaluation record into the `_alert_history` stream of the alert's
+/// organization.
+pub async fn save(history: AlertHistory) -> Result<(), anyhow::Error> {
+    if history.is_realtime && history.stream_name.eq(ALERT_HISTORY_STREAM) {
+        // a realtime alert on the history stream would trigger itself forever
+        return Ok(());
+    }
+    let org_id = history.org_id.clone();
+    let req = cluster_rpc::UsageRequest {
+        stream_name: ALERT_HISTORY_STREAM.to_owned(),
+        data: Some(cluster_rpc::UsageData::from(vec![json::to_value(history)?])),
+    };
+    let resp = ingestion_service::ingest(&org_id, req).await?;
+    if resp.status_code != 200 {
+        return Err(anyhow::anyhow!(
+            "Error saving alert history: {}",
+            resp.message
+        ));
+    }
+    Ok(())
+}
+
+pub async fn list(
+    org_id: &str,
+    stream_type: StreamType,
+    stream_name: &str,
+    alert_name: &str,
+    time_range: (i64, i64),
+    from: usize,
+    size: usize,
+) -> Result<search::Response, anyhow::Error> {
+    let schema = db::schema::get(org_id, ALERT_HISTORY_STREAM, StreamType::Logs).await?;
+    if schema.fields().is_empty() {
+        // no alert has been evaluated yet
+        return Ok(search::Response::new(from, size));
+    }
+
+    let sql = format!(
+        "SELECT * FROM \"{}\" WHERE stream_type = '{}' AND stream_name = '{}' AND alert_name = '{}' ORDER BY {} DESC",
+        ALERT_HISTORY_STREAM,
+        stream_type,
+        stream_name.replace('\'', "''"),
+        alert_name.replace('\'', "''"),
+        CONFIG.common.column_timestamp,
+    );
+    let req = search::Request {
+        query: search::Query {
+            sql,
+            from,
+            size,
+            start_time: time_range.0,
+            end_time: time_range.1,
+            sort_by: None,
+            sql_mode: "full".to_string(),
+            query_type: "".to_string(),
+            track_total_hits: false,
+            uses_zo_fn: false,
+            query_context: None,
+            query_fn: None,
+        },
+        aggs: HashMap::new(),
+        encoding: search::RequestEncoding::Empty,
+        timeout: 0,
+    };
+    let session_id = uuid::Uuid::new_v4().to_string();
+    SearchService::search(&session_id, org_id, StreamType::Logs, &req)
+        .await
+        .map_err(|e| anyhow::anyhow!("Error searching alert history: {}", e))
+}
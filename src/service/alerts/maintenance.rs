@@ -0,0 +1,275 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::str::FromStr;
+
+use actix_web::http;
+use chrono::{Duration, TimeZone, Utc};
+use chrono_tz::Tz;
+use cron::Schedule;
+
+use crate::{
+    common::{
+        infra::config::MAINTENANCE_WINDOWS,
+        meta::alerts::{maintenance::MaintenanceWindow, Alert},
+    },
+    service::db,
+};
+
+pub async fn save(
+    org_id: &str,
+    name: &str,
+    mut window: MaintenanceWindow,
+) -> Result<(), anyhow::Error> {
+    window.name = name.trim().to_string();
+    if window.name.is_empty() {
+        return Err(anyhow::anyhow!("Maintenance window name is required"));
+    }
+    validate(&window)?;
+    db::alerts::maintenance::set(org_id, &window.name, &window).await
+}
+
+pub async fn get(org_id: &str, name: &str) -> Result<MaintenanceWindow, anyhow::Error> {
+    db::alerts::maintenance::get(org_id, name)
+        .await
+        .map_err(|_| anyhow::anyhow!("Maintenance window not found"))
+}
+
+pub async fn list(org_id: &str) -> Result<Vec<MaintenanceWindow>, anyhow::Error> {
+    db::alerts::maintenance::list(org_id).await
+}
+
+pub async fn delete(org_id: &str, name: &str) -> Result<(), (http::StatusCode, anyhow::Error)> {
+    if db::alerts::maintenance::get(org_id, name).await.is_err() {
+        return Err((
+            http::StatusCode::NOT_FOUND,
+            anyhow::anyhow!("Maintenance window not found {}", name),
+        ));
+    }
+    db::alerts::maintenance::delete(org_id, name)
+        .await
+        .map_err(|e| (http::StatusCode::INTERNAL_SERVER_ERROR, e))
+}
+
+/// Returns the name of the maintenance window which mutes the alert at the
+/// given time, in microseconds.
+pub fn active_window(alert: &Alert, now: i64) -> Option<String> {
+    let prefix = format!("{}/", alert.org_id);
+    for item in MAINTENANCE_WINDOWS.iter() {
+        if !item.key().starts_with(&prefix) {
+            continue;
+        }
+        let window = item.value();
+        if window.enabled && matches(window, alert) && is_active(window, now) {
+            return Some(window.name.clone());
+        }
+    }
+    None
+}
+
+fn validate(window: &MaintenanceWindow) -> Result<(), anyhow::Error> {
+    if let Some(pattern) = window.matchers.alert_name.as_ref() {
+        glob::Pattern::new(pattern)
+            .map_err(|e| anyhow::anyhow!("Invalid alert name pattern {pattern}: {e}"))?;
+    }
+    match window.cron.as_ref() {
+        Some(expr) => {
+            parse_schedule(expr)?;
+            parse_timezone(&window.timezone)?;
+            if window.duration <= 0 {
+                return Err(anyhow::anyhow!(
+                    "Recurring maintenance window should have a duration"
+                ));
+            }
+            if window.end_time > 0 && window.end_time <= window.start_time {
+                return Err(anyhow::anyhow!(
+                    "end_time should be greater than start_time"
+                ));
+            }
+        }
+        None => {
+            if window.start_time <= 0 || window.end_time <= window.start_time {
+                return Err(anyhow::anyhow!(
+                    "Maintenance window should have a start_time and a greater end_time"
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn matches(window: &MaintenanceWindow, alert: &Alert) -> bool {
+    let matchers = &window.matchers;
+    if let Some(pattern) = matchers.alert_name.as_ref() {
+        match glob::Pattern::new(pattern) {
+            Ok(pattern) if pattern.matches(&alert.name) => {}
+            _ => return false,
+        }
+    }
+    if matchers
+        .stream_type
+        .is_some_and(|stream_type| stream_type != alert.stream_type)
+    {
+        return false;
+    }
+    if matchers
+        .stream_name
+        .as_ref()
+        .is_some_and(|stream_name| stream_name != &alert.stream_name)
+    {
+        return false;
+    }
+    matchers.labels.iter().all(|(key, value)| {
+        alert
+            .context_attributes
+            .as_ref()
+            .and_then(|attrs| attrs.get(key))
+            .is_some_and(|v| v == value)
+    })
+}
+
+fn is_active(window: &MaintenanceWindow, now: i64) -> bool {
+    let expr = match window.cron.as_ref() {
+        Some(expr) => expr,
+        None => return window.start_time <= now && now < window.end_time,
+    };
+    if (window.start_time > 0 && now < window.start_time)
+        || (window.end_time > 0 && now >= window.end_time)
+    {
+        return false;
+    }
+    let (schedule, tz) = match (parse_schedule(expr), parse_timezone(&window.timezone)) {
+        (Ok(schedule), Ok(tz)) => (schedule, tz),
+        _ => return false,
+    };
+    // active when the schedule started a window within the last duration
+    let since = now
+        - Duration::minutes(window.duration)
+            .num_microseconds()
+            .unwrap();
+    let since = tz.from_utc_datetime(&Utc.timestamp_nanos(since * 1000).naive_utc());
+    match schedule.after(&since).next() {
+        Some(start) => start.timestamp_micros() <= now,
+        None => false,
+    }
+}
+
+/// Parses a cron expression, the seconds field is optional.
+fn parse_schedule(expr: &str) -> Result<Schedule, anyhow::Error> {
+    let expr = expr.trim();
+    let expr = if expr.split_whitespace().count() == 5 {
+        format!("0 {expr}")
+    } else {
+        expr.to_string()
+    };
+    Schedule::from_str(&expr).map_err(|e| anyhow::anyhow!("Invalid cron expression: {e}"))
+}
+
+fn parse_timezone(timezone: &str) -> Result<Tz, anyhow::Error> {
+    timezone
+        .parse::<Tz>()
+        .map_err(|e| anyhow::anyhow!("Invalid timezone {timezone}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::meta::{alerts::maintenance::MaintenanceMatchers, StreamType};
+
+    fn micros(rfc3339: &str) -> i64 {
+        chrono::DateTime::parse_from_rfc3339(rfc3339)
+            .unwrap()
+            .timestamp_micros()
+    }
+
+    #[test]
+    fn test_matches() {
+        let alert = Alert {
+            name: "api_latency".to_string(),
+            stream_type: StreamType::Logs,
+            stream_name: "api".to_string(),
+            context_attributes: Some(
+                [("team".to_string(), "core".to_string())]
+                    .into_iter()
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+        let mut window = MaintenanceWindow::default();
+        assert!(matches(&window, &alert));
+
+        window.matchers = MaintenanceMatchers {
+            alert_name: Some("api_*".to_string()),
+            stream_name: Some("api".to_string()),
+            ..Default::default()
+        };
+        assert!(matches(&window, &alert));
+        window
+            .matchers
+            .labels
+            .insert("team".to_string(), "web".to_string());
+        assert!(!matches(&window, &alert));
+        window
+            .matchers
+            .labels
+            .insert("team".to_string(), "core".to_string());
+        assert!(matches(&window, &alert));
+        window.matchers.alert_name = Some("db_*".to_string());
+        assert!(!matches(&window, &alert));
+    }
+
+    #[test]
+    fn test_is_active_once() {
+        let window = MaintenanceWindow {
+            start_time: micros("2023-10-01T10:00:00Z"),
+            end_time: micros("2023-10-01T12:00:00Z"),
+            ..Default::default()
+        };
+        assert!(!is_active(&window, micros("2023-10-01T09:59:59Z")));
+        assert!(is_active(&window, micros("2023-10-01T10:00:00Z")));
+        assert!(!is_active(&window, micros("2023-10-01T12:00:00Z")));
+    }
+
+    #[test]
+    fn test_is_active_recurring() {
+        // every day from 02:00 to 03:00 Shanghai time, 18:00 to 19:00 UTC
+        let window = MaintenanceWindow {
+            cron: Some("0 2 * * *".to_string()),
+            duration: 60,
+            timezone: "Asia/Shanghai".to_string(),
+            ..Default::default()
+        };
+        assert!(is_active(&window, micros("2023-10-01T18:30:00Z")));
+        assert!(is_active(&window, micros("2023-10-01T18:00:00Z")));
+        assert!(!is_active(&window, micros("2023-10-01T19:00:00Z")));
+        assert!(!is_active(&window, micros("2023-10-01T02:30:00Z")));
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut window = MaintenanceWindow {
+            cron: Some("0 2 * * *".to_string()),
+            timezone: "UTC".to_string(),
+            ..Default::default()
+        };
+        assert!(validate(&window).is_err());
+        window.duration = 30;
+        assert!(validate(&window).is_ok());
+        window.timezone = "Mars/Olympus".to_string();
+        assert!(validate(&window).is_err());
+        window.cron = None;
+        assert!(validate(&window).is_err());
+    }
+}
@@ -16,7 +16,7 @@
 use std::collections::{HashMap, HashSet};
 
 use actix_web::http;
-use arrow_schema::DataType;
+use arrow_schema::{DataType, Schema};
 use chrono::{Duration, Local, TimeZone, Utc};
 
 use crate::{
@@ -24,8 +24,8 @@ use crate::{
         infra::config::CONFIG,
         meta::{
             alerts::{
-                destinations::{DestinationWithTemplate, HTTPType},
-                Alert, Condition, Operator, QueryCondition, QueryType,
+                destinations::DestinationWithTemplate, triggers::AlertState, AggFunction, Alert,
+                Condition, LogicalOperator, Operator, QueryCondition, QueryType, TriggerCondition,
             },
             search, StreamType,
         },
@@ -34,11 +34,17 @@ use crate::{
             schema_ext::SchemaExt,
         },
     },
-    service::{db, search as SearchService},
+    service::{db, promql, search as SearchService},
 };
 
 pub mod alert_manager;
+pub mod anomaly;
 pub mod destinations;
+pub mod history;
+pub mod maintenance;
+pub mod notification;
+pub mod preview;
+pub mod retries;
 pub mod templates;
 pub mod triggers;
 
@@ -92,6 +98,10 @@ pub async fn save(
             "Realtime alert should use Custom query type"
         ));
     }
+    if alert.is_real_time && alert.trigger_condition.notify_on_resolved {
+        // realtime alerts are only evaluated for the records which match
+        return Err(anyhow::anyhow!("Realtime alert can not notify on resolved"));
+    }
 
     match alert.query_condition.query_type {
         QueryType::Custom => {
@@ -123,9 +133,42 @@ pub async fn save(
             {
                 return Err(anyhow::anyhow!("Alert should have a PromQL"));
             }
+            if matches!(
+                alert.trigger_condition.operator,
+                Operator::Contains | Operator::NotContains
+            ) {
+                return Err(anyhow::anyhow!(
+                    "PromQL alert should compare the number of series"
+                ));
+            }
+        }
+        QueryType::Anomaly => {
+            let anomaly = match alert.query_condition.anomaly.as_ref() {
+                Some(anomaly) => anomaly,
+                None => {
+                    return Err(anyhow::anyhow!("Alert should have an anomaly detection"));
+                }
+            };
+            if anomaly.periods < 0 || anomaly.baseline_periods() < 2 {
+                return Err(anyhow::anyhow!(
+                    "Anomaly detection needs at least 2 baseline periods"
+                ));
+            }
+            if anomaly.sigma <= 0.0 {
+                return Err(anyhow::anyhow!("Anomaly sigma should be positive"));
+            }
+            // the detection decides whether to fire
+            alert.trigger_condition.operator = Operator::GreaterThanEquals;
+            alert.trigger_condition.threshold = 1;
         }
     }
 
+    if alert.trigger_condition.pending_evaluations < 0 {
+        return Err(anyhow::anyhow!(
+            "Alert pending evaluations should not be negative"
+        ));
+    }
+
     // test the alert
     _ = &alert.evaluate(None).await?;
 
@@ -213,10 +256,16 @@ pub async fn trigger(
             ));
         }
     };
-    alert
-        .send_notification(&[])
-        .await
-        .map_err(|e| (http::StatusCode::INTERNAL_SERVER_ERROR, e))
+    let failed = alert
+        .send_notification(&[], AlertState::Firing, Utc::now().timestamp_micros())
+        .await;
+    match failed.first() {
+        Some((dest, e)) => Err((
+            http::StatusCode::INTERNAL_SERVER_ERROR,
+            anyhow::anyhow!("Error sending notification to {dest}: {e}"),
+        )),
+        None => Ok(()),
+    }
 }
 
 impl Alert {
@@ -231,13 +280,53 @@ impl Alert {
         }
     }
 
+    pub fn has_group_by(&self) -> bool {
+        self.query_condition
+            .aggregation
+            .as_ref()
+            .and_then(|agg| agg.group_by.as_ref())
+            .map(|group| !group.is_empty())
+            .unwrap_or_default()
+    }
+
+    /// Returns the group-by key of the row, the values of the group-by
+    /// columns joined by `/`. Alerts without group-by have no key.
+    pub fn group_key(&self, row: &Map<String, Value>) -> Option<String> {
+        if !self.has_group_by() {
+            return None;
+        }
+        let group = self
+            .query_condition
+            .aggregation
+            .as_ref()?
+            .group_by
+            .as_ref()?;
+        let values = group
+            .iter()
+            .map(|col| match row.get(col) {
+                Some(Value::String(v)) => v.to_string(),
+                Some(v) => v.to_string(),
+                None => String::new(),
+            })
+            .collect::<Vec<_>>();
+        Some(values.join("/"))
+    }
+
+    /// Sends the notification to all the destinations of the alert, returns
+    /// the destinations which failed along with the error.
     pub async fn send_notification(
         &self,
         rows: &[Map<String, Value>],
-    ) -> Result<(), anyhow::Error> {
-        for dest in self.destinations.iter() {
-            let dest = destinations::get_with_template(&self.org_id, dest).await?;
-            if let Err(e) = send_notification(self, &dest, rows).await {
+        state: AlertState,
+        fired_at: i64,
+    ) -> Vec<(String, String)> {
+        let mut failed = Vec::new();
+        for name in self.destinations.iter() {
+            let ret = match destinations::get_with_template(&self.org_id, name).await {
+                Ok(dest) => send_notification(self, &dest, rows, state, fired_at).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = ret {
                 log::error!(
                     "Error sending notification for {}/{}/{}/{} err: {}",
                     self.org_id,
@@ -246,9 +335,10 @@ impl Alert {
                     self.name,
                     e
                 );
+                failed.push((name.to_string(), e.to_string()));
             }
         }
-        Ok(())
+        failed
     }
 }
 
@@ -270,10 +360,30 @@ impl QueryCondition {
         if conditions.is_empty() {
             return Ok(None);
         }
-        for condition in conditions.iter() {
-            if !condition.evaluate(row).await {
-                return Ok(None);
+        let matched = match self.logical_operator {
+            LogicalOperator::And => {
+                let mut matched = true;
+                for condition in conditions.iter() {
+                    if !condition.evaluate(row).await {
+                        matched = false;
+                        break;
+                    }
+                }
+                matched
             }
+            LogicalOperator::Or => {
+                let mut matched = false;
+                for condition in conditions.iter() {
+                    if condition.evaluate(row).await {
+                        matched = true;
+                        break;
+                    }
+                }
+                matched
+            }
+        };
+        if !matched {
+            return Ok(None);
         }
         Ok(Some(vec![row.to_owned()]))
     }
@@ -282,6 +392,63 @@ impl QueryCondition {
         &self,
         alert: &Alert,
     ) -> Result<Option<Vec<Map<String, Value>>>, anyhow::Error> {
+        let now = Utc::now().timestamp_micros();
+        let start_time = now
+            - Duration::minutes(alert.trigger_condition.period)
+                .num_microseconds()
+                .unwrap();
+        self.evaluate_window(alert, start_time, now).await
+    }
+
+    /// Evaluates the alert over the given time range, in microseconds.
+    pub async fn evaluate_window(
+        &self,
+        alert: &Alert,
+        start_time: i64,
+        end_time: i64,
+    ) -> Result<Option<Vec<Map<String, Value>>>, anyhow::Error> {
+        if self.query_type == QueryType::Anomaly {
+            return match self.anomaly.as_ref() {
+                Some(anomaly) => anomaly::evaluate(alert, anomaly, end_time).await,
+                None => Ok(None),
+            };
+        }
+        if self.query_type == QueryType::PromQL {
+            return match self.promql.as_ref() {
+                Some(query) if !query.is_empty() => evaluate_promql(alert, query, end_time).await,
+                _ => Ok(None),
+            };
+        }
+        let sql = match self.build_query(alert, end_time).await? {
+            Some(sql) => sql,
+            None => return Ok(None),
+        };
+
+        // fire the query
+        let req = search_request(sql, start_time, end_time, 100);
+        let session_id = uuid::Uuid::new_v4().to_string();
+        let resp =
+            SearchService::search(&session_id, &alert.org_id, alert.stream_type, &req).await?;
+        if resp.total < alert.trigger_condition.threshold as usize {
+            Ok(None)
+        } else {
+            Ok(Some(
+                resp.hits
+                    .iter()
+                    .map(|hit| hit.as_object().unwrap().clone())
+                    .collect(),
+            ))
+        }
+    }
+
+    /// Builds the SQL which the scheduled alert runs for the period ending at
+    /// `end_time`, or the PromQL of a PromQL alert, returns `None` when there
+    /// is nothing to evaluate.
+    pub async fn build_query(
+        &self,
+        alert: &Alert,
+        end_time: i64,
+    ) -> Result<Option<String>, anyhow::Error> {
         let sql = match self.query_type {
             QueryType::Custom => {
                 if let Some(v) = self.conditions.as_ref() {
@@ -305,48 +472,93 @@ impl QueryCondition {
                     return Ok(None);
                 }
             }
-            QueryType::PromQL => {
-                return Err(anyhow::anyhow!("PromQL is not supported yet"));
-            }
-        };
-
-        // fire the query
-        let now = Utc::now().timestamp_micros();
-        let req = search::Request {
-            query: search::Query {
-                sql: sql.clone(),
-                from: 0,
-                size: 100,
-                start_time: now
-                    - Duration::minutes(alert.trigger_condition.period)
-                        .num_microseconds()
-                        .unwrap(),
-                end_time: now,
-                sort_by: None,
-                sql_mode: "full".to_string(),
-                query_type: "".to_string(),
-                track_total_hits: false,
-                uses_zo_fn: false,
-                query_context: None,
-                query_fn: None,
+            QueryType::PromQL => match self.promql.as_ref() {
+                Some(v) if !v.is_empty() => v.to_string(),
+                _ => return Ok(None),
+            },
+            QueryType::Anomaly => match self.anomaly.as_ref() {
+                Some(anomaly) => anomaly::build_sql(alert, anomaly, end_time).await?,
+                None => return Ok(None),
             },
-            aggs: HashMap::new(),
-            encoding: search::RequestEncoding::Empty,
-            timeout: 0,
         };
-        let session_id = uuid::Uuid::new_v4().to_string();
-        let resp =
-            SearchService::search(&session_id, &alert.org_id, alert.stream_type, &req).await?;
-        if resp.total < alert.trigger_condition.threshold as usize {
-            Ok(None)
-        } else {
-            Ok(Some(
-                resp.hits
-                    .iter()
-                    .map(|hit| hit.as_object().unwrap().clone())
-                    .collect(),
-            ))
+        Ok(Some(sql))
+    }
+}
+
+/// Runs the PromQL as an instant query at `end_time`, every series of the
+/// result becomes a row with its labels and the `value` of the sample. The
+/// alert matches when the number of series satisfies its trigger condition.
+async fn evaluate_promql(
+    alert: &Alert,
+    query: &str,
+    end_time: i64,
+) -> Result<Option<Vec<Map<String, Value>>>, anyhow::Error> {
+    let req = promql::MetricsQueryRequest {
+        query: query.to_string(),
+        start: end_time,
+        end: end_time,
+        step: 300_000_000, // 5m
+    };
+    let rows = match promql::search::search(&alert.org_id, &req, 0).await? {
+        promql::value::Value::Vector(values) => values
+            .into_iter()
+            .map(|v| {
+                let mut row = Map::new();
+                for label in v.labels.iter() {
+                    row.insert(label.name.clone(), Value::String(label.value.clone()));
+                }
+                row.insert("value".to_string(), Value::from(v.sample.value));
+                row
+            })
+            .collect::<Vec<_>>(),
+        promql::value::Value::None => vec![],
+        _ => {
+            return Err(anyhow::anyhow!(
+                "PromQL of the alert should return a vector"
+            ));
         }
+    };
+    if series_matched(&alert.trigger_condition, rows.len()) {
+        Ok(Some(rows))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Compares the number of series with the threshold of the trigger condition.
+fn series_matched(trigger: &TriggerCondition, series: usize) -> bool {
+    let (series, threshold) = (series as i64, trigger.threshold);
+    match trigger.operator {
+        Operator::EqualTo => series == threshold,
+        Operator::NotEqualTo => series != threshold,
+        Operator::GreaterThan => series > threshold,
+        Operator::GreaterThanEquals => series >= threshold,
+        Operator::LessThan => series < threshold,
+        Operator::LessThanEquals => series <= threshold,
+        // rejected when the alert is saved
+        Operator::Contains | Operator::NotContains => series >= threshold,
+    }
+}
+
+fn search_request(sql: String, start_time: i64, end_time: i64, size: usize) -> search::Request {
+    search::Request {
+        query: search::Query {
+            sql,
+            from: 0,
+            size,
+            start_time,
+            end_time,
+            sort_by: None,
+            sql_mode: "full".to_string(),
+            query_type: "".to_string(),
+            track_total_hits: false,
+            uses_zo_fn: false,
+            query_context: None,
+            query_fn: None,
+        },
+        aggs: HashMap::new(),
+        encoding: search::RequestEncoding::Empty,
+        timeout: 0,
     }
 }
 
@@ -418,26 +630,7 @@ impl Condition {
 
 async fn build_sql(alert: &Alert, conditions: &[Condition]) -> Result<String, anyhow::Error> {
     let schema = db::schema::get(&alert.org_id, &alert.stream_name, alert.stream_type).await?;
-    let mut wheres = Vec::with_capacity(conditions.len());
-    for cond in conditions.iter() {
-        let data_type = match schema.field_with_name(&cond.column) {
-            Ok(field) => field.data_type(),
-            Err(_) => {
-                return Err(anyhow::anyhow!(
-                    "Column {} not found on stream {}",
-                    &cond.column,
-                    &alert.stream_name
-                ));
-            }
-        };
-        let expr = build_expr(cond, "", data_type)?;
-        wheres.push(expr);
-    }
-    let where_sql = if !wheres.is_empty() {
-        format!("WHERE {}", wheres.join(" AND "))
-    } else {
-        String::new()
-    };
+    let where_sql = build_where(alert, &schema, conditions)?;
     if alert.query_condition.aggregation.is_none() {
         return Ok(format!(
             "SELECT * FROM \"{}\" {}",
@@ -459,15 +652,23 @@ async fn build_sql(alert: &Alert, conditions: &[Condition]) -> Result<String, an
                 ));
             }
         };
+        // count and percentiles don't keep the type of the column
+        let data_type = match agg.function {
+            AggFunction::Count => &DataType::Int64,
+            AggFunction::Median | AggFunction::P50 | AggFunction::P95 | AggFunction::P99 => {
+                &DataType::Float64
+            }
+            _ => data_type,
+        };
         build_expr(&agg.having, "alert_agg_value", data_type)?
     };
+    let agg_expr = agg.function.to_sql(&agg.having.column);
     if let Some(group) = agg.group_by.as_ref() {
         if !group.is_empty() {
             sql = format!(
-                "SELECT {}, {}(\"{}\") AS alert_agg_value, MIN({}) as zo_sql_min_time, MAX({}) AS zo_sql_max_time FROM \"{}\" {} GROUP BY {} HAVING {}",
+                "SELECT {}, {} AS alert_agg_value, MIN({}) as zo_sql_min_time, MAX({}) AS zo_sql_max_time FROM \"{}\" {} GROUP BY {} HAVING {}",
                 group.join(", "),
-                agg.function.to_string(),
-                agg.having.column,
+                agg_expr,
                 CONFIG.common.column_timestamp,
                 CONFIG.common.column_timestamp,
                 alert.stream_name,
@@ -479,9 +680,8 @@ async fn build_sql(alert: &Alert, conditions: &[Condition]) -> Result<String, an
     }
     if sql.is_empty() {
         sql = format!(
-            "SELECT {}(\"{}\") AS alert_agg_value, MIN({}) as zo_sql_min_time, MAX({}) AS zo_sql_max_time FROM \"{}\" {} HAVING {}",
-            agg.function.to_string(),
-            agg.having.column,
+            "SELECT {} AS alert_agg_value, MIN({}) as zo_sql_min_time, MAX({}) AS zo_sql_max_time FROM \"{}\" {} HAVING {}",
+            agg_expr,
             CONFIG.common.column_timestamp,
             CONFIG.common.column_timestamp,
             alert.stream_name,
@@ -492,6 +692,36 @@ async fn build_sql(alert: &Alert, conditions: &[Condition]) -> Result<String, an
     Ok(sql)
 }
 
+/// Builds the WHERE clause from the conditions, empty when there are none.
+fn build_where(
+    alert: &Alert,
+    schema: &Schema,
+    conditions: &[Condition],
+) -> Result<String, anyhow::Error> {
+    let mut wheres = Vec::with_capacity(conditions.len());
+    for cond in conditions.iter() {
+        let data_type = match schema.field_with_name(&cond.column) {
+            Ok(field) => field.data_type(),
+            Err(_) => {
+                return Err(anyhow::anyhow!(
+                    "Column {} not found on stream {}",
+                    &cond.column,
+                    &alert.stream_name
+                ));
+            }
+        };
+        let expr = build_expr(cond, "", data_type)?;
+        wheres.push(expr);
+    }
+    let where_sql = if !wheres.is_empty() {
+        let logical_operator = alert.query_condition.logical_operator.to_string();
+        format!("WHERE ({})", wheres.join(&format!(" {logical_operator} ")))
+    } else {
+        String::new()
+    };
+    Ok(where_sql)
+}
+
 fn build_expr(
     cond: &Condition,
     field_alias: &str,
@@ -648,7 +878,28 @@ pub async fn send_notification(
     alert: &Alert,
     dest: &DestinationWithTemplate,
     rows: &[Map<String, Value>],
+    state: AlertState,
+    fired_at: i64,
 ) -> Result<(), anyhow::Error> {
+    let msg = render_notification(alert, &dest.template.body, rows, state, fired_at)?;
+    if let Err(e) = notification::send(alert, dest, msg.clone(), state).await {
+        if let Err(err) = retries::enqueue(alert, &dest.name, msg, state, &e.to_string()).await {
+            log::error!("Error queueing notification for retry: {}", err);
+        }
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Renders the template body with the variables of the alert and the
+/// matched rows.
+pub fn render_notification(
+    alert: &Alert,
+    body: &Value,
+    rows: &[Map<String, Value>],
+    state: AlertState,
+    fired_at: i64,
+) -> Result<Value, anyhow::Error> {
     // format values
     let alert_count = rows.len();
     let mut vars = HashMap::with_capacity(rows.len());
@@ -711,12 +962,21 @@ pub async fn send_notification(
         String::from("N/A")
     };
 
+    let alert_fired_at = if fired_at > 0 {
+        Local
+            .timestamp_nanos(fired_at * 1000)
+            .format("%Y-%m-%dT%H:%M:%S")
+            .to_string()
+    } else {
+        String::from("N/A")
+    };
+
     let alert_type = if alert.is_real_time {
         "realtime"
     } else {
         "scheduled"
     };
-    let resp = json::to_string(&dest.template.body)?;
+    let resp = json::to_string(body)?;
     let mut resp = resp
         .replace("{org_name}", &alert.org_id)
         .replace("{stream_type}", &alert.stream_type.to_string())
@@ -737,7 +997,9 @@ pub async fn send_notification(
         )
         .replace("{alert_count}", &alert_count.to_string())
         .replace("{alert_start_time}", &alert_start_time)
-        .replace("{alert_end_time}", &alert_end_time);
+        .replace("{alert_end_time}", &alert_end_time)
+        .replace("{alert_state}", &state.to_string())
+        .replace("{alert_fired_at}", &alert_fired_at);
     for (key, value) in vars.iter() {
         if resp.contains(&format!("{{{key}}}")) {
             let val = value.iter().cloned().collect::<Vec<_>>();
@@ -761,36 +1023,7 @@ pub async fn send_notification(
         },
         _ => msg,
     };
-    let client = if dest.skip_tls_verify {
-        reqwest::Client::builder()
-            .danger_accept_invalid_certs(true)
-            .build()?
-    } else {
-        reqwest::Client::new()
-    };
-    let url = url::Url::parse(&dest.url)?;
-    let mut req = match dest.method {
-        HTTPType::POST => client.post(url),
-        HTTPType::PUT => client.put(url),
-        HTTPType::GET => client.get(url),
-    }
-    .header("Content-type", "application/json");
-
-    // Add additional headers if any from destination description
-    if let Some(headers) = &dest.headers {
-        for (key, value) in headers.iter() {
-            if !key.is_empty() && !value.is_empty() {
-                req = req.header(key, value);
-            }
-        }
-    };
-
-    let resp = req.json(&msg).send().await?;
-    if !resp.status().is_success() {
-        return Err(anyhow::anyhow!("sent error: {:?}", resp.bytes().await));
-    }
-
-    Ok(())
+    Ok(msg)
 }
 
 fn format_variable_value(val: &str) -> String {
@@ -798,3 +1031,23 @@ fn format_variable_value(val: &str) -> String {
         .replace('\r', "\\\\r")
         .replace('\"', "\\\\\\\"")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_series_matched() {
+        let trigger = |operator, threshold| TriggerCondition {
+            operator,
+            threshold,
+            ..Default::default()
+        };
+        assert!(series_matched(&trigger(Operator::GreaterThanEquals, 1), 2));
+        assert!(!series_matched(&trigger(Operator::GreaterThanEquals, 1), 0));
+        assert!(series_matched(&trigger(Operator::LessThan, 1), 0));
+        assert!(!series_matched(&trigger(Operator::LessThan, 1), 3));
+        assert!(series_matched(&trigger(Operator::EqualTo, 3), 3));
+        assert!(series_matched(&trigger(Operator::NotEqualTo, 3), 2));
+    }
+}
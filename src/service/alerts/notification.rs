@@ -0,0 +1,472 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+
+use crate::common::{
+    infra::config::{CONFIG, SMTP_CLIENT},
+    meta::alerts::{
+        destinations::{DestinationType, DestinationWithTemplate, HTTPType},
+        triggers::AlertState,
+        Alert,
+    },
+    utils::json::{json, Value},
+};
+
+const PAGERDUTY_EVENTS_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+const OPSGENIE_ALERTS_URL: &str = "https://api.opsgenie.com/v2/alerts";
+const OPSGENIE_MESSAGE_LIMIT: usize = 130;
+const PAGERDUTY_SEVERITIES: [&str; 4] = ["critical", "error", "warning", "info"];
+
+/// Delivers the rendered template to the destination, the payload is shaped
+/// according to the destination type.
+pub async fn send(
+    alert: &Alert,
+    dest: &DestinationWithTemplate,
+    msg: Value,
+    state: AlertState,
+) -> Result<(), anyhow::Error> {
+    match dest.destination_type {
+        DestinationType::Webhook => send_http(dest, &dest.url, &dest.method, &msg, None).await,
+        DestinationType::Slack => {
+            send_http(dest, &dest.url, &HTTPType::POST, &slack_payload(msg), None).await
+        }
+        DestinationType::PagerDutyEventsV2 => {
+            let url = if dest.url.is_empty() {
+                PAGERDUTY_EVENTS_URL
+            } else {
+                dest.url.as_str()
+            };
+            let routing_key = dest.api_key.as_deref().unwrap_or_default();
+            let payload = pagerduty_payload(alert, routing_key, msg, state);
+            send_http(dest, url, &HTTPType::POST, &payload, None).await
+        }
+        DestinationType::Opsgenie => {
+            let base_url = if dest.url.is_empty() {
+                OPSGENIE_ALERTS_URL
+            } else {
+                dest.url.trim_end_matches('/')
+            };
+            let auth = format!("GenieKey {}", dest.api_key.as_deref().unwrap_or_default());
+            let (url, payload) = opsgenie_request(alert, base_url, msg, state);
+            send_http(dest, &url, &HTTPType::POST, &payload, Some(&auth)).await
+        }
+        DestinationType::Email => match SMTP_CLIENT.as_ref() {
+            Some(Ok(client)) => {
+                let email = build_email(
+                    alert,
+                    dest,
+                    msg,
+                    state,
+                    &CONFIG.smtp.smtp_from_email,
+                    &CONFIG.smtp.smtp_reply_to,
+                )?;
+                send_email(client, email).await
+            }
+            Some(Err(e)) => Err(anyhow::anyhow!("Invalid SMTP settings: {e}")),
+            None => Err(anyhow::anyhow!("SMTP is not configured")),
+        },
+    }
+}
+
+async fn send_http(
+    dest: &DestinationWithTemplate,
+    url: &str,
+    method: &HTTPType,
+    payload: &Value,
+    auth: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    let client = if dest.skip_tls_verify {
+        reqwest::Client::builder()
+            .danger_accept_invalid_certs(true)
+            .build()?
+    } else {
+        reqwest::Client::new()
+    };
+    let url = url::Url::parse(url)?;
+    let mut req = match method {
+        HTTPType::POST => client.post(url),
+        HTTPType::PUT => client.put(url),
+        HTTPType::GET => client.get(url),
+    }
+    .header("Content-type", "application/json");
+    if let Some(auth) = auth {
+        req = req.header("Authorization", auth);
+    }
+
+    // Add additional headers if any from destination description
+    if let Some(headers) = &dest.headers {
+        for (key, value) in headers.iter() {
+            if !key.is_empty() && !value.is_empty() {
+                req = req.header(key, value);
+            }
+        }
+    };
+
+    let resp = req.json(payload).send().await?;
+    if !resp.status().is_success() {
+        return Err(anyhow::anyhow!("sent error: {:?}", resp.bytes().await));
+    }
+    Ok(())
+}
+
+async fn send_email(
+    client: &AsyncSmtpTransport<Tokio1Executor>,
+    email: Message,
+) -> Result<(), anyhow::Error> {
+    client
+        .send(email)
+        .await
+        .map_err(|e| anyhow::anyhow!("sent email error: {}", e))?;
+    Ok(())
+}
+
+fn build_email(
+    alert: &Alert,
+    dest: &DestinationWithTemplate,
+    msg: Value,
+    state: AlertState,
+    from: &str,
+    reply_to: &str,
+) -> Result<Message, anyhow::Error> {
+    let mut email = Message::builder()
+        .from(from.parse::<Mailbox>()?)
+        .subject(summary(alert, state));
+    for to in dest.emails.iter() {
+        email = email.to(to.parse::<Mailbox>()?);
+    }
+    if !reply_to.is_empty() {
+        email = email.reply_to(reply_to.parse::<Mailbox>()?);
+    }
+    // a template with plain string body is treated as html
+    let (content_type, body) = match msg {
+        Value::String(v) => (ContentType::TEXT_HTML, v),
+        _ => (ContentType::TEXT_PLAIN, msg.to_string()),
+    };
+    Ok(email.header(content_type).body(body)?)
+}
+
+/// Identifies the alert on the incident management side, so that the resolved
+/// event closes the incident opened by the firing one.
+fn dedup_key(alert: &Alert) -> String {
+    format!(
+        "{}/{}/{}/{}",
+        alert.org_id, alert.stream_type, alert.stream_name, alert.name
+    )
+}
+
+fn summary(alert: &Alert, state: AlertState) -> String {
+    format!(
+        "[{}] {} on {}/{}",
+        state.to_string().to_uppercase(),
+        alert.name,
+        alert.stream_type,
+        alert.stream_name
+    )
+}
+
+fn slack_payload(msg: Value) -> Value {
+    match msg {
+        Value::Object(ref obj)
+            if obj.contains_key("text")
+                || obj.contains_key("blocks")
+                || obj.contains_key("attachments") =>
+        {
+            msg
+        }
+        Value::String(v) => json!({ "text": v }),
+        _ => json!({ "text": msg.to_string() }),
+    }
+}
+
+fn pagerduty_payload(alert: &Alert, routing_key: &str, msg: Value, state: AlertState) -> Value {
+    if state == AlertState::Resolved {
+        return json!({
+            "routing_key": routing_key,
+            "event_action": "resolve",
+            "dedup_key": dedup_key(alert),
+        });
+    }
+    let severity = match msg.get("severity").and_then(|v| v.as_str()) {
+        Some(v) if PAGERDUTY_SEVERITIES.contains(&v) => v.to_string(),
+        _ => "critical".to_string(),
+    };
+    json!({
+        "routing_key": routing_key,
+        "event_action": "trigger",
+        "dedup_key": dedup_key(alert),
+        "payload": {
+            "summary": summary(alert, state),
+            "source": format!("{}/{}", alert.org_id, alert.stream_name),
+            "severity": severity,
+            "custom_details": msg,
+        },
+    })
+}
+
+fn opsgenie_request(
+    alert: &Alert,
+    base_url: &str,
+    msg: Value,
+    state: AlertState,
+) -> (String, Value) {
+    let alias = dedup_key(alert);
+    if state == AlertState::Resolved {
+        let alias = url::form_urlencoded::byte_serialize(alias.as_bytes()).collect::<String>();
+        return (
+            format!("{base_url}/{alias}/close?identifierType=alias"),
+            json!({
+                "source": CONFIG.common.app_name,
+                "note": summary(alert, state),
+            }),
+        );
+    }
+    let message = summary(alert, state)
+        .chars()
+        .take(OPSGENIE_MESSAGE_LIMIT)
+        .collect::<String>();
+    let description = match msg {
+        Value::String(v) => v,
+        _ => msg.to_string(),
+    };
+    (
+        base_url.to_string(),
+        json!({
+            "message": message,
+            "alias": alias,
+            "description": description,
+            "source": CONFIG.common.app_name,
+            "details": {
+                "org_id": alert.org_id,
+                "stream_type": alert.stream_type.to_string(),
+                "stream_name": alert.stream_name,
+                "alert_name": alert.name,
+            },
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use lettre::transport::smtp::client::Tls;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+        sync::oneshot,
+    };
+
+    use super::*;
+    use crate::common::meta::alerts::templates::Template;
+
+    fn test_alert() -> Alert {
+        Alert {
+            name: "high_error_rate".to_string(),
+            org_id: "default".to_string(),
+            stream_name: "k8s".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn test_dest(destination_type: DestinationType, url: &str) -> DestinationWithTemplate {
+        DestinationWithTemplate {
+            name: "test".to_string(),
+            destination_type,
+            url: url.to_string(),
+            method: HTTPType::POST,
+            skip_tls_verify: false,
+            headers: None,
+            api_key: Some("secret".to_string()),
+            emails: vec!["oncall@example.com".to_string()],
+            template: Template::default(),
+        }
+    }
+
+    /// Accepts one HTTP request, replies with 200 and hands over the request.
+    async fn http_stand_in() -> (String, oneshot::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = Vec::new();
+            let mut chunk = [0u8; 4096];
+            loop {
+                let n = stream.read(&mut chunk).await.unwrap();
+                buf.extend_from_slice(&chunk[..n]);
+                let req = String::from_utf8_lossy(&buf).to_string();
+                if let Some(pos) = req.find("\r\n\r\n") {
+                    let content_length = req[..pos]
+                        .lines()
+                        .find_map(|l| {
+                            l.to_lowercase()
+                                .strip_prefix("content-length:")
+                                .map(|v| v.trim().parse::<usize>().unwrap())
+                        })
+                        .unwrap_or_default();
+                    if buf.len() >= pos + 4 + content_length || n == 0 {
+                        break;
+                    }
+                }
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            tx.send(String::from_utf8_lossy(&buf).to_string()).unwrap();
+        });
+        (format!("http://{addr}/"), rx)
+    }
+
+    /// Speaks just enough SMTP to accept one message and hands over its data.
+    async fn smtp_stand_in() -> (u16, oneshot::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+            let mut tx = Some(tx);
+            while let Ok(Some(line)) = lines.next_line().await {
+                let cmd = line.to_uppercase();
+                if cmd.starts_with("EHLO") || cmd.starts_with("HELO") {
+                    writer.write_all(b"250 localhost\r\n").await.unwrap();
+                } else if cmd.starts_with("DATA") {
+                    writer.write_all(b"354 go ahead\r\n").await.unwrap();
+                    let mut data = Vec::new();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        if line == "." {
+                            break;
+                        }
+                        data.push(line);
+                    }
+                    writer.write_all(b"250 OK\r\n").await.unwrap();
+                    if let Some(tx) = tx.take() {
+                        tx.send(data.join("\n")).unwrap();
+                    }
+                } else if cmd.starts_with("QUIT") {
+                    writer.write_all(b"221 bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    writer.write_all(b"250 OK\r\n").await.unwrap();
+                }
+            }
+        });
+        (port, rx)
+    }
+
+    #[test]
+    fn test_slack_payload() {
+        assert_eq!(
+            slack_payload(json!("disk is full")),
+            json!({"text": "disk is full"})
+        );
+        let blocks = json!({"blocks": [{"type": "section"}]});
+        assert_eq!(slack_payload(blocks.clone()), blocks);
+        assert_eq!(
+            slack_payload(json!({"count": 3})),
+            json!({"text": "{\"count\":3}"})
+        );
+    }
+
+    #[test]
+    fn test_pagerduty_payload() {
+        let alert = test_alert();
+        let payload = pagerduty_payload(
+            &alert,
+            "key",
+            json!({"severity": "warning"}),
+            AlertState::Firing,
+        );
+        assert_eq!(payload["event_action"], "trigger");
+        assert_eq!(payload["dedup_key"], "default/logs/k8s/high_error_rate");
+        assert_eq!(payload["payload"]["severity"], "warning");
+
+        let payload = pagerduty_payload(&alert, "key", json!({}), AlertState::Resolved);
+        assert_eq!(payload["event_action"], "resolve");
+        assert_eq!(payload["dedup_key"], "default/logs/k8s/high_error_rate");
+        assert!(payload.get("payload").is_none());
+    }
+
+    #[test]
+    fn test_opsgenie_request() {
+        let alert = test_alert();
+        let (url, payload) = opsgenie_request(
+            &alert,
+            OPSGENIE_ALERTS_URL,
+            json!("boom"),
+            AlertState::Firing,
+        );
+        assert_eq!(url, OPSGENIE_ALERTS_URL);
+        assert_eq!(payload["alias"], "default/logs/k8s/high_error_rate");
+        assert_eq!(payload["description"], "boom");
+
+        let (url, _) = opsgenie_request(
+            &alert,
+            OPSGENIE_ALERTS_URL,
+            json!("boom"),
+            AlertState::Resolved,
+        );
+        assert_eq!(
+            url,
+            format!(
+                "{OPSGENIE_ALERTS_URL}/default%2Flogs%2Fk8s%2Fhigh_error_rate/close?identifierType=alias"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_pagerduty() {
+        let (url, rx) = http_stand_in().await;
+        let alert = test_alert();
+        let dest = test_dest(DestinationType::PagerDutyEventsV2, &url);
+        send(&alert, &dest, json!({"error": "boom"}), AlertState::Firing)
+            .await
+            .unwrap();
+        let req = rx.await.unwrap();
+        assert!(req.starts_with("POST / HTTP/1.1"));
+        assert!(req.contains(r#""routing_key":"secret""#));
+        assert!(req.contains(r#""event_action":"trigger""#));
+    }
+
+    #[tokio::test]
+    async fn test_send_email() {
+        let (port, rx) = smtp_stand_in().await;
+        let client = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous("127.0.0.1")
+            .port(port)
+            .tls(Tls::None)
+            .build();
+        let alert = test_alert();
+        let dest = test_dest(DestinationType::Email, "");
+        let email = build_email(
+            &alert,
+            &dest,
+            json!("<b>disk is full</b>"),
+            AlertState::Firing,
+            "alerts@example.com",
+            "",
+        )
+        .unwrap();
+        send_email(&client, email).await.unwrap();
+        let data = rx.await.unwrap();
+        assert!(data.contains("To: oncall@example.com"));
+        assert!(data.contains("Subject: [FIRING] high_error_rate on logs/k8s"));
+        assert!(data.contains("<b>disk is full</b>"));
+    }
+}
@@ -0,0 +1,140 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use chrono::Duration;
+
+use super::{
+    alert_manager::{self, Decision},
+    destinations, render_notification,
+};
+use crate::{
+    common::meta::{
+        alerts::{
+            preview::{AlertPreview, PreviewInterval},
+            triggers::Trigger,
+            Alert, Operator, QueryType,
+        },
+        StreamType,
+    },
+    service::db,
+};
+
+/// Upper bound of the evaluations a single preview runs.
+const MAX_PREVIEW_INTERVALS: i64 = 500;
+
+/// Replays the alert over a historical time range without touching the
+/// triggers or sending any notification, the decisions are the ones of the
+/// alert manager, including the maintenance windows at the evaluation times.
+pub async fn preview(
+    org_id: &str,
+    stream_type: StreamType,
+    stream_name: &str,
+    mut alert: Alert,
+    time_range: (i64, i64),
+    template: Option<&str>,
+) -> Result<AlertPreview, anyhow::Error> {
+    alert.org_id = org_id.to_string();
+    alert.stream_type = stream_type;
+    alert.stream_name = stream_name.to_string();
+    let (start_time, end_time) = time_range;
+    if start_time >= end_time {
+        return Err(anyhow::anyhow!("start_time should be less than end_time"));
+    }
+
+    // same as saving the alert
+    if (alert.query_condition.query_type == QueryType::Custom
+        && alert.query_condition.aggregation.is_some())
+        || alert.query_condition.query_type == QueryType::Anomaly
+    {
+        alert.trigger_condition.operator = Operator::GreaterThanEquals;
+        alert.trigger_condition.threshold = 1;
+    }
+    let period = std::cmp::max(1, alert.trigger_condition.period);
+    alert.trigger_condition.frequency = std::cmp::max(
+        1,
+        period / std::cmp::max(1, alert.trigger_condition.threshold),
+    );
+    let period = Duration::minutes(period).num_microseconds().unwrap();
+    let frequency = Duration::minutes(alert.trigger_condition.frequency)
+        .num_microseconds()
+        .unwrap();
+    if (end_time - start_time - period) / frequency >= MAX_PREVIEW_INTERVALS {
+        return Err(anyhow::anyhow!(
+            "Preview is limited to {MAX_PREVIEW_INTERVALS} evaluations, please narrow the time range"
+        ));
+    }
+
+    let query = match alert.query_condition.build_query(&alert, end_time).await? {
+        Some(query) => query,
+        None => return Err(anyhow::anyhow!("Alert should have conditions")),
+    };
+    let template = match template {
+        Some(name) => Some(db::alerts::templates::get(org_id, name).await?),
+        None => match alert.destinations.first() {
+            Some(name) => destinations::get_with_template(org_id, name)
+                .await
+                .ok()
+                .map(|dest| dest.template),
+            None => None,
+        },
+    };
+
+    let mut trigger = Trigger::default();
+    let mut intervals = Vec::new();
+    let mut notification = None;
+    let mut now = start_time + period;
+    while now <= end_time {
+        let ret = alert
+            .query_condition
+            .evaluate_window(&alert, now - period, now)
+            .await?;
+        let matched_rows = ret.as_ref().map(|v| v.len()).unwrap_or_default();
+        let Decision {
+            state,
+            notify,
+            rows,
+            maintenance_window,
+        } = alert_manager::decide(&alert, &mut trigger, ret, now);
+        if notify && notification.is_none() {
+            if let Some(template) = template.as_ref() {
+                notification = Some(render_notification(
+                    &alert,
+                    &template.body,
+                    &rows,
+                    state,
+                    trigger.fired_at,
+                )?);
+            }
+        }
+        intervals.push(PreviewInterval {
+            start_time: now - period,
+            end_time: now,
+            matched_rows,
+            state,
+            notify,
+            maintenance_window,
+        });
+        now = trigger.next_run_at;
+    }
+
+    Ok(AlertPreview {
+        query,
+        start_time,
+        end_time,
+        intervals,
+        template: template.map(|t| t.name).unwrap_or_default(),
+        notification,
+    })
+}
@@ -0,0 +1,194 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use actix_web::http;
+use chrono::{Duration, Utc};
+
+use super::{destinations, notification};
+use crate::{
+    common::{
+        infra::{cluster::LOCAL_NODE_UUID, config::CONFIG, ider},
+        meta::alerts::{retries::FailedNotification, triggers::AlertState, Alert},
+        utils::json::Value,
+    },
+    service::db,
+};
+
+/// Upper bound of the delay between two delivery attempts, in seconds.
+const MAX_BACKOFF: i64 = 3600;
+
+/// Queues a notification which failed to deliver so that it is retried later,
+/// the first attempt is the one which already failed.
+pub async fn enqueue(
+    alert: &Alert,
+    destination: &str,
+    payload: Value,
+    state: AlertState,
+    error: &str,
+) -> Result<(), anyhow::Error> {
+    let now = Utc::now().timestamp_micros();
+    let item = FailedNotification {
+        id: ider::generate(),
+        org_id: alert.org_id.clone(),
+        stream_type: alert.stream_type,
+        stream_name: alert.stream_name.clone(),
+        alert_name: alert.name.clone(),
+        destination: destination.to_string(),
+        payload,
+        state,
+        attempts: 1,
+        next_retry_at: now + backoff(CONFIG.limit.alert_notification_retry_interval, 1),
+        created_at: now,
+        last_error: error.to_string(),
+    };
+    if item.attempts >= CONFIG.limit.alert_notification_max_attempts {
+        return db::alerts::retries::set_dead_letter(&item).await;
+    }
+    db::alerts::retries::set_retry(&item).await
+}
+
+/// Retries the due notifications, it only runs on the node which holds the
+/// alert manager mark.
+pub async fn run() -> Result<(), anyhow::Error> {
+    let node = db::alerts::alert_manager::get_mark("default").await;
+    if LOCAL_NODE_UUID.ne(&node) {
+        return Ok(());
+    }
+
+    let now = Utc::now().timestamp_micros();
+    for mut item in db::alerts::retries::list_retries().await? {
+        if item.next_retry_at > now {
+            continue;
+        }
+        match deliver(&item).await {
+            Ok(_) => {
+                db::alerts::retries::delete_retry(&item.org_id, &item.id).await?;
+            }
+            Err(e) => {
+                log::error!(
+                    "[ALERT_MANAGER] retry notification {}/{}/{} to {} attempt {} err: {}",
+                    item.org_id,
+                    item.stream_name,
+                    item.alert_name,
+                    item.destination,
+                    item.attempts + 1,
+                    e
+                );
+                item.attempts += 1;
+                item.last_error = e.to_string();
+                if item.attempts >= CONFIG.limit.alert_notification_max_attempts {
+                    db::alerts::retries::set_dead_letter(&item).await?;
+                    db::alerts::retries::delete_retry(&item.org_id, &item.id).await?;
+                } else {
+                    item.next_retry_at = Utc::now().timestamp_micros()
+                        + backoff(
+                            CONFIG.limit.alert_notification_retry_interval,
+                            item.attempts,
+                        );
+                    db::alerts::retries::set_retry(&item).await?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn list_dead_letters(org_id: &str) -> Result<Vec<FailedNotification>, anyhow::Error> {
+    db::alerts::retries::list_dead_letters(org_id).await
+}
+
+/// Sends a dead letter again, it is removed once delivered.
+pub async fn replay(org_id: &str, id: &str) -> Result<(), (http::StatusCode, anyhow::Error)> {
+    let mut item = match db::alerts::retries::get_dead_letter(org_id, id).await {
+        Ok(item) => item,
+        Err(_) => {
+            return Err((
+                http::StatusCode::NOT_FOUND,
+                anyhow::anyhow!("Dead letter not found"),
+            ));
+        }
+    };
+    if let Err(e) = deliver(&item).await {
+        item.attempts += 1;
+        item.last_error = e.to_string();
+        _ = db::alerts::retries::set_dead_letter(&item).await;
+        return Err((http::StatusCode::INTERNAL_SERVER_ERROR, e));
+    }
+    db::alerts::retries::delete_dead_letter(org_id, id)
+        .await
+        .map_err(|e| (http::StatusCode::INTERNAL_SERVER_ERROR, e))
+}
+
+pub async fn delete_dead_letter(
+    org_id: &str,
+    id: &str,
+) -> Result<(), (http::StatusCode, anyhow::Error)> {
+    if db::alerts::retries::get_dead_letter(org_id, id)
+        .await
+        .is_err()
+    {
+        return Err((
+            http::StatusCode::NOT_FOUND,
+            anyhow::anyhow!("Dead letter not found"),
+        ));
+    }
+    db::alerts::retries::delete_dead_letter(org_id, id)
+        .await
+        .map_err(|e| (http::StatusCode::INTERNAL_SERVER_ERROR, e))
+}
+
+async fn deliver(item: &FailedNotification) -> Result<(), anyhow::Error> {
+    let dest = destinations::get_with_template(&item.org_id, &item.destination).await?;
+    let alert = match db::alerts::get(
+        &item.org_id,
+        item.stream_type,
+        &item.stream_name,
+        &item.alert_name,
+    )
+    .await
+    {
+        Ok(Some(alert)) => alert,
+        _ => Alert {
+            name: item.alert_name.clone(),
+            org_id: item.org_id.clone(),
+            stream_type: item.stream_type,
+            stream_name: item.stream_name.clone(),
+            ..Default::default()
+        },
+    };
+    notification::send(&alert, &dest, item.payload.clone(), item.state).await
+}
+
+/// Exponential backoff based on the number of attempts already made, in
+/// microseconds.
+fn backoff(base_secs: i64, attempts: u32) -> i64 {
+    let exp = attempts.saturating_sub(1).min(16);
+    let secs = base_secs.max(1).saturating_mul(1 << exp).min(MAX_BACKOFF);
+    Duration::seconds(secs).num_microseconds().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(30, 1), 30_000_000);
+        assert_eq!(backoff(30, 2), 60_000_000);
+        assert_eq!(backoff(30, 3), 120_000_000);
+        assert_eq!(backoff(30, 20), MAX_BACKOFF * 1_000_000);
+        assert_eq!(backoff(0, 1), 1_000_000);
+    }
+}
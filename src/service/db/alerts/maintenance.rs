@@ -0,0 +1,126 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::sync::Arc;
+
+use itertools::Itertools;
+
+use crate::common::{
+    infra::{config::MAINTENANCE_WINDOWS, db as infra_db},
+    meta::alerts::maintenance::MaintenanceWindow,
+    utils::json,
+};
+
+pub async fn get(org_id: &str, name: &str) -> Result<MaintenanceWindow, anyhow::Error> {
+    let map_key = format!("{org_id}/{name}");
+    if let Some(v) = MAINTENANCE_WINDOWS.get(&map_key) {
+        return Ok(v.value().clone());
+    }
+    let db = infra_db::get_db().await;
+    let key = format!("/maintenance_windows/{org_id}/{name}");
+    Ok(json::from_slice(&db.get(&key).await?)?)
+}
+
+pub async fn set(
+    org_id: &str,
+    name: &str,
+    window: &MaintenanceWindow,
+) -> Result<(), anyhow::Error> {
+    let db = infra_db::get_db().await;
+    let key = format!("/maintenance_windows/{org_id}/{name}");
+    Ok(db
+        .put(
+            &key,
+            json::to_vec(window).unwrap().into(),
+            infra_db::NEED_WATCH,
+        )
+        .await?)
+}
+
+pub async fn delete(org_id: &str, name: &str) -> Result<(), anyhow::Error> {
+    let db = infra_db::get_db().await;
+    let key = format!("/maintenance_windows/{org_id}/{name}");
+    Ok(db.delete(&key, false, infra_db::NEED_WATCH).await?)
+}
+
+pub async fn list(org_id: &str) -> Result<Vec<MaintenanceWindow>, anyhow::Error> {
+    let cache = MAINTENANCE_WINDOWS.clone();
+    if !cache.is_empty() {
+        return Ok(cache
+            .iter()
+            .filter_map(|window| {
+                window
+                    .key()
+                    .starts_with(&format!("{org_id}/"))
+                    .then(|| window.value().clone())
+            })
+            .sorted_by(|a, b| a.name.cmp(&b.name))
+            .collect());
+    }
+
+    let db = infra_db::get_db().await;
+    let key = format!("/maintenance_windows/{org_id}/");
+    let ret = db.list_values(key.as_str()).await?;
+    let mut items = Vec::new();
+    for item_value in ret {
+        let json_val: MaintenanceWindow = json::from_slice(&item_value).unwrap();
+        items.push(json_val);
+    }
+    items.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(items)
+}
+
+pub async fn watch() -> Result<(), anyhow::Error> {
+    let key = "/maintenance_windows/";
+    let cluster_coordinator = infra_db::get_coordinator().await;
+    let mut events = cluster_coordinator.watch(key).await?;
+    let events = Arc::get_mut(&mut events).unwrap();
+    log::info!("Start watching maintenance windows");
+    loop {
+        let ev = match events.recv().await {
+            Some(ev) => ev,
+            None => {
+                log::error!("watch_maintenance_windows: event channel closed");
+                break;
+            }
+        };
+        match ev {
+            infra_db::Event::Put(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                let item_value: MaintenanceWindow = json::from_slice(&ev.value.unwrap()).unwrap();
+                MAINTENANCE_WINDOWS.insert(item_key.to_owned(), item_value);
+            }
+            infra_db::Event::Delete(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                MAINTENANCE_WINDOWS.remove(item_key);
+            }
+            infra_db::Event::Empty => {}
+        }
+    }
+    Ok(())
+}
+
+pub async fn cache() -> Result<(), anyhow::Error> {
+    let db = infra_db::get_db().await;
+    let key = "/maintenance_windows/";
+    let ret = db.list(key).await?;
+    for (item_key, item_value) in ret {
+        let item_key = item_key.strip_prefix(key).unwrap();
+        let json_val: MaintenanceWindow = json::from_slice(&item_value).unwrap();
+        MAINTENANCE_WINDOWS.insert(item_key.to_owned(), json_val);
+    }
+    log::info!("Maintenance windows Cached");
+    Ok(())
+}
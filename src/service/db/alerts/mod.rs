@@ -18,7 +18,7 @@ use std::sync::Arc;
 use crate::common::{
     infra::{
         cluster::{is_alert_manager, LOCAL_NODE_ROLE},
-        config::STREAM_ALERTS,
+        config::{STREAM_ALERTS, TRIGGERS},
         db as infra_db,
     },
     meta::{
@@ -30,6 +30,8 @@ use crate::common::{
 
 pub mod alert_manager;
 pub mod destinations;
+pub mod maintenance;
+pub mod retries;
 pub mod templates;
 pub mod triggers;
 
@@ -151,10 +153,13 @@ pub async fn watch() -> Result<(), anyhow::Error> {
                     let stream_type: StreamType = columns[1].into();
                     let stream_name = columns[2];
                     let alert_name = columns[3];
+                    // keep the alert state when the alert is updated
+                    let old_trigger = TRIGGERS.read().await.get(item_key).cloned();
                     let trigger = Trigger {
                         next_run_at: chrono::Utc::now().timestamp_micros(),
                         is_realtime,
                         is_silenced: false,
+                        ..old_trigger.unwrap_or_default()
                     };
                     if let Err(e) = crate::service::alerts::triggers::save(
                         org_id,
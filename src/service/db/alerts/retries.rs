@@ -0,0 +1,80 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::common::{
+    infra::db as infra_db, meta::alerts::retries::FailedNotification, utils::json,
+};
+
+const RETRY_KEY: &str = "/notifications/retry/";
+const DEAD_LETTER_KEY: &str = "/notifications/dead_letter/";
+
+pub async fn set_retry(item: &FailedNotification) -> Result<(), anyhow::Error> {
+    put(RETRY_KEY, item).await
+}
+
+pub async fn delete_retry(org_id: &str, id: &str) -> Result<(), anyhow::Error> {
+    delete(RETRY_KEY, org_id, id).await
+}
+
+pub async fn list_retries() -> Result<Vec<FailedNotification>, anyhow::Error> {
+    list(RETRY_KEY).await
+}
+
+pub async fn get_dead_letter(org_id: &str, id: &str) -> Result<FailedNotification, anyhow::Error> {
+    let db = infra_db::get_db().await;
+    let key = format!("{DEAD_LETTER_KEY}{org_id}/{id}");
+    let val = db.get(&key).await?;
+    Ok(json::from_slice(&val)?)
+}
+
+pub async fn set_dead_letter(item: &FailedNotification) -> Result<(), anyhow::Error> {
+    put(DEAD_LETTER_KEY, item).await
+}
+
+pub async fn delete_dead_letter(org_id: &str, id: &str) -> Result<(), anyhow::Error> {
+    delete(DEAD_LETTER_KEY, org_id, id).await
+}
+
+pub async fn list_dead_letters(org_id: &str) -> Result<Vec<FailedNotification>, anyhow::Error> {
+    list(&format!("{DEAD_LETTER_KEY}{org_id}/")).await
+}
+
+async fn put(prefix: &str, item: &FailedNotification) -> Result<(), anyhow::Error> {
+    let db = infra_db::get_db().await;
+    let key = format!("{prefix}{}/{}", item.org_id, item.id);
+    Ok(db
+        .put(
+            &key,
+            json::to_vec(item).unwrap().into(),
+            infra_db::NO_NEED_WATCH,
+        )
+        .await?)
+}
+
+async fn delete(prefix: &str, org_id: &str, id: &str) -> Result<(), anyhow::Error> {
+    let db = infra_db::get_db().await;
+    let key = format!("{prefix}{org_id}/{id}");
+    Ok(db.delete(&key, false, infra_db::NO_NEED_WATCH).await?)
+}
+
+async fn list(prefix: &str) -> Result<Vec<FailedNotification>, anyhow::Error> {
+    let db = infra_db::get_db().await;
+    let mut items = Vec::new();
+    for item_value in db.list_values(prefix).await? {
+        items.push(json::from_slice(&item_value)?);
+    }
+    items.sort_by(|a: &FailedNotification, b| a.created_at.cmp(&b.created_at));
+    Ok(items)
+}
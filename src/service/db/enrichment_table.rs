@@ -13,14 +13,15 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
+use itertools::Itertools;
 use vrl::prelude::NotNan;
 
 use crate::{
     common::{
-        infra::cache::stats,
-        meta::{self, search::Request},
+        infra::{cache::stats, config::ENRICHMENT_TABLE_SCHEDULES, db as infra_db},
+        meta::{self, enrichment_table::ScheduledEnrichmentTable, search::Request},
         utils::json,
     },
     service::search as SearchService,
@@ -63,6 +64,112 @@ pub async fn get(org_id: &str, name: &str) -> Result<Vec<vrl::value::Value>, any
     }
 }
 
+pub async fn get_schedule(
+    org_id: &str,
+    name: &str,
+) -> Result<ScheduledEnrichmentTable, anyhow::Error> {
+    let map_key = format!("{org_id}/{name}");
+    if let Some(v) = ENRICHMENT_TABLE_SCHEDULES.get(&map_key) {
+        return Ok(v.value().clone());
+    }
+    let db = infra_db::get_db().await;
+    let key = format!("/enrichment_table_schedules/{org_id}/{name}");
+    Ok(json::from_slice(&db.get(&key).await?)?)
+}
+
+pub async fn set_schedule(
+    org_id: &str,
+    name: &str,
+    table: &ScheduledEnrichmentTable,
+) -> Result<(), anyhow::Error> {
+    let db = infra_db::get_db().await;
+    let key = format!("/enrichment_table_schedules/{org_id}/{name}");
+    Ok(db
+        .put(
+            &key,
+            json::to_vec(table).unwrap().into(),
+            infra_db::NEED_WATCH,
+        )
+        .await?)
+}
+
+pub async fn delete_schedule(org_id: &str, name: &str) -> Result<(), anyhow::Error> {
+    let db = infra_db::get_db().await;
+    let key = format!("/enrichment_table_schedules/{org_id}/{name}");
+    Ok(db.delete(&key, false, infra_db::NEED_WATCH).await?)
+}
+
+pub async fn list_schedules(org_id: &str) -> Result<Vec<ScheduledEnrichmentTable>, anyhow::Error> {
+    let cache = ENRICHMENT_TABLE_SCHEDULES.clone();
+    if !cache.is_empty() {
+        return Ok(cache
+            .iter()
+            .filter_map(|table| {
+                table
+                    .key()
+                    .starts_with(&format!("{org_id}/"))
+                    .then(|| table.value().clone())
+            })
+            .sorted_by(|a, b| a.name.cmp(&b.name))
+            .collect());
+    }
+
+    let db = infra_db::get_db().await;
+    let key = format!("/enrichment_table_schedules/{org_id}/");
+    let ret = db.list_values(key.as_str()).await?;
+    let mut items = Vec::new();
+    for item_value in ret {
+        let json_val: ScheduledEnrichmentTable = json::from_slice(&item_value).unwrap();
+        items.push(json_val);
+    }
+    items.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(items)
+}
+
+pub async fn watch() -> Result<(), anyhow::Error> {
+    let key = "/enrichment_table_schedules/";
+    let cluster_coordinator = infra_db::get_coordinator().await;
+    let mut events = cluster_coordinator.watch(key).await?;
+    let events = Arc::get_mut(&mut events).unwrap();
+    log::info!("Start watching scheduled enrichment tables");
+    loop {
+        let ev = match events.recv().await {
+            Some(ev) => ev,
+            None => {
+                log::error!("watch_enrichment_table_schedules: event channel closed");
+                break;
+            }
+        };
+        match ev {
+            infra_db::Event::Put(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                let item_value: ScheduledEnrichmentTable =
+                    json::from_slice(&ev.value.unwrap()).unwrap();
+                ENRICHMENT_TABLE_SCHEDULES.insert(item_key.to_owned(), item_value);
+            }
+            infra_db::Event::Delete(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                ENRICHMENT_TABLE_SCHEDULES.remove(item_key);
+            }
+            infra_db::Event::Empty => {}
+        }
+    }
+    Ok(())
+}
+
+pub async fn cache() -> Result<(), anyhow::Error> {
+    let db = infra_db::get_db().await;
+    let key = "/enrichment_table_schedules/";
+    let ret = db.list(key).await?;
+    for (item_key, item_value) in ret {
+        let item_key = item_key.strip_prefix(key).unwrap();
+        let json_val: ScheduledEnrichmentTable = json::from_slice(&item_value).unwrap();
+        ENRICHMENT_TABLE_SCHEDULES.insert(item_key.to_owned(), json_val);
+    }
+    log::info!("Scheduled enrichment tables Cached");
+    Ok(())
+}
+
 fn convert_to_vrl(value: &json::Value) -> vrl::value::Value {
     match value {
         json::Value::Null => vrl::value::Value::Null,
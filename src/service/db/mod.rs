@@ -24,8 +24,10 @@ pub mod functions;
 pub mod kv;
 pub mod metrics;
 pub mod organization;
+pub mod rules;
 pub mod saved_view;
 pub mod schema;
+pub mod service_graph;
 pub mod syslog;
 pub mod user;
 pub mod version;
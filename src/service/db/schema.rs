@@ -18,6 +18,7 @@ use std::sync::Arc;
 use ahash::AHashSet;
 use chrono::Utc;
 use datafusion::arrow::datatypes::Schema;
+use vector_enrichment::Table;
 
 use crate::{
     common::{
@@ -327,16 +328,10 @@ pub async fn watch() -> Result<(), anyhow::Error> {
                 let stream_name = keys[2];
 
                 if stream_type.eq(&StreamType::EnrichmentTables) {
-                    ENRICHMENT_TABLES.insert(
-                        item_key.to_owned(),
-                        StreamTable {
-                            org_id: org_id.to_string(),
-                            stream_name: stream_name.to_string(),
-                            data: super::enrichment_table::get(org_id, stream_name)
-                                .await
-                                .unwrap(),
-                        },
-                    );
+                    let table = load_enrichment_table(item_key, org_id, stream_name)
+                        .await
+                        .unwrap();
+                    ENRICHMENT_TABLES.insert(item_key.to_owned(), table);
                 }
             }
             infra_db::Event::Delete(ev) => {
@@ -423,19 +418,30 @@ pub async fn cache_enrichment_tables() -> Result<(), anyhow::Error> {
         if !stream_type.eq(&StreamType::EnrichmentTables) {
             continue;
         }
-        ENRICHMENT_TABLES.insert(
-            schema_key.to_owned(),
-            StreamTable {
-                org_id: org_id.to_string(),
-                stream_name: stream_name.to_string(),
-                data: super::enrichment_table::get(org_id, stream_name).await?,
-            },
-        );
+        let table = load_enrichment_table(schema_key, org_id, stream_name).await?;
+        ENRICHMENT_TABLES.insert(schema_key.to_owned(), table);
     }
     log::info!("EnrichmentTables Cached");
     Ok(())
 }
 
+/// Loads the enrichment table, with the indexes requested from its cached
+/// version, so that the lookups of the VRL functions stay indexed.
+async fn load_enrichment_table(
+    key: &str,
+    org_id: &str,
+    stream_name: &str,
+) -> Result<StreamTable, anyhow::Error> {
+    let data = super::enrichment_table::get(org_id, stream_name).await?;
+    let table = StreamTable::new(org_id, stream_name, data);
+    let index_fields = ENRICHMENT_TABLES
+        .get(key)
+        .map(|table| table.index_fields())
+        .unwrap_or_default();
+    table.build_indexes(&index_fields);
+    Ok(table)
+}
+
 pub fn filter_schema_version_id(schemas: &[Schema], _start_dt: i64, end_dt: i64) -> Option<usize> {
     for (i, schema) in schemas.iter().enumerate() {
         let metadata = schema.metadata();
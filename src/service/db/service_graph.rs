@@ -0,0 +1,41 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::common::infra::db as infra_db;
+
+fn mk_key(org_id: &str) -> String {
+    format!("/service_graph/offset/{org_id}")
+}
+
+/// Returns the end of the last window of the organization whose service graph
+/// was materialized, in microseconds, or 0.
+pub async fn get_offset(org_id: &str) -> i64 {
+    let db = infra_db::get_db().await;
+    match db.get(&mk_key(org_id)).await {
+        Ok(ret) => String::from_utf8_lossy(&ret).parse().unwrap_or_default(),
+        Err(_) => 0,
+    }
+}
+
+pub async fn set_offset(org_id: &str, offset: i64) -> Result<(), anyhow::Error> {
+    let db = infra_db::get_db().await;
+    db.put(
+        &mk_key(org_id),
+        offset.to_string().into(),
+        infra_db::NO_NEED_WATCH,
+    )
+    .await
+    .map_err(Into::into)
+}
@@ -1,8 +1,13 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap},
+    hash::Hasher,
+    sync::Arc,
+};
 
 use async_trait::async_trait;
-use vector_enrichment::{Case, IndexHandle, Table};
-use vrl::value::Value;
+use parking_lot::RwLock;
+use vector_enrichment::{Case, Condition, IndexHandle, Table};
+use vrl::value::{ObjectMap, Value};
 
 use crate::common::utils::time::parse_str_to_time;
 
@@ -13,9 +18,128 @@ pub struct StreamTableConfig {}
 pub struct StreamTable {
     pub org_id: String,
     pub stream_name: String,
-    pub data: Vec<vrl::value::Value>,
+    pub data: Arc<Vec<Value>>,
+    /// The indexes requested by the VRL functions, shared by the clones of the
+    /// table. An index handle is the position of its index.
+    indexes: Arc<RwLock<Vec<Arc<TableIndex>>>>,
+}
+
+/// The rows of the table, keyed by the hash of the values of the indexed
+/// fields. The rows found are checked against the conditions, as the hashes
+/// may collide.
+#[derive(Debug)]
+struct TableIndex {
+    case: Case,
+    fields: Vec<String>,
+    rows: HashMap<u64, Vec<usize>>,
+}
+
+impl TableIndex {
+    fn new(data: &[Value], case: Case, fields: Vec<String>) -> Self {
+        let mut rows: HashMap<u64, Vec<usize>> = HashMap::new();
+        for (i, row) in data.iter().enumerate() {
+            let Value::Object(row) = row else {
+                continue;
+            };
+            let values = fields
+                .iter()
+                .map(|field| row.get(field.as_str()))
+                .collect::<Option<Vec<_>>>();
+            // a row missing an indexed field never matches
+            if let Some(values) = values {
+                rows.entry(hash_values(case, values.into_iter()))
+                    .or_default()
+                    .push(i);
+            }
+        }
+        Self { case, fields, rows }
+    }
+
+    /// Returns the candidate rows for the conditions, `None` when they don't
+    /// set all the indexed fields.
+    fn lookup(&self, conditions: &[Condition]) -> Option<&[usize]> {
+        let values = self
+            .fields
+            .iter()
+            .map(|field| {
+                conditions.iter().find_map(|cond| match cond {
+                    Condition::Equals { field: f, value } if *f == field.as_str() => Some(value),
+                    _ => None,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(
+            self.rows
+                .get(&hash_values(self.case, values.into_iter()))
+                .map_or(&[], |rows| rows.as_slice()),
+        )
+    }
+}
+
+impl StreamTable {
+    pub fn new(org_id: &str, stream_name: &str, data: Vec<Value>) -> Self {
+        Self {
+            org_id: org_id.to_string(),
+            stream_name: stream_name.to_string(),
+            data: Arc::new(data),
+            indexes: Arc::default(),
+        }
+    }
+
+    /// Builds the indexes, eg: the ones requested from the previous version of
+    /// the table, before the table is used.
+    pub fn build_indexes(&self, index_fields: &[(Case, Vec<String>)]) {
+        for (case, fields) in index_fields {
+            let fields = fields.iter().map(|f| f.as_str()).collect::<Vec<_>>();
+            self.get_or_build_index(*case, &fields);
+        }
+    }
+
+    fn get_or_build_index(&self, case: Case, fields: &[&str]) -> IndexHandle {
+        let position = |indexes: &[Arc<TableIndex>]| {
+            indexes
+                .iter()
+                .position(|index| index.case == case && index.fields == fields)
+        };
+        if let Some(i) = position(&self.indexes.read()) {
+            return IndexHandle(i);
+        }
+        let index = TableIndex::new(
+            &self.data,
+            case,
+            fields.iter().map(|f| f.to_string()).collect(),
+        );
+        let mut indexes = self.indexes.write();
+        // another clone of the table may have built it meanwhile
+        if let Some(i) = position(&indexes) {
+            return IndexHandle(i);
+        }
+        indexes.push(Arc::new(index));
+        IndexHandle(indexes.len() - 1)
+    }
+
+    fn get_data(
+        &self,
+        case: Case,
+        conditions: &[Condition],
+        select: Option<&[String]>,
+        index: Option<IndexHandle>,
+    ) -> Vec<BTreeMap<String, Value>> {
+        let index = index.and_then(|IndexHandle(i)| self.indexes.read().get(i).cloned());
+        let candidates = index.as_ref().and_then(|index| index.lookup(conditions));
+        let rows: Box<dyn Iterator<Item = &Value> + '_> = match candidates {
+            Some(candidates) => Box::new(candidates.iter().map(|i| &self.data[*i])),
+            None => Box::new(self.data.iter()),
+        };
+        rows.filter_map(|row| match row {
+            Value::Object(row) if matches(row, case, conditions) => {
+                Some(select_fields(row, select))
+            }
+            _ => None,
+        })
+        .collect()
+    }
 }
-impl StreamTable {}
 
 #[async_trait]
 impl Table for StreamTable {
@@ -24,10 +148,10 @@ impl Table for StreamTable {
         case: vector_enrichment::Case,
         conditions: &[vector_enrichment::Condition],
         select: Option<&[String]>,
-        _index: Option<vector_enrichment::IndexHandle>,
+        index: Option<vector_enrichment::IndexHandle>,
     ) -> std::result::Result<BTreeMap<std::string::String, vrl::value::Value>, std::string::String>
     {
-        let resp = get_data(self, conditions, select, case);
+        let resp = self.get_data(case, conditions, select, index);
         let record = if resp.is_empty() {
             BTreeMap::new()
         } else {
@@ -42,22 +166,29 @@ impl Table for StreamTable {
         case: vector_enrichment::Case,
         conditions: &[vector_enrichment::Condition],
         select: Option<&[String]>,
-        _index: Option<vector_enrichment::IndexHandle>,
+        index: Option<vector_enrichment::IndexHandle>,
     ) -> Result<Vec<BTreeMap<String, vrl::value::Value>>, String> {
-        let resp = get_data(self, conditions, select, case);
+        let resp = self.get_data(case, conditions, select, index);
         Ok(resp)
     }
 
     fn add_index(
         &mut self,
-        _case: vector_enrichment::Case,
-        _fields: &[&str],
+        case: vector_enrichment::Case,
+        fields: &[&str],
     ) -> Result<vector_enrichment::IndexHandle, String> {
-        Ok(IndexHandle(1))
+        if fields.is_empty() {
+            return Err("An index needs at least one field".to_string());
+        }
+        Ok(self.get_or_build_index(case, fields))
     }
 
     fn index_fields(&self) -> Vec<(vector_enrichment::Case, Vec<String>)> {
-        Vec::new()
+        self.indexes
+            .read()
+            .iter()
+            .map(|index| (index.case, index.fields.clone()))
+            .collect()
     }
 
     fn needs_reload(&self) -> bool {
@@ -65,89 +196,166 @@ impl Table for StreamTable {
     }
 }
 
-fn get_data(
-    table: &StreamTable,
-    condition: &[vector_enrichment::Condition],
-    select: Option<&[String]>,
-    case: vector_enrichment::Case,
-) -> Vec<BTreeMap<String, vrl::value::Value>> {
-    let mut resp = vec![];
-    let filtered: Vec<&vrl::value::Value> = table
-        .data
-        .iter()
-        .filter(|v| {
-            if let vrl::value::Value::Object(map) = v {
-                for cond in condition {
-                    match cond {
-                        vector_enrichment::Condition::Equals { field, value } => match case {
-                            Case::Insensitive => {
-                                if let Some(Value::Bytes(bytes1)) = map.get(field.to_owned()) {
-                                    if let Value::Bytes(bytes2) = value {
-                                        match (
-                                            std::str::from_utf8(bytes1),
-                                            std::str::from_utf8(bytes2),
-                                        ) {
-                                            (Ok(s1), Ok(s2)) => {
-                                                return s1.eq_ignore_ascii_case(s2);
-                                            }
-                                            (Err(_), Err(_)) => return bytes1 == bytes2,
-                                            _ => return false,
-                                        }
-                                    }
-                                }
-                            }
-                            Case::Sensitive => {
-                                if let Some(v) = map.get(field.to_owned()) {
-                                    if v.clone() == value.clone() {
-                                        return true;
-                                    }
-                                }
-                            }
-                        },
-                        vector_enrichment::Condition::BetweenDates { field, from, to } => {
-                            if let Some(v) = map.get(field.to_owned()) {
-                                if let Some(v) = v.as_str() {
-                                    if let Ok(v) = parse_str_to_time(&v) {
-                                        if v >= *from && v <= *to {
-                                            return true;
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
+/// Whether the row matches all the conditions.
+fn matches(row: &ObjectMap, case: Case, conditions: &[Condition]) -> bool {
+    conditions.iter().all(|cond| match cond {
+        Condition::Equals { field, value } => match (row.get(*field), value, case) {
+            (Some(Value::Bytes(bytes1)), Value::Bytes(bytes2), Case::Insensitive) => {
+                match (std::str::from_utf8(bytes1), std::str::from_utf8(bytes2)) {
+                    (Ok(s1), Ok(s2)) => s1.eq_ignore_ascii_case(s2),
+                    (Err(_), Err(_)) => bytes1 == bytes2,
+                    _ => false,
                 }
             }
-            false
-        })
-        .collect();
+            (Some(v), ..) => v == value,
+            (None, ..) => false,
+        },
+        Condition::BetweenDates { field, from, to } => row
+            .get(*field)
+            .and_then(|v| v.as_str())
+            .and_then(|v| parse_str_to_time(&v).ok())
+            .map_or(false, |v| v >= *from && v <= *to),
+    })
+}
 
-    match select {
-        Some(val) => {
-            for value in filtered {
-                if let Some(map) = value.as_object() {
-                    let mut btree_map = BTreeMap::new();
-                    for field in val {
-                        if let Some(v) = map.get(field) {
-                            btree_map.insert(field.to_owned(), v.clone());
-                        }
-                    }
-                    resp.push(btree_map);
-                };
+/// Hashes the values of the indexed fields, consistently with the comparison
+/// of [`matches`].
+fn hash_values<'a>(case: Case, values: impl Iterator<Item = &'a Value>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for value in values {
+        match value {
+            Value::Bytes(bytes) if case == Case::Insensitive => {
+                for b in bytes.iter() {
+                    hasher.write_u8(b.to_ascii_lowercase());
+                }
             }
+            Value::Bytes(bytes) => hasher.write(bytes),
+            v => hasher.write(v.to_string().as_bytes()),
         }
-        None => {
-            for value in filtered {
-                if let Value::Object(map) = value {
-                    let btree_map: BTreeMap<String, Value> = map
-                        .iter()
-                        .map(|(k, v)| (k.to_owned(), v.clone()))
-                        .collect::<BTreeMap<String, vrl::value::Value>>();
-                    resp.push(btree_map);
-                };
-            }
+        // separates the values
+        hasher.write_u8(0xff);
+    }
+    hasher.finish()
+}
+
+fn select_fields(row: &ObjectMap, select: Option<&[String]>) -> BTreeMap<String, Value> {
+    match select {
+        Some(fields) => fields
+            .iter()
+            .filter_map(|field| Some((field.to_owned(), row.get(field.as_str())?.clone())))
+            .collect(),
+        None => row
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(rows: usize) -> StreamTable {
+        let data = (0..rows)
+            .map(|i| {
+                Value::Object(ObjectMap::from([
+                    ("id".into(), Value::from(i.to_string())),
+                    ("name".into(), Value::from(format!("Host-{}", i % 10))),
+                    ("zone".into(), Value::from(format!("zone-{}", i % 3))),
+                ]))
+            })
+            .collect();
+        StreamTable::new("default", "hosts", data)
+    }
+
+    fn equals<'a>(field: &'a str, value: &str) -> Condition<'a> {
+        Condition::Equals {
+            field,
+            value: Value::from(value),
         }
-    };
+    }
+
+    #[test]
+    fn test_find_table_rows_with_index() {
+        let mut table = table(100);
+        let index = table.add_index(Case::Sensitive, &["name"]).unwrap();
+        assert_eq!(table.add_index(Case::Sensitive, &["name"]), Ok(index));
+        let conditions = [equals("name", "Host-3"), equals("zone", "zone-1")];
+        let indexed = table
+            .find_table_rows(Case::Sensitive, &conditions, None, Some(index))
+            .unwrap();
+        let scanned = table
+            .find_table_rows(Case::Sensitive, &conditions, None, None)
+            .unwrap();
+        assert_eq!(indexed, scanned);
+        // the ids 3, 13, ..., 93 having zone-1
+        assert_eq!(
+            indexed
+                .iter()
+                .map(|row| row["id"].clone())
+                .collect::<Vec<_>>(),
+            vec![Value::from("13"), Value::from("43"), Value::from("73")]
+        );
 
-    resp
+        let row = table
+            .find_table_row(
+                Case::Sensitive,
+                &[equals("id", "42")],
+                Some(&["zone".to_string()]),
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            row,
+            BTreeMap::from([("zone".to_string(), Value::from("zone-0"))])
+        );
+    }
+
+    #[test]
+    fn test_find_table_rows_case_insensitive() {
+        let mut table = table(20);
+        let index = table.add_index(Case::Insensitive, &["name"]).unwrap();
+        let rows = table
+            .find_table_rows(
+                Case::Insensitive,
+                &[equals("name", "HOST-5")],
+                None,
+                Some(index),
+            )
+            .unwrap();
+        assert_eq!(rows.len(), 2);
+        let rows = table
+            .find_table_rows(Case::Sensitive, &[equals("name", "HOST-5")], None, None)
+            .unwrap();
+        assert!(rows.is_empty());
+    }
+
+    #[test]
+    fn test_build_indexes() {
+        let mut old = table(10);
+        old.add_index(Case::Sensitive, &["zone", "name"]).unwrap();
+        // the clones share the indexes
+        let clone = old.clone();
+        assert_eq!(clone.index_fields().len(), 1);
+
+        let new = table(10);
+        new.build_indexes(&old.index_fields());
+        assert_eq!(
+            new.index_fields(),
+            vec![(
+                Case::Sensitive,
+                vec!["zone".to_string(), "name".to_string()]
+            )]
+        );
+        // an index on fields missing from the conditions is not used
+        let rows = new
+            .find_table_rows(
+                Case::Sensitive,
+                &[equals("name", "Host-1")],
+                None,
+                Some(IndexHandle(0)),
+            )
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+    }
 }
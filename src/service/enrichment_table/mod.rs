@@ -52,6 +52,7 @@ use crate::{
 };
 
 pub mod geoip;
+pub mod schedule;
 
 pub async fn save_enrichment_data(
     org_id: &str,
@@ -60,18 +61,80 @@ pub async fn save_enrichment_data(
     thread_id: usize,
     append_data: bool,
 ) -> Result<HttpResponse, Error> {
+    let mut records = vec![];
+    while let Ok(Some(mut field)) = payload.try_next().await {
+        let content_disposition = field.content_disposition();
+        let filename = content_disposition.get_filename();
+        let mut data = bytes::Bytes::new();
+
+        if filename.is_some() {
+            while let Some(chunk) = field.next().await {
+                let chunked_data = chunk.unwrap();
+                // Reconstruct entire CSV data bytes here to prevent fragmentation of values.
+                data = Bytes::from([data.as_ref(), chunked_data.as_ref()].concat());
+            }
+            records.extend(parse_csv(&data)?);
+        }
+    }
+
+    if records.is_empty() {
+        return Ok(
+            HttpResponse::BadRequest().json(meta::http::HttpResponse::error(
+                http::StatusCode::BAD_REQUEST.into(),
+                "No records to ingest for look up table".to_string(),
+            )),
+        );
+    }
+
+    match save_enrichment_records(org_id, table_name, records, thread_id, append_data).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(MetaHttpResponse::error(
+            StatusCode::OK.into(),
+            "Saved enrichment table".to_string(),
+        ))),
+        Err(e) => Ok(
+            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                e.to_string(),
+            )),
+        ),
+    }
+}
+
+/// Parses the CSV data into records, the values are kept as strings.
+pub fn parse_csv(data: &[u8]) -> Result<Vec<json::Map<String, json::Value>>, csv::Error> {
+    let mut rdr = csv::Reader::from_reader(data);
+    let headers = rdr.headers()?.clone();
+    let mut records = vec![];
+    for result in rdr.records() {
+        // The iterator yields Result<StringRecord, Error>, so we check the
+        // error here.
+        let record = result?;
+        // Transform the record to a JSON value
+        let mut json_record = json::Map::new();
+        for (header, field) in headers.iter().zip(record.iter()) {
+            json_record.insert(header.into(), json::Value::String(field.into()));
+        }
+        records.push(json_record);
+    }
+    Ok(records)
+}
+
+/// Saves the records as the rows of the enrichment table, replacing the
+/// existing rows unless `append_data` is set. Must be called on an ingester.
+pub async fn save_enrichment_records(
+    org_id: &str,
+    table_name: &str,
+    records: Vec<json::Map<String, json::Value>>,
+    thread_id: usize,
+    append_data: bool,
+) -> Result<(), anyhow::Error> {
     let start = std::time::Instant::now();
     let mut hour_key = String::new();
     let mut buf: AHashMap<String, Vec<String>> = AHashMap::new();
     let stream_name = &format_stream_name(table_name);
 
     if !cluster::is_ingester(&cluster::LOCAL_NODE_ROLE) {
-        return Ok(
-            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
-                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
-                "not an ingester".to_string(),
-            )),
-        );
+        return Err(anyhow::anyhow!("not an ingester"));
     }
 
     // check if we are allowed to ingest
@@ -81,12 +144,9 @@ pub async fn save_enrichment_data(
         StreamType::EnrichmentTables,
         None,
     ) {
-        return Ok(
-            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
-                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
-                format!("enrichment table [{stream_name}] is being deleted"),
-            )),
-        );
+        return Err(anyhow::anyhow!(
+            "enrichment table [{stream_name}] is being deleted"
+        ));
     }
 
     let mut stream_schema_map: AHashMap<String, Schema> = AHashMap::new();
@@ -102,7 +162,6 @@ pub async fn save_enrichment_data(
         delete_enrichment_table(org_id, stream_name, StreamType::EnrichmentTables).await;
     }
 
-    let mut records = vec![];
     let timestamp = if !append_data {
         Utc::now().timestamp_micros()
     } else {
@@ -114,69 +173,36 @@ pub async fn save_enrichment_data(
             .parse::<i64>()
             .unwrap()
     };
-    while let Ok(Some(mut field)) = payload.try_next().await {
-        let content_disposition = field.content_disposition();
-        let filename = content_disposition.get_filename();
-        let mut data = bytes::Bytes::new();
-
-        if filename.is_some() {
-            while let Some(chunk) = field.next().await {
-                let chunked_data = chunk.unwrap();
-                // Reconstruct entire CSV data bytes here to prevent fragmentation of values.
-                data = Bytes::from([data.as_ref(), chunked_data.as_ref()].concat());
-            }
-            let mut rdr = csv::Reader::from_reader(data.as_ref());
-            let headers = rdr.headers()?.clone();
-
-            for result in rdr.records() {
-                // The iterator yields Result<StringRecord, Error>, so we check the
-                // error here.
-                let record = result?;
-                // Transform the record to a JSON value
-                let mut json_record = json::Map::new();
-
-                for (header, field) in headers.iter().zip(record.iter()) {
-                    json_record.insert(header.into(), json::Value::String(field.into()));
-                }
-                json_record.insert(
-                    CONFIG.common.column_timestamp.clone(),
-                    json::Value::Number(timestamp.into()),
-                );
-                let value_str = json::to_string(&json_record).unwrap();
-                chk_schema_by_record(
-                    &mut stream_schema_map,
-                    org_id,
-                    StreamType::EnrichmentTables,
-                    stream_name,
-                    timestamp,
-                    &value_str,
-                )
-                .await;
-
-                if records.is_empty() {
-                    hour_key = super::ingestion::get_wal_time_key(
-                        timestamp,
-                        &vec![],
-                        PartitionTimeLevel::Unset,
-                        &json_record,
-                        None,
-                    );
-                }
-                records.push(value_str);
-            }
-        }
-    }
-
-    if records.is_empty() {
-        return Ok(
-            HttpResponse::BadRequest().json(meta::http::HttpResponse::error(
-                http::StatusCode::BAD_REQUEST.into(),
-                "No records to ingest for look up table".to_string(),
-            )),
+    let mut values = Vec::with_capacity(records.len());
+    for mut json_record in records {
+        json_record.insert(
+            CONFIG.common.column_timestamp.clone(),
+            json::Value::Number(timestamp.into()),
         );
+        let value_str = json::to_string(&json_record).unwrap();
+        chk_schema_by_record(
+            &mut stream_schema_map,
+            org_id,
+            StreamType::EnrichmentTables,
+            stream_name,
+            timestamp,
+            &value_str,
+        )
+        .await;
+
+        if values.is_empty() {
+            hour_key = super::ingestion::get_wal_time_key(
+                timestamp,
+                &vec![],
+                PartitionTimeLevel::Unset,
+                &json_record,
+                None,
+            );
+        }
+        values.push(value_str);
     }
 
-    buf.insert(hour_key.clone(), records.clone());
+    buf.insert(hour_key, values);
     let mut stream_file_name = "".to_string();
     let mut req_stats = write_file(
         &buf,
@@ -197,11 +223,7 @@ pub async fn save_enrichment_data(
         0,
     )
     .await;
-
-    Ok(HttpResponse::Ok().json(MetaHttpResponse::error(
-        StatusCode::OK.into(),
-        "Saved enrichment table".to_string(),
-    )))
+    Ok(())
 }
 
 async fn delete_enrichment_table(org_id: &str, stream_name: &str, stream_type: StreamType) {
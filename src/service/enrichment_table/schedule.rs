@@ -0,0 +1,385 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Enrichment tables refreshed on a schedule from a stream query or a file in
+//! the object storage.
+//!
+//! The rows are fetched before the table is replaced, so a failed or empty
+//! fetch keeps the previous version of the table. The refreshes run on a
+//! single ingester, the online one with the lowest node id.
+
+use std::collections::HashMap;
+
+use actix_web::http;
+use chrono::Utc;
+
+use crate::{
+    common::{
+        infra::{
+            cluster,
+            config::{CONFIG, ENRICHMENT_TABLE_SCHEDULES, SIZE_IN_MB},
+            storage,
+        },
+        meta::{
+            enrichment_table::{EnrichmentTableSource, RefreshStatus, ScheduledEnrichmentTable},
+            search,
+        },
+        utils::{json, time::parse_milliseconds},
+    },
+    service::{db, format_stream_name, search as search_service},
+};
+
+/// The maximum rows fetched by a query without a limit.
+const QUERY_MAX_ROWS: usize = 100_000;
+
+/// The minimum frequency of the refreshes, in milliseconds.
+const MIN_FREQUENCY: u64 = 60_000;
+
+/// The files of an organization are read under `{FILE_SOURCE_ROOT}/{org_id}/`.
+const FILE_SOURCE_ROOT: &str = "enrichment_sources";
+
+/// Saves the scheduled table, the status of the previous refreshes is kept.
+pub async fn save(
+    org_id: &str,
+    name: &str,
+    mut table: ScheduledEnrichmentTable,
+) -> Result<(), anyhow::Error> {
+    table.name = format_stream_name(name.trim());
+    validate(&table)?;
+    table.status = db::enrichment_table::get_schedule(org_id, &table.name)
+        .await
+        .map(|previous| previous.status)
+        .unwrap_or_default();
+    db::enrichment_table::set_schedule(org_id, &table.name, &table).await
+}
+
+pub async fn get(org_id: &str, name: &str) -> Result<ScheduledEnrichmentTable, anyhow::Error> {
+    let name = format_stream_name(name.trim());
+    db::enrichment_table::get_schedule(org_id, &name)
+        .await
+        .map_err(|_| anyhow::anyhow!("Scheduled enrichment table not found"))
+}
+
+pub async fn list(org_id: &str) -> Result<Vec<ScheduledEnrichmentTable>, anyhow::Error> {
+    db::enrichment_table::list_schedules(org_id).await
+}
+
+/// Deletes the schedule, the rows of the table are kept until the enrichment
+/// table stream is deleted.
+pub async fn delete(org_id: &str, name: &str) -> Result<(), (http::StatusCode, anyhow::Error)> {
+    let name = format_stream_name(name.trim());
+    if db::enrichment_table::get_schedule(org_id, &name)
+        .await
+        .is_err()
+    {
+        return Err((
+            http::StatusCode::NOT_FOUND,
+            anyhow::anyhow!("Scheduled enrichment table not found {}", name),
+        ));
+    }
+    db::enrichment_table::delete_schedule(org_id, &name)
+        .await
+        .map_err(|e| (http::StatusCode::INTERNAL_SERVER_ERROR, e))
+}
+
+fn validate(table: &ScheduledEnrichmentTable) -> Result<(), anyhow::Error> {
+    if table.name.is_empty() {
+        return Err(anyhow::anyhow!("Table name is required"));
+    }
+    if parse_milliseconds(&table.frequency)? < MIN_FREQUENCY {
+        return Err(anyhow::anyhow!(
+            "Frequency should be at least {}s",
+            MIN_FREQUENCY / 1000
+        ));
+    }
+    match &table.source {
+        EnrichmentTableSource::Query { sql, period, .. } => {
+            if sql.trim().is_empty() {
+                return Err(anyhow::anyhow!("Query source requires the sql"));
+            }
+            if parse_milliseconds(period)? == 0 {
+                return Err(anyhow::anyhow!("Query source requires a period"));
+            }
+        }
+        EnrichmentTableSource::File { path } => {
+            if path.trim().is_empty() {
+                return Err(anyhow::anyhow!("File source requires the path"));
+            }
+            if path.starts_with('/')
+                || path.starts_with('\\')
+                || path.split(['/', '\\']).any(|part| part == "..")
+            {
+                return Err(anyhow::anyhow!(
+                    "File source path should be relative to the organization folder"
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Refreshes the tables whose frequency has elapsed since their last attempt.
+pub async fn run() -> Result<(), anyhow::Error> {
+    if !is_refresh_node() {
+        return Ok(());
+    }
+
+    let tables: Vec<(String, ScheduledEnrichmentTable)> = ENRICHMENT_TABLE_SCHEDULES
+        .iter()
+        .map(|item| (item.key().clone(), item.value().clone()))
+        .collect();
+    let now = Utc::now().timestamp_micros();
+    for (key, table) in tables {
+        let org_id = match key.split_once('/') {
+            Some((org_id, _)) => org_id,
+            None => continue,
+        };
+        let frequency = match parse_milliseconds(&table.frequency) {
+            Ok(v) => v as i64 * 1000,
+            Err(e) => {
+                log::error!("[ENRICHMENT TABLE] schedule {} err: {}", key, e);
+                continue;
+            }
+        };
+        if !is_due(&table.status, frequency, now) {
+            continue;
+        }
+        let ret = refresh(org_id, &table, now).await;
+        if let Err(e) = &ret {
+            log::error!("[ENRICHMENT TABLE] refresh {} err: {}", key, e);
+        }
+        // the schedule may have been updated or deleted meanwhile
+        let mut current = match db::enrichment_table::get_schedule(org_id, &table.name).await {
+            Ok(current) => current,
+            Err(_) => continue,
+        };
+        current.status = next_status(&table.status, ret.map(|_| ()), now);
+        if let Err(e) = db::enrichment_table::set_schedule(org_id, &table.name, &current).await {
+            log::error!("[ENRICHMENT TABLE] save status {} err: {}", key, e);
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the rows of the table with the ones fetched from its source.
+/// Returns the number of rows.
+async fn refresh(
+    org_id: &str,
+    table: &ScheduledEnrichmentTable,
+    now: i64,
+) -> Result<usize, anyhow::Error> {
+    let records = match &table.source {
+        EnrichmentTableSource::Query {
+            stream_type,
+            sql,
+            period,
+        } => {
+            let period = parse_milliseconds(period)? as i64 * 1000;
+            let req = search::Request {
+                query: search::Query {
+                    sql: sql.clone(),
+                    from: 0,
+                    size: QUERY_MAX_ROWS,
+                    start_time: now - period,
+                    end_time: now,
+                    sql_mode: "full".to_string(),
+                    ..Default::default()
+                },
+                aggs: HashMap::new(),
+                encoding: search::RequestEncoding::Empty,
+                timeout: 0,
+            };
+            let res = search_service::search("", org_id, *stream_type, &req)
+                .await
+                .map_err(|e| anyhow::anyhow!("query error: {}", e))?;
+            check_size(json::to_vec(&res.hits)?.len())?;
+            res.hits
+                .into_iter()
+                .filter_map(|hit| match hit {
+                    json::Value::Object(mut row) => {
+                        // the table sets its own timestamp
+                        row.remove(&CONFIG.common.column_timestamp);
+                        Some(row)
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        }
+        EnrichmentTableSource::File { path } => {
+            let path = file_path(org_id, path);
+            let data = storage::get(&path)
+                .await
+                .map_err(|e| anyhow::anyhow!("read file {} error: {}", path, e))?;
+            check_size(data.len())?;
+            super::parse_csv(&data)?
+        }
+    };
+    if records.is_empty() {
+        return Err(anyhow::anyhow!("no rows fetched from the source"));
+    }
+    let rows = records.len();
+    super::save_enrichment_records(org_id, &table.name, records, 0, false).await?;
+    Ok(rows)
+}
+
+/// The storage path of a file source, scoped to the folder of the organization.
+fn file_path(org_id: &str, path: &str) -> String {
+    format!("{FILE_SOURCE_ROOT}/{org_id}/{}", path.trim())
+}
+
+fn check_size(size: usize) -> Result<(), anyhow::Error> {
+    if size as f64 / SIZE_IN_MB > CONFIG.limit.enrichment_table_limit as f64 {
+        return Err(anyhow::anyhow!(
+            "exceeds allowed limit of {} mb",
+            CONFIG.limit.enrichment_table_limit
+        ));
+    }
+    Ok(())
+}
+
+/// Whether the frequency, in microseconds, has elapsed since the last attempt.
+fn is_due(status: &RefreshStatus, frequency: i64, now: i64) -> bool {
+    status.last_attempt_at + frequency <= now
+}
+
+/// A failed refresh keeps the version and the time of the last successful one.
+fn next_status(status: &RefreshStatus, ret: Result<(), anyhow::Error>, now: i64) -> RefreshStatus {
+    match ret {
+        Ok(_) => RefreshStatus {
+            version: status.version + 1,
+            last_refreshed_at: now,
+            last_attempt_at: now,
+            last_error: String::new(),
+        },
+        Err(e) => RefreshStatus {
+            last_attempt_at: now,
+            last_error: e.to_string(),
+            ..status.clone()
+        },
+    }
+}
+
+/// The refreshes run on the online ingester with the lowest node id, or on the
+/// local node when the cluster is not known yet.
+fn is_refresh_node() -> bool {
+    match cluster::get_cached_online_ingester_nodes() {
+        Some(nodes) => nodes
+            .iter()
+            .min_by_key(|node| node.id)
+            .map_or(true, |node| node.uuid.eq(&*cluster::LOCAL_NODE_UUID)),
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(frequency: &str, source: EnrichmentTableSource) -> ScheduledEnrichmentTable {
+        ScheduledEnrichmentTable {
+            name: "host_owners".to_string(),
+            source,
+            frequency: frequency.to_string(),
+            status: RefreshStatus::default(),
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let query = EnrichmentTableSource::Query {
+            stream_type: Default::default(),
+            sql: "SELECT host, owner FROM inventory".to_string(),
+            period: "1d".to_string(),
+        };
+        assert!(validate(&table("1h", query.clone())).is_ok());
+        assert!(validate(&table("10s", query)).is_err());
+        let query = EnrichmentTableSource::Query {
+            stream_type: Default::default(),
+            sql: " ".to_string(),
+            period: "1d".to_string(),
+        };
+        assert!(validate(&table("1h", query)).is_err());
+        let file = EnrichmentTableSource::File {
+            path: "lookups/hosts.csv".to_string(),
+        };
+        assert!(validate(&table("30m", file)).is_ok());
+    }
+
+    #[test]
+    fn test_validate_file_path() {
+        for path in [
+            "../other_org/hosts.csv",
+            "lookups/../../other_org/hosts.csv",
+            "/other_org/hosts.csv",
+            "..\\other_org\\hosts.csv",
+        ] {
+            let file = EnrichmentTableSource::File {
+                path: path.to_string(),
+            };
+            assert!(validate(&table("30m", file)).is_err(), "{path}");
+        }
+        assert_eq!(
+            file_path("default", "lookups/hosts.csv"),
+            "enrichment_sources/default/lookups/hosts.csv"
+        );
+    }
+
+    #[test]
+    fn test_source_deserialize() {
+        let table: ScheduledEnrichmentTable = json::from_str(
+            r#"{"source":{"type":"query","stream_type":"logs","sql":"SELECT host, owner FROM inventory"}}"#,
+        )
+        .unwrap();
+        assert_eq!(table.frequency, "1h");
+        match table.source {
+            EnrichmentTableSource::Query { period, .. } => assert_eq!(period, "1d"),
+            _ => panic!("expected a query source"),
+        }
+        let table: ScheduledEnrichmentTable =
+            json::from_str(r#"{"source":{"type":"file","path":"hosts.csv"},"frequency":"5m"}"#)
+                .unwrap();
+        assert!(matches!(table.source, EnrichmentTableSource::File { .. }));
+    }
+
+    #[test]
+    fn test_next_status() {
+        let hour = 3_600_000_000;
+        let status = RefreshStatus::default();
+        assert!(is_due(&status, hour, hour));
+
+        let status = next_status(&status, Ok(()), hour);
+        assert_eq!(status.version, 1);
+        assert!(!is_due(&status, hour, hour + 1));
+        assert!(is_due(&status, hour, 2 * hour));
+
+        // a failed refresh keeps the previous version
+        let failed = next_status(&status, Err(anyhow::anyhow!("query error")), 2 * hour);
+        assert_eq!(failed.version, 1);
+        assert_eq!(failed.last_refreshed_at, hour);
+        assert_eq!(failed.last_attempt_at, 2 * hour);
+        assert_eq!(failed.last_error, "query error");
+
+        let status = next_status(&failed, Ok(()), 3 * hour);
+        assert_eq!(status.version, 2);
+        assert!(status.last_error.is_empty());
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let records = super::super::parse_csv(b"host,owner\nweb-1,alice\nweb-2,bob\n").unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1]["owner"], json::Value::from("bob"));
+    }
+}
@@ -19,7 +19,7 @@ use ahash::AHashMap;
 use arrow::json::ReaderBuilder;
 use arrow_schema::Schema;
 use bytes::{BufMut, BytesMut};
-use chrono::{TimeZone, Utc};
+use chrono::{Duration, TimeZone, Utc};
 use vector_enrichment::TableRegistry;
 use vrl::{
     compiler::{runtime::Runtime, CompilationResult, TargetValueRef},
@@ -34,7 +34,7 @@ use crate::{
             wal::{get_or_create, get_or_create_arrow},
         },
         meta::{
-            alerts::Alert,
+            alerts::{history::AlertHistory, Alert},
             functions::{StreamTransform, VRLResultResolver, VRLRuntimeConfig},
             stream::{PartitionTimeLevel, PartitioningDetails, SchemaRecords, StreamParams},
             usage::RequestStats,
@@ -48,7 +48,8 @@ use crate::{
         },
     },
     service::{
-        db, format_partition_key, schema::filter_schema_null_fields, stream::stream_settings,
+        alerts::alert_manager, db, format_partition_key, schema::filter_schema_null_fields,
+        stream::stream_settings,
     },
 };
 
@@ -187,8 +188,68 @@ pub async fn evaluate_trigger(trigger: TriggerAlertData) {
     }
     let trigger = trigger.unwrap();
     for (alert, val) in trigger.iter() {
-        if let Err(e) = alert.send_notification(val).await {
-            log::error!("Failed to send notification: {}", e)
+        let now = Utc::now().timestamp_micros();
+        let key = format!(
+            "{}/{}/{}/{}",
+            alert.org_id, alert.stream_type, alert.stream_name, alert.name
+        );
+        let old_trigger = TRIGGERS.read().await.get(&key).cloned();
+        let mut new_trigger = old_trigger.clone().unwrap_or_default();
+        new_trigger.is_realtime = true;
+        // realtime alerts are only evaluated for the matched records
+        let (state, mut should_notify) =
+            alert_manager::next_state(alert, &mut new_trigger, true, now);
+        new_trigger.state = state;
+
+        let mut history = AlertHistory::new(alert);
+        history.matched_rows = val.len();
+        history.outcome = state.into();
+        if should_notify {
+            // muted by a maintenance window, only record the evaluation
+            if let Some(window) = crate::service::alerts::maintenance::active_window(alert, now) {
+                history.maintenance_window = window;
+                should_notify = false;
+            }
+        }
+        if should_notify {
+            let failed = alert
+                .send_notification(val, state, new_trigger.fired_at)
+                .await;
+            history.set_delivery(&failed);
+            if alert.trigger_condition.silence > 0 {
+                // silence the realtime alert, alert manager will wake it up later
+                new_trigger.next_run_at = now
+                    + Duration::minutes(alert.trigger_condition.silence)
+                        .num_microseconds()
+                        .unwrap();
+                new_trigger.is_silenced = true;
+            }
+        }
+        if let Err(e) = crate::service::alerts::history::save(history).await {
+            log::error!("Failed to save alert history: {}", e)
+        }
+
+        let changed = match old_trigger {
+            Some(old) => {
+                old.state != new_trigger.state
+                    || old.pending_count != new_trigger.pending_count
+                    || old.is_silenced != new_trigger.is_silenced
+            }
+            None => true,
+        };
+        if !changed {
+            continue;
+        }
+        if let Err(e) = crate::service::alerts::triggers::save(
+            &alert.org_id,
+            alert.stream_type,
+            &alert.stream_name,
+            &alert.name,
+            &new_trigger,
+        )
+        .await
+        {
+            log::error!("Failed to save trigger: {}", e)
         }
     }
 }
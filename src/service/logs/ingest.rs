@@ -128,6 +128,10 @@ pub async fn ingest(
             ep = "/api/org/ingest/logs/_kinesis";
             IngestionData::KinesisFH(req)
         }
+        IngestionRequest::Loki(req) => {
+            ep = "/api/org/loki/api/v1/push";
+            IngestionData::JSON(req)
+        }
     };
 
     for rec in data.iter() {
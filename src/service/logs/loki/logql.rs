@@ -0,0 +1,828 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! A subset of LogQL: the stream selector, the line filters, the `json` and
+//! `logfmt` parsers, the label filters and `line_format`, and the `rate` and
+//! `count_over_time` metric queries, optionally summed by labels.
+//!
+//! The selector, and the filters before the first parser or `line_format`,
+//! are translated into the conditions of the SQL query. The rest of the
+//! pipeline is applied to the records found.
+
+use std::collections::BTreeMap;
+
+use regex::Regex;
+
+use crate::common::utils::{
+    flatten::{self, format_key},
+    json,
+    time::parse_milliseconds,
+};
+
+/// The field holding the log line.
+pub const LINE_FIELD: &str = "message";
+
+/// The label set on the records which a parser failed to parse.
+const ERROR_LABEL: &str = "__error__";
+
+#[derive(Clone, Debug)]
+pub enum Expr {
+    Log(LogExpr),
+    Metric(MetricExpr),
+}
+
+#[derive(Clone, Debug)]
+pub struct LogExpr {
+    pub selector: Vec<Filter>,
+    pub pipeline: Vec<Stage>,
+}
+
+#[derive(Clone, Debug)]
+pub struct MetricExpr {
+    pub func: RangeFunc,
+    /// The range of the range vector, in microseconds.
+    pub range: i64,
+    pub log: LogExpr,
+    /// The labels summed by, `None` without a `sum` which keeps the labels
+    /// of the selector.
+    pub grouping: Option<Vec<String>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RangeFunc {
+    Rate,
+    CountOverTime,
+}
+
+#[derive(Clone, Debug)]
+pub enum Stage {
+    /// `|= "x"`, `!= "x"`, `|~ "x"` or `!~ "x"` on the line.
+    LineFilter(Op, String),
+    Json,
+    Logfmt,
+    LabelFilter(Filter),
+    LineFormat(String),
+}
+
+/// A matcher of the selector or a label filter.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    pub name: String,
+    pub op: Op,
+    pub value: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Op {
+    Eq,
+    Neq,
+    Re,
+    Nre,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+pub fn parse(query: &str) -> Result<Expr, anyhow::Error> {
+    let mut parser = Parser::new(query);
+    let expr = parser.expr()?;
+    parser.expect_end()?;
+    let log = match &expr {
+        Expr::Log(log) => log,
+        Expr::Metric(metric) => {
+            if !metric.log.post_stages().is_empty() {
+                return Err(anyhow::anyhow!(
+                    "parsers and line_format are not supported in metric queries"
+                ));
+            }
+            &metric.log
+        }
+    };
+    // checks the regular expressions
+    Pipeline::new(&log.pipeline)?;
+    Ok(expr)
+}
+
+/// Parses the labels of a pushed stream, such as `{app="cart", env="prod"}`.
+pub fn parse_labels(labels: &str) -> Result<Vec<(String, String)>, anyhow::Error> {
+    let mut parser = Parser::new(labels);
+    let selector = parser.selector()?;
+    parser.expect_end()?;
+    selector
+        .into_iter()
+        .map(|filter| match filter.op {
+            Op::Eq => Ok((filter.name, filter.value)),
+            _ => Err(anyhow::anyhow!(
+                "the labels of a stream should be name=\"value\" pairs"
+            )),
+        })
+        .collect()
+}
+
+impl LogExpr {
+    /// The stages applied to the records found, from the first parser or
+    /// `line_format`.
+    pub fn post_stages(&self) -> &[Stage] {
+        let pos = self
+            .pipeline
+            .iter()
+            .position(|stage| matches!(stage, Stage::Json | Stage::Logfmt | Stage::LineFormat(_)))
+            .unwrap_or(self.pipeline.len());
+        &self.pipeline[pos..]
+    }
+
+    fn pre_stages(&self) -> &[Stage] {
+        &self.pipeline[..self.pipeline.len() - self.post_stages().len()]
+    }
+
+    /// Translates the selector and the stages before the first parser into
+    /// the conditions of the SQL `WHERE` clause.
+    pub fn condition(&self) -> String {
+        let mut conditions: Vec<String> = self.selector.iter().map(label_condition).collect();
+        for stage in self.pre_stages() {
+            match stage {
+                Stage::LineFilter(op, value) => conditions.push(line_condition(*op, value)),
+                Stage::LabelFilter(filter) => conditions.push(label_condition(filter)),
+                _ => {}
+            }
+        }
+        conditions.join(" AND ")
+    }
+
+    /// The fields of the conditions of the SQL query.
+    pub fn condition_fields(&self) -> Vec<String> {
+        let mut fields = self.stream_labels();
+        for stage in self.pre_stages() {
+            match stage {
+                Stage::LineFilter(..) => fields.push(LINE_FIELD.to_string()),
+                Stage::LabelFilter(filter) => fields.push(format_key(&filter.name)),
+                _ => {}
+            }
+        }
+        fields.sort();
+        fields.dedup();
+        fields
+    }
+
+    /// The names of the labels matched by the selector, which label the
+    /// streams of the results.
+    pub fn stream_labels(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.selector.iter().map(|f| format_key(&f.name)).collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+fn label_condition(filter: &Filter) -> String {
+    let column = format!("\"{}\"", format_key(&filter.name));
+    let value = quote(&filter.value);
+    match filter.op {
+        // a missing label matches the empty value
+        Op::Eq if filter.value.is_empty() => format!("({column} IS NULL OR {column} = '')"),
+        Op::Neq if filter.value.is_empty() => {
+            format!("({column} IS NOT NULL AND {column} != '')")
+        }
+        Op::Eq => format!("{column} = {value}"),
+        Op::Neq => format!("{column} != {value}"),
+        Op::Re => format!("re_match({column}, {})", quote(&anchored(&filter.value))),
+        Op::Nre => format!(
+            "re_not_match({column}, {})",
+            quote(&anchored(&filter.value))
+        ),
+        op => {
+            // the numeric comparisons
+            let value = match filter.value.parse::<f64>() {
+                Ok(_) => filter.value.clone(),
+                Err(_) => value,
+            };
+            format!("{column} {} {value}", op.as_str())
+        }
+    }
+}
+
+fn line_condition(op: Op, value: &str) -> String {
+    let column = format!("\"{LINE_FIELD}\"");
+    match op {
+        Op::Eq => format!("str_match({column}, {})", quote(value)),
+        Op::Neq => format!("NOT str_match({column}, {})", quote(value)),
+        Op::Re => format!("re_match({column}, {})", quote(value)),
+        _ => format!("re_not_match({column}, {})", quote(value)),
+    }
+}
+
+fn quote(v: &str) -> String {
+    format!("'{}'", v.replace('\'', "''"))
+}
+
+/// The label matchers match the whole value.
+fn anchored(re: &str) -> String {
+    format!("^(?:{re})$")
+}
+
+impl Op {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Op::Eq => "=",
+            Op::Neq => "!=",
+            Op::Re => "=~",
+            Op::Nre => "!~",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+        }
+    }
+}
+
+/// The stages applied to the records found, with their regular expressions
+/// compiled.
+pub struct Pipeline {
+    stages: Vec<(Stage, Option<Regex>)>,
+}
+
+impl Pipeline {
+    pub fn new(stages: &[Stage]) -> Result<Self, anyhow::Error> {
+        let stages = stages
+            .iter()
+            .map(|stage| {
+                let re = match stage {
+                    Stage::LineFilter(Op::Re | Op::Nre, re) => Some(Regex::new(re)?),
+                    Stage::LabelFilter(Filter {
+                        op: Op::Re | Op::Nre,
+                        value,
+                        ..
+                    }) => Some(Regex::new(&anchored(value))?),
+                    _ => None,
+                };
+                Ok((stage.clone(), re))
+            })
+            .collect::<Result<Vec<_>, regex::Error>>()?;
+        Ok(Self { stages })
+    }
+
+    /// Returns the line, as formatted by the pipeline, or `None` when the line
+    /// is filtered out. The labels extracted by the parsers are added.
+    pub fn process(
+        &self,
+        mut line: String,
+        labels: &mut BTreeMap<String, String>,
+    ) -> Option<String> {
+        for (stage, re) in self.stages.iter() {
+            match stage {
+                Stage::LineFilter(op, value) => {
+                    let matched = match re {
+                        Some(re) => re.is_match(&line),
+                        None => line.contains(value.as_str()),
+                    };
+                    if matched != matches!(op, Op::Eq | Op::Re) {
+                        return None;
+                    }
+                }
+                Stage::Json => match json::from_str::<json::Value>(&line) {
+                    Ok(value @ json::Value::Object(_)) => {
+                        if let Ok(json::Value::Object(fields)) = flatten::flatten(&value) {
+                            for (name, value) in fields {
+                                labels.insert(name, value_string(&value));
+                            }
+                        }
+                    }
+                    _ => {
+                        labels.insert(ERROR_LABEL.to_string(), "JSONParserErr".to_string());
+                    }
+                },
+                Stage::Logfmt => match parse_logfmt(&line) {
+                    Some(fields) => labels.extend(fields),
+                    None => {
+                        labels.insert(ERROR_LABEL.to_string(), "LogfmtParserErr".to_string());
+                    }
+                },
+                Stage::LabelFilter(filter) => {
+                    let value = labels
+                        .get(&format_key(&filter.name))
+                        .map(|v| v.as_str())
+                        .unwrap_or_default();
+                    if !filter_matches(filter, re.as_ref(), value) {
+                        return None;
+                    }
+                }
+                Stage::LineFormat(template) => line = format_line(template, labels),
+            }
+        }
+        Some(line)
+    }
+}
+
+fn filter_matches(filter: &Filter, re: Option<&Regex>, value: &str) -> bool {
+    match filter.op {
+        Op::Eq => value == filter.value,
+        Op::Neq => value != filter.value,
+        Op::Re => re.map_or(false, |re| re.is_match(value)),
+        Op::Nre => re.map_or(true, |re| !re.is_match(value)),
+        op => {
+            let (Ok(value), Ok(bound)) = (value.parse::<f64>(), filter.value.parse::<f64>()) else {
+                return false;
+            };
+            match op {
+                Op::Gt => value > bound,
+                Op::Ge => value >= bound,
+                Op::Lt => value < bound,
+                _ => value <= bound,
+            }
+        }
+    }
+}
+
+pub fn value_string(value: &json::Value) -> String {
+    match value {
+        json::Value::String(v) => v.clone(),
+        json::Value::Null => String::new(),
+        v => v.to_string(),
+    }
+}
+
+/// Parses the `key=value` pairs of a logfmt line, the values can be quoted.
+fn parse_logfmt(line: &str) -> Option<Vec<(String, String)>> {
+    let mut fields = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|c| *c != '=' && !c.is_whitespace()) {
+            key.push(c);
+        }
+        let mut value = String::new();
+        if chars.next_if_eq(&'=').is_some() {
+            if chars.next_if_eq(&'"').is_some() {
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => value.push(chars.next()?),
+                        c => value.push(c),
+                    }
+                }
+            } else {
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    value.push(c);
+                }
+            }
+        }
+        if key.is_empty() {
+            return None;
+        }
+        fields.push((format_key(&key), value));
+    }
+    Some(fields)
+}
+
+/// Replaces the `{{.label}}` actions of the template by the label values.
+fn format_line(template: &str, labels: &BTreeMap<String, String>) -> String {
+    let mut line = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        line.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        let action = rest[start + 2..start + end].trim();
+        if let Some(name) = action.strip_prefix('.') {
+            if let Some(value) = labels.get(&format_key(name)) {
+                line.push_str(value);
+            }
+        }
+        rest = &rest[start + end + 2..];
+    }
+    line.push_str(rest);
+    line
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(query: &str) -> Self {
+        Self {
+            chars: query.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, anyhow::Error> {
+        self.skip_whitespace();
+        if self.peek() == Some('{') {
+            return Ok(Expr::Log(self.log_expr()?));
+        }
+        let name = self.ident()?;
+        match name.as_str() {
+            "sum" => {
+                let mut grouping = self.grouping()?;
+                self.expect('(')?;
+                let (func, range, log) = self.range_expr()?;
+                self.expect(')')?;
+                if grouping.is_none() {
+                    grouping = self.grouping()?;
+                }
+                Ok(Expr::Metric(MetricExpr {
+                    func,
+                    range,
+                    log,
+                    grouping: Some(grouping.unwrap_or_default()),
+                }))
+            }
+            _ => {
+                self.pos -= name.chars().count();
+                let (func, range, log) = self.range_expr()?;
+                Ok(Expr::Metric(MetricExpr {
+                    func,
+                    range,
+                    log,
+                    grouping: None,
+                }))
+            }
+        }
+    }
+
+    /// Parses `by (label, ...)`, when present.
+    fn grouping(&mut self) -> Result<Option<Vec<String>>, anyhow::Error> {
+        self.skip_whitespace();
+        if !self.rest_starts_with("by") {
+            return Ok(None);
+        }
+        self.pos += 2;
+        self.expect('(')?;
+        let mut labels = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(')') {
+                self.pos += 1;
+                break;
+            }
+            labels.push(format_key(&self.ident()?));
+            self.skip_whitespace();
+            if self.peek() == Some(',') {
+                self.pos += 1;
+            }
+        }
+        Ok(Some(labels))
+    }
+
+    fn range_expr(&mut self) -> Result<(RangeFunc, i64, LogExpr), anyhow::Error> {
+        let func = match self.ident()?.as_str() {
+            "rate" => RangeFunc::Rate,
+            "count_over_time" => RangeFunc::CountOverTime,
+            name => return Err(anyhow::anyhow!("unsupported function: {name}")),
+        };
+        self.expect('(')?;
+        let log = self.log_expr()?;
+        self.expect('[')?;
+        let start = self.pos;
+        while self.peek().map_or(false, |c| c != ']') {
+            self.pos += 1;
+        }
+        let range: String = self.chars[start..self.pos].iter().collect();
+        let range = parse_milliseconds(range.trim())
+            .ok()
+            .filter(|v| *v > 0)
+            .ok_or_else(|| anyhow::anyhow!("invalid range: {range}"))?;
+        self.expect(']')?;
+        self.expect(')')?;
+        Ok((func, range as i64 * 1000, log))
+    }
+
+    fn log_expr(&mut self) -> Result<LogExpr, anyhow::Error> {
+        let selector = self.selector()?;
+        if selector.is_empty() {
+            return Err(anyhow::anyhow!(
+                "the stream selector should have at least one matcher"
+            ));
+        }
+        let mut pipeline = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let op = if self.rest_starts_with("|=") {
+                Op::Eq
+            } else if self.rest_starts_with("!=") {
+                Op::Neq
+            } else if self.rest_starts_with("|~") {
+                Op::Re
+            } else if self.rest_starts_with("!~") {
+                Op::Nre
+            } else if self.peek() == Some('|') {
+                self.pos += 1;
+                pipeline.push(self.stage()?);
+                continue;
+            } else {
+                break;
+            };
+            self.pos += 2;
+            pipeline.push(Stage::LineFilter(op, self.string()?));
+        }
+        Ok(LogExpr { selector, pipeline })
+    }
+
+    fn selector(&mut self) -> Result<Vec<Filter>, anyhow::Error> {
+        self.expect('{')?;
+        let mut filters = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some('}') {
+                self.pos += 1;
+                break;
+            }
+            let filter = self.filter()?;
+            if !matches!(filter.op, Op::Eq | Op::Neq | Op::Re | Op::Nre) {
+                return Err(anyhow::anyhow!(
+                    "unsupported operator in the stream selector: {}",
+                    filter.op.as_str()
+                ));
+            }
+            filters.push(filter);
+            self.skip_whitespace();
+            if self.peek() == Some(',') {
+                self.pos += 1;
+            }
+        }
+        Ok(filters)
+    }
+
+    fn stage(&mut self) -> Result<Stage, anyhow::Error> {
+        self.skip_whitespace();
+        let start = self.pos;
+        match self.ident()?.as_str() {
+            "json" => Ok(Stage::Json),
+            "logfmt" => Ok(Stage::Logfmt),
+            "line_format" => Ok(Stage::LineFormat(self.string()?)),
+            _ => {
+                self.pos = start;
+                Ok(Stage::LabelFilter(self.filter()?))
+            }
+        }
+    }
+
+    fn filter(&mut self) -> Result<Filter, anyhow::Error> {
+        let name = self.ident()?;
+        self.skip_whitespace();
+        let op = [
+            ("=~", Op::Re),
+            ("!~", Op::Nre),
+            ("!=", Op::Neq),
+            (">=", Op::Ge),
+            ("<=", Op::Le),
+            ("==", Op::Eq),
+            ("=", Op::Eq),
+            (">", Op::Gt),
+            ("<", Op::Lt),
+        ]
+        .into_iter()
+        .find(|(token, _)| self.rest_starts_with(token));
+        let Some((token, op)) = op else {
+            return Err(anyhow::anyhow!("expected an operator at {}", self.pos));
+        };
+        self.pos += token.len();
+        self.skip_whitespace();
+        let value = match self.peek() {
+            Some('"' | '`') => self.string()?,
+            _ => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .map_or(false, |c| c.is_ascii_alphanumeric() || ".-+_".contains(c))
+                {
+                    self.pos += 1;
+                }
+                if start == self.pos {
+                    return Err(anyhow::anyhow!("expected a value at {}", self.pos));
+                }
+                self.chars[start..self.pos].iter().collect()
+            }
+        };
+        Ok(Filter { name, op, value })
+    }
+
+    fn ident(&mut self) -> Result<String, anyhow::Error> {
+        self.skip_whitespace();
+        let start = self.pos;
+        while self
+            .peek()
+            .map_or(false, |c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err(anyhow::anyhow!("expected an identifier at {}", self.pos));
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    /// Parses a double quoted string with escapes, or a raw string between
+    /// backticks.
+    fn string(&mut self) -> Result<String, anyhow::Error> {
+        self.skip_whitespace();
+        let quote = match self.peek() {
+            Some(c @ ('"' | '`')) => c,
+            _ => return Err(anyhow::anyhow!("expected a string at {}", self.pos)),
+        };
+        self.pos += 1;
+        let mut value = String::new();
+        loop {
+            let c = self
+                .peek()
+                .ok_or_else(|| anyhow::anyhow!("unterminated string"))?;
+            self.pos += 1;
+            match c {
+                c if c == quote => break,
+                '\\' if quote == '"' => {
+                    let c = self
+                        .peek()
+                        .ok_or_else(|| anyhow::anyhow!("unterminated string"))?;
+                    self.pos += 1;
+                    value.push(match c {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        c => c,
+                    });
+                }
+                c => value.push(c),
+            }
+        }
+        Ok(value)
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), anyhow::Error> {
+        self.skip_whitespace();
+        if self.peek() != Some(c) {
+            return Err(anyhow::anyhow!("expected '{c}' at {}", self.pos));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn expect_end(&mut self) -> Result<(), anyhow::Error> {
+        self.skip_whitespace();
+        if self.pos < self.chars.len() {
+            return Err(anyhow::anyhow!("unexpected input at {}", self.pos));
+        }
+        Ok(())
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn rest_starts_with(&self, token: &str) -> bool {
+        token
+            .chars()
+            .enumerate()
+            .all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().map_or(false, char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log_expr(query: &str) -> LogExpr {
+        match parse(query).unwrap() {
+            Expr::Log(log) => log,
+            expr => panic!("expected a log query, got {expr:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_log_query() {
+        let log = log_expr(
+            r#"{app="cart", env=~"prod|stg"} |= "error" != `timeout` | json | level="error" | line_format "{{.msg}}""#,
+        );
+        assert_eq!(log.selector.len(), 2);
+        assert_eq!(log.selector[1].op, Op::Re);
+        assert_eq!(log.pipeline.len(), 5);
+        assert_eq!(log.post_stages().len(), 3);
+        assert_eq!(
+            log.condition(),
+            "\"app\" = 'cart' AND re_match(\"env\", '^(?:prod|stg)$') AND str_match(\"message\", 'error') AND NOT str_match(\"message\", 'timeout')"
+        );
+        assert_eq!(log.condition_fields(), vec!["app", "env", "message"]);
+        assert_eq!(log.stream_labels(), vec!["app", "env"]);
+
+        let log = log_expr(r#"{app=""} | status >= 500"#);
+        assert_eq!(
+            log.condition(),
+            "(\"app\" IS NULL OR \"app\" = '') AND \"status\" >= 500"
+        );
+
+        assert!(parse("{}").is_err());
+        assert!(parse(r#"{app>"1"}"#).is_err());
+        assert!(parse(r#"{app="cart"} |~ "(""#).is_err());
+        assert!(parse(r#"{app="cart"} |= "error"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_metric_query() {
+        let Expr::Metric(metric) =
+            parse(r#"sum by (app) (rate({env="prod"} |= "error" [5m]))"#).unwrap()
+        else {
+            panic!("expected a metric query");
+        };
+        assert_eq!(metric.func, RangeFunc::Rate);
+        assert_eq!(metric.range, 300_000_000);
+        assert_eq!(metric.grouping, Some(vec!["app".to_string()]));
+
+        let Expr::Metric(metric) =
+            parse(r#"sum(count_over_time({env="prod"}[1m])) by (host)"#).unwrap()
+        else {
+            panic!("expected a metric query");
+        };
+        assert_eq!(metric.func, RangeFunc::CountOverTime);
+        assert_eq!(metric.grouping, Some(vec!["host".to_string()]));
+
+        let Expr::Metric(metric) = parse(r#"count_over_time({env="prod"}[30s])"#).unwrap() else {
+            panic!("expected a metric query");
+        };
+        assert_eq!(metric.grouping, None);
+
+        assert!(parse(r#"rate({env="prod"} | json [5m])"#).is_err());
+        assert!(parse(r#"avg_over_time({env="prod"}[5m])"#).is_err());
+        assert!(parse(r#"rate({env="prod"})"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_labels() {
+        assert_eq!(
+            parse_labels(r#"{app="cart", pod="cart-1"}"#).unwrap(),
+            vec![
+                ("app".to_string(), "cart".to_string()),
+                ("pod".to_string(), "cart-1".to_string())
+            ]
+        );
+        assert!(parse_labels(r#"{app=~"cart"}"#).is_err());
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let log = log_expr(
+            r#"{app="cart"} | json | status >= 500 | line_format "{{.status}} {{ .req_path }}" |~ "^5""#,
+        );
+        let pipeline = Pipeline::new(log.post_stages()).unwrap();
+        let mut labels = BTreeMap::new();
+        let line = pipeline.process(
+            r#"{"status":503,"req":{"path":"/cart"}}"#.to_string(),
+            &mut labels,
+        );
+        assert_eq!(line.as_deref(), Some("503 /cart"));
+        assert_eq!(labels["req_path"], "/cart");
+
+        let mut labels = BTreeMap::new();
+        assert!(
+            pipeline
+                .process(r#"{"status":200}"#.to_string(), &mut labels)
+                .is_none()
+        );
+
+        let log = log_expr(r#"{app="cart"} | logfmt | level!="debug""#);
+        let pipeline = Pipeline::new(log.post_stages()).unwrap();
+        let mut labels = BTreeMap::new();
+        let line = r#"level=info msg="cart updated" items=3"#;
+        assert_eq!(
+            pipeline.process(line.to_string(), &mut labels).as_deref(),
+            Some(line)
+        );
+        assert_eq!(labels["msg"], "cart updated");
+        assert!(
+            pipeline
+                .process("level=debug".to_string(), &mut BTreeMap::new())
+                .is_none()
+        );
+
+        let log = log_expr(r#"{app="cart"} | json"#);
+        let pipeline = Pipeline::new(log.post_stages()).unwrap();
+        let mut labels = BTreeMap::new();
+        pipeline.process("not json".to_string(), &mut labels);
+        assert_eq!(labels[ERROR_LABEL], "JSONParserErr");
+    }
+}
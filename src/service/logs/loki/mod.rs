@@ -0,0 +1,720 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Loki compatible APIs: the push API of the log shippers, such as Promtail
+//! and the Grafana Agent, and the query API read by the Grafana Loki
+//! datasource, translating a subset of LogQL onto the SQL search.
+
+use std::collections::{BTreeMap, HashMap};
+
+use actix_web::web;
+use chrono::{Duration, Utc};
+use prost::Message;
+
+use self::logql::{Expr, LogExpr, MetricExpr, Pipeline, RangeFunc, Stage, LINE_FIELD};
+use crate::{
+    common::{
+        infra::{config::CONFIG, errors},
+        meta::{
+            ingestion::IngestionRequest,
+            loki::{PushRequest, QueryResult, Series, Stream},
+            search, StreamType,
+        },
+        utils::{
+            flatten::format_key,
+            json,
+            time::{
+                parse_milliseconds, parse_str_to_timestamp_micros, parse_timestamp_micro_from_value,
+            },
+        },
+    },
+    handler::http::request::CONTENT_TYPE_PROTO,
+    service::{db, format_stream_name, search as search_service},
+};
+
+pub mod logql;
+
+pub(crate) mod logproto {
+    include!(concat!(env!("OUT_DIR"), "/logproto.rs"));
+}
+
+/// The stream written and queried without a stream name header.
+const DEFAULT_STREAM: &str = "default";
+
+/// Maximum number of the buckets read by a metric query.
+const MAX_BUCKETS: usize = 100_000;
+
+/// Maximum number of the values of a label.
+const MAX_LABEL_VALUES: usize = 1000;
+
+/// Maximum number of the points of a series, as Loki limits the resolution.
+const MAX_POINTS: i64 = 11_000;
+
+/// Records read per page when the lines are filtered after the parsers.
+const FILTERED_PAGE_SIZE: usize = 1000;
+
+/// Maximum number of the records read by a log query filtering the lines after
+/// the parsers, the lines matched until then are returned.
+const MAX_FILTERED_RECORDS: usize = 100_000;
+
+/// The query of the `query_range` API, the times in microseconds.
+#[derive(Clone, Debug)]
+pub struct QueryRangeRequest {
+    pub start: i64,
+    pub end: i64,
+    pub step: i64,
+    /// Maximum number of lines returned by a log query.
+    pub limit: usize,
+    /// Returns the oldest lines first.
+    pub forward: bool,
+}
+
+/// Ingests the streams of a push request, their labels and structured
+/// metadata become the fields of the records. Fails when some of the entries
+/// could not be ingested, as Loki does.
+pub async fn push(
+    org_id: &str,
+    in_stream_name: Option<&str>,
+    content_type: &str,
+    body: &web::Bytes,
+    thread_id: usize,
+) -> Result<(), anyhow::Error> {
+    let records = if content_type.starts_with(CONTENT_TYPE_PROTO) {
+        proto_records(body)?
+    } else {
+        json_records(body)?
+    };
+    let resp = super::ingest::ingest(
+        org_id,
+        in_stream_name.unwrap_or(DEFAULT_STREAM),
+        IngestionRequest::Loki(&records),
+        thread_id,
+    )
+    .await?;
+    match resp.failed() {
+        (0, _) => Ok(()),
+        (failed, error) => Err(anyhow::anyhow!(
+            "{failed} of {} entries failed: {}",
+            records.len(),
+            error.unwrap_or_default()
+        )),
+    }
+}
+
+/// Decodes the snappy compressed protobuf push request.
+fn proto_records(body: &[u8]) -> Result<Vec<json::Value>, anyhow::Error> {
+    let decoded = snap::raw::Decoder::new()
+        .decompress_vec(body)
+        .map_err(|e| anyhow::anyhow!("Invalid snappy compressed data: {}", e.to_string()))?;
+    let request = logproto::PushRequest::decode(bytes::Bytes::from(decoded))
+        .map_err(|e| anyhow::anyhow!("Invalid protobuf: {}", e.to_string()))?;
+    let mut records = Vec::new();
+    for stream in request.streams {
+        let labels = logql::parse_labels(&stream.labels)
+            .map_err(|e| anyhow::anyhow!("Invalid labels {}: {}", stream.labels, e))?;
+        for entry in stream.entries {
+            let timestamp = entry
+                .timestamp
+                .map_or(0, |t| t.seconds * 1_000_000 + t.nanos as i64 / 1000);
+            let metadata = entry
+                .structured_metadata
+                .into_iter()
+                .map(|label| (label.name, label.value));
+            records.push(record(&labels, metadata, entry.line, timestamp));
+        }
+    }
+    Ok(records)
+}
+
+/// Decodes the JSON push request.
+fn json_records(body: &[u8]) -> Result<Vec<json::Value>, anyhow::Error> {
+    let request: PushRequest = json::from_slice(body)?;
+    let mut records = Vec::new();
+    for stream in request.streams {
+        let labels: Vec<(String, String)> = stream.stream.into_iter().collect();
+        for entry in stream.values {
+            let (timestamp, line) = match entry.as_slice() {
+                [timestamp, json::Value::String(line), ..] => (timestamp, line),
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "the values should be [timestamp, line] arrays"
+                    ));
+                }
+            };
+            let timestamp = parse_timestamp_micro_from_value(timestamp)?;
+            let metadata = entry
+                .get(2)
+                .and_then(|v| v.as_object())
+                .into_iter()
+                .flatten()
+                .map(|(name, value)| (name.clone(), logql::value_string(value)));
+            records.push(record(&labels, metadata, line.clone(), timestamp));
+        }
+    }
+    Ok(records)
+}
+
+fn record(
+    labels: &[(String, String)],
+    metadata: impl Iterator<Item = (String, String)>,
+    line: String,
+    timestamp: i64,
+) -> json::Value {
+    let mut record = json::Map::new();
+    for (name, value) in labels.iter() {
+        record.insert(name.clone(), json::Value::String(value.clone()));
+    }
+    for (name, value) in metadata {
+        record.insert(name, json::Value::String(value));
+    }
+    record.insert(LINE_FIELD.to_string(), json::Value::String(line));
+    record.insert(
+        CONFIG.common.column_timestamp.clone(),
+        json::Value::Number(timestamp.into()),
+    );
+    json::Value::Object(record)
+}
+
+/// Parses the time range of a query, the times are in nanoseconds, seconds or
+/// RFC3339. The range is the last hour by default.
+pub fn time_range(start: Option<&str>, end: Option<&str>) -> Result<(i64, i64), anyhow::Error> {
+    let end = match end {
+        Some(end) => parse_str_to_timestamp_micros(end)
+            .map_err(|e| anyhow::anyhow!("invalid parameter \"end\": {e}"))?,
+        None => Utc::now().timestamp_micros(),
+    };
+    let start = match start {
+        Some(start) => parse_str_to_timestamp_micros(start)
+            .map_err(|e| anyhow::anyhow!("invalid parameter \"start\": {e}"))?,
+        None => end - Duration::hours(1).num_microseconds().unwrap(),
+    };
+    if end < start {
+        return Err(anyhow::anyhow!(
+            "invalid time range: the end is before the start"
+        ));
+    }
+    Ok((start, end))
+}
+
+/// Parses the step of a query in microseconds, a duration such as `15s` or a
+/// number of seconds. By default the range is split into 250 steps of at
+/// least one second, as Loki does.
+pub fn parse_step(step: Option<&str>, start: i64, end: i64) -> Result<i64, anyhow::Error> {
+    let step = match step {
+        Some(step) => match step.parse::<f64>() {
+            Ok(secs) => (secs * 1_000_000.0) as i64,
+            Err(_) => {
+                parse_milliseconds(step)
+                    .map_err(|e| anyhow::anyhow!("invalid parameter \"step\": {e}"))?
+                    as i64
+                    * 1000
+            }
+        },
+        None => (end - start) / 250 / 1_000_000 * 1_000_000,
+    };
+    let step = step.max(1_000_000);
+    if (end - start) / step > MAX_POINTS {
+        return Err(anyhow::anyhow!(
+            "exceeded maximum resolution of {MAX_POINTS} points per timeseries. Try increasing the value of the step parameter"
+        ));
+    }
+    Ok(step)
+}
+
+/// Returns the stream queried, named by the stream name header.
+pub fn stream_name(in_stream_name: Option<&str>) -> String {
+    format_stream_name(in_stream_name.unwrap_or(DEFAULT_STREAM))
+}
+
+pub async fn query_range(
+    org_id: &str,
+    stream_name: &str,
+    expr: &Expr,
+    req: &QueryRangeRequest,
+) -> errors::Result<QueryResult> {
+    let fields = stream_fields(org_id, stream_name).await;
+    let log = match expr {
+        Expr::Log(log) => log,
+        Expr::Metric(metric) => &metric.log,
+    };
+    // a condition on a missing field fails the SQL query, nothing matches
+    let missing = log
+        .condition_fields()
+        .iter()
+        .any(|field| !fields.contains(field));
+    match expr {
+        Expr::Log(_) if missing => Ok(QueryResult::Streams(vec![])),
+        Expr::Metric(_) if missing => Ok(QueryResult::Matrix(vec![])),
+        Expr::Log(log) => query_logs(org_id, stream_name, log, req).await,
+        Expr::Metric(metric) => query_metric(org_id, stream_name, metric, &fields, req).await,
+    }
+}
+
+async fn query_logs(
+    org_id: &str,
+    stream_name: &str,
+    log: &LogExpr,
+    req: &QueryRangeRequest,
+) -> errors::Result<QueryResult> {
+    let order = if req.forward { "ASC" } else { "DESC" };
+    let sql = format!(
+        "SELECT * FROM \"{stream_name}\" WHERE {} ORDER BY {} {order}",
+        log.condition(),
+        CONFIG.common.column_timestamp
+    );
+    let pipeline =
+        Pipeline::new(log.post_stages()).map_err(|e| errors::Error::Message(e.to_string()))?;
+    let stream_labels = log.stream_labels();
+    // the filters after the parsers can't run in SQL, the records are read by
+    // pages until enough lines pass them
+    let filtered = log
+        .post_stages()
+        .iter()
+        .any(|stage| matches!(stage, Stage::LineFilter(..) | Stage::LabelFilter(_)));
+    let page_size = if filtered {
+        req.limit.max(FILTERED_PAGE_SIZE)
+    } else {
+        req.limit
+    };
+    let mut streams = Streams::default();
+    let mut from = 0;
+    loop {
+        let resp = search(org_id, sql.clone(), req.start, req.end, from, page_size).await?;
+        streams.extend(&resp.hits, &stream_labels, &pipeline, req.limit);
+        from += resp.hits.len();
+        if !filtered
+            || streams.lines >= req.limit
+            || resp.hits.len() < page_size
+            || from >= MAX_FILTERED_RECORDS
+        {
+            break;
+        }
+    }
+    Ok(QueryResult::Streams(streams.streams))
+}
+
+/// The lines of a log query grouped by the labels of the selector and the
+/// labels extracted by the pipeline.
+#[derive(Default)]
+struct Streams {
+    streams: Vec<Stream>,
+    positions: HashMap<BTreeMap<String, String>, usize>,
+    lines: usize,
+}
+
+impl Streams {
+    /// Adds the lines of the records passing the pipeline, until there are
+    /// `limit` lines.
+    fn extend(
+        &mut self,
+        hits: &[json::Value],
+        stream_labels: &[String],
+        pipeline: &Pipeline,
+        limit: usize,
+    ) {
+        for hit in hits {
+            if self.lines >= limit {
+                return;
+            }
+            if let Some((labels, line)) = process_hit(hit, stream_labels, pipeline) {
+                self.push(labels, line);
+            }
+        }
+    }
+
+    fn push(&mut self, labels: BTreeMap<String, String>, line: [String; 2]) {
+        let streams = &mut self.streams;
+        let pos = *self.positions.entry(labels.clone()).or_insert_with(|| {
+            streams.push(Stream {
+                stream: labels,
+                values: vec![],
+            });
+            streams.len() - 1
+        });
+        streams[pos].values.push(line);
+        self.lines += 1;
+    }
+}
+
+/// Returns the labels and the timestamped line of the record, `None` when the
+/// line is filtered out by the pipeline.
+fn process_hit(
+    hit: &json::Value,
+    stream_labels: &[String],
+    pipeline: &Pipeline,
+) -> Option<(BTreeMap<String, String>, [String; 2])> {
+    let hit = hit.as_object()?;
+    let mut labels: BTreeMap<String, String> = stream_labels
+        .iter()
+        .filter_map(|name| Some((name.clone(), logql::value_string(hit.get(name)?))))
+        .collect();
+    let timestamp = hit
+        .get(&CONFIG.common.column_timestamp)
+        .and_then(|v| v.as_i64())
+        .unwrap_or_default();
+    let line = match hit.get(LINE_FIELD) {
+        Some(line) => logql::value_string(line),
+        // the records without a line read as JSON
+        None => {
+            let mut hit = hit.clone();
+            hit.remove(&CONFIG.common.column_timestamp);
+            json::Value::Object(hit).to_string()
+        }
+    };
+    let line = pipeline.process(line, &mut labels)?;
+    Some((labels, [(timestamp * 1000).to_string(), line]))
+}
+
+/// Counts the lines in buckets of the greatest common divisor of the step and
+/// of the range, and sums the buckets of the range before every step. The
+/// windows are exact when the start is a multiple of the bucket.
+async fn query_metric(
+    org_id: &str,
+    stream_name: &str,
+    metric: &MetricExpr,
+    fields: &[String],
+    req: &QueryRangeRequest,
+) -> errors::Result<QueryResult> {
+    let labels: Vec<String> = match &metric.grouping {
+        Some(labels) => labels.clone(),
+        None => metric.log.stream_labels(),
+    };
+    // the missing labels are empty
+    let labels: Vec<String> = labels
+        .into_iter()
+        .filter(|label| fields.contains(label))
+        .collect();
+    let bucket = bucket_seconds(req.step, metric.range);
+    let columns: String = labels.iter().map(|l| format!(", \"{l}\"")).collect();
+    let sql = format!(
+        "SELECT histogram({}, '{bucket} second') AS zo_sql_key{columns}, COUNT(*) AS zo_sql_num FROM \"{stream_name}\" WHERE {} GROUP BY zo_sql_key{columns} ORDER BY zo_sql_key",
+        CONFIG.common.column_timestamp,
+        metric.log.condition(),
+    );
+    let resp = search(
+        org_id,
+        sql,
+        req.start - metric.range,
+        req.end,
+        0,
+        MAX_BUCKETS,
+    )
+    .await?;
+    Ok(QueryResult::Matrix(to_series(
+        &resp.hits, &labels, metric, req,
+    )))
+}
+
+/// The bucket of a metric query in seconds, at least one second.
+fn bucket_seconds(step: i64, range: i64) -> i64 {
+    let (mut a, mut b) = ((step / 1_000_000).max(1), (range / 1_000_000).max(1));
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+fn to_series(
+    rows: &[json::Value],
+    labels: &[String],
+    metric: &MetricExpr,
+    req: &QueryRangeRequest,
+) -> Vec<Series> {
+    let mut buckets: BTreeMap<BTreeMap<String, String>, BTreeMap<i64, u64>> = BTreeMap::new();
+    for row in rows {
+        let Some(bucket) = row
+            .get("zo_sql_key")
+            .and_then(|v| v.as_str())
+            .and_then(|v| parse_str_to_timestamp_micros(v).ok())
+        else {
+            continue;
+        };
+        let count = row.get("zo_sql_num").and_then(|v| v.as_u64()).unwrap_or(0);
+        let metric_labels = labels
+            .iter()
+            .filter_map(|name| Some((name.clone(), logql::value_string(row.get(name)?))))
+            .filter(|(_, value)| !value.is_empty())
+            .collect();
+        *buckets
+            .entry(metric_labels)
+            .or_default()
+            .entry(bucket)
+            .or_default() += count;
+    }
+
+    let step = req.step.max(1);
+    buckets
+        .into_iter()
+        .filter_map(|(labels, counts)| {
+            let mut values = Vec::new();
+            let mut t = req.start;
+            while t <= req.end {
+                let count: u64 = counts.range(t - metric.range..t).map(|(_, v)| v).sum();
+                if count > 0 {
+                    let value = match metric.func {
+                        RangeFunc::CountOverTime => count as f64,
+                        RangeFunc::Rate => count as f64 / (metric.range as f64 / 1_000_000.0),
+                    };
+                    values.push((t as f64 / 1_000_000.0, value.to_string()));
+                }
+                t += step;
+            }
+            (!values.is_empty()).then_some(Series {
+                metric: labels,
+                values,
+            })
+        })
+        .collect()
+}
+
+/// Returns the labels, the fields of the stream other than the timestamp and
+/// the line.
+pub async fn labels(org_id: &str, stream_name: &str) -> Vec<String> {
+    let mut fields = stream_fields(org_id, stream_name).await;
+    fields.retain(|field| *field != CONFIG.common.column_timestamp && field.as_str() != LINE_FIELD);
+    fields.sort();
+    fields
+}
+
+/// Returns the values of the label in the time range, in the streams matching
+/// the selector when given.
+pub async fn label_values(
+    org_id: &str,
+    stream_name: &str,
+    label: &str,
+    selector: Option<&LogExpr>,
+    start: i64,
+    end: i64,
+) -> errors::Result<Vec<String>> {
+    let label = format_key(label);
+    let fields = stream_fields(org_id, stream_name).await;
+    if !fields.contains(&label) {
+        return Ok(vec![]);
+    }
+    let mut sql =
+        format!("SELECT \"{label}\" AS zo_sql_key, COUNT(*) AS zo_sql_num FROM \"{stream_name}\"");
+    if let Some(selector) = selector {
+        if selector
+            .condition_fields()
+            .iter()
+            .any(|field| !fields.contains(field))
+        {
+            return Ok(vec![]);
+        }
+        sql.push_str(&format!(" WHERE {}", selector.condition()));
+    }
+    sql.push_str(" GROUP BY zo_sql_key ORDER BY zo_sql_key");
+    let resp = search(org_id, sql, start, end, 0, MAX_LABEL_VALUES).await?;
+    Ok(resp
+        .hits
+        .iter()
+        .filter_map(|hit| hit.get("zo_sql_key"))
+        .map(logql::value_string)
+        .filter(|value| !value.is_empty())
+        .collect())
+}
+
+async fn stream_fields(org_id: &str, stream_name: &str) -> Vec<String> {
+    db::schema::get(org_id, stream_name, StreamType::Logs)
+        .await
+        // `db::schema::get` never fails, so it's safe to unwrap
+        .unwrap()
+        .fields()
+        .iter()
+        .map(|field| field.name().to_string())
+        .collect()
+}
+
+async fn search(
+    org_id: &str,
+    sql: String,
+    start_time: i64,
+    end_time: i64,
+    from: usize,
+    size: usize,
+) -> errors::Result<search::Response> {
+    let req = search::Request {
+        query: search::Query {
+            sql,
+            from,
+            size,
+            start_time,
+            end_time,
+            sql_mode: "full".to_string(),
+            ..Default::default()
+        },
+        aggs: HashMap::new(),
+        encoding: search::RequestEncoding::Empty,
+        timeout: 0,
+    };
+    search_service::search("", org_id, StreamType::Logs, &req)
+        .await
+        .map_err(|e| {
+            log::error!("search loki query error: {e}");
+            e
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proto_records() {
+        let request = logproto::PushRequest {
+            streams: vec![logproto::StreamAdapter {
+                labels: r#"{app="cart", env="prod"}"#.to_string(),
+                entries: vec![logproto::EntryAdapter {
+                    timestamp: Some(logproto::Timestamp {
+                        seconds: 1_700_000_000,
+                        nanos: 5_000,
+                    }),
+                    line: "cart updated".to_string(),
+                    structured_metadata: vec![logproto::LabelPairAdapter {
+                        name: "trace_id".to_string(),
+                        value: "abc".to_string(),
+                    }],
+                }],
+                hash: 0,
+            }],
+        };
+        let body = snap::raw::Encoder::new()
+            .compress_vec(&request.encode_to_vec())
+            .unwrap();
+        let records = proto_records(&body).unwrap();
+        assert_eq!(
+            records,
+            vec![json::json!({
+                "app": "cart",
+                "env": "prod",
+                "trace_id": "abc",
+                "message": "cart updated",
+                "_timestamp": 1_700_000_000_000_005i64,
+            })]
+        );
+        assert!(proto_records(b"not snappy").is_err());
+    }
+
+    #[test]
+    fn test_json_records() {
+        let body = br#"{"streams":[{"stream":{"app":"cart"},"values":[["1700000000000000000","cart updated",{"trace_id":"abc"}],["1700000001000000000","cart read"]]}]}"#;
+        let records = json_records(body).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["trace_id"], "abc");
+        assert_eq!(records[0]["_timestamp"], 1_700_000_000_000_000i64);
+        assert_eq!(records[1]["message"], "cart read");
+        assert!(json_records(br#"{"streams":[{"values":[["1700000000000000000"]]}]}"#).is_err());
+    }
+
+    #[test]
+    fn test_to_streams() {
+        let hits = vec![
+            json::json!({"_timestamp": 2, "app": "cart", "message": "{\"level\":\"error\"}"}),
+            json::json!({"_timestamp": 1, "app": "web", "message": "{\"level\":\"info\"}"}),
+        ];
+        let Expr::Log(log) = logql::parse(r#"{app=~".+"} | json"#).unwrap() else {
+            panic!("expected a log query");
+        };
+        let pipeline = Pipeline::new(log.post_stages()).unwrap();
+        let mut streams = Streams::default();
+        streams.extend(&hits, &log.stream_labels(), &pipeline, 100);
+        let streams = streams.streams;
+        assert_eq!(streams.len(), 2);
+        assert_eq!(streams[0].stream["app"], "cart");
+        assert_eq!(streams[0].stream["level"], "error");
+        assert_eq!(
+            streams[0].values,
+            vec![["2000".to_string(), "{\"level\":\"error\"}".to_string()]]
+        );
+    }
+
+    #[test]
+    fn test_streams_limit() {
+        let hits: Vec<json::Value> = (0..4)
+            .map(|i| {
+                let level = if i % 2 == 0 { "error" } else { "info" };
+                json::json!({"_timestamp": i, "app": "cart", "message": format!("level={level}")})
+            })
+            .collect();
+        let Expr::Log(log) = logql::parse(r#"{app="cart"} | logfmt | level="error""#).unwrap()
+        else {
+            panic!("expected a log query");
+        };
+        let pipeline = Pipeline::new(log.post_stages()).unwrap();
+        let mut streams = Streams::default();
+        // the lines filtered out don't count, the pages add up to the limit
+        streams.extend(&hits[..2], &log.stream_labels(), &pipeline, 2);
+        assert_eq!(streams.lines, 1);
+        streams.extend(&hits[2..], &log.stream_labels(), &pipeline, 2);
+        assert_eq!(streams.lines, 2);
+        assert_eq!(streams.streams.len(), 1);
+        assert_eq!(streams.streams[0].values[1][0], "2000");
+        streams.extend(&hits, &log.stream_labels(), &pipeline, 2);
+        assert_eq!(streams.lines, 2);
+    }
+
+    #[test]
+    fn test_to_series() {
+        let Expr::Metric(metric) =
+            logql::parse(r#"sum by (app) (count_over_time({env="prod"}[2m]))"#).unwrap()
+        else {
+            panic!("expected a metric query");
+        };
+        assert_eq!(bucket_seconds(60_000_000, metric.range), 60);
+        let rows = vec![
+            json::json!({"zo_sql_key": "2023-11-14T22:12:00", "app": "cart", "zo_sql_num": 2}),
+            json::json!({"zo_sql_key": "2023-11-14T22:13:00", "app": "cart", "zo_sql_num": 3}),
+            json::json!({"zo_sql_key": "2023-11-14T22:13:00", "app": "web", "zo_sql_num": 1}),
+        ];
+        // 2023-11-14T22:13:00
+        let start = 1_699_999_980_000_000;
+        let req = QueryRangeRequest {
+            start,
+            end: start + 120_000_000,
+            step: 60_000_000,
+            limit: 0,
+            forward: false,
+        };
+        let series = to_series(&rows, &["app".to_string()], &metric, &req);
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[0].metric["app"], "cart");
+        assert_eq!(
+            series[0].values,
+            vec![
+                (1_699_999_980.0, "2".to_string()),
+                (1_700_000_040.0, "5".to_string()),
+                (1_700_000_100.0, "3".to_string())
+            ]
+        );
+        assert_eq!(series[1].values.len(), 2);
+    }
+
+    #[test]
+    fn test_time_params() {
+        let (start, end) =
+            time_range(Some("1700000000000000000"), Some("2023-11-14T22:23:20Z")).unwrap();
+        assert_eq!(start, 1_700_000_000_000_000);
+        assert_eq!(end, 1_700_000_600_000_000);
+        assert!(time_range(Some("2023-11-14T22:23:20Z"), Some("1700000000")).is_err());
+
+        assert_eq!(parse_step(Some("15s"), 0, 0).unwrap(), 15_000_000);
+        assert_eq!(parse_step(Some("30"), 0, 0).unwrap(), 30_000_000);
+        assert_eq!(parse_step(None, 0, 3_600_000_000).unwrap(), 14_000_000);
+        assert_eq!(parse_step(None, 0, 60_000_000).unwrap(), 1_000_000);
+        // at most 11000 points per series
+        assert!(parse_step(Some("1s"), 0, 11_000_000_000).is_ok());
+        assert!(parse_step(Some("1s"), 0, 11_001_000_000).is_err());
+    }
+}
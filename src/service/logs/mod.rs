@@ -42,9 +42,11 @@ use crate::{
 
 pub mod bulk;
 pub mod ingest;
+pub mod loki;
 pub mod multi;
 pub mod otlp_grpc;
 pub mod otlp_http;
+pub mod splunk;
 pub mod syslog;
 
 static BULK_OPERATORS: [&str; 3] = ["create", "index", "update"];
@@ -0,0 +1,272 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Splunk HTTP Event Collector compatible ingestion: the events are grouped
+//! by their index, the stream written, and ingested as multi-line JSON.
+
+use actix_web::web;
+use ahash::AHashMap;
+
+use crate::common::{
+    infra::config::CONFIG,
+    meta::ingestion::{HecEvent, HecParams, HecResponse},
+    utils::json,
+};
+
+/// The stream written by the events without an index.
+const DEFAULT_STREAM: &str = "default";
+
+/// The field holding the events which are not JSON objects, and the lines of
+/// the raw endpoint.
+const MESSAGE_FIELD: &str = "message";
+
+/// Ingests the concatenated JSON events of the `event` endpoint.
+pub async fn ingest_events(
+    org_id: &str,
+    body: &[u8],
+    params: &HecParams,
+    thread_id: usize,
+) -> HecResponse {
+    match event_records(body, params) {
+        Ok(streams) => ingest(org_id, streams, thread_id).await,
+        Err(resp) => resp,
+    }
+}
+
+/// Ingests the lines of the `raw` endpoint, one event per line.
+pub async fn ingest_raw(
+    org_id: &str,
+    body: &[u8],
+    params: &HecParams,
+    thread_id: usize,
+) -> HecResponse {
+    match raw_records(body, params) {
+        Ok(streams) => ingest(org_id, streams, thread_id).await,
+        Err(resp) => resp,
+    }
+}
+
+async fn ingest(
+    org_id: &str,
+    streams: AHashMap<String, Vec<json::Value>>,
+    thread_id: usize,
+) -> HecResponse {
+    let extend_json = AHashMap::new();
+    for (stream_name, records) in streams {
+        let body = records
+            .iter()
+            .map(|record| record.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let resp = match super::multi::ingest_with_keys(
+            org_id,
+            &stream_name,
+            web::Bytes::from(body),
+            &extend_json,
+            thread_id,
+        )
+        .await
+        {
+            Ok(resp) => resp,
+            Err(e) => {
+                log::error!("Error processing splunk hec request: {:?}", e);
+                return HecResponse::internal_error();
+            }
+        };
+        // the events are sent again by the forwarder when they aren't
+        // acknowledged, as a whole
+        let (failed, error) = resp.failed();
+        if failed > 0 {
+            log::error!(
+                "Error processing splunk hec request: {failed} events of stream {stream_name} failed: {}",
+                error.unwrap_or_default()
+            );
+            return HecResponse::events_failed();
+        }
+    }
+    HecResponse::success()
+}
+
+/// Parses the events, keyed by the stream. The events are numbered from 0 in
+/// the errors, as the collector does.
+fn event_records(
+    body: &[u8],
+    params: &HecParams,
+) -> Result<AHashMap<String, Vec<json::Value>>, HecResponse> {
+    let mut streams: AHashMap<String, Vec<json::Value>> = AHashMap::new();
+    let events = serde_json::Deserializer::from_slice(body).into_iter::<HecEvent>();
+    for (i, event) in events.enumerate() {
+        let event = event.map_err(|_| HecResponse::invalid_data_format(i))?;
+        let mut record = match event.event {
+            None => return Err(HecResponse::event_required(i)),
+            Some(json::Value::Null) => return Err(HecResponse::event_blank(i)),
+            Some(json::Value::String(v)) if v.is_empty() => {
+                return Err(HecResponse::event_blank(i));
+            }
+            Some(json::Value::Object(fields)) => fields,
+            Some(json::Value::String(v)) => {
+                json::Map::from_iter([(MESSAGE_FIELD.to_string(), json::Value::String(v))])
+            }
+            Some(v) => json::Map::from_iter([(MESSAGE_FIELD.to_string(), v)]),
+        };
+        record.extend(event.fields);
+        let metadata = HecParams {
+            index: None,
+            host: event.host.or_else(|| params.host.clone()),
+            source: event.source.or_else(|| params.source.clone()),
+            sourcetype: event.sourcetype.or_else(|| params.sourcetype.clone()),
+        };
+        add_metadata(&mut record, &metadata);
+        if let Some(time) = event.time {
+            let time = match &time {
+                json::Value::Number(v) => v.as_f64(),
+                json::Value::String(v) => v.parse::<f64>().ok(),
+                _ => None,
+            }
+            .ok_or_else(|| HecResponse::invalid_data_format(i))?;
+            record.insert(
+                CONFIG.common.column_timestamp.clone(),
+                json::Value::from((time * 1_000_000.0) as i64),
+            );
+        }
+        let stream_name = event
+            .index
+            .as_deref()
+            .or(params.index.as_deref())
+            .unwrap_or(DEFAULT_STREAM);
+        streams
+            .entry(stream_name.to_string())
+            .or_default()
+            .push(json::Value::Object(record));
+    }
+    if streams.is_empty() {
+        return Err(HecResponse::no_data());
+    }
+    Ok(streams)
+}
+
+fn raw_records(
+    body: &[u8],
+    params: &HecParams,
+) -> Result<AHashMap<String, Vec<json::Value>>, HecResponse> {
+    let records: Vec<json::Value> = String::from_utf8_lossy(body)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut record = json::Map::from_iter([(
+                MESSAGE_FIELD.to_string(),
+                json::Value::String(line.to_string()),
+            )]);
+            add_metadata(&mut record, params);
+            json::Value::Object(record)
+        })
+        .collect();
+    if records.is_empty() {
+        return Err(HecResponse::no_data());
+    }
+    let stream_name = params.index.as_deref().unwrap_or(DEFAULT_STREAM);
+    Ok(AHashMap::from_iter([(stream_name.to_string(), records)]))
+}
+
+fn add_metadata(record: &mut json::Map<String, json::Value>, metadata: &HecParams) {
+    for (name, value) in [
+        ("host", &metadata.host),
+        ("source", &metadata.source),
+        ("sourcetype", &metadata.sourcetype),
+    ] {
+        if let Some(value) = value {
+            record.insert(name.to_string(), json::Value::String(value.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_records() {
+        let body = br#"{"time": 1700000000.5, "host": "fw-1", "index": "firewall", "event": {"action": "deny"}, "fields": {"zone": "dmz"}}
+{"event": "link down", "sourcetype": "syslog"}{"event": 42}"#;
+        let params = HecParams {
+            source: Some("appliance".to_string()),
+            ..Default::default()
+        };
+        let streams = event_records(body, &params).unwrap();
+        assert_eq!(
+            streams["firewall"],
+            vec![json::json!({
+                "action": "deny",
+                "zone": "dmz",
+                "host": "fw-1",
+                "source": "appliance",
+                "_timestamp": 1_700_000_000_500_000i64,
+            })]
+        );
+        assert_eq!(
+            streams["default"],
+            vec![
+                json::json!({"message": "link down", "source": "appliance", "sourcetype": "syslog"}),
+                json::json!({"message": 42, "source": "appliance"}),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_event_records_errors() {
+        let params = HecParams::default();
+        assert_eq!(
+            event_records(b"", &params).unwrap_err(),
+            HecResponse::no_data()
+        );
+        assert_eq!(
+            event_records(br#"{"event": "a"}{"host": "b"}"#, &params).unwrap_err(),
+            HecResponse::event_required(1)
+        );
+        assert_eq!(
+            event_records(br#"{"event": ""}"#, &params).unwrap_err(),
+            HecResponse::event_blank(0)
+        );
+        assert_eq!(
+            event_records(br#"{"event": "a"} not json"#, &params).unwrap_err(),
+            HecResponse::invalid_data_format(1)
+        );
+        assert_eq!(
+            event_records(br#"{"event": "a", "time": "yesterday"}"#, &params).unwrap_err(),
+            HecResponse::invalid_data_format(0)
+        );
+    }
+
+    #[test]
+    fn test_raw_records() {
+        let params = HecParams {
+            index: Some("appliance".to_string()),
+            host: Some("fw-1".to_string()),
+            ..Default::default()
+        };
+        let streams = raw_records(b"link up\n\nlink down\n", &params).unwrap();
+        assert_eq!(
+            streams["appliance"],
+            vec![
+                json::json!({"message": "link up", "host": "fw-1"}),
+                json::json!({"message": "link down", "host": "fw-1"}),
+            ]
+        );
+        assert_eq!(
+            raw_records(b"\n", &params).unwrap_err(),
+            HecResponse::no_data()
+        );
+    }
+}
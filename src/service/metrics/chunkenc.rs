@@ -0,0 +1,270 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Prometheus XOR chunks and the framing of the streamed remote-read
+//! responses.
+//!
+//! See <https://github.com/prometheus/prometheus/blob/main/tsdb/chunkenc/xor.go>
+//! and <https://github.com/prometheus/prometheus/blob/main/storage/remote/chunked.go>.
+
+use crc::{Crc, CRC_32_ISCSI};
+
+/// Maximum number of samples of a chunk, as cut by the Prometheus TSDB.
+pub(crate) const MAX_SAMPLES_PER_CHUNK: usize = 120;
+
+const CASTAGNOLI: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+
+#[derive(Debug, Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// Bits still free in the last byte.
+    free: u8,
+}
+
+impl BitWriter {
+    fn write_bit(&mut self, bit: bool) {
+        if self.free == 0 {
+            self.bytes.push(0);
+            self.free = 8;
+        }
+        self.free -= 1;
+        if bit {
+            *self.bytes.last_mut().unwrap() |= 1 << self.free;
+        }
+    }
+
+    /// Writes the `nbits` lowest bits of `value`, most significant first.
+    fn write_bits(&mut self, value: u64, nbits: u32) {
+        for i in (0..nbits).rev() {
+            self.write_bit((value >> i) & 1 == 1);
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.write_bits(u64::from(*b), 8);
+        }
+    }
+}
+
+/// Encodes samples, given as millisecond timestamps and values, into a XOR
+/// chunk. At most [`MAX_SAMPLES_PER_CHUNK`] samples should go into a chunk.
+pub(crate) fn encode_xor_chunk(samples: &[(i64, f64)]) -> Vec<u8> {
+    let mut w = BitWriter::default();
+    w.write_bits(samples.len() as u64, 16);
+
+    let mut t_prev = 0;
+    let mut t_delta = 0;
+    let mut v_prev = 0.0;
+    let mut leading = 0xff;
+    let mut trailing = 0;
+    for (i, (t, v)) in samples.iter().enumerate() {
+        match i {
+            0 => {
+                w.write_bytes(&varint(*t));
+                w.write_bits(v.to_bits(), 64);
+            }
+            1 => {
+                t_delta = t - t_prev;
+                w.write_bytes(&uvarint(t_delta as u64));
+                write_value(&mut w, *v, v_prev, &mut leading, &mut trailing);
+            }
+            _ => {
+                let delta = t - t_prev;
+                let dod = delta - t_delta;
+                if dod == 0 {
+                    w.write_bit(false);
+                } else if bit_range(dod, 14) {
+                    w.write_bits(0b10, 2);
+                    w.write_bits(dod as u64, 14);
+                } else if bit_range(dod, 17) {
+                    w.write_bits(0b110, 3);
+                    w.write_bits(dod as u64, 17);
+                } else if bit_range(dod, 20) {
+                    w.write_bits(0b1110, 4);
+                    w.write_bits(dod as u64, 20);
+                } else {
+                    w.write_bits(0b1111, 4);
+                    w.write_bits(dod as u64, 64);
+                }
+                t_delta = delta;
+                write_value(&mut w, *v, v_prev, &mut leading, &mut trailing);
+            }
+        }
+        t_prev = *t;
+        v_prev = *v;
+    }
+    w.bytes
+}
+
+/// Frames a message of a streamed remote-read response: its length as an
+/// uvarint, the message, then its CRC32 Castagnoli checksum in big-endian.
+pub(crate) fn frame(message: &[u8]) -> Vec<u8> {
+    let mut buf = uvarint(message.len() as u64);
+    buf.extend_from_slice(message);
+    buf.extend_from_slice(&CASTAGNOLI.checksum(message).to_be_bytes());
+    buf
+}
+
+fn write_value(w: &mut BitWriter, value: f64, prev: f64, leading: &mut u32, trailing: &mut u32) {
+    let delta = value.to_bits() ^ prev.to_bits();
+    if delta == 0 {
+        w.write_bit(false);
+        return;
+    }
+    w.write_bit(true);
+
+    // clamp the leading zeros so that they fit in 5 bits
+    let new_leading = delta.leading_zeros().min(31);
+    let new_trailing = delta.trailing_zeros();
+    if *leading != 0xff && new_leading >= *leading && new_trailing >= *trailing {
+        // the meaningful bits fit in the previous window
+        w.write_bit(false);
+        w.write_bits(delta >> *trailing, 64 - *leading - *trailing);
+        return;
+    }
+    *leading = new_leading;
+    *trailing = new_trailing;
+    w.write_bit(true);
+    w.write_bits(u64::from(new_leading), 5);
+    // 64 significant bits overflow to 0, which readers take for 64
+    let sigbits = 64 - new_leading - new_trailing;
+    w.write_bits(u64::from(sigbits), 6);
+    w.write_bits(delta >> new_trailing, sigbits);
+}
+
+fn bit_range(x: i64, nbits: u32) -> bool {
+    -((1 << (nbits - 1)) - 1) <= x && x <= 1 << (nbits - 1)
+}
+
+fn uvarint(mut x: u64) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(10);
+    while x >= 0x80 {
+        buf.push(x as u8 | 0x80);
+        x >>= 7;
+    }
+    buf.push(x as u8);
+    buf
+}
+
+fn varint(x: i64) -> Vec<u8> {
+    // zigzag encoding
+    uvarint(((x << 1) ^ (x >> 63)) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decodes a XOR chunk, following the Prometheus reader.
+    fn decode_xor_chunk(data: &[u8]) -> Vec<(i64, f64)> {
+        let mut pos = 0;
+        let mut read_bit = || {
+            let bit = data[pos / 8] & (0x80 >> (pos % 8)) != 0;
+            pos += 1;
+            bit
+        };
+        let mut read_bits = |n: u32| (0..n).fold(0u64, |acc, _| acc << 1 | read_bit() as u64);
+        let read_uvarint = |read_bits: &mut dyn FnMut(u32) -> u64| {
+            let (mut x, mut shift) = (0u64, 0);
+            loop {
+                let b = read_bits(8);
+                x |= (b & 0x7f) << shift;
+                if b < 0x80 {
+                    return x;
+                }
+                shift += 7;
+            }
+        };
+
+        let num = read_bits(16) as usize;
+        let mut samples = Vec::with_capacity(num);
+        let (mut t, mut t_delta, mut v) = (0i64, 0i64, 0u64);
+        let (mut leading, mut sigbits) = (0u32, 0u32);
+        for i in 0..num {
+            match i {
+                0 => {
+                    let ux = read_uvarint(&mut read_bits);
+                    t = (ux >> 1) as i64 ^ -((ux & 1) as i64);
+                    v = read_bits(64);
+                }
+                _ => {
+                    if i == 1 {
+                        t_delta = read_uvarint(&mut read_bits) as i64;
+                    } else {
+                        let mut prefix = 0;
+                        while prefix < 4 && read_bits(1) == 1 {
+                            prefix += 1;
+                        }
+                        let nbits = [0, 14, 17, 20, 64][prefix];
+                        if nbits > 0 {
+                            let mut dod = read_bits(nbits) as i64;
+                            if nbits < 64 && dod > 1 << (nbits - 1) {
+                                dod -= 1 << nbits;
+                            }
+                            t_delta += dod;
+                        }
+                    }
+                    t += t_delta;
+                    if read_bits(1) == 1 {
+                        if read_bits(1) == 1 {
+                            leading = read_bits(5) as u32;
+                            sigbits = read_bits(6) as u32;
+                            if sigbits == 0 {
+                                sigbits = 64;
+                            }
+                        }
+                        v ^= read_bits(sigbits) << (64 - leading - sigbits);
+                    }
+                }
+            }
+            samples.push((t, f64::from_bits(v)));
+        }
+        samples
+    }
+
+    #[test]
+    fn test_encode_xor_chunk_single_sample() {
+        assert_eq!(
+            encode_xor_chunk(&[(1000, 1.0)]),
+            vec![0x00, 0x01, 0xd0, 0x0f, 0x3f, 0xf0, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_encode_xor_chunk_round_trip() {
+        let samples = vec![
+            (1_600_000_000_000, 1.0),
+            (1_600_000_015_000, 1.0),
+            (1_600_000_030_000, 2.5),
+            (1_600_000_045_001, 2.75),
+            (1_600_000_060_000, -3.0),
+            (1_600_000_600_000, 1e300),
+            (1_600_100_000_000, 0.1),
+            (1_600_100_000_001, f64::MAX),
+        ];
+        assert_eq!(decode_xor_chunk(&encode_xor_chunk(&samples)), samples);
+        assert!(decode_xor_chunk(&encode_xor_chunk(&[])).is_empty());
+    }
+
+    #[test]
+    fn test_frame() {
+        let framed = frame(b"123456789");
+        assert_eq!(framed[0], 9);
+        assert_eq!(&framed[1..10], b"123456789");
+        // CRC-32C check value
+        assert_eq!(&framed[10..], &0xe306_9283_u32.to_be_bytes());
+    }
+}
@@ -21,16 +21,26 @@ use crate::{
     common,
     common::{
         infra::config::CONFIG,
-        meta::prom::{Metadata, HASH_LABEL, METADATA_LABEL, VALUE_LABEL},
+        meta::prom::{
+            Metadata, EXEMPLARS_LABEL, HASH_LABEL, HISTOGRAM_LABEL, METADATA_LABEL, VALUE_LABEL,
+        },
     },
 };
 
+pub(crate) mod chunkenc;
 pub mod json;
 pub mod otlp_grpc;
 pub mod otlp_http;
 pub mod prom;
+pub mod rules;
 
-const EXCLUDE_LABELS: [&str; 4] = [VALUE_LABEL, HASH_LABEL, "is_monotonic", "exemplars"];
+const EXCLUDE_LABELS: [&str; 5] = [
+    VALUE_LABEL,
+    HASH_LABEL,
+    "is_monotonic",
+    EXEMPLARS_LABEL,
+    HISTOGRAM_LABEL,
+];
 
 static RE_CORRECT_LABEL_NAME: Lazy<Regex> = Lazy::new(|| Regex::new(r"[^a-zA-Z0-9_]+").unwrap());
 
@@ -638,43 +638,49 @@ fn process_exp_hist_data_point(
     let mut sum_rec = rec.clone();
     sum_rec[VALUE_LABEL] = data_point.sum.into();
     sum_rec[NAME_LABEL] = format!("{}_sum", sum_rec[NAME_LABEL].as_str().unwrap()).into();
+    let sum = sum_rec[VALUE_LABEL].as_f64().unwrap_or_default();
     bucket_recs.push(sum_rec);
 
-    let base = 2 ^ (2 ^ -data_point.scale);
-    // add negative bucket records
-    match &data_point.negative {
-        Some(buckets) => {
-            let offset = buckets.offset;
-            for (i, val) in buckets.bucket_counts.iter().enumerate() {
-                let mut bucket_rec = rec.clone();
-                bucket_rec[NAME_LABEL] =
-                    format!("{}_bucket", rec[NAME_LABEL].as_str().unwrap()).into();
-                bucket_rec[VALUE_LABEL] = (*val as f64).into();
-                bucket_rec["le"] = (base ^ (offset + (i as i32) + 1)).to_string().into();
-                bucket_recs.push(bucket_rec);
-            }
-        }
-        None => {}
-    }
-    // add positive bucket records
-    match &data_point.positive {
-        Some(buckets) => {
-            let offset = buckets.offset;
-            for (i, val) in buckets.bucket_counts.iter().enumerate() {
-                let mut bucket_rec = rec.clone();
-                bucket_rec[NAME_LABEL] =
-                    format!("{}_bucket", rec[NAME_LABEL].as_str().unwrap()).into();
-                bucket_rec[VALUE_LABEL] = (*val as f64).into();
-                bucket_rec["le"] = (base ^ (offset + (i as i32) + 1)).to_string().into();
-                bucket_recs.push(bucket_rec);
-            }
-        }
-        None => {}
+    // add the native histogram record, the buckets are kept as they are so
+    // that `histogram_quantile` can compute on them
+    let hist = NativeHistogram {
+        schema: data_point.scale,
+        count: data_point.count as f64,
+        sum,
+        zero_threshold: 0.0,
+        zero_count: data_point.zero_count as f64,
+        positive: to_histogram_buckets(&data_point.positive),
+        negative: to_histogram_buckets(&data_point.negative),
+    };
+    let mut hist_rec = rec.clone();
+    hist_rec[VALUE_LABEL] = hist.count.into();
+    hist_rec[HISTOGRAM_LABEL] = json::to_string(&hist).unwrap().into();
+    bucket_recs.push(hist_rec);
+
+    // add the bucket records of the equivalent classic histogram
+    for (le, count) in hist.cumulative_buckets() {
+        let mut bucket_rec = rec.clone();
+        bucket_rec[NAME_LABEL] = format!("{}_bucket", rec[NAME_LABEL].as_str().unwrap()).into();
+        bucket_rec[VALUE_LABEL] = count.into();
+        bucket_rec["le"] = le.to_string().into();
+        bucket_recs.push(bucket_rec);
     }
 
     bucket_recs
 }
 
+fn to_histogram_buckets(
+    buckets: &Option<exponential_histogram_data_point::Buckets>,
+) -> HistogramBuckets {
+    match buckets {
+        Some(buckets) => HistogramBuckets {
+            offset: buckets.offset,
+            counts: buckets.bucket_counts.iter().map(|v| *v as f64).collect(),
+        },
+        None => HistogramBuckets::default(),
+    }
+}
+
 fn process_summary_data_point(
     rec: &mut json::Value,
     data_point: &SummaryDataPoint,
@@ -746,7 +752,7 @@ fn process_exemplars(rec: &mut json::Value, exemplars: &Vec<Exemplar>) {
 
         exemplar_coll.push(exemplar_rec)
     }
-    rec["exemplars"] = exemplar_coll.into();
+    rec[EXEMPLARS_LABEL] = exemplar_coll.into();
 }
 
 fn process_aggregation_temporality(rec: &mut json::Value, val: i32) {
@@ -32,7 +32,10 @@ use crate::{
             self,
             alerts::Alert,
             http::HttpResponse as MetaHttpResponse,
-            prom::{self, MetricType, HASH_LABEL, NAME_LABEL, VALUE_LABEL},
+            prom::{
+                self, HistogramBuckets, MetricType, NativeHistogram, EXEMPLARS_LABEL, HASH_LABEL,
+                HISTOGRAM_LABEL, NAME_LABEL, VALUE_LABEL,
+            },
             stream::{PartitioningDetails, StreamParams},
             usage::UsageType,
             StreamType,
@@ -845,48 +848,51 @@ fn process_exp_hist_data_point(
     sum_rec[NAME_LABEL] = format!("{}_sum", sum_rec[NAME_LABEL].as_str().unwrap()).into();
     bucket_recs.push(sum_rec);
 
-    let base = 2 ^ (2 ^ -data_point.get("scale").unwrap().as_i64().unwrap());
-    // add negative bucket records
-    if let Some(buckets) = data_point.get("negative") {
-        let buckets = buckets.as_object().unwrap();
-        let offset = buckets.get("offset").unwrap().as_i64().unwrap();
-        for (i, val) in buckets
-            .get("bucket_counts")
-            .unwrap()
-            .as_array()
-            .unwrap()
-            .iter()
-            .enumerate()
-        {
-            let mut bucket_rec = rec.clone();
-            bucket_rec[NAME_LABEL] = format!("{}_bucket", rec[NAME_LABEL].as_str().unwrap()).into();
-            bucket_rec[VALUE_LABEL] = get_float_value(val).into();
-            bucket_rec["le"] = (base ^ (offset + (i as i64) + 1)).to_string().into();
-            bucket_recs.push(bucket_rec);
-        }
-    }
-    // add positive bucket records
-    if let Some(buckets) = data_point.get("positive") {
-        let buckets = buckets.as_object().unwrap();
-        let offset = buckets.get("offset").unwrap().as_i64().unwrap();
-        for (i, val) in buckets
-            .get("bucket_counts")
-            .unwrap()
-            .as_array()
-            .unwrap()
-            .iter()
-            .enumerate()
-        {
-            let mut bucket_rec = rec.clone();
-            bucket_rec[VALUE_LABEL] = get_float_value(val).into();
-            bucket_rec["le"] = (base ^ (offset + (i as i64) + 1)).to_string().into();
-            bucket_recs.push(bucket_rec);
-        }
+    // add the native histogram record, the buckets are kept as they are so
+    // that `histogram_quantile` can compute on them
+    let hist = NativeHistogram {
+        schema: data_point
+            .get("scale")
+            .map_or(0, |v| get_int_value(v) as i32),
+        count: get_float_value(data_point.get("count").unwrap()),
+        sum: get_float_value(data_point.get("sum").unwrap()),
+        zero_threshold: data_point.get("zeroThreshold").map_or(0.0, get_float_value),
+        zero_count: data_point.get("zeroCount").map_or(0.0, get_float_value),
+        positive: get_histogram_buckets(data_point.get("positive")),
+        negative: get_histogram_buckets(data_point.get("negative")),
+    };
+    let mut hist_rec = rec.clone();
+    hist_rec[VALUE_LABEL] = hist.count.into();
+    hist_rec[HISTOGRAM_LABEL] = json::to_string(&hist).unwrap().into();
+    bucket_recs.push(hist_rec);
+
+    // add the bucket records of the equivalent classic histogram
+    for (le, count) in hist.cumulative_buckets() {
+        let mut bucket_rec = rec.clone();
+        bucket_rec[NAME_LABEL] = format!("{}_bucket", rec[NAME_LABEL].as_str().unwrap()).into();
+        bucket_rec[VALUE_LABEL] = count.into();
+        bucket_rec["le"] = le.to_string().into();
+        bucket_recs.push(bucket_rec);
     }
 
     bucket_recs
 }
 
+fn get_histogram_buckets(buckets: Option<&json::Value>) -> HistogramBuckets {
+    let buckets = match buckets.and_then(|v| v.as_object()) {
+        Some(v) => v,
+        None => return HistogramBuckets::default(),
+    };
+    let counts = buckets
+        .get("bucketCounts")
+        .or_else(|| buckets.get("bucket_counts"))
+        .and_then(|v| v.as_array());
+    HistogramBuckets {
+        offset: buckets.get("offset").map_or(0, |v| get_int_value(v) as i32),
+        counts: counts.map_or_else(Vec::new, |v| v.iter().map(get_float_value).collect()),
+    }
+}
+
 fn process_summary_data_point(
     rec: &mut json::Value,
     data_point: &json::Map<String, json::Value>,
@@ -1016,7 +1022,7 @@ fn process_exemplars(rec: &mut json::Value, data_point: &json::Map<String, json:
 
         exemplar_coll.push(exemplar_rec)
     }
-    rec["exemplars"] = exemplar_coll.into();
+    rec[EXEMPLARS_LABEL] = exemplar_coll.into();
 }
 
 fn set_data_point_value(rec: &mut json::Value, data_point: &json::Map<String, json::Value>) {
@@ -15,12 +15,14 @@
 
 use std::collections::HashMap;
 
-use actix_web::web;
+use actix_web::{http, web};
 use ahash::AHashMap;
 use chrono::{Duration, TimeZone, Utc};
 use datafusion::arrow::datatypes::Schema;
+use futures::{stream::LocalBoxStream, StreamExt, TryStreamExt};
 use promql_parser::{label::MatchOp, parser};
 use prost::Message;
+use tonic::{codec::CompressionEncoding, metadata::MetadataValue, transport::Channel, Request};
 
 use crate::{
     common::{
@@ -42,10 +44,12 @@ use crate::{
         },
         utils::{json, time::parse_i64_to_timestamp_micros},
     },
+    handler::grpc::cluster_rpc,
     service::{
         db, format_stream_name,
         ingestion::{chk_schema_by_record, evaluate_trigger, write_file, TriggerAlertData},
-        metrics::format_label_name,
+        metrics::{chunkenc, format_label_name},
+        promql,
         schema::{set_schema_metadata, stream_schema_exists},
         search as search_service,
         stream::unwrap_partition_time_level,
@@ -150,9 +154,13 @@ pub async fn remote_write(
 
         let buf = metric_data_map.entry(metric_name.to_owned()).or_default();
 
+        let exemplars = event.exemplars.iter().map(to_exemplar).collect::<Vec<_>>();
+        let (samples, exemplars_at) = series_samples(&event);
+
         // parse samples
-        for sample in event.samples {
-            let mut sample_val = sample.value;
+        for (i, (sample_timestamp, sample_val)) in samples.into_iter().enumerate() {
+            let has_value = sample_val.is_some();
+            let mut sample_val = sample_val.unwrap_or_default();
             // revisit in future
             if sample_val.is_infinite() {
                 if sample_val == f64::INFINITY || sample_val > f64::MAX {
@@ -169,7 +177,7 @@ pub async fn remote_write(
                 value: sample_val,
             };
 
-            let timestamp = parse_i64_to_timestamp_micros(sample.timestamp);
+            let timestamp = parse_i64_to_timestamp_micros(sample_timestamp);
             if timestamp < min_ts {
                 min_ts = timestamp;
             }
@@ -257,6 +265,9 @@ pub async fn remote_write(
             // End Register Transforms for stream
 
             let mut value: json::Value = json::to_value(&metric).unwrap();
+            if !has_value {
+                value.as_object_mut().unwrap().remove(VALUE_LABEL);
+            }
 
             // Start row based transform
 
@@ -278,6 +289,12 @@ pub async fn remote_write(
                 CONFIG.common.column_timestamp.clone(),
                 json::Value::Number(timestamp.into()),
             );
+            if i == exemplars_at && !exemplars.is_empty() {
+                val_map.insert(
+                    EXEMPLARS_LABEL.to_string(),
+                    json::Value::String(json::to_string(&exemplars).unwrap()),
+                );
+            }
             let value_str = crate::common::utils::json::to_string(&val_map).unwrap();
             chk_schema_by_record(
                 &mut metric_schema_map,
@@ -401,6 +418,288 @@ pub async fn remote_write(
     Ok(())
 }
 
+/// Returns the timestamps and values of the samples of the series, and the
+/// position of the sample the exemplars are stored with: the last one which is
+/// stored, or a sample without a value added for them when there is none.
+fn series_samples(event: &prometheus::TimeSeries) -> (Vec<(i64, Option<f64>)>, usize) {
+    let mut samples: Vec<(i64, Option<f64>)> = event
+        .samples
+        .iter()
+        .map(|sample| (sample.timestamp, Some(sample.value)))
+        .collect();
+    // the NaN values, stale markers included, are not stored
+    let exemplars_at = match event.samples.iter().rposition(|s| !s.value.is_nan()) {
+        Some(pos) => pos,
+        None => {
+            if let Some(timestamp) = event.exemplars.iter().map(|e| e.timestamp).max() {
+                samples.push((timestamp, None));
+            }
+            samples.len().saturating_sub(1)
+        }
+    };
+    (samples, exemplars_at)
+}
+
+/// Converts a remote-write exemplar into the form the OTLP ingestion stores:
+/// its labels, with the trace id under `trace_id`, its value and timestamp.
+fn to_exemplar(exemplar: &prometheus::Exemplar) -> json::Value {
+    let mut rec = json::Map::new();
+    for label in exemplar.labels.iter() {
+        let name = match label.name.as_str() {
+            "traceID" | "traceId" | "trace_id" => "trace_id".to_string(),
+            "spanID" | "spanId" | "span_id" => "span_id".to_string(),
+            name => format_label_name(name),
+        };
+        rec.insert(name, label.value.clone().into());
+    }
+    rec.insert(VALUE_LABEL.to_string(), exemplar.value.into());
+    rec.insert(
+        CONFIG.common.column_timestamp.clone(),
+        parse_i64_to_timestamp_micros(exemplar.timestamp).into(),
+    );
+    json::Value::Object(rec)
+}
+
+/// Writes the time series through the remote-write ingestion, on the local node
+/// when it is an ingester or else on a random ingester of the cluster.
+pub async fn write_timeseries(
+    org_id: &str,
+    request: &prometheus::WriteRequest,
+) -> std::result::Result<(), anyhow::Error> {
+    let body = snap::raw::Encoder::new()
+        .compress_vec(&request.encode_to_vec())
+        .map_err(|e| anyhow::anyhow!("snappy compress error: {}", e.to_string()))?;
+    if cluster::is_ingester(&cluster::LOCAL_NODE_ROLE) {
+        return remote_write(org_id, 0, body.into()).await;
+    }
+
+    let grpc_addr = crate::router::grpc::ingest::get_rand_ingester_addr()
+        .map_err(|e| anyhow::anyhow!(e.message().to_string()))?;
+    let token: MetadataValue<_> = cluster::get_internal_grpc_token()
+        .parse()
+        .map_err(|_| anyhow::anyhow!("invalid token"))?;
+    let org_header: MetadataValue<_> = org_id
+        .parse()
+        .map_err(|_| anyhow::anyhow!("invalid org_id"))?;
+    let channel = Channel::from_shared(grpc_addr.clone())
+        .unwrap()
+        .connect()
+        .await
+        .map_err(|e| anyhow::anyhow!("connect ingester {} error: {:?}", grpc_addr, e))?;
+    let client = cluster_rpc::metrics_client::MetricsClient::with_interceptor(
+        channel,
+        move |mut req: Request<()>| {
+            req.metadata_mut().insert("authorization", token.clone());
+            req.metadata_mut()
+                .insert(CONFIG.grpc.org_header_key.as_str(), org_header.clone());
+            Ok(req)
+        },
+    );
+    client
+        .send_compressed(CompressionEncoding::Gzip)
+        .accept_compressed(CompressionEncoding::Gzip)
+        .remote_write(cluster_rpc::MetricsRemoteWriteRequest {
+            org_id: org_id.to_string(),
+            data: body,
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("remote write to {} error: {}", grpc_addr, e.message()))?;
+    Ok(())
+}
+
+/// Response of a remote-read request, in the first response type accepted by
+/// the client that is implemented.
+pub enum RemoteReadResponse {
+    /// Snappy compressed `ReadResponse`.
+    Samples(Vec<u8>),
+    /// Framed `ChunkedReadResponse` messages, one per series. The queries are
+    /// run one after another while the frames are sent.
+    Chunks(LocalBoxStream<'static, std::result::Result<web::Bytes, anyhow::Error>>),
+}
+
+/// Reads the series of the remote-read request. The errors of the request
+/// are bad requests, the errors of the queries are internal errors.
+pub async fn remote_read(
+    org_id: &str,
+    body: web::Bytes,
+) -> std::result::Result<RemoteReadResponse, (http::StatusCode, anyhow::Error)> {
+    let decoded = snap::raw::Decoder::new()
+        .decompress_vec(&body)
+        .map_err(|e| {
+            (
+                http::StatusCode::BAD_REQUEST,
+                anyhow::anyhow!("Invalid snappy compressed data: {}", e.to_string()),
+            )
+        })?;
+    let request = prometheus::ReadRequest::decode(bytes::Bytes::from(decoded)).map_err(|e| {
+        (
+            http::StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("Invalid protobuf: {}", e.to_string()),
+        )
+    })?;
+    let response_type = request
+        .accepted_response_types()
+        .next()
+        .unwrap_or(prometheus::read_request::ResponseType::Samples);
+
+    match response_type {
+        prometheus::read_request::ResponseType::Samples => {
+            let mut results = Vec::with_capacity(request.queries.len());
+            for query in request.queries.iter() {
+                let timeseries = read_query(org_id, query)
+                    .await
+                    .map_err(|e| (http::StatusCode::INTERNAL_SERVER_ERROR, e))?;
+                results.push(prometheus::QueryResult { timeseries });
+            }
+            let resp = prometheus::ReadResponse { results };
+            let body = snap::raw::Encoder::new()
+                .compress_vec(&resp.encode_to_vec())
+                .map_err(|e| {
+                    (
+                        http::StatusCode::INTERNAL_SERVER_ERROR,
+                        anyhow::anyhow!("snappy compress error: {}", e.to_string()),
+                    )
+                })?;
+            Ok(RemoteReadResponse::Samples(body))
+        }
+        prometheus::read_request::ResponseType::StreamedXorChunks => {
+            let org_id = org_id.to_string();
+            let frames = futures::stream::iter(request.queries.into_iter().enumerate())
+                .then(move |(query_index, query)| {
+                    let org_id = org_id.clone();
+                    async move {
+                        let frames = read_query(&org_id, &query)
+                            .await?
+                            .into_iter()
+                            .map(|series| {
+                                let resp = prometheus::ChunkedReadResponse {
+                                    chunked_series: vec![to_chunked_series(series)],
+                                    query_index: query_index as i64,
+                                };
+                                Ok::<_, anyhow::Error>(web::Bytes::from(chunkenc::frame(
+                                    &resp.encode_to_vec(),
+                                )))
+                            })
+                            .collect::<Vec<_>>();
+                        Ok::<_, anyhow::Error>(futures::stream::iter(frames))
+                    }
+                })
+                .try_flatten();
+            Ok(RemoteReadResponse::Chunks(frames.boxed_local()))
+        }
+    }
+}
+
+/// Loads the samples of the series matching the query, through a PromQL range
+/// selector evaluated at the end of the query.
+async fn read_query(
+    org_id: &str,
+    query: &prometheus::Query,
+) -> std::result::Result<Vec<prometheus::TimeSeries>, anyhow::Error> {
+    let Some(selector) = remote_read_selector(&query.matchers) else {
+        return Ok(vec![]);
+    };
+    if query.end_timestamp_ms < query.start_timestamp_ms {
+        return Ok(vec![]);
+    }
+    let req = promql::MetricsQueryRequest {
+        query: format!(
+            "{selector}[{}ms]",
+            query.end_timestamp_ms - query.start_timestamp_ms + 1
+        ),
+        start: query.end_timestamp_ms * 1000,
+        end: query.end_timestamp_ms * 1000,
+        step: 300_000_000, // 5m
+    };
+    let value = promql::search::search(org_id, &req, 0)
+        .await
+        .map_err(|e| anyhow::anyhow!("query error: {}", e))?;
+    let promql::value::Value::Matrix(matrix) = value else {
+        return Ok(vec![]);
+    };
+    Ok(matrix
+        .into_iter()
+        .map(|series| prometheus::TimeSeries {
+            labels: series
+                .labels
+                .iter()
+                .map(|label| prometheus::Label {
+                    name: label.name.clone(),
+                    value: label.value.clone(),
+                })
+                .collect(),
+            samples: series
+                .samples
+                .iter()
+                .map(|sample| prometheus::Sample {
+                    value: sample.value,
+                    timestamp: sample.timestamp / 1000,
+                })
+                .filter(|sample| sample.timestamp >= query.start_timestamp_ms)
+                .collect(),
+            ..Default::default()
+        })
+        .filter(|series| !series.samples.is_empty())
+        .collect())
+}
+
+/// Builds the PromQL selector of the matchers. A remote-read query without an
+/// equality matcher on the metric name can't be mapped to a stream and reads
+/// nothing.
+fn remote_read_selector(matchers: &[prometheus::LabelMatcher]) -> Option<String> {
+    use prometheus::label_matcher::Type;
+
+    let metric_name = matchers
+        .iter()
+        .find(|m| m.name == NAME_LABEL && m.r#type() == Type::Eq)?;
+    let filters = matchers
+        .iter()
+        .filter(|m| !std::ptr::eq(*m, metric_name))
+        .map(|m| {
+            let op = match m.r#type() {
+                Type::Eq => "=",
+                Type::Neq => "!=",
+                Type::Re => "=~",
+                Type::Nre => "!~",
+            };
+            let value = m
+                .value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}{op}\"{value}\"", m.name)
+        })
+        .collect::<Vec<_>>();
+    if filters.is_empty() {
+        Some(metric_name.value.clone())
+    } else {
+        Some(format!("{}{{{}}}", metric_name.value, filters.join(",")))
+    }
+}
+
+fn to_chunked_series(series: prometheus::TimeSeries) -> prometheus::ChunkedSeries {
+    let chunks = series
+        .samples
+        .chunks(chunkenc::MAX_SAMPLES_PER_CHUNK)
+        .map(|samples| {
+            let samples = samples
+                .iter()
+                .map(|s| (s.timestamp, s.value))
+                .collect::<Vec<_>>();
+            prometheus::Chunk {
+                min_time_ms: samples[0].0,
+                max_time_ms: samples[samples.len() - 1].0,
+                r#type: prometheus::chunk::Encoding::Xor as i32,
+                data: chunkenc::encode_xor_chunk(&samples),
+            }
+        })
+        .collect();
+    prometheus::ChunkedSeries {
+        labels: series.labels,
+        chunks,
+    }
+}
+
 pub(crate) async fn get_metadata(org_id: &str, req: RequestMetadata) -> Result<ResponseMetadata> {
     if req.limit == Some(0) {
         return Ok(ahash::HashMap::default());
@@ -508,7 +807,7 @@ pub(crate) async fn get_series(
         .fields()
         .iter()
         .map(|f| f.name().as_str())
-        .filter(|&s| s != CONFIG.common.column_timestamp && s != VALUE_LABEL && s != HASH_LABEL)
+        .filter(|&s| is_label_name(s))
         .collect::<Vec<_>>()
         .join(", ");
     if label_names.is_empty() {
@@ -516,30 +815,8 @@ pub(crate) async fn get_series(
     }
 
     let mut sql = format!("SELECT DISTINCT({HASH_LABEL}), {label_names} FROM {metric_name}");
-    let mut sql_where = Vec::new();
     if let Some(selector) = selector {
-        for mat in selector.matchers.matchers.iter() {
-            if mat.name == CONFIG.common.column_timestamp
-                || mat.name == VALUE_LABEL
-                || schema.field_with_name(&mat.name).is_err()
-            {
-                continue;
-            }
-            match &mat.op {
-                MatchOp::Equal => {
-                    sql_where.push(format!("{} = '{}'", mat.name, mat.value));
-                }
-                MatchOp::NotEqual => {
-                    sql_where.push(format!("{} != '{}'", mat.name, mat.value));
-                }
-                MatchOp::Re(_re) => {
-                    sql_where.push(format!("re_match({}, '{}')", mat.name, mat.value));
-                }
-                MatchOp::NotRe(_re) => {
-                    sql_where.push(format!("re_not_match({}, '{}')", mat.name, mat.value));
-                }
-            }
-        }
+        let sql_where = selector_sql_filters(&selector, &schema);
         if !sql_where.is_empty() {
             sql.push_str(" WHERE ");
             sql.push_str(&sql_where.join(" AND "));
@@ -606,9 +883,7 @@ pub(crate) async fn get_labels(
                 .fields()
                 .iter()
                 .map(|f| f.name())
-                .filter(|&s| {
-                    s != &CONFIG.common.column_timestamp && s != VALUE_LABEL && s != HASH_LABEL
-                })
+                .filter(|&s| is_label_name(s))
                 .cloned();
             label_names.extend(field_names);
         }
@@ -719,7 +994,204 @@ pub(crate) async fn get_label_values(
     Ok(label_values)
 }
 
-fn try_into_metric_name(selector: &parser::VectorSelector) -> Option<String> {
+/// Returns the exemplars, with timestamps between `start` and `end`, of the
+/// series selected by the expression.
+pub(crate) async fn get_exemplars(
+    org_id: &str,
+    expr: &parser::Expr,
+    start: i64,
+    end: i64,
+) -> Result<Vec<ExemplarSeries>> {
+    let mut selectors = Vec::new();
+    collect_selectors(expr, &mut selectors);
+
+    let mut series = Vec::new();
+    for selector in selectors {
+        series.extend(get_selector_exemplars(org_id, &selector, start, end).await?);
+    }
+    Ok(series)
+}
+
+async fn get_selector_exemplars(
+    org_id: &str,
+    selector: &parser::VectorSelector,
+    start: i64,
+    end: i64,
+) -> Result<Vec<ExemplarSeries>> {
+    let metric_name = match try_into_metric_name(selector) {
+        Some(name) => name,
+        None => return Ok(vec![]),
+    };
+    let schema = db::schema::get(org_id, &metric_name, StreamType::Metrics)
+        .await
+        // `db::schema::get` never fails, so it's safe to unwrap
+        .unwrap();
+    if schema.field_with_name(EXEMPLARS_LABEL).is_err() {
+        return Ok(vec![]);
+    }
+
+    let mut sql_where = selector_sql_filters(selector, &schema);
+    sql_where.push(format!("{EXEMPLARS_LABEL} IS NOT NULL"));
+    let req = search::Request {
+        query: search::Query {
+            sql: format!(
+                "SELECT * FROM {metric_name} WHERE {}",
+                sql_where.join(" AND ")
+            ),
+            from: 0,
+            size: 1000,
+            start_time: start,
+            end_time: end,
+            sql_mode: "full".to_string(),
+            ..Default::default()
+        },
+        aggs: HashMap::new(),
+        encoding: search::RequestEncoding::Empty,
+        timeout: 0,
+    };
+    let hits = match search_service::search("", org_id, StreamType::Metrics, &req).await {
+        Ok(resp) => resp.hits,
+        Err(err) => {
+            log::error!("search exemplars error: {err}");
+            return Err(err);
+        }
+    };
+
+    let mut series: FxIndexMap<String, ExemplarSeries> = FxIndexMap::default();
+    for hit in hits {
+        let hit = match hit.as_object() {
+            Some(v) => v,
+            None => continue,
+        };
+        let exemplars = match hit.get(EXEMPLARS_LABEL).and_then(|v| v.as_str()) {
+            Some(v) => json::from_str::<Vec<json::Value>>(v).unwrap_or_default(),
+            None => continue,
+        };
+        let hash = hit
+            .get(HASH_LABEL)
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let entry = series.entry(hash).or_insert_with(|| ExemplarSeries {
+            series_labels: hit
+                .iter()
+                .filter(|(k, _)| is_label_name(k))
+                .filter_map(|(k, v)| Some((k.to_string(), v.as_str()?.to_string())))
+                .collect(),
+            exemplars: vec![],
+        });
+        entry.exemplars.extend(
+            exemplars
+                .iter()
+                .filter_map(from_exemplar)
+                .filter(|(ts, _)| start <= *ts && *ts <= end)
+                .map(|(_, exemplar)| exemplar),
+        );
+    }
+
+    Ok(series
+        .into_values()
+        .filter_map(|mut series| {
+            series
+                .exemplars
+                .sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+            series.exemplars.dedup();
+            (!series.exemplars.is_empty()).then_some(series)
+        })
+        .collect())
+}
+
+/// Parses a stored exemplar, returning its timestamp in microseconds along
+/// with it.
+fn from_exemplar(exemplar: &json::Value) -> Option<(i64, ExemplarData)> {
+    let exemplar = exemplar.as_object()?;
+    let timestamp = exemplar.get(&CONFIG.common.column_timestamp)?.as_i64()?;
+    let value = exemplar.get(VALUE_LABEL)?.as_f64()?;
+    let labels = exemplar
+        .iter()
+        .filter(|(k, _)| *k != VALUE_LABEL && **k != CONFIG.common.column_timestamp)
+        .map(|(k, v)| {
+            let v = match v.as_str() {
+                Some(v) => v.to_string(),
+                None => v.to_string(),
+            };
+            (k.to_string(), v)
+        })
+        .collect();
+    Some((
+        timestamp,
+        ExemplarData {
+            labels,
+            value: value.to_string(),
+            timestamp: timestamp as f64 / 1_000_000.0,
+        },
+    ))
+}
+
+/// Collects the vector selectors of the expression, including those of range
+/// selectors.
+pub(crate) fn collect_selectors(expr: &parser::Expr, selectors: &mut Vec<parser::VectorSelector>) {
+    use parser::Expr;
+
+    match expr {
+        Expr::Aggregate(parser::AggregateExpr { expr, .. })
+        | Expr::Unary(parser::UnaryExpr { expr })
+        | Expr::Paren(parser::ParenExpr { expr }) => collect_selectors(expr, selectors),
+        Expr::Binary(parser::BinaryExpr { lhs, rhs, .. }) => {
+            collect_selectors(lhs, selectors);
+            collect_selectors(rhs, selectors);
+        }
+        Expr::Subquery(expr) => collect_selectors(&expr.expr, selectors),
+        Expr::VectorSelector(selector) => selectors.push(selector.clone()),
+        Expr::MatrixSelector(parser::MatrixSelector { vs, .. }) => selectors.push(vs.clone()),
+        Expr::Call(parser::Call { args, .. }) => {
+            for arg in args.args.iter() {
+                collect_selectors(arg, selectors);
+            }
+        }
+        Expr::NumberLiteral(_) | Expr::StringLiteral(_) | Expr::Extension(_) => {}
+    }
+}
+
+/// Translates the label matchers of the selector into SQL conditions.
+fn selector_sql_filters(selector: &parser::VectorSelector, schema: &Schema) -> Vec<String> {
+    let mut sql_where = Vec::new();
+    for mat in selector.matchers.matchers.iter() {
+        if mat.name == CONFIG.common.column_timestamp
+            || mat.name == VALUE_LABEL
+            || schema.field_with_name(&mat.name).is_err()
+        {
+            continue;
+        }
+        match &mat.op {
+            MatchOp::Equal => {
+                sql_where.push(format!("{} = '{}'", mat.name, mat.value));
+            }
+            MatchOp::NotEqual => {
+                sql_where.push(format!("{} != '{}'", mat.name, mat.value));
+            }
+            MatchOp::Re(_re) => {
+                sql_where.push(format!("re_match({}, '{}')", mat.name, mat.value));
+            }
+            MatchOp::NotRe(_re) => {
+                sql_where.push(format!("re_not_match({}, '{}')", mat.name, mat.value));
+            }
+        }
+    }
+    sql_where
+}
+
+/// Tells whether the column of a metric stream is a label, i.e. not the
+/// timestamp, the value, the hash, the exemplars or the native histogram.
+fn is_label_name(name: &str) -> bool {
+    name != CONFIG.common.column_timestamp
+        && name != VALUE_LABEL
+        && name != HASH_LABEL
+        && name != EXEMPLARS_LABEL
+        && name != HISTOGRAM_LABEL
+}
+
+pub(crate) fn try_into_metric_name(selector: &parser::VectorSelector) -> Option<String> {
     match &selector.name {
         Some(name) => {
             // `match[]` argument contains a metric name, e.g.
@@ -805,3 +1277,110 @@ async fn prom_ha_handler(
 
     _accept_record
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exemplar_round_trip() {
+        let exemplar = prometheus::Exemplar {
+            labels: vec![prometheus::Label {
+                name: "traceID".to_string(),
+                value: "4bf92f3577b34da6a3ce929d0e0e4736".to_string(),
+            }],
+            value: 0.46,
+            timestamp: 1600096945479,
+        };
+        let (timestamp, exemplar) = from_exemplar(&to_exemplar(&exemplar)).unwrap();
+        assert_eq!(timestamp, 1600096945479000);
+        assert_eq!(exemplar.value, "0.46");
+        assert_eq!(exemplar.timestamp, 1600096945.479);
+        assert_eq!(
+            exemplar.labels.get("trace_id").map(|v| v.as_str()),
+            Some("4bf92f3577b34da6a3ce929d0e0e4736")
+        );
+        assert_eq!(exemplar.labels.len(), 1);
+    }
+
+    #[test]
+    fn test_series_samples() {
+        let exemplar = prometheus::Exemplar {
+            labels: vec![],
+            value: 0.46,
+            timestamp: 1600096945479,
+        };
+        let sample = |timestamp, value| prometheus::Sample { value, timestamp };
+
+        // a series of exemplars only gets a row without a value
+        let event = prometheus::TimeSeries {
+            exemplars: vec![exemplar.clone()],
+            ..Default::default()
+        };
+        assert_eq!(series_samples(&event), (vec![(1600096945479, None)], 0));
+
+        // the exemplars go with the last sample which is stored
+        let event = prometheus::TimeSeries {
+            samples: vec![sample(1, 1.0), sample(2, 2.0), sample(3, f64::NAN)],
+            exemplars: vec![exemplar.clone()],
+            ..Default::default()
+        };
+        let (samples, exemplars_at) = series_samples(&event);
+        assert_eq!(samples.len(), 3);
+        assert_eq!(exemplars_at, 1);
+
+        let event = prometheus::TimeSeries {
+            samples: vec![sample(1, f64::NAN)],
+            exemplars: vec![exemplar],
+            ..Default::default()
+        };
+        let (samples, exemplars_at) = series_samples(&event);
+        assert_eq!(samples[exemplars_at], (1600096945479, None));
+
+        let event = prometheus::TimeSeries::default();
+        assert_eq!(series_samples(&event), (vec![], 0));
+    }
+
+    #[test]
+    fn test_collect_selectors() {
+        let expr = parser::parse(
+            "histogram_quantile(0.9, sum by (le) (rate(http_duration_bucket{job=\"api\"}[5m]))) > up",
+        )
+        .unwrap();
+        let mut selectors = Vec::new();
+        collect_selectors(&expr, &mut selectors);
+        let names = selectors
+            .iter()
+            .filter_map(try_into_metric_name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["http_duration_bucket", "up"]);
+    }
+
+    #[test]
+    fn test_remote_read_selector() {
+        use prometheus::label_matcher::Type;
+
+        let matcher = |r#type: Type, name: &str, value: &str| prometheus::LabelMatcher {
+            r#type: r#type as i32,
+            name: name.to_string(),
+            value: value.to_string(),
+        };
+        assert_eq!(
+            remote_read_selector(&[matcher(Type::Eq, "__name__", "up")]),
+            Some("up".to_string())
+        );
+        assert_eq!(
+            remote_read_selector(&[
+                matcher(Type::Neq, "job", "node"),
+                matcher(Type::Eq, "__name__", "up"),
+                matcher(Type::Re, "instance", "host\\d+"),
+                matcher(Type::Nre, "env", "\"dev\""),
+            ]),
+            Some(r#"up{job!="node",instance=~"host\\d+",env!~"\"dev\""}"#.to_string())
+        );
+        assert_eq!(
+            remote_read_selector(&[matcher(Type::Re, "__name__", "up|down")]),
+            None
+        );
+    }
+}
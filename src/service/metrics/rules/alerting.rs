@@ -0,0 +1,233 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashSet;
+
+use promql_parser::parser;
+
+use crate::{
+    common::{
+        meta::{
+            alerts::{Alert, Operator, QueryCondition, QueryType, TriggerCondition},
+            rules::{Rule, RuleGroup},
+            StreamType,
+        },
+        utils::time::parse_milliseconds,
+    },
+    service::{
+        alerts as AlertService,
+        metrics::prom::{collect_selectors, try_into_metric_name},
+    },
+};
+
+/// Converts the alerting rules of the group into PromQL alerts.
+pub fn to_alerts(
+    org_id: &str,
+    group: &RuleGroup,
+    destinations: &[String],
+) -> Result<Vec<Alert>, anyhow::Error> {
+    let mut keys = HashSet::new();
+    let mut alerts = Vec::new();
+    for rule in group.rules.iter().filter(|rule| rule.is_alerting()) {
+        if destinations.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Alerting rules need the destinations to notify"
+            ));
+        }
+        let alert = to_alert(org_id, group, rule, destinations)?;
+        if !keys.insert((alert.stream_name.clone(), alert.name.clone())) {
+            return Err(anyhow::anyhow!(
+                "Duplicate alerting rule {} on metric {}",
+                alert.name,
+                alert.stream_name
+            ));
+        }
+        alerts.push(alert);
+    }
+    Ok(alerts)
+}
+
+/// Converts an alerting rule into an alert of the first metric the expression
+/// selects, named after the group and the rule. The alert is evaluated at the
+/// interval of the group and `for` becomes the consecutive evaluations which
+/// should match before firing. The labels and annotations of the rule are the
+/// context attributes of the alert.
+pub fn to_alert(
+    org_id: &str,
+    group: &RuleGroup,
+    rule: &Rule,
+    destinations: &[String],
+) -> Result<Alert, anyhow::Error> {
+    let stream_name = rule_metric_name(rule)
+        .ok_or_else(|| anyhow::anyhow!("Alerting rule {} should select a metric", rule.alert))?;
+    // alerts are evaluated in minutes
+    let period = std::cmp::max(1, (super::interval(group)? + 59_999_999) / 60_000_000);
+    let for_duration = match rule.for_duration.as_ref() {
+        Some(v) => parse_milliseconds(v)
+            .map_err(|e| anyhow::anyhow!("Invalid for {} of rule {}: {}", v, rule.alert, e))?
+            as i64,
+        None => 0,
+    };
+    // the first match starts pending, it fires once pending for the duration
+    let pending_evaluations = if for_duration > 0 {
+        (for_duration + period * 60_000 - 1) / (period * 60_000) + 1
+    } else {
+        0
+    };
+    let description = rule
+        .annotations
+        .get("summary")
+        .or_else(|| rule.annotations.get("description"))
+        .cloned()
+        .unwrap_or_default();
+
+    let mut context_attributes = rule.annotations.clone();
+    context_attributes.extend(rule.labels.clone());
+    context_attributes.insert("alertname".to_string(), rule.alert.clone());
+
+    Ok(Alert {
+        name: alert_name(group, rule),
+        org_id: org_id.to_string(),
+        stream_type: StreamType::Metrics,
+        stream_name,
+        is_real_time: false,
+        query_condition: QueryCondition {
+            query_type: QueryType::PromQL,
+            promql: Some(rule.expr.clone()),
+            ..Default::default()
+        },
+        trigger_condition: TriggerCondition {
+            period,
+            operator: Operator::GreaterThanEquals,
+            threshold: 1,
+            frequency: period,
+            silence: 0,
+            pending_evaluations,
+            notify_on_resolved: true,
+        },
+        destinations: destinations.to_vec(),
+        context_attributes: Some(context_attributes),
+        description,
+        enabled: true,
+    })
+}
+
+/// Saves the alerts of the group and deletes the alerts of the previous
+/// version of the group which are gone.
+pub async fn sync_alerts(
+    org_id: &str,
+    previous: Option<&RuleGroup>,
+    alerts: Vec<Alert>,
+) -> Result<(), anyhow::Error> {
+    let keys = alerts
+        .iter()
+        .map(|alert| (alert.stream_name.clone(), alert.name.clone()))
+        .collect::<HashSet<_>>();
+    for alert in alerts {
+        let (stream_name, name) = (alert.stream_name.clone(), alert.name.clone());
+        AlertService::save(org_id, StreamType::Metrics, &stream_name, &name, alert)
+            .await
+            .map_err(|e| anyhow::anyhow!("Alerting rule {}: {}", name, e))?;
+    }
+
+    let previous = match previous {
+        Some(group) => group,
+        None => return Ok(()),
+    };
+    for rule in previous.rules.iter().filter(|rule| rule.is_alerting()) {
+        let stream_name = match rule_metric_name(rule) {
+            Some(v) => v,
+            None => continue,
+        };
+        let name = alert_name(previous, rule);
+        if keys.contains(&(stream_name.clone(), name.clone())) {
+            continue;
+        }
+        if let Err((_, e)) =
+            AlertService::delete(org_id, StreamType::Metrics, &stream_name, &name).await
+        {
+            log::warn!(
+                "[RULES] delete alert {}/{}/{} of group {} err: {}",
+                org_id,
+                stream_name,
+                name,
+                previous.name,
+                e
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Returns the name of the alert of the rule, the rules of different groups
+/// may share the same name.
+pub(crate) fn alert_name(group: &RuleGroup, rule: &Rule) -> String {
+    format!("{}:{}", group.name, rule.alert)
+}
+
+/// Returns the metric stream which the alert of the rule belongs to, the first
+/// metric the expression selects.
+pub(crate) fn rule_metric_name(rule: &Rule) -> Option<String> {
+    let expr = parser::parse(&rule.expr).ok()?;
+    let mut selectors = Vec::new();
+    collect_selectors(&expr, &mut selectors);
+    selectors.iter().find_map(try_into_metric_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_alert() {
+        let mut rule = Rule {
+            alert: "HighErrorRate".to_string(),
+            expr: "sum(rate(http_errors_total[5m])) / sum(rate(http_requests_total[5m])) > 0.1"
+                .to_string(),
+            for_duration: Some("10m".to_string()),
+            ..Default::default()
+        };
+        rule.annotations
+            .insert("summary".to_string(), "High error rate".to_string());
+        rule.labels
+            .insert("severity".to_string(), "page".to_string());
+        let group = RuleGroup {
+            name: "example".to_string(),
+            interval: "2m".to_string(),
+            rules: vec![rule.clone()],
+        };
+        let destinations = vec!["slack".to_string()];
+        let alert = to_alert("default", &group, &rule, &destinations).unwrap();
+        assert_eq!(alert.name, "example:HighErrorRate");
+        assert_eq!(alert.stream_type, StreamType::Metrics);
+        assert_eq!(alert.stream_name, "http_errors_total");
+        assert_eq!(alert.query_condition.query_type, QueryType::PromQL);
+        assert_eq!(alert.trigger_condition.period, 2);
+        assert_eq!(alert.trigger_condition.pending_evaluations, 6);
+        assert_eq!(alert.description, "High error rate");
+        let attrs = alert.context_attributes.unwrap();
+        assert_eq!(attrs.get("summary").unwrap(), "High error rate");
+        assert_eq!(attrs.get("severity").unwrap(), "page");
+        assert_eq!(attrs.get("alertname").unwrap(), "HighErrorRate");
+
+        rule.for_duration = None;
+        let alert = to_alert("default", &group, &rule, &destinations).unwrap();
+        assert_eq!(alert.trigger_condition.pending_evaluations, 0);
+
+        rule.expr = "vector(1)".to_string();
+        assert!(to_alert("default", &group, &rule, &destinations).is_err());
+        assert!(to_alerts("default", &group, &[]).is_err());
+    }
+}
@@ -0,0 +1,408 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashSet;
+
+use actix_web::http;
+use chrono::{SecondsFormat, TimeZone, Utc};
+use once_cell::sync::Lazy;
+use promql_parser::parser;
+use regex::Regex;
+
+use crate::{
+    common::{
+        infra::config::TRIGGERS,
+        meta::{
+            alerts::triggers::{AlertState, Trigger},
+            rules::{
+                ActiveAlert, AlertDiscovery, Rule, RuleDiscovery, RuleGroup, RuleGroupStatus,
+                RuleGroups, RuleStatus,
+            },
+            StreamType,
+        },
+        utils::time::parse_milliseconds,
+    },
+    service::{db, promql::value::Label},
+};
+
+pub mod alerting;
+pub mod recording;
+
+static RE_METRIC_NAME: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^[a-zA-Z_:][a-zA-Z0-9_:]*$").unwrap());
+
+/// Saves the group, the alerting rules of the group are saved as alerts which
+/// notify the given destinations.
+pub async fn save(
+    org_id: &str,
+    name: &str,
+    mut group: RuleGroup,
+    destinations: &[String],
+) -> Result<(), anyhow::Error> {
+    group.name = name.trim().to_string();
+    validate(&group)?;
+    let alerts = alerting::to_alerts(org_id, &group, destinations)?;
+    let previous = db::rules::get(org_id, &group.name).await.ok();
+    alerting::sync_alerts(org_id, previous.as_ref(), alerts).await?;
+    db::rules::set(org_id, &group.name, &group).await
+}
+
+/// Imports the groups of a Prometheus rules file in YAML, the existing groups
+/// with the same names are replaced. Returns the names of the imported groups.
+pub async fn import(
+    org_id: &str,
+    body: &[u8],
+    destinations: &[String],
+) -> Result<Vec<String>, anyhow::Error> {
+    let file: RuleGroups = serde_yaml::from_slice(body)
+        .map_err(|e| anyhow::anyhow!("Invalid rules file: {}", e.to_string()))?;
+    let mut names = HashSet::new();
+    let mut group_alerts = Vec::with_capacity(file.groups.len());
+    for group in file.groups.iter() {
+        validate(group)?;
+        if !names.insert(group.name.as_str()) {
+            return Err(anyhow::anyhow!("Duplicate rule group {}", group.name));
+        }
+        group_alerts.push(alerting::to_alerts(org_id, group, destinations)?);
+    }
+    for (group, alerts) in file.groups.iter().zip(group_alerts) {
+        let previous = db::rules::get(org_id, &group.name).await.ok();
+        alerting::sync_alerts(org_id, previous.as_ref(), alerts).await?;
+        db::rules::set(org_id, &group.name, group).await?;
+    }
+    Ok(file.groups.into_iter().map(|group| group.name).collect())
+}
+
+pub async fn get(org_id: &str, name: &str) -> Result<RuleGroup, anyhow::Error> {
+    db::rules::get(org_id, name)
+        .await
+        .map_err(|_| anyhow::anyhow!("Rule group not found"))
+}
+
+pub async fn list(org_id: &str) -> Result<Vec<RuleGroup>, anyhow::Error> {
+    db::rules::list(org_id).await
+}
+
+pub async fn delete(org_id: &str, name: &str) -> Result<(), (http::StatusCode, anyhow::Error)> {
+    let group = match db::rules::get(org_id, name).await {
+        Ok(group) => group,
+        Err(_) => {
+            return Err((
+                http::StatusCode::NOT_FOUND,
+                anyhow::anyhow!("Rule group not found {}", name),
+            ));
+        }
+    };
+    alerting::sync_alerts(org_id, Some(&group), vec![])
+        .await
+        .map_err(|e| (http::StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    db::rules::delete(org_id, name)
+        .await
+        .map_err(|e| (http::StatusCode::INTERNAL_SERVER_ERROR, e))
+}
+
+/// Returns the rule groups of the organization in the format of the Prometheus
+/// rules API, `rule_type` keeps only the `alert` or the `record` rules.
+pub async fn list_rules_status(
+    org_id: &str,
+    rule_type: Option<&str>,
+) -> Result<RuleDiscovery, anyhow::Error> {
+    let rule_groups = db::rules::list(org_id).await?;
+    let triggers = TRIGGERS.read().await;
+    let mut groups = Vec::new();
+    for group in rule_groups {
+        let mut rules = Vec::with_capacity(group.rules.len());
+        for (index, rule) in group.rules.iter().enumerate() {
+            match rule_type {
+                Some("alert") if !rule.is_alerting() => continue,
+                Some("record") if rule.is_alerting() => continue,
+                _ => {}
+            }
+            if !rule.is_alerting() {
+                let health = recording::rule_health(org_id, &group.name, index);
+                rules.push(RuleStatus {
+                    rule_type: "recording".to_string(),
+                    name: rule.record.clone(),
+                    query: rule.expr.clone(),
+                    labels: rule.labels.clone(),
+                    health: match health.as_ref() {
+                        Some((_, e)) if !e.is_empty() => "err".to_string(),
+                        Some(_) => "ok".to_string(),
+                        None => "unknown".to_string(),
+                    },
+                    last_error: health.as_ref().map(|(_, e)| e.clone()).unwrap_or_default(),
+                    last_evaluation: health.map(|(time, _)| format_time(time)),
+                    ..Default::default()
+                });
+                continue;
+            }
+
+            let trigger = alerting::rule_metric_name(rule).and_then(|stream_name| {
+                triggers.get(&format!(
+                    "{org_id}/{}/{stream_name}/{}",
+                    StreamType::Metrics,
+                    alerting::alert_name(&group, rule)
+                ))
+            });
+            let alert = trigger.and_then(|trigger| active_alert(rule, trigger));
+            let duration = rule
+                .for_duration
+                .as_ref()
+                .and_then(|v| parse_milliseconds(v).ok())
+                .unwrap_or_default();
+            rules.push(RuleStatus {
+                rule_type: "alerting".to_string(),
+                name: rule.alert.clone(),
+                query: rule.expr.clone(),
+                duration: Some(duration as f64 / 1000.0),
+                labels: rule.labels.clone(),
+                annotations: Some(rule.annotations.clone()),
+                state: Some(
+                    alert
+                        .as_ref()
+                        .map_or("inactive".to_string(), |alert| alert.state.clone()),
+                ),
+                alerts: Some(alert.into_iter().collect()),
+                health: if trigger.is_some() { "ok" } else { "unknown" }.to_string(),
+                ..Default::default()
+            });
+        }
+        if rule_type.is_some() && rules.is_empty() {
+            continue;
+        }
+        groups.push(RuleGroupStatus {
+            interval: interval(&group).unwrap_or_default() as f64 / 1_000_000.0,
+            name: group.name,
+            file: String::new(),
+            rules,
+        });
+    }
+    Ok(RuleDiscovery { groups })
+}
+
+/// Returns the pending and firing alerts of the alerting rules of the
+/// organization in the format of the Prometheus alerts API.
+pub async fn list_active_alerts(org_id: &str) -> Result<AlertDiscovery, anyhow::Error> {
+    let rule_groups = db::rules::list(org_id).await?;
+    let triggers = TRIGGERS.read().await;
+    let mut alerts = Vec::new();
+    for group in rule_groups {
+        for rule in group.rules.iter().filter(|rule| rule.is_alerting()) {
+            let trigger = alerting::rule_metric_name(rule).and_then(|stream_name| {
+                triggers.get(&format!(
+                    "{org_id}/{}/{stream_name}/{}",
+                    StreamType::Metrics,
+                    alerting::alert_name(&group, rule)
+                ))
+            });
+            if let Some(alert) = trigger.and_then(|trigger| active_alert(rule, trigger)) {
+                alerts.push(alert);
+            }
+        }
+    }
+    Ok(AlertDiscovery { alerts })
+}
+
+fn active_alert(rule: &Rule, trigger: &Trigger) -> Option<ActiveAlert> {
+    let state = match trigger.state {
+        AlertState::Pending => "pending",
+        AlertState::Firing => "firing",
+        _ => return None,
+    };
+    let mut labels = rule.labels.clone();
+    labels.insert("alertname".to_string(), rule.alert.clone());
+    Some(ActiveAlert {
+        labels,
+        annotations: rule.annotations.clone(),
+        state: state.to_string(),
+        active_at: (trigger.fired_at > 0).then(|| format_time(trigger.fired_at)),
+    })
+}
+
+fn format_time(time: i64) -> String {
+    Utc.timestamp_nanos(time * 1000)
+        .to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Returns the evaluation interval of the group, in microseconds.
+pub(crate) fn interval(group: &RuleGroup) -> Result<i64, anyhow::Error> {
+    let interval = parse_milliseconds(&group.interval)
+        .map_err(|e| anyhow::anyhow!("Invalid interval {}: {}", group.interval, e))?;
+    if interval == 0 {
+        return Err(anyhow::anyhow!("Interval should be greater than 0"));
+    }
+    Ok(interval as i64 * 1000)
+}
+
+fn validate(group: &RuleGroup) -> Result<(), anyhow::Error> {
+    if group.name.is_empty() {
+        return Err(anyhow::anyhow!("Rule group name is required"));
+    }
+    if group.name.contains('/') {
+        return Err(anyhow::anyhow!("Rule group name should not contain '/'"));
+    }
+    interval(group)?;
+    for rule in group.rules.iter() {
+        let name = if rule.is_alerting() {
+            if !rule.record.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "Rule {} in group {} should not be both a recording and an alerting rule",
+                    rule.alert,
+                    group.name
+                ));
+            }
+            if rule.alert.contains('/') {
+                return Err(anyhow::anyhow!(
+                    "Alert {} in group {} should not contain '/'",
+                    rule.alert,
+                    group.name
+                ));
+            }
+            if let Some(v) = rule.for_duration.as_ref() {
+                parse_milliseconds(v).map_err(|e| {
+                    anyhow::anyhow!("Invalid for {} of rule {}: {}", v, rule.alert, e)
+                })?;
+            }
+            &rule.alert
+        } else {
+            if !RE_METRIC_NAME.is_match(&rule.record) {
+                return Err(anyhow::anyhow!(
+                    "Invalid record {:?} in group {}, it should be a valid metric name",
+                    rule.record,
+                    group.name
+                ));
+            }
+            if !rule.annotations.is_empty() || rule.for_duration.is_some() {
+                return Err(anyhow::anyhow!(
+                    "Recording rule {} should not have for or annotations",
+                    rule.record
+                ));
+            }
+            &rule.record
+        };
+        parser::parse(&rule.expr)
+            .map_err(|e| anyhow::anyhow!("Invalid expr of rule {}: {}", name, e))?;
+        if let Some(label) = rule
+            .labels
+            .keys()
+            .chain(rule.annotations.keys())
+            .find(|label| !Label::is_valid_label_name(label))
+        {
+            return Err(anyhow::anyhow!(
+                "Invalid label name {} of rule {}",
+                label,
+                name
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rules_file() {
+        let body = r#"
+groups:
+  - name: example
+    interval: 30s
+    rules:
+      - record: job:http_requests:rate5m
+        expr: sum by (job) (rate(http_requests_total[5m]))
+        labels:
+          team: infra
+      - alert: TooManyRequests
+        expr: job:http_requests:rate5m > 100
+        for: 10m
+        annotations:
+          summary: Too many requests
+  - name: defaults
+    rules:
+      - record: up:count
+        expr: count(up)
+"#;
+        let file: RuleGroups = serde_yaml::from_str(body).unwrap();
+        assert_eq!(file.groups.len(), 2);
+        assert_eq!(interval(&file.groups[0]).unwrap(), 30_000_000);
+        assert_eq!(interval(&file.groups[1]).unwrap(), 60_000_000);
+        assert_eq!(file.groups[0].rules[0].labels.get("team").unwrap(), "infra");
+        let rule = &file.groups[0].rules[1];
+        assert!(rule.is_alerting());
+        assert_eq!(rule.for_duration.as_deref(), Some("10m"));
+        assert_eq!(
+            rule.annotations.get("summary").unwrap(),
+            "Too many requests"
+        );
+        for group in file.groups.iter() {
+            assert!(validate(group).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let rule = Rule {
+            record: "job:up:sum".to_string(),
+            expr: "sum by (job) (up)".to_string(),
+            ..Default::default()
+        };
+        let mut group = RuleGroup {
+            name: "example".to_string(),
+            interval: "1m".to_string(),
+            rules: vec![rule],
+        };
+        assert!(validate(&group).is_ok());
+
+        group.rules[0].record = "job-up".to_string();
+        assert!(validate(&group).is_err());
+        group.rules[0].record = "job:up:sum".to_string();
+        group.rules[0].expr = "sum by (job) (".to_string();
+        assert!(validate(&group).is_err());
+        group.rules[0].expr = "sum by (job) (up)".to_string();
+        group.rules[0]
+            .labels
+            .insert("bad-name".to_string(), "x".to_string());
+        assert!(validate(&group).is_err());
+        group.rules[0].labels.clear();
+        group.interval = "0s".to_string();
+        assert!(validate(&group).is_err());
+        group.interval = "1m".to_string();
+        group.rules[0].alert = "JobDown".to_string();
+        assert!(validate(&group).is_err());
+        group.rules[0].record.clear();
+        assert!(validate(&group).is_ok());
+    }
+    #[test]
+    fn test_active_alert() {
+        let mut rule = Rule {
+            alert: "InstanceDown".to_string(),
+            expr: "up == 0".to_string(),
+            ..Default::default()
+        };
+        rule.labels
+            .insert("severity".to_string(), "page".to_string());
+        let mut trigger = Trigger::default();
+        assert!(active_alert(&rule, &trigger).is_none());
+
+        trigger.state = AlertState::Firing;
+        trigger.fired_at = 1_700_000_000_000_000;
+        let alert = active_alert(&rule, &trigger).unwrap();
+        assert_eq!(alert.state, "firing");
+        assert_eq!(alert.labels.get("alertname").unwrap(), "InstanceDown");
+        assert_eq!(alert.labels.get("severity").unwrap(), "page");
+        assert_eq!(alert.active_at.unwrap(), "2023-11-14T22:13:20.000Z");
+    }
+}
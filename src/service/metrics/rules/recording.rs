@@ -0,0 +1,270 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashSet;
+
+use chrono::Utc;
+use once_cell::sync::Lazy;
+
+use crate::{
+    common::{
+        infra::{
+            cluster::LOCAL_NODE_UUID,
+            config::{RwHashMap, RULE_GROUPS},
+        },
+        meta::{
+            prom::NAME_LABEL,
+            rules::{Rule, RuleGroup},
+        },
+    },
+    service::{
+        db,
+        metrics::prom::{self, prometheus},
+        promql::{
+            self,
+            value::{Labels, LabelsExt, Value},
+            MetricsQueryRequest,
+        },
+    },
+};
+
+/// The last evaluation time of the rule groups, keyed by `org_id/group`.
+static LAST_EVALUATION: Lazy<RwHashMap<String, i64>> = Lazy::new(Default::default);
+
+/// The last evaluation time and error of the recording rules, keyed by
+/// `org_id/group/index`. Only known on the node which evaluates the rules.
+static RULE_HEALTH: Lazy<RwHashMap<String, (i64, String)>> = Lazy::new(Default::default);
+
+/// Returns the last evaluation time and error of the rule at `index` of the
+/// group, `None` when it was not evaluated by this node.
+pub fn rule_health(org_id: &str, group: &str, index: usize) -> Option<(i64, String)> {
+    RULE_HEALTH
+        .get(&format!("{org_id}/{group}/{index}"))
+        .map(|v| v.value().clone())
+}
+
+/// Evaluates the rule groups whose interval has elapsed since their last
+/// evaluation, the evaluation times are aligned to the interval.
+pub async fn run() -> Result<(), anyhow::Error> {
+    let node = db::alerts::alert_manager::get_mark("default").await;
+    if LOCAL_NODE_UUID.ne(&node) {
+        return Ok(());
+    }
+
+    LAST_EVALUATION.retain(|key, _| RULE_GROUPS.contains_key(key));
+    RULE_HEALTH.retain(|key, _| {
+        key.rsplit_once('/')
+            .map_or(false, |(group, _)| RULE_GROUPS.contains_key(group))
+    });
+    let groups: Vec<(String, RuleGroup)> = RULE_GROUPS
+        .iter()
+        .map(|item| (item.key().clone(), item.value().clone()))
+        .collect();
+    let now = Utc::now().timestamp_micros();
+    for (key, group) in groups {
+        let org_id = match key.split_once('/') {
+            Some((org_id, _)) => org_id,
+            None => continue,
+        };
+        let interval = match super::interval(&group) {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("[RULES] group {} err: {}", key, e);
+                continue;
+            }
+        };
+        let eval_time = now - now % interval;
+        if LAST_EVALUATION
+            .get(&key)
+            .map_or(false, |last| *last >= eval_time)
+        {
+            continue;
+        }
+        LAST_EVALUATION.insert(key.clone(), eval_time);
+        if let Err(e) = evaluate(org_id, &group, eval_time).await {
+            log::error!("[RULES] evaluate group {} err: {}", key, e);
+        }
+    }
+    Ok(())
+}
+
+/// Evaluates the rules of the group at the given time, in microseconds, and
+/// writes their results through the remote-write ingestion.
+pub async fn evaluate(org_id: &str, group: &RuleGroup, time: i64) -> Result<(), anyhow::Error> {
+    let mut timeseries = Vec::new();
+    for (index, rule) in group.rules.iter().enumerate() {
+        if rule.is_alerting() {
+            continue; // alerting rules are evaluated as alerts
+        }
+        let req = MetricsQueryRequest {
+            query: rule.expr.clone(),
+            start: time,
+            end: time,
+            step: 300_000_000, // 5m
+        };
+        let ret = match promql::search::search(org_id, &req, 0).await {
+            Ok(value) => to_timeseries(rule, value, time),
+            Err(e) => Err(anyhow::anyhow!("query error: {}", e)),
+        };
+        let health_key = format!("{org_id}/{}/{index}", group.name);
+        match ret {
+            Ok(series) => {
+                timeseries.extend(series);
+                RULE_HEALTH.insert(health_key, (time, String::new()));
+            }
+            Err(e) => {
+                log::error!(
+                    "[RULES] evaluate rule {}/{}/{} err: {}",
+                    org_id,
+                    group.name,
+                    rule.record,
+                    e
+                );
+                RULE_HEALTH.insert(health_key, (time, e.to_string()));
+            }
+        }
+    }
+    if timeseries.is_empty() {
+        return Ok(());
+    }
+    prom::write_timeseries(
+        org_id,
+        &prometheus::WriteRequest {
+            timeseries,
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+/// Converts the result of a recording rule into time series named by the
+/// record, the labels of the rule override the labels of the result.
+fn to_timeseries(
+    rule: &Rule,
+    value: Value,
+    time: i64,
+) -> Result<Vec<prometheus::TimeSeries>, anyhow::Error> {
+    let samples = match value {
+        Value::Vector(values) => values
+            .into_iter()
+            .map(|v| (v.labels, v.sample.value))
+            .collect(),
+        Value::Sample(sample) => vec![(Labels::default(), sample.value)],
+        Value::Float(value) => vec![(Labels::default(), value)],
+        Value::None => vec![],
+        _ => {
+            return Err(anyhow::anyhow!(
+                "expression should return a vector or a scalar"
+            ));
+        }
+    };
+
+    let mut signatures = HashSet::new();
+    let mut timeseries = Vec::with_capacity(samples.len());
+    for (labels, value) in samples {
+        let mut labels = labels.without_metric_name();
+        labels.set(NAME_LABEL, &rule.record);
+        for (name, value) in rule.labels.iter() {
+            if value.is_empty() {
+                labels = labels.without_label(name);
+            } else {
+                labels.set(name, value);
+            }
+        }
+        if !signatures.insert(labels.signature()) {
+            return Err(anyhow::anyhow!(
+                "vector contains metrics with the same labelset after applying rule labels"
+            ));
+        }
+        timeseries.push(prometheus::TimeSeries {
+            labels: labels
+                .iter()
+                .map(|label| prometheus::Label {
+                    name: label.name.clone(),
+                    value: label.value.clone(),
+                })
+                .collect(),
+            samples: vec![prometheus::Sample {
+                value,
+                timestamp: time / 1000, // milliseconds
+            }],
+            ..Default::default()
+        });
+    }
+    Ok(timeseries)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::service::promql::value::{InstantValue, Label, Sample};
+
+    fn labels(pairs: &[(&str, &str)]) -> Labels {
+        pairs
+            .iter()
+            .map(|(name, value)| {
+                Arc::new(Label {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_to_timeseries() {
+        let mut rule = Rule {
+            record: "job:up:sum".to_string(),
+            expr: "sum by (job) (up)".to_string(),
+            ..Default::default()
+        };
+        rule.labels.insert("team".to_string(), "infra".to_string());
+        rule.labels.insert("env".to_string(), "".to_string());
+        let value = Value::Vector(vec![InstantValue {
+            labels: labels(&[("__name__", "up"), ("env", "prod"), ("job", "api")]),
+            sample: Sample {
+                timestamp: 1_700_000_000_000_000,
+                value: 3.0,
+            },
+        }]);
+        let series = to_timeseries(&rule, value, 1_700_000_000_000_000).unwrap();
+        assert_eq!(series.len(), 1);
+        let names: Vec<_> = series[0]
+            .labels
+            .iter()
+            .map(|l| format!("{}={}", l.name, l.value))
+            .collect();
+        assert_eq!(names, vec!["__name__=job:up:sum", "job=api", "team=infra"]);
+        assert_eq!(series[0].samples[0].timestamp, 1_700_000_000_000);
+        assert_eq!(series[0].samples[0].value, 3.0);
+
+        let series = to_timeseries(&rule, Value::Float(1.5), 0).unwrap();
+        assert_eq!(series.len(), 1);
+
+        let value = Value::Vector(vec![
+            InstantValue {
+                labels: labels(&[("env", "prod")]),
+                sample: Sample::default(),
+            },
+            InstantValue {
+                labels: labels(&[("env", "dev")]),
+                sample: Sample::default(),
+            },
+        ]);
+        assert!(to_timeseries(&rule, value, 0).is_err());
+    }
+}
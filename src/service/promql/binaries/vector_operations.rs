@@ -242,8 +242,20 @@ fn vector_arithmatic_operators(
         }
     };
 
-    // Get the hash for the labels on the right
-    let rhs_sig: HashMap<Signature, InstantValue> = right
+    // With `group_right` the right-hand side is the "many" side, the output
+    // series are taken from it and the operands keep their order.
+    let group_right = matches!(
+        expr.modifier.as_ref().map(|modifier| &modifier.card),
+        Some(VectorMatchCardinality::OneToMany(_))
+    );
+    let (many, one) = if group_right {
+        (right, left)
+    } else {
+        (left, right)
+    };
+
+    // Get the hash for the labels on the "one" side
+    let one_sig: HashMap<Signature, InstantValue> = one
         .par_iter()
         .map(|instant| {
             let signature = labels_to_compare(&instant.labels).signature();
@@ -251,16 +263,21 @@ fn vector_arithmatic_operators(
         })
         .collect();
 
-    // Iterate over left and pick up the corresponding instance from rhs
-    let output: Vec<InstantValue> = left
+    // Iterate over the "many" side and pick up the corresponding instance
+    let output: Vec<InstantValue> = many
         .par_iter()
         .flat_map(|instant| {
-            let left_sig = labels_to_compare(&instant.labels).signature();
-            rhs_sig
-                .get(&left_sig)
-                .map(|rhs_instant| (instant, rhs_instant))
+            let many_sig = labels_to_compare(&instant.labels).signature();
+            one_sig
+                .get(&many_sig)
+                .map(|one_instant| (instant, one_instant))
         })
-        .flat_map(|(lhs_instant, rhs_instant)| {
+        .flat_map(|(many_instant, one_instant)| {
+            let (lhs_instant, rhs_instant) = if group_right {
+                (one_instant, many_instant)
+            } else {
+                (many_instant, one_instant)
+            };
             scalar_binary_operations(
                 operator,
                 lhs_instant.sample.value,
@@ -271,9 +288,9 @@ fn vector_arithmatic_operators(
             .ok()
             .map(|value| {
                 let mut labels = if return_bool || DROP_METRIC_VECTOR_BIN_OP.contains(&operator) {
-                    lhs_instant.labels.without_metric_name()
+                    many_instant.labels.without_metric_name()
                 } else {
-                    lhs_instant.labels.clone()
+                    many_instant.labels.clone()
                 };
 
                 if let Some(modifier) = expr.modifier.as_ref() {
@@ -281,11 +298,14 @@ fn vector_arithmatic_operators(
                         labels = labels_to_compare(&labels);
                     }
 
-                    // group_labels from the `group_x` modifier are taken from the "one"-side.
+                    // group_labels from the `group_x` modifier are taken from the "one"-side,
+                    // a label missing there is removed from the output.
                     if let Some(group_labels) = modifier.card.labels() {
                         for ln in group_labels.labels.iter() {
-                            let value = rhs_instant.labels.get_value(ln);
-                            if !value.is_empty() {
+                            let value = one_instant.labels.get_value(ln);
+                            if value.is_empty() {
+                                labels = labels.without_label(ln);
+                            } else {
                                 labels.set(ln, &value);
                             }
                         }
@@ -294,7 +314,7 @@ fn vector_arithmatic_operators(
                 InstantValue {
                     labels,
                     sample: Sample {
-                        timestamp: lhs_instant.sample.timestamp,
+                        timestamp: many_instant.sample.timestamp,
                         value,
                     },
                 }
@@ -13,7 +13,12 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use std::{collections::HashSet, str::FromStr, sync::Arc, time::Duration};
+use std::{
+    collections::HashSet,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
 
 use ahash::AHashMap as HashMap;
 use async_recursion::async_recursion;
@@ -21,17 +26,18 @@ use datafusion::{
     arrow::{
         array::{Float64Array, Int64Array, StringArray},
         datatypes::Schema,
+        record_batch::RecordBatch,
     },
     error::{DataFusionError, Result},
-    prelude::{col, lit, SessionContext},
+    prelude::{col, lit, DataFrame, SessionContext},
 };
 use futures::future::try_join_all;
 use promql_parser::{
     label::MatchOp,
     parser::{
-        token, AggregateExpr, Call, Expr as PromExpr, Function, FunctionArgs, LabelModifier,
-        MatrixSelector, NumberLiteral, Offset, ParenExpr, StringLiteral, TokenType, UnaryExpr,
-        VectorSelector,
+        token, AggregateExpr, AtModifier, BinaryExpr, Call, Expr as PromExpr, Function,
+        FunctionArgs, LabelModifier, MatrixSelector, NumberLiteral, Offset, ParenExpr,
+        StringLiteral, SubqueryExpr, TokenType, UnaryExpr, VectorSelector,
     },
 };
 use rayon::prelude::*;
@@ -39,20 +45,25 @@ use rayon::prelude::*;
 use crate::{
     common::{
         infra::config::CONFIG,
-        meta::prom::{HASH_LABEL, NAME_LABEL, VALUE_LABEL},
+        meta::prom::{
+            NativeHistogram, EXEMPLARS_LABEL, HASH_LABEL, HISTOGRAM_LABEL, NAME_LABEL, VALUE_LABEL,
+        },
+        utils::json,
+    },
+    service::promql::{
+        aggregations, binaries, exec::Query, functions, micros, value::*, DEFAULT_EVAL_INTERVAL,
     },
-    service::promql::{aggregations, binaries, functions, micros, value::*},
 };
 
 pub struct Engine {
-    ctx: Arc<super::exec::Query>,
+    ctx: Arc<Query>,
     /// The time boundaries for the evaluation.
     time: i64,
     result_type: Option<String>,
 }
 
 impl Engine {
-    pub fn new(ctx: Arc<super::exec::Query>, time: i64) -> Self {
+    pub fn new(ctx: Arc<Query>, time: i64) -> Self {
         Self {
             ctx,
             time,
@@ -118,9 +129,12 @@ impl Engine {
 
                 // This is a very special case, as we treat the float also a
                 // `Value::Vector(vec![element])` therefore, better convert it
-                // back to its representation.
+                // back to its representation. A vector with labels is kept, it
+                // has to be matched against the left-hand side.
                 let rhs = match rhs {
-                    Value::Vector(v) if v.len() == 1 => Value::Float(v[0].sample.value),
+                    Value::Vector(v) if v.len() == 1 && v[0].labels.is_empty() => {
+                        Value::Float(v[0].sample.value)
+                    }
                     _ => rhs,
                 };
                 match (lhs.clone(), rhs.clone()) {
@@ -153,45 +167,12 @@ impl Engine {
             }
             PromExpr::Paren(ParenExpr { expr }) => self.exec_expr(expr).await?,
             PromExpr::Subquery(expr) => {
-                let val = self.exec_expr(&expr.expr).await?;
-                let time_window = Some(TimeWindow::new(self.time, expr.range));
-                let matrix = match val {
-                    Value::Vector(v) => v
-                        .iter()
-                        .map(|v| RangeValue {
-                            labels: v.labels.to_owned(),
-                            samples: vec![v.sample],
-                            time_window: time_window.clone(),
-                        })
-                        .collect(),
-                    Value::Instant(v) => {
-                        vec![RangeValue {
-                            labels: v.labels.to_owned(),
-                            samples: vec![v.sample],
-                            time_window,
-                        }]
-                    }
-                    Value::Range(v) => vec![v],
-                    Value::Matrix(vs) => vs,
-                    Value::Sample(s) => vec![RangeValue {
-                        labels: Labels::default(),
-                        samples: vec![s],
-                        time_window,
-                    }],
-                    Value::Float(val) => vec![RangeValue {
-                        labels: Labels::default(),
-                        samples: vec![Sample::new(self.time, val)],
-                        time_window,
-                    }],
-                    v => {
-                        return Err(DataFusionError::NotImplemented(format!(
-                            "Unsupported subquery, the return value should have been a matrix but got {:?}",
-                            v.get_type()
-                        )));
-                    }
-                };
-
-                Value::Matrix(matrix)
+                let data = self.eval_subquery(expr).await?;
+                if data.is_empty() {
+                    Value::None
+                } else {
+                    Value::Matrix(data)
+                }
             }
             PromExpr::NumberLiteral(NumberLiteral { val }) => Value::Float(*val),
             PromExpr::StringLiteral(StringLiteral { val }) => Value::String(val.clone()),
@@ -238,28 +219,32 @@ impl Engine {
         &mut self,
         selector: &VectorSelector,
     ) -> Result<Vec<InstantValue>> {
+        Ok(self
+            .eval_vector_selector_with_timestamps(selector)
+            .await?
+            .into_iter()
+            .map(|(value, _)| value)
+            .collect())
+    }
+
+    /// Same as [`Engine::eval_vector_selector`], but also returns the
+    /// timestamps of the selected samples, used by `timestamp()`.
+    async fn eval_vector_selector_with_timestamps(
+        &mut self,
+        selector: &VectorSelector,
+    ) -> Result<Vec<(InstantValue, i64)>> {
         if self.result_type.is_none() {
             self.result_type = Some("vector".to_string());
         }
 
-        let mut selector = selector.clone();
-        if selector.name.is_none() {
-            let name = selector
-                .matchers
-                .find_matcher_value(NAME_LABEL)
-                .expect("Missing selector name");
-
-            selector.name = Some(name);
-        }
-
-        let metrics_name = selector.name.as_ref().expect("Missing selector name");
-
-        let cache_exists = { self.ctx.data_cache.read().await.contains_key(metrics_name) };
+        let selector = normalize_selector(selector);
+        let cache_key = selector_key(&selector);
+        let cache_exists = { self.ctx.data_cache.read().await.contains_key(&cache_key) };
         if !cache_exists {
             self.selector_load_data(&selector, None).await?;
         }
         let metrics_cache = self.ctx.data_cache.read().await;
-        let metrics_cache = match metrics_cache.get(metrics_name) {
+        let metrics_cache = match metrics_cache.get(&cache_key) {
             Some(v) => match v.get_ref_matrix_values() {
                 Some(v) => v,
                 None => return Ok(vec![]),
@@ -271,34 +256,30 @@ impl Engine {
         let eval_ts = self.time;
         let start = eval_ts - self.ctx.lookback_delta;
 
-        let mut offset_modifier: i64 = 0;
-        if let Some(offset) = selector.offset.clone() {
-            match offset {
-                Offset::Pos(off) => {
-                    offset_modifier = micros(off);
-                }
-                Offset::Neg(off) => {
-                    offset_modifier = -micros(off);
-                }
-            }
-        }
+        // The `@` and `offset` modifiers move the selection; samples are
+        // shifted back so that they line up with the evaluation timestamp.
+        let offset_modifier =
+            eval_ts - modified_time(&self.ctx, eval_ts, &selector.at, &selector.offset);
 
         let mut values = vec![];
         for metric in metrics_cache {
-            if let Some(last_value) = metric
+            if let Some(last) = metric
                 .samples
                 .iter()
-                .map(|s| Sample::new(s.timestamp + offset_modifier, s.value))
-                .filter_map(|s| (start < s.timestamp && s.timestamp <= eval_ts).then_some(s.value))
+                .filter(|s| {
+                    let ts = s.timestamp + offset_modifier;
+                    start < ts && ts <= eval_ts
+                })
                 .last()
             {
-                values.push(
+                values.push((
                     // See https://promlabs.com/blog/2020/06/18/the-anatomy-of-a-promql-query/#instant-queries
                     InstantValue {
                         labels: metric.labels.clone(),
-                        sample: Sample::new(eval_ts, last_value),
+                        sample: Sample::new(eval_ts, last.value),
                     },
-                );
+                    last.timestamp,
+                ));
             }
         }
         Ok(values)
@@ -320,23 +301,14 @@ impl Engine {
             self.result_type = Some("matrix".to_string());
         }
 
-        let mut selector = selector.clone();
-        if selector.name.is_none() {
-            let name = selector
-                .matchers
-                .find_matcher_value(NAME_LABEL)
-                .expect("Missing selector name");
-
-            selector.name = Some(name);
-        }
-
-        let metrics_name = selector.name.as_ref().expect("Missing selector name");
-        let cache_exists = { self.ctx.data_cache.read().await.contains_key(metrics_name) };
+        let selector = normalize_selector(selector);
+        let cache_key = selector_key(&selector);
+        let cache_exists = { self.ctx.data_cache.read().await.contains_key(&cache_key) };
         if !cache_exists {
             self.selector_load_data(&selector, Some(range)).await?;
         }
         let metrics_cache = self.ctx.data_cache.read().await;
-        let metrics_cache = match metrics_cache.get(metrics_name) {
+        let metrics_cache = match metrics_cache.get(&cache_key) {
             Some(v) => match v.get_ref_matrix_values() {
                 Some(v) => v,
                 None => return Ok(vec![]),
@@ -348,17 +320,8 @@ impl Engine {
         let eval_ts = self.time;
         // Start of the time window.
         let start = eval_ts - micros(range); // e.g. [5m]
-        let mut offset_modifier = 0;
-        if let Some(offset) = selector.offset.clone() {
-            match offset {
-                Offset::Pos(offset) => {
-                    offset_modifier = micros(offset);
-                }
-                Offset::Neg(offset) => {
-                    offset_modifier = -micros(offset);
-                }
-            }
-        };
+        let offset_modifier =
+            eval_ts - modified_time(&self.ctx, eval_ts, &selector.at, &selector.offset);
 
         let mut values = Vec::with_capacity(metrics_cache.len());
         for metric in metrics_cache {
@@ -381,29 +344,79 @@ impl Engine {
         Ok(values)
     }
 
+    /// Subquery --- evaluate the inner expression at each step of the
+    /// subquery range and collect the results into a range vector.
+    ///
+    /// Steps are aligned to multiples of the step, like Prometheus does, so
+    /// the same subquery returns the same points regardless of the
+    /// evaluation timestamp.
+    async fn eval_subquery(&mut self, expr: &SubqueryExpr) -> Result<Vec<RangeValue>> {
+        if self.result_type.is_none() {
+            self.result_type = Some("matrix".to_string());
+        }
+
+        // without a step, the subquery is evaluated at the interval of the
+        // range query, or the default evaluation interval for instant queries
+        let step = match expr.step {
+            Some(step) => micros(step),
+            None if self.ctx.start != self.ctx.end => self.ctx.interval,
+            None => micros(DEFAULT_EVAL_INTERVAL),
+        };
+        if step <= 0 {
+            return Err(DataFusionError::Plan(
+                "Subquery step should be greater than zero".to_string(),
+            ));
+        }
+        let end = modified_time(&self.ctx, self.time, &expr.at, &expr.offset);
+        let start = end - micros(expr.range);
+        // shift the results back so that they line up with the evaluation timestamp
+        let offset_modifier = self.time - end;
+
+        let mut series: Vec<RangeValue> = Vec::new();
+        let mut series_index: HashMap<Signature, usize> = HashMap::default();
+        let mut ts = start - start.rem_euclid(step) + step;
+        while ts <= end {
+            let mut engine = Engine::new(self.ctx.clone(), ts);
+            let values = match engine.exec_expr(&expr.expr).await? {
+                Value::Vector(vs) => vs.into_iter().map(|v| (v.labels, v.sample.value)).collect(),
+                Value::Instant(v) => vec![(v.labels, v.sample.value)],
+                Value::Sample(s) => vec![(Labels::default(), s.value)],
+                Value::Float(val) => vec![(Labels::default(), val)],
+                Value::None => vec![],
+                v => {
+                    return Err(DataFusionError::NotImplemented(format!(
+                        "Unsupported subquery, the inner expression should have been an instant vector or a scalar but got {:?}",
+                        v.get_type()
+                    )));
+                }
+            };
+            for (labels, value) in values {
+                let index = *series_index.entry(signature(&labels)).or_insert_with(|| {
+                    series.push(RangeValue {
+                        labels,
+                        samples: vec![],
+                        time_window: Some(TimeWindow::new(self.time, expr.range)),
+                    });
+                    series.len() - 1
+                });
+                series[index]
+                    .samples
+                    .push(Sample::new(ts + offset_modifier, value));
+            }
+            ts += step;
+        }
+
+        Ok(series)
+    }
+
     #[tracing::instrument(name = "promql:engine:load_data", skip_all)]
     async fn selector_load_data(
         &mut self,
         selector: &VectorSelector,
         range: Option<Duration>,
     ) -> Result<()> {
-        // https://promlabs.com/blog/2020/07/02/selecting-data-in-promql/#lookback-delta
-
-        let mut start = self.ctx.start - range.map_or(self.ctx.lookback_delta, micros);
-        let mut end = self.ctx.end; // 30 minutes + 5m = 35m
-
-        if let Some(offset) = selector.offset.clone() {
-            match offset {
-                Offset::Pos(offset) => {
-                    start -= micros(offset);
-                    end -= micros(offset);
-                }
-                Offset::Neg(offset) => {
-                    start += micros(offset);
-                    end += micros(offset);
-                }
-            }
-        }
+        let cache_key = selector_key(selector);
+        let (start, end) = self.selector_time_range(selector, range);
 
         // 1. Group by metrics (sets of label name-value pairs)
         let table_name = selector.name.as_ref().unwrap();
@@ -422,6 +435,13 @@ impl Engine {
 
         let mut tasks = Vec::new();
         for (ctx, schema, scan_stats) in ctxs {
+            if schema.field_with_name(HISTOGRAM_LABEL).is_ok() {
+                self.ctx
+                    .histogram_metrics
+                    .write()
+                    .await
+                    .insert(table_name.to_string());
+            }
             let selector = selector.clone();
             let task = tokio::time::timeout(Duration::from_secs(self.ctx.timeout), async move {
                 selector_load_data_from_datafusion(ctx, schema, selector, start, end).await
@@ -457,7 +477,7 @@ impl Engine {
                 .data_cache
                 .write()
                 .await
-                .insert(table_name.to_string(), Value::None);
+                .insert(cache_key, Value::None);
             return Ok(());
         }
 
@@ -471,11 +491,229 @@ impl Engine {
         } else {
             Value::Matrix(metric_values)
         };
+        self.ctx.data_cache.write().await.insert(cache_key, values);
+        Ok(())
+    }
+
+    /// Returns the time range of the samples to load for the selector.
+    fn selector_time_range(
+        &self,
+        selector: &VectorSelector,
+        range: Option<Duration>,
+    ) -> (i64, i64) {
+        // https://promlabs.com/blog/2020/07/02/selecting-data-in-promql/#lookback-delta
+        match self.ctx.selector_ranges.get(&selector_key(selector)) {
+            Some(v) => *v,
+            None => {
+                let lookback = range.map_or(self.ctx.lookback_delta, micros);
+                (
+                    modified_time(&self.ctx, self.ctx.start, &selector.at, &selector.offset)
+                        - lookback,
+                    modified_time(&self.ctx, self.ctx.end, &selector.at, &selector.offset),
+                )
+            }
+        }
+    }
+
+    /// Evaluates the argument of `histogram_quantile` over the native
+    /// histograms it selects: a selector, `rate()`-like functions of a range
+    /// selector and `sum` aggregations of those. Returns `None` for other
+    /// expressions, which only apply to conventional histograms.
+    #[async_recursion]
+    async fn eval_native_histograms(
+        &mut self,
+        expr: &PromExpr,
+    ) -> Result<Option<Vec<(Labels, NativeHistogram)>>> {
+        Ok(match expr {
+            PromExpr::Paren(ParenExpr { expr }) => self.eval_native_histograms(expr).await?,
+            PromExpr::VectorSelector(selector) => Some(
+                self.eval_histogram_selector(selector, None)
+                    .await?
+                    .into_iter()
+                    .filter_map(|mut series| {
+                        let (_, hist) = series.histograms.pop()?;
+                        Some((series.labels, hist))
+                    })
+                    .collect(),
+            ),
+            PromExpr::Call(Call { func, args })
+                if matches!(
+                    func.name,
+                    "rate" | "increase" | "delta" | "irate" | "idelta"
+                ) =>
+            {
+                let (selector, range) = match args.args.first().map(|arg| arg.as_ref()) {
+                    Some(PromExpr::MatrixSelector(MatrixSelector { vs, range })) => (vs, *range),
+                    _ => return Ok(None),
+                };
+                // The quantile doesn't depend on the scale of the counts, so
+                // the increase stands for the rate as well.
+                let last_two = matches!(func.name, "irate" | "idelta");
+                let values = self
+                    .eval_histogram_selector(selector, Some(range))
+                    .await?
+                    .into_iter()
+                    .filter(|series| series.histograms.len() >= 2)
+                    .map(|series| {
+                        let histograms = &series.histograms;
+                        let (_, last) = &histograms[histograms.len() - 1];
+                        let (_, first) = if last_two {
+                            &histograms[histograms.len() - 2]
+                        } else {
+                            &histograms[0]
+                        };
+                        (
+                            series.labels.without_metric_name(),
+                            functions::native_histogram_increase(last, first),
+                        )
+                    })
+                    .collect();
+                Some(values)
+            }
+            PromExpr::Aggregate(AggregateExpr {
+                op, expr, modifier, ..
+            }) if op.id() == token::T_SUM => {
+                let values = match self.eval_native_histograms(expr).await? {
+                    Some(values) => values,
+                    None => return Ok(None),
+                };
+                let mut groups: HashMap<Signature, (Labels, NativeHistogram)> = HashMap::default();
+                for (labels, hist) in values {
+                    let labels = match modifier {
+                        Some(LabelModifier::Include(names)) => {
+                            aggregations::labels_to_include(&names.labels, &labels)
+                        }
+                        Some(LabelModifier::Exclude(names)) => {
+                            aggregations::labels_to_exclude(&names.labels, &labels)
+                        }
+                        None => Labels::default(),
+                    };
+                    match groups.get_mut(&labels.signature()) {
+                        Some((_, total)) => *total = functions::add_native_histograms(total, &hist),
+                        None => {
+                            groups.insert(labels.signature(), (labels, hist));
+                        }
+                    }
+                }
+                Some(groups.into_values().collect())
+            }
+            _ => None,
+        })
+    }
+
+    /// Selects the native histograms of the selector in the time window
+    /// ending at the evaluation timestamp, `range` long or the lookback delta
+    /// for an instant selector.
+    async fn eval_histogram_selector(
+        &mut self,
+        selector: &VectorSelector,
+        range: Option<Duration>,
+    ) -> Result<Vec<HistogramRangeValue>> {
+        let selector = normalize_selector(selector);
+        let metric_name = selector.name.as_deref().unwrap_or_default();
+        if !self
+            .ctx
+            .histogram_metrics
+            .read()
+            .await
+            .contains(metric_name)
+        {
+            return Ok(vec![]);
+        }
+        let cache_key = selector_key(&selector);
+        let cache_exists = {
+            self.ctx
+                .histogram_cache
+                .read()
+                .await
+                .contains_key(&cache_key)
+        };
+        if !cache_exists {
+            self.selector_load_histograms(&selector, range).await?;
+        }
+
+        let eval_ts = self.time;
+        let start = eval_ts - range.map_or(self.ctx.lookback_delta, micros);
+        let offset_modifier =
+            eval_ts - modified_time(&self.ctx, eval_ts, &selector.at, &selector.offset);
+        let histogram_cache = self.ctx.histogram_cache.read().await;
+        let series = match histogram_cache.get(&cache_key) {
+            Some(v) => v,
+            None => return Ok(vec![]),
+        };
+        Ok(series
+            .iter()
+            .map(|series| HistogramRangeValue {
+                labels: series.labels.clone(),
+                histograms: series
+                    .histograms
+                    .iter()
+                    .map(|(ts, hist)| (ts + offset_modifier, hist))
+                    .filter(|(ts, _)| start < *ts && *ts <= eval_ts)
+                    .map(|(ts, hist)| (ts, hist.clone()))
+                    .collect(),
+            })
+            .filter(|series| !series.histograms.is_empty())
+            .collect())
+    }
+
+    #[tracing::instrument(name = "promql:engine:load_histograms", skip_all)]
+    async fn selector_load_histograms(
+        &mut self,
+        selector: &VectorSelector,
+        range: Option<Duration>,
+    ) -> Result<()> {
+        let cache_key = selector_key(selector);
+        let (start, end) = self.selector_time_range(selector, range);
+
+        let table_name = selector.name.as_ref().unwrap();
+        let filters = selector
+            .matchers
+            .matchers
+            .iter()
+            .filter(|mat| mat.op == MatchOp::Equal)
+            .map(|mat| (mat.name.as_str(), vec![mat.value.as_str()]))
+            .collect::<Vec<(_, _)>>();
+        let ctxs = self
+            .ctx
+            .table_provider
+            .create_context(&self.ctx.org_id, table_name, (start, end), &filters)
+            .await?;
+
+        let mut tasks = Vec::new();
+        for (ctx, schema, scan_stats) in ctxs {
+            let selector = selector.clone();
+            let task = tokio::time::timeout(Duration::from_secs(self.ctx.timeout), async move {
+                selector_load_histograms_from_datafusion(ctx, schema, selector, start, end).await
+            });
+            tasks.push(task);
+            let mut ctx_scan_stats = self.ctx.scan_stats.write().await;
+            ctx_scan_stats.add(&scan_stats);
+        }
+        let task_results = try_join_all(tasks)
+            .await
+            .map_err(|e| DataFusionError::Plan(format!("task error: {:?}", e)))?;
+
+        let mut series: HashMap<String, HistogramRangeValue> = HashMap::default();
+        for task_result in task_results {
+            for (key, value) in task_result? {
+                match series.get_mut(&key) {
+                    Some(entry) => entry.histograms.extend(value.histograms),
+                    None => {
+                        series.insert(key, value);
+                    }
+                }
+            }
+        }
+        let mut series = series.into_values().collect::<Vec<_>>();
+        for entry in series.iter_mut() {
+            entry.histograms.sort_by(|a, b| a.0.cmp(&b.0));
+        }
         self.ctx
-            .data_cache
+            .histogram_cache
             .write()
             .await
-            .insert(table_name.to_string(), values);
+            .insert(cache_key, series);
         Ok(())
     }
 
@@ -601,6 +839,7 @@ impl Engine {
             "hour",
             "minute",
             "month",
+            "pi",
             "time",
             "year",
         ]);
@@ -635,6 +874,12 @@ impl Engine {
             Func::Abs => functions::abs(&input)?,
             Func::Absent => functions::absent(&input, self.time)?,
             Func::AbsentOverTime => functions::absent_over_time(&input)?,
+            Func::Acos => functions::acos(&input)?,
+            Func::Acosh => functions::acosh(&input)?,
+            Func::Asin => functions::asin(&input)?,
+            Func::Asinh => functions::asinh(&input)?,
+            Func::Atan => functions::atan(&input)?,
+            Func::Atanh => functions::atanh(&input)?,
             Func::AvgOverTime => functions::avg_over_time(&input)?,
             Func::Ceil => functions::ceil(&input)?,
             Func::Changes => functions::changes(&input)?,
@@ -688,11 +933,14 @@ impl Engine {
                 };
                 functions::clamp(&input, min_f, f64::MAX)?
             }
+            Func::Cos => functions::cos(&input)?,
+            Func::Cosh => functions::cosh(&input)?,
             Func::CountOverTime => functions::count_over_time(&input)?,
             Func::DayOfMonth => functions::day_of_month(&input)?,
             Func::DayOfWeek => functions::day_of_week(&input)?,
             Func::DayOfYear => functions::day_of_year(&input)?,
             Func::DaysInMonth => functions::days_in_month(&input)?,
+            Func::Deg => functions::deg(&input)?,
             Func::Delta => functions::delta(&input)?,
             Func::Deriv => functions::deriv(&input)?,
             Func::Exp => functions::exp(&input)?,
@@ -730,7 +978,32 @@ impl Engine {
                     }
                 };
                 let sample_time = self.time;
-                functions::histogram_quantile(sample_time, phi, input)?
+                let value = functions::histogram_quantile(sample_time, phi, input)?;
+                // the native histograms are read only when one of the metrics
+                // evaluated has them
+                if self.ctx.histogram_metrics.read().await.is_empty() {
+                    return Ok(value);
+                }
+                let native = match self.eval_native_histograms(&args[1]).await? {
+                    Some(histograms) => histograms,
+                    None => return Ok(value),
+                };
+                let mut values = match value {
+                    Value::Vector(v) => v,
+                    _ => vec![],
+                };
+                values.extend(native.into_iter().map(|(labels, hist)| InstantValue {
+                    labels: labels.without_metric_name(),
+                    sample: Sample::new(
+                        sample_time,
+                        functions::native_histogram_quantile(phi, &hist),
+                    ),
+                }));
+                if values.is_empty() {
+                    Value::None
+                } else {
+                    Value::Vector(values)
+                }
             }
             Func::HistogramSum => {
                 return Err(DataFusionError::NotImplemented(format!(
@@ -808,10 +1081,12 @@ impl Engine {
             Func::Ln => functions::ln(&input)?,
             Func::Log10 => functions::log10(&input)?,
             Func::Log2 => functions::log2(&input)?,
+            Func::MadOverTime => functions::mad_over_time(&input)?,
             Func::MaxOverTime => functions::max_over_time(&input)?,
             Func::MinOverTime => functions::min_over_time(&input)?,
             Func::Minute => functions::minute(&input)?,
             Func::Month => functions::month(&input)?,
+            Func::Pi => Value::Float(std::f64::consts::PI),
             Func::PredictLinear => {
                 let err = "Invalid args, expected \"predict_linear(v range-vector, t scalar)\"";
 
@@ -840,63 +1115,175 @@ impl Engine {
                 let input = self.call_expr_second_arg(args).await?;
                 functions::quantile_over_time(self.time, phi_quantile, &input)?
             }
+            Func::PresentOverTime => functions::present_over_time(&input)?,
+            Func::Rad => functions::rad(&input)?,
             Func::Rate => functions::rate(&input)?,
             Func::Resets => functions::resets(&input)?,
-            Func::Round => functions::round(&input)?,
-            Func::Scalar => match input {
-                Value::Float(_) => input,
+            Func::Round => match args.len() {
+                1 => functions::round(&input)?,
+                2 => {
+                    let err =
+                        "Invalid args, expected \"round(v instant-vector, to_nearest scalar)\"";
+                    let input = self.call_expr_first_arg(args).await?;
+                    let to_nearest = self.call_expr_second_arg(args).await?;
+                    let to_nearest = self.parse_f64_else_err(&to_nearest, err)?;
+                    functions::round_to_nearest(&input, to_nearest)?
+                }
                 _ => {
-                    return Err(DataFusionError::NotImplemented(format!(
-                        "Invalid scalar value: {:?}",
-                        input
-                    )));
+                    return Err(DataFusionError::NotImplemented(
+                        "Invalid args, expected \"round(v instant-vector, to_nearest=1 scalar)\""
+                            .into(),
+                    ));
                 }
             },
+            Func::Scalar => functions::scalar(&input)?,
             Func::Sgn => functions::sgn(&input)?,
-            Func::Sort => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
-            Func::SortDesc => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
+            Func::Sin => functions::sin(&input)?,
+            Func::Sinh => functions::sinh(&input)?,
+            Func::Sort => functions::sort(&input)?,
+            Func::SortDesc => functions::sort_desc(&input)?,
             Func::Sqrt => functions::sqrt(&input)?,
             Func::StddevOverTime => functions::stddev_over_time(&input)?,
             Func::StdvarOverTime => functions::stdvar_over_time(&input)?,
             Func::SumOverTime => functions::sum_over_time(&input)?,
             Func::Time => Value::Float((self.time / 1_000_000) as f64),
-            Func::Timestamp => match &input {
-                Value::Vector(instant_value) => {
-                    let out: Vec<InstantValue> = instant_value
-                        .iter()
-                        .map(|instant| InstantValue {
-                            labels: instant.labels.without_metric_name(),
-                            sample: Sample {
-                                timestamp: instant.sample.timestamp,
-                                value: (instant.sample.timestamp / 1000 / 1000) as f64,
-                            },
-                        })
-                        .collect();
-                    Value::Vector(out)
-                }
-                _ => {
-                    return Err(DataFusionError::NotImplemented(format!(
-                        "Unexpected input to timestamp function: {:?}",
-                        &input
-                    )));
+            Func::Tan => functions::tan(&input)?,
+            Func::Tanh => functions::tanh(&input)?,
+            Func::Timestamp => {
+                // A vector selector returns its samples at the evaluation
+                // timestamp, so select the sample timestamps separately.
+                match args.args.first().map(|arg| arg.as_ref()) {
+                    Some(PromExpr::VectorSelector(selector)) => {
+                        let (values, timestamps): (Vec<_>, Vec<_>) = self
+                            .eval_vector_selector_with_timestamps(selector)
+                            .await?
+                            .into_iter()
+                            .unzip();
+                        functions::timestamp(&Value::Vector(values), Some(&timestamps))?
+                    }
+                    _ => functions::timestamp(&input, None)?,
                 }
-            },
+            }
             Func::Vector => functions::vector(&input, self.time)?,
             Func::Year => functions::year(&input)?,
         })
     }
 }
 
+/// Fills in the metric name of a selector that only has a `__name__` matcher.
+fn normalize_selector(selector: &VectorSelector) -> VectorSelector {
+    let mut selector = selector.clone();
+    if selector.name.is_none() {
+        let name = selector
+            .matchers
+            .find_matcher_value(NAME_LABEL)
+            .expect("Missing selector name");
+
+        selector.name = Some(name);
+    }
+    selector
+}
+
+/// Key of the data cache. Selectors on the same metric with different
+/// matchers or modifiers select different data, so they are cached apart.
+fn selector_key(selector: &VectorSelector) -> String {
+    let mut matchers = selector
+        .matchers
+        .matchers
+        .iter()
+        .map(|mat| format!("{}{:?}{}", mat.name, mat.op, mat.value))
+        .collect::<Vec<_>>();
+    matchers.sort();
+    format!(
+        "{}{{{}}}{:?}{:?}",
+        selector.name.as_deref().unwrap_or_default(),
+        matchers.join(","),
+        selector.at,
+        selector.offset
+    )
+}
+
+/// Returns the timestamp an expression evaluated at `time` selects data at,
+/// once the `@` and `offset` modifiers are applied.
+fn modified_time(ctx: &Query, time: i64, at: &Option<AtModifier>, offset: &Option<Offset>) -> i64 {
+    let time = match at {
+        Some(AtModifier::Start) => ctx.start,
+        Some(AtModifier::End) => ctx.end,
+        Some(AtModifier::At(t)) => match t.duration_since(UNIX_EPOCH) {
+            Ok(d) => micros(d),
+            Err(e) => -micros(e.duration()),
+        },
+        None => time,
+    };
+    match offset {
+        Some(Offset::Pos(offset)) => time - micros(*offset),
+        Some(Offset::Neg(offset)) => time + micros(*offset),
+        None => time,
+    }
+}
+
+/// Walks the expression and collects, for every selector, the time range of
+/// the samples needed to evaluate it between `start` and `end`, taking
+/// subqueries and the `@` and `offset` modifiers into account.
+pub(crate) fn collect_selector_ranges(
+    ctx: &Query,
+    expr: &PromExpr,
+    (start, end): (i64, i64),
+    ranges: &mut HashMap<String, (i64, i64)>,
+) {
+    match expr {
+        PromExpr::Aggregate(AggregateExpr { expr, param, .. }) => {
+            collect_selector_ranges(ctx, expr, (start, end), ranges);
+            if let Some(param) = param {
+                collect_selector_ranges(ctx, param, (start, end), ranges);
+            }
+        }
+        PromExpr::Unary(UnaryExpr { expr }) | PromExpr::Paren(ParenExpr { expr }) => {
+            collect_selector_ranges(ctx, expr, (start, end), ranges)
+        }
+        PromExpr::Binary(BinaryExpr { lhs, rhs, .. }) => {
+            collect_selector_ranges(ctx, lhs, (start, end), ranges);
+            collect_selector_ranges(ctx, rhs, (start, end), ranges);
+        }
+        PromExpr::Subquery(expr) => {
+            let sub_start = modified_time(ctx, start, &expr.at, &expr.offset) - micros(expr.range);
+            let sub_end = modified_time(ctx, end, &expr.at, &expr.offset);
+            collect_selector_ranges(ctx, &expr.expr, (sub_start, sub_end), ranges);
+        }
+        PromExpr::VectorSelector(selector) => {
+            add_selector_range(ctx, selector, ctx.lookback_delta, (start, end), ranges)
+        }
+        PromExpr::MatrixSelector(MatrixSelector { vs, range }) => {
+            add_selector_range(ctx, vs, micros(*range), (start, end), ranges)
+        }
+        PromExpr::Call(Call { args, .. }) => {
+            for arg in args.args.iter() {
+                collect_selector_ranges(ctx, arg, (start, end), ranges);
+            }
+        }
+        PromExpr::NumberLiteral(_) | PromExpr::StringLiteral(_) | PromExpr::Extension(_) => {}
+    }
+}
+
+fn add_selector_range(
+    ctx: &Query,
+    selector: &VectorSelector,
+    lookback: i64,
+    (start, end): (i64, i64),
+    ranges: &mut HashMap<String, (i64, i64)>,
+) {
+    let selector = normalize_selector(selector);
+    let range_start = modified_time(ctx, start, &selector.at, &selector.offset) - lookback;
+    let range_end = modified_time(ctx, end, &selector.at, &selector.offset);
+    ranges
+        .entry(selector_key(&selector))
+        .and_modify(|(s, e)| {
+            *s = (*s).min(range_start);
+            *e = (*e).max(range_end);
+        })
+        .or_insert((range_start, range_end));
+}
+
 async fn selector_load_data_from_datafusion(
     ctx: SessionContext,
     schema: Arc<Schema>,
@@ -912,7 +1299,124 @@ async fn selector_load_data_from_datafusion(
         }
     };
 
-    let mut df_group = table.clone().filter(
+    // the rows of the exemplars without a sample have no value
+    if schema.field_with_name(VALUE_LABEL).is_err() {
+        return Ok(HashMap::default());
+    }
+    let batches = filter_selector(table, &schema, &selector, start, end)?
+        .filter(col(VALUE_LABEL).is_not_null())?
+        .sort(vec![col(&CONFIG.common.column_timestamp).sort(true, true)])?
+        .collect()
+        .await?;
+
+    let mut metrics: HashMap<String, RangeValue> = HashMap::default();
+    for batch in &batches {
+        let hash_values = batch
+            .column_by_name(HASH_LABEL)
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        let time_values = batch
+            .column_by_name(&CONFIG.common.column_timestamp)
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        let value_values = batch
+            .column_by_name(VALUE_LABEL)
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        for i in 0..batch.num_rows() {
+            let hash = hash_values.value(i).to_string();
+            let entry = metrics
+                .entry(hash)
+                .or_insert_with(|| RangeValue::new(batch_labels(batch, i), Vec::with_capacity(20)));
+            entry
+                .samples
+                .push(Sample::new(time_values.value(i), value_values.value(i)));
+        }
+    }
+    Ok(metrics)
+}
+
+async fn selector_load_histograms_from_datafusion(
+    ctx: SessionContext,
+    schema: Arc<Schema>,
+    selector: VectorSelector,
+    start: i64,
+    end: i64,
+) -> Result<HashMap<String, HistogramRangeValue>> {
+    if schema.field_with_name(HISTOGRAM_LABEL).is_err() {
+        return Ok(HashMap::default());
+    }
+    let table_name = selector.name.as_ref().unwrap();
+    let table = match ctx.table(table_name).await {
+        Ok(v) => v,
+        Err(_) => {
+            return Ok(HashMap::default());
+        }
+    };
+
+    let batches = filter_selector(table, &schema, &selector, start, end)?
+        .filter(col(HISTOGRAM_LABEL).is_not_null())?
+        .collect()
+        .await?;
+
+    let mut series: HashMap<String, HistogramRangeValue> = HashMap::default();
+    for batch in &batches {
+        let hash_values = batch
+            .column_by_name(HASH_LABEL)
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        let time_values = batch
+            .column_by_name(&CONFIG.common.column_timestamp)
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        let histogram_values = batch
+            .column_by_name(HISTOGRAM_LABEL)
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        for i in 0..batch.num_rows() {
+            let hist: NativeHistogram = match json::from_str(histogram_values.value(i)) {
+                Ok(v) => v,
+                Err(e) => {
+                    log::warn!("[PromQL] invalid native histogram: {e}");
+                    continue;
+                }
+            };
+            let hash = hash_values.value(i).to_string();
+            series
+                .entry(hash)
+                .or_insert_with(|| HistogramRangeValue {
+                    labels: batch_labels(batch, i),
+                    histograms: Vec::new(),
+                })
+                .histograms
+                .push((time_values.value(i), hist));
+        }
+    }
+    Ok(series)
+}
+
+/// Filters the table by the time range and the label matchers of the
+/// selector.
+fn filter_selector(
+    table: DataFrame,
+    schema: &Schema,
+    selector: &VectorSelector,
+    start: i64,
+    end: i64,
+) -> Result<DataFrame> {
+    let mut df_group = table.filter(
         col(&CONFIG.common.column_timestamp)
             .gt(lit(start))
             .and(col(&CONFIG.common.column_timestamp).lt_eq(lit(end))),
@@ -947,56 +1451,29 @@ async fn selector_load_data_from_datafusion(
             }
         }
     }
-    let batches = df_group
-        .sort(vec![col(&CONFIG.common.column_timestamp).sort(true, true)])?
-        .collect()
-        .await?;
+    Ok(df_group)
+}
 
-    let mut metrics: HashMap<String, RangeValue> = HashMap::default();
-    for batch in &batches {
-        let hash_values = batch
-            .column_by_name(HASH_LABEL)
-            .unwrap()
-            .as_any()
-            .downcast_ref::<StringArray>()
-            .unwrap();
-        let time_values = batch
-            .column_by_name(&CONFIG.common.column_timestamp)
-            .unwrap()
-            .as_any()
-            .downcast_ref::<Int64Array>()
-            .unwrap();
-        let value_values = batch
-            .column_by_name(VALUE_LABEL)
-            .unwrap()
-            .as_any()
-            .downcast_ref::<Float64Array>()
-            .unwrap();
-        for i in 0..batch.num_rows() {
-            let hash = hash_values.value(i).to_string();
-            let entry = metrics.entry(hash).or_insert_with(|| {
-                let mut labels = Vec::with_capacity(batch.num_columns());
-                for (k, v) in batch.schema().fields().iter().zip(batch.columns()) {
-                    let name = k.name();
-                    if name == &CONFIG.common.column_timestamp
-                        || name == HASH_LABEL
-                        || name == VALUE_LABEL
-                    {
-                        continue;
-                    }
-                    let value = v.as_any().downcast_ref::<StringArray>().unwrap();
-                    labels.push(Arc::new(Label {
-                        name: name.to_string(),
-                        value: value.value(i).to_string(),
-                    }));
-                }
-                labels.sort_by(|a, b| a.name.cmp(&b.name));
-                RangeValue::new(labels, Vec::with_capacity(20))
-            });
-            entry
-                .samples
-                .push(Sample::new(time_values.value(i), value_values.value(i)));
+/// Returns the labels of the row `i`: every column but the timestamp, the
+/// hash, the value, the exemplars and the native histogram.
+fn batch_labels(batch: &RecordBatch, i: usize) -> Labels {
+    let mut labels = Vec::with_capacity(batch.num_columns());
+    for (k, v) in batch.schema().fields().iter().zip(batch.columns()) {
+        let name = k.name();
+        if name == &CONFIG.common.column_timestamp
+            || name == HASH_LABEL
+            || name == VALUE_LABEL
+            || name == EXEMPLARS_LABEL
+            || name == HISTOGRAM_LABEL
+        {
+            continue;
         }
+        let value = v.as_any().downcast_ref::<StringArray>().unwrap();
+        labels.push(Arc::new(Label {
+            name: name.to_string(),
+            value: value.value(i).to_string(),
+        }));
     }
-    Ok(metrics)
+    labels.sort_by(|a, b| a.name.cmp(&b.name));
+    labels
 }
@@ -14,13 +14,14 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use std::{
+    collections::HashSet,
     sync::Arc,
     time::{Duration, SystemTime},
 };
 
 use ahash::AHashMap as HashMap;
 use datafusion::error::Result;
-use promql_parser::parser::EvalStmt;
+use promql_parser::parser::{EvalStmt, Expr};
 use tokio::sync::RwLock;
 
 use crate::{
@@ -42,6 +43,13 @@ pub struct Query {
     pub lookback_delta: i64,
     /// key — metric name; value — time series data
     pub data_cache: Arc<RwLock<HashMap<String, Value>>>,
+    /// key — selector; value — native histogram series
+    pub histogram_cache: Arc<RwLock<HashMap<String, Vec<HistogramRangeValue>>>>,
+    /// The metrics whose streams have the native histogram column, as seen
+    /// when loading their samples.
+    pub histogram_metrics: Arc<RwLock<HashSet<String>>>,
+    /// key — selector; value — time range of the samples to load for it
+    pub selector_ranges: HashMap<String, (i64, i64)>,
     pub scan_stats: Arc<RwLock<ScanStats>>,
     pub timeout: u64, // seconds, query timeout
 }
@@ -61,6 +69,9 @@ impl Query {
             interval: five_min,
             lookback_delta: five_min,
             data_cache: Arc::new(RwLock::new(HashMap::default())),
+            histogram_cache: Arc::new(RwLock::new(HashMap::default())),
+            histogram_metrics: Arc::new(RwLock::new(HashSet::new())),
+            selector_ranges: HashMap::default(),
             scan_stats: Arc::new(RwLock::new(ScanStats::default())),
             timeout,
         }
@@ -77,6 +88,15 @@ impl Query {
             self.lookback_delta = micros(stmt.lookback_delta);
         }
 
+        let mut selector_ranges = HashMap::default();
+        super::engine::collect_selector_ranges(
+            self,
+            &stmt.expr,
+            (self.start, self.end),
+            &mut selector_ranges,
+        );
+        self.selector_ranges = selector_ranges;
+
         let ctx = Arc::new(self.clone());
         let expr = Arc::new(stmt.expr);
         let mut result_type: Option<String> = None;
@@ -92,7 +112,10 @@ impl Query {
             if let Value::Float(val) = value {
                 value = Value::Sample(Sample::new(self.end, val));
             }
-            value.sort();
+            // keep the order of `sort()` and `sort_desc()`
+            if !is_sort_expr(&expr) {
+                value.sort();
+            }
             if result_type_exec.is_some() {
                 result_type = result_type_exec;
             }
@@ -174,3 +197,11 @@ impl Query {
         Ok((value, result_type, *self.scan_stats.read().await))
     }
 }
+
+fn is_sort_expr(expr: &Expr) -> bool {
+    match expr {
+        Expr::Call(call) => matches!(call.func.name, "sort" | "sort_desc"),
+        Expr::Paren(paren) => is_sort_expr(&paren.expr),
+        _ => false,
+    }
+}
@@ -13,11 +13,13 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::BTreeMap;
+
 use ahash::AHashMap as HashMap;
 use datafusion::error::{DataFusionError, Result};
 
 use crate::{
-    common::meta::prom::{HASH_LABEL, LE_LABEL, NAME_LABEL},
+    common::meta::prom::{HistogramBuckets, NativeHistogram, HASH_LABEL, LE_LABEL, NAME_LABEL},
     service::promql::value::{
         signature_without_labels, InstantValue, Labels, LabelsExt, Sample, Signature, Value,
     },
@@ -44,9 +46,8 @@ struct MetricWithBuckets {
     buckets: Vec<Bucket>,
 }
 
-/// TODO: support native histograms; see [`histogramQuantile`]
-///
-/// [`histogramQuantile`]: https://github.com/prometheus/prometheus/blob/f7c6130ff27a2a12412c02cce223f7a8abc59e49/promql/quantile.go#L146
+/// Computes the quantile of conventional histograms, made of `le` bucket
+/// series. Native histograms are handled by [`native_histogram_quantile`].
 pub(crate) fn histogram_quantile(sample_time: i64, phi: f64, data: Value) -> Result<Value> {
     let in_vec = match data {
         Value::Vector(v) => v,
@@ -196,6 +197,196 @@ fn ensure_monotonic(buckets: &mut [Bucket]) {
     }
 }
 
+// cf. https://github.com/prometheus/prometheus/blob/f7c6130ff27a2a12412c02cce223f7a8abc59e49/promql/quantile.go#L146
+/// Computes the `phi` quantile of a native histogram. The quantile is
+/// interpolated exponentially within its bucket, and linearly within the zero
+/// bucket.
+pub(crate) fn native_histogram_quantile(phi: f64, hist: &NativeHistogram) -> f64 {
+    if phi.is_nan() || hist.count <= 0.0 {
+        return f64::NAN;
+    }
+    if phi < 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if phi > 1.0 {
+        return f64::INFINITY;
+    }
+
+    let rank = phi * hist.count;
+    let mut count = 0.0;
+    let mut bucket = None;
+    for b in native_buckets(hist) {
+        count += b.count;
+        bucket = Some(b);
+        if count >= rank {
+            break;
+        }
+    }
+    let NativeBucket {
+        mut lower,
+        mut upper,
+        count: bucket_count,
+    } = match bucket {
+        Some(b) => b,
+        None => return f64::NAN,
+    };
+    if lower < 0.0 && upper > 0.0 {
+        // the zero bucket only spans the side which has observations
+        if hist.negative.counts.is_empty() && !hist.positive.counts.is_empty() {
+            lower = 0.0;
+        } else if hist.positive.counts.is_empty() && !hist.negative.counts.is_empty() {
+            upper = 0.0;
+        }
+    }
+    // Due to numerical inaccuracies the buckets may add up to more than the
+    // count of the histogram.
+    let count = count.min(hist.count);
+    if count < rank {
+        // observations which are not in any bucket, e.g. NaN
+        return upper;
+    }
+
+    let mut fraction = (rank - (count - bucket_count)) / bucket_count;
+    if lower <= 0.0 && upper >= 0.0 {
+        return lower + (upper - lower) * fraction;
+    }
+    if lower < 0.0 {
+        fraction = 1.0 - fraction;
+    }
+    let log_lower = lower.abs().log2();
+    let log_upper = upper.abs().log2();
+    if lower > 0.0 {
+        (log_lower + (log_upper - log_lower) * fraction).exp2()
+    } else {
+        -(log_upper + (log_lower - log_upper) * fraction).exp2()
+    }
+}
+
+/// Returns the increase from `first` to `last` of a native histogram counter.
+/// A counter reset in between is detected by a decreasing count and yields
+/// `last` as it is.
+pub(crate) fn native_histogram_increase(
+    last: &NativeHistogram,
+    first: &NativeHistogram,
+) -> NativeHistogram {
+    if last.count < first.count {
+        return last.clone();
+    }
+    let hist = combine_native_histograms(last, first, -1.0);
+    let is_reset = hist.zero_count < 0.0
+        || hist
+            .positive
+            .counts
+            .iter()
+            .chain(hist.negative.counts.iter())
+            .any(|c| *c < 0.0);
+    if is_reset { last.clone() } else { hist }
+}
+
+/// Adds up two native histograms, used to aggregate them.
+pub(crate) fn add_native_histograms(a: &NativeHistogram, b: &NativeHistogram) -> NativeHistogram {
+    combine_native_histograms(a, b, 1.0)
+}
+
+/// Adds `b` multiplied by `sign` to `a`. The result uses the coarsest schema
+/// and the widest zero bucket of the two histograms.
+fn combine_native_histograms(
+    a: &NativeHistogram,
+    b: &NativeHistogram,
+    sign: f64,
+) -> NativeHistogram {
+    let mut hist = NativeHistogram {
+        schema: a.schema.min(b.schema),
+        count: a.count + sign * b.count,
+        sum: a.sum + sign * b.sum,
+        zero_threshold: a.zero_threshold.max(b.zero_threshold),
+        zero_count: a.zero_count + sign * b.zero_count,
+        ..Default::default()
+    };
+    let mut positive = BTreeMap::new();
+    let mut negative = BTreeMap::new();
+    for (h, factor) in [(a, 1.0), (b, sign)] {
+        let shift = h.schema - hist.schema;
+        add_buckets(&mut positive, &h.positive, shift, factor);
+        add_buckets(&mut negative, &h.negative, shift, factor);
+    }
+    // buckets which now lie within the zero bucket
+    for buckets in [&mut positive, &mut negative] {
+        buckets.retain(|index, count| {
+            if hist.bucket_bound(*index) <= hist.zero_threshold {
+                hist.zero_count += *count;
+                false
+            } else {
+                true
+            }
+        });
+    }
+    hist.positive = to_dense_buckets(positive);
+    hist.negative = to_dense_buckets(negative);
+    hist
+}
+
+/// Adds the buckets, multiplied by `factor`, merging them by `shift` schemas:
+/// each step halves the resolution, so that buckets `2i` and `2i + 1` become
+/// bucket `i`.
+fn add_buckets(to: &mut BTreeMap<i32, f64>, buckets: &HistogramBuckets, shift: i32, factor: f64) {
+    for (i, count) in buckets.counts.iter().enumerate() {
+        let index = (buckets.offset + i as i32) >> shift;
+        *to.entry(index).or_default() += factor * count;
+    }
+}
+
+fn to_dense_buckets(buckets: BTreeMap<i32, f64>) -> HistogramBuckets {
+    let offset = match buckets.keys().next() {
+        Some(offset) => *offset,
+        None => return HistogramBuckets::default(),
+    };
+    let mut counts = Vec::with_capacity(buckets.len());
+    for (index, count) in buckets {
+        counts.resize((index - offset) as usize, 0.0);
+        counts.push(count);
+    }
+    HistogramBuckets { offset, counts }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct NativeBucket {
+    lower: f64,
+    upper: f64,
+    count: f64,
+}
+
+/// Returns the non-empty buckets of a native histogram in ascending order:
+/// the negative buckets, the zero bucket, then the positive buckets.
+fn native_buckets(hist: &NativeHistogram) -> Vec<NativeBucket> {
+    let mut buckets = Vec::new();
+    let negative = &hist.negative;
+    for (i, count) in negative.counts.iter().enumerate().rev() {
+        let index = negative.offset + i as i32;
+        buckets.push(NativeBucket {
+            lower: -hist.bucket_bound(index),
+            upper: -hist.bucket_bound(index - 1),
+            count: *count,
+        });
+    }
+    buckets.push(NativeBucket {
+        lower: -hist.zero_threshold,
+        upper: hist.zero_threshold,
+        count: hist.zero_count,
+    });
+    let positive = &hist.positive;
+    for (i, count) in positive.counts.iter().enumerate() {
+        let index = positive.offset + i as i32;
+        buckets.push(NativeBucket {
+            lower: hist.bucket_bound(index - 1),
+            upper: hist.bucket_bound(index),
+            count: *count,
+        });
+    }
+    buckets.retain(|b| b.count > 0.0);
+    buckets
+}
+
 #[cfg(test)]
 mod tests {
     use expect_test::expect;
@@ -423,4 +614,81 @@ mod tests {
             ]
         );
     }
+
+    fn native_histogram(positive: Vec<f64>) -> NativeHistogram {
+        NativeHistogram {
+            schema: 0,
+            count: positive.iter().sum(),
+            positive: HistogramBuckets {
+                offset: 0,
+                counts: positive,
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_native_histogram_quantile() {
+        // buckets (1, 2], (2, 4], (4, 8]
+        let hist = native_histogram(vec![2.0, 4.0, 2.0]);
+        assert_eq!(native_histogram_quantile(0.0, &hist), 1.0);
+        assert_eq!(native_histogram_quantile(0.25, &hist), 2.0);
+        // halfway through (2, 4], interpolated exponentially
+        assert!((native_histogram_quantile(0.5, &hist) - 8_f64.sqrt()).abs() < 1e-12);
+        assert_eq!(native_histogram_quantile(1.0, &hist), 8.0);
+        assert_eq!(native_histogram_quantile(-1.0, &hist), f64::NEG_INFINITY);
+        assert_eq!(native_histogram_quantile(2.0, &hist), f64::INFINITY);
+        assert!(native_histogram_quantile(0.5, &NativeHistogram::default()).is_nan());
+    }
+
+    #[test]
+    fn test_native_histogram_quantile_negative_and_zero() {
+        let hist = NativeHistogram {
+            schema: 0,
+            count: 4.0,
+            zero_threshold: 0.5,
+            zero_count: 2.0,
+            // bucket [-2, -1)
+            negative: HistogramBuckets {
+                offset: 0,
+                counts: vec![2.0],
+            },
+            ..Default::default()
+        };
+        assert_eq!(native_histogram_quantile(0.0, &hist), -2.0);
+        // the zero bucket is cut at zero, as there are no positive buckets
+        assert_eq!(native_histogram_quantile(0.75, &hist), -0.25);
+        assert_eq!(native_histogram_quantile(1.0, &hist), 0.0);
+    }
+
+    #[test]
+    fn test_add_native_histograms() {
+        let a = native_histogram(vec![1.0, 2.0, 3.0, 4.0]);
+        let mut b = native_histogram(vec![1.0, 1.0]);
+        b.schema = -1;
+        b.positive.offset = 1;
+        let hist = add_native_histograms(&a, &b);
+        assert_eq!(hist.schema, -1);
+        assert_eq!(hist.count, 12.0);
+        assert_eq!(
+            hist.positive,
+            HistogramBuckets {
+                offset: 0,
+                counts: vec![3.0, 8.0, 1.0],
+            }
+        );
+    }
+
+    #[test]
+    fn test_native_histogram_increase() {
+        let first = native_histogram(vec![1.0, 2.0]);
+        let last = native_histogram(vec![3.0, 2.0, 1.0]);
+        let hist = native_histogram_increase(&last, &first);
+        assert_eq!(hist.count, 3.0);
+        assert_eq!(hist.positive.counts, vec![2.0, 0.0, 1.0]);
+
+        // counter reset
+        let last = native_histogram(vec![0.0, 5.0]);
+        assert_eq!(native_histogram_increase(&last, &first), last);
+    }
 }
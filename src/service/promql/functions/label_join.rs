@@ -13,15 +13,10 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use std::{collections::HashSet, sync::Arc};
-
 use datafusion::error::{DataFusionError, Result};
 use itertools::Itertools;
 
-use crate::{
-    common::meta::prom::NAME_LABEL,
-    service::promql::value::{InstantValue, Label, Value},
-};
+use crate::service::promql::value::{InstantValue, LabelsExt, Value};
 
 /// https://prometheus.io/docs/prometheus/latest/querying/functions/#label_join
 pub(crate) fn label_join(
@@ -40,22 +35,19 @@ pub(crate) fn label_join(
         }
     };
 
-    let keep_source_labels: HashSet<String> = HashSet::from_iter(source_labels);
     let rate_values: Vec<InstantValue> = data
         .iter()
         .map(|instant| {
-            let new_label = instant
-                .labels
+            // the source labels are joined in the given order
+            let new_label = source_labels
                 .iter()
-                .filter(|l| l.name != NAME_LABEL && keep_source_labels.contains(&l.name))
-                .map(|label| label.value.clone())
+                .map(|name| instant.labels.get_value(name))
                 .join(separator);
 
-            let mut new_labels = instant.labels.clone();
-            new_labels.push(Arc::new(Label {
-                name: dest_label.to_string(),
-                value: new_label,
-            }));
+            let mut new_labels = instant.labels.without_label(dest_label);
+            if !new_label.is_empty() {
+                new_labels.set(dest_label, &new_label);
+            }
             InstantValue {
                 labels: new_labels,
                 sample: instant.sample,
@@ -64,3 +56,38 @@ pub(crate) fn label_join(
         .collect();
     Ok(Value::Vector(rate_values))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::service::promql::value::{Label, Sample};
+
+    #[test]
+    fn test_label_join() {
+        let data = Value::Vector(vec![InstantValue {
+            labels: vec![
+                Arc::new(Label::new("__name__", "up")),
+                Arc::new(Label::new("dst", "old")),
+                Arc::new(Label::new("instance", "host:9100")),
+                Arc::new(Label::new("job", "node")),
+            ],
+            sample: Sample::new(1, 1.0),
+        }]);
+        let out = label_join(&data, "dst", "-", vec!["job".into(), "instance".into()]).unwrap();
+        let labels = match out {
+            Value::Vector(v) => v[0].labels.clone(),
+            _ => panic!("vector expected"),
+        };
+        assert_eq!(labels.get_value("dst"), "node-host:9100");
+        assert_eq!(labels.len(), 4);
+
+        let out = label_join(&data, "dst", "-", vec!["missing".into()]).unwrap();
+        let labels = match out {
+            Value::Vector(v) => v[0].labels.clone(),
+            _ => panic!("vector expected"),
+        };
+        assert_eq!(labels.keys(), vec!["__name__", "instance", "job"]);
+    }
+}
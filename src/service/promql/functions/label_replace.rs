@@ -13,8 +13,6 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use std::sync::Arc;
-
 use datafusion::error::{DataFusionError, Result};
 use rayon::prelude::*;
 use regex::{self, Regex};
@@ -46,25 +44,25 @@ pub(crate) fn label_replace(
         )));
     }
 
-    let re = Regex::new(regex)
+    // The regex is anchored, like in Prometheus.
+    let re = Regex::new(&format!("^(?:{regex})$"))
         .map_err(|_e| DataFusionError::NotImplemented("Invalid regex found".into()))?;
 
     let rate_values: Vec<InstantValue> = data
         .par_iter()
         .map(|instant| {
-            let labels = if replacement.is_empty() {
-                instant.labels.without_label(dest_label)
-            } else {
-                let label_value = instant.labels.get_value(source_label);
-                let output_value = re.replace_all(&label_value, replacement);
-                let mut new_labels = instant.labels.clone();
-                if output_value != label_value {
-                    new_labels.push(Arc::new(Label {
-                        name: dest_label.to_string(),
-                        value: output_value.to_string(),
-                    }));
+            let label_value = instant.labels.get_value(source_label);
+            let labels = match re.captures(&label_value) {
+                Some(captures) => {
+                    let mut output_value = String::new();
+                    captures.expand(replacement, &mut output_value);
+                    let mut new_labels = instant.labels.without_label(dest_label);
+                    if !output_value.is_empty() {
+                        new_labels.set(dest_label, &output_value);
+                    }
+                    new_labels
                 }
-                new_labels
+                None => instant.labels.clone(),
             };
             InstantValue {
                 labels,
@@ -74,3 +72,35 @@ pub(crate) fn label_replace(
         .collect();
     Ok(Value::Vector(rate_values))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::service::promql::value::Sample;
+
+    fn replace(regex: &str, replacement: &str) -> Vec<String> {
+        let data = Value::Vector(vec![InstantValue {
+            labels: vec![
+                Arc::new(Label::new("__name__", "up")),
+                Arc::new(Label::new("instance", "host:9100")),
+                Arc::new(Label::new("port", "80")),
+            ],
+            sample: Sample::new(1, 1.0),
+        }]);
+        match label_replace(&data, "port", replacement, "instance", regex).unwrap() {
+            Value::Vector(v) => v[0].labels.values(),
+            _ => panic!("vector expected"),
+        }
+    }
+
+    #[test]
+    fn test_label_replace() {
+        assert_eq!(replace(".*:(.*)", "$1"), vec!["up", "host:9100", "9100"]);
+        // the regex must match the whole value
+        assert_eq!(replace(":(.*)", "$1"), vec!["up", "host:9100", "80"]);
+        // an empty result removes the label
+        assert_eq!(replace(".*", ""), vec!["up", "host:9100"]);
+    }
+}
@@ -0,0 +1,65 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use datafusion::error::Result;
+
+use crate::service::promql::value::{RangeValue, Value};
+
+/// https://prometheus.io/docs/prometheus/latest/querying/functions/#aggregation_over_time
+///
+/// The median absolute deviation of all points in the specified interval.
+pub(crate) fn mad_over_time(data: &Value) -> Result<Value> {
+    super::eval_idelta(data, "mad_over_time", exec, false)
+}
+
+fn exec(data: &RangeValue) -> Option<f64> {
+    if data.samples.is_empty() {
+        return None;
+    }
+    let values = data.samples.iter().map(|s| s.value).collect::<Vec<_>>();
+    let center = median(values.clone());
+    Some(median(
+        values.into_iter().map(|v| (v - center).abs()).collect(),
+    ))
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    if values.len() % 2 == 0 {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::promql::value::{Labels, Sample};
+
+    #[test]
+    fn test_mad_over_time() {
+        let data = RangeValue::new(
+            Labels::default(),
+            [1.0, 1.0, 2.0, 2.0, 4.0, 6.0, 9.0]
+                .iter()
+                .enumerate()
+                .map(|(i, v)| Sample::new(i as i64, *v)),
+        );
+        assert_eq!(exec(&data), Some(1.0));
+        assert_eq!(exec(&RangeValue::new(Labels::default(), [])), None);
+    }
+}
@@ -21,15 +21,29 @@ use crate::service::promql::value::{InstantValue, LabelsExt, Sample, Value};
 #[derive(Debug, EnumIter)]
 pub enum MathOperationsType {
     Abs,
+    Acos,
+    Acosh,
+    Asin,
+    Asinh,
+    Atan,
+    Atanh,
     Ceil,
+    Cos,
+    Cosh,
+    Deg,
     Exp,
     Floor,
     Ln,
     Log10,
     Log2,
+    Rad,
     Round,
     Sgn,
+    Sin,
+    Sinh,
     Sqrt,
+    Tan,
+    Tanh,
 }
 
 impl MathOperationsType {
@@ -37,15 +51,36 @@ impl MathOperationsType {
     pub fn apply(&self, input: f64) -> f64 {
         match self {
             Self::Abs => input.abs(),
+            Self::Acos => input.acos(),
+            Self::Acosh => input.acosh(),
+            Self::Asin => input.asin(),
+            Self::Asinh => input.asinh(),
+            Self::Atan => input.atan(),
+            Self::Atanh => input.atanh(),
             Self::Ceil => input.ceil(),
+            Self::Cos => input.cos(),
+            Self::Cosh => input.cosh(),
+            Self::Deg => input.to_degrees(),
             Self::Exp => input.exp(),
             Self::Floor => input.floor(),
             Self::Ln => input.ln(),
             Self::Log2 => input.log2(),
             Self::Log10 => input.log10(),
-            Self::Sgn => input.signum(),
+            Self::Rad => input.to_radians(),
+            // Prometheus rounds halves up, i.e. round(-2.5) is -2
+            Self::Round => (input + 0.5).floor(),
+            Self::Sgn => {
+                if input == 0.0 {
+                    0.0
+                } else {
+                    input.signum()
+                }
+            }
+            Self::Sin => input.sin(),
+            Self::Sinh => input.sinh(),
             Self::Sqrt => input.sqrt(),
-            Self::Round => input.round(),
+            Self::Tan => input.tan(),
+            Self::Tanh => input.tanh(),
         }
     }
 }
@@ -90,6 +125,90 @@ pub(crate) fn sgn(data: &Value) -> Result<Value> {
     exec(data, &MathOperationsType::Sgn)
 }
 
+pub(crate) fn acos(data: &Value) -> Result<Value> {
+    exec(data, &MathOperationsType::Acos)
+}
+
+pub(crate) fn acosh(data: &Value) -> Result<Value> {
+    exec(data, &MathOperationsType::Acosh)
+}
+
+pub(crate) fn asin(data: &Value) -> Result<Value> {
+    exec(data, &MathOperationsType::Asin)
+}
+
+pub(crate) fn asinh(data: &Value) -> Result<Value> {
+    exec(data, &MathOperationsType::Asinh)
+}
+
+pub(crate) fn atan(data: &Value) -> Result<Value> {
+    exec(data, &MathOperationsType::Atan)
+}
+
+pub(crate) fn atanh(data: &Value) -> Result<Value> {
+    exec(data, &MathOperationsType::Atanh)
+}
+
+pub(crate) fn cos(data: &Value) -> Result<Value> {
+    exec(data, &MathOperationsType::Cos)
+}
+
+pub(crate) fn cosh(data: &Value) -> Result<Value> {
+    exec(data, &MathOperationsType::Cosh)
+}
+
+pub(crate) fn deg(data: &Value) -> Result<Value> {
+    exec(data, &MathOperationsType::Deg)
+}
+
+pub(crate) fn rad(data: &Value) -> Result<Value> {
+    exec(data, &MathOperationsType::Rad)
+}
+
+pub(crate) fn sin(data: &Value) -> Result<Value> {
+    exec(data, &MathOperationsType::Sin)
+}
+
+pub(crate) fn sinh(data: &Value) -> Result<Value> {
+    exec(data, &MathOperationsType::Sinh)
+}
+
+pub(crate) fn tan(data: &Value) -> Result<Value> {
+    exec(data, &MathOperationsType::Tan)
+}
+
+pub(crate) fn tanh(data: &Value) -> Result<Value> {
+    exec(data, &MathOperationsType::Tanh)
+}
+
+/// round(v instant-vector, to_nearest scalar) rounds the sample values to the
+/// nearest multiple of `to_nearest`.
+pub(crate) fn round_to_nearest(data: &Value, to_nearest: f64) -> Result<Value> {
+    // Invert as it seems to cause fewer floating point accuracy issues.
+    let to_nearest_inverse = 1.0 / to_nearest;
+    let data = match data {
+        Value::Vector(v) => v,
+        Value::None => return Ok(Value::None),
+        _ => {
+            return Err(DataFusionError::NotImplemented(format!(
+                "Invalid input for round value: {:?}",
+                data
+            )));
+        }
+    };
+    let out = data
+        .iter()
+        .map(|instant| InstantValue {
+            labels: instant.labels.without_metric_name(),
+            sample: Sample::new(
+                instant.sample.timestamp,
+                (instant.sample.value * to_nearest_inverse + 0.5).floor() / to_nearest_inverse,
+            ),
+        })
+        .collect();
+    Ok(Value::Vector(out))
+}
+
 fn exec(data: &Value, op: &MathOperationsType) -> Result<Value> {
     match &data {
         Value::Vector(v) => {
@@ -112,3 +231,89 @@ fn exec(data: &Value, op: &MathOperationsType) -> Result<Value> {
         ))),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+
+    use super::*;
+    use crate::service::promql::value::Labels;
+
+    fn values(data: Value) -> Vec<f64> {
+        match data {
+            Value::Vector(v) => v.iter().map(|i| i.sample.value).collect(),
+            _ => panic!("vector expected"),
+        }
+    }
+
+    fn vector(values: &[f64]) -> Value {
+        Value::Vector(
+            values
+                .iter()
+                .map(|v| InstantValue {
+                    labels: Labels::default(),
+                    sample: Sample::new(1, *v),
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_apply() {
+        assert_eq!(MathOperationsType::Round.apply(-2.5), -2.0);
+        assert_eq!(MathOperationsType::Round.apply(2.5), 3.0);
+        assert_eq!(MathOperationsType::Sgn.apply(0.0), 0.0);
+        assert_eq!(MathOperationsType::Sgn.apply(-3.0), -1.0);
+        assert_eq!(MathOperationsType::Deg.apply(std::f64::consts::PI), 180.0);
+        assert_eq!(MathOperationsType::Rad.apply(180.0), std::f64::consts::PI);
+        assert_eq!(MathOperationsType::Cos.apply(0.0), 1.0);
+        assert_eq!(MathOperationsType::Atanh.apply(0.0), 0.0);
+        assert!(MathOperationsType::Acos.apply(2.0).is_nan());
+    }
+
+    #[test]
+    fn test_trigonometric() {
+        use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+        let approx = |op: MathOperationsType, input: f64, expected: f64| {
+            let got = op.apply(input);
+            assert!(
+                approx_eq!(f64, got, expected, epsilon = 1e-12),
+                "{op:?}({input}) = {got}, expected {expected}"
+            );
+        };
+        approx(MathOperationsType::Sin, FRAC_PI_2, 1.0);
+        approx(MathOperationsType::Sin, 0.0, 0.0);
+        approx(MathOperationsType::Cos, PI, -1.0);
+        approx(MathOperationsType::Tan, FRAC_PI_4, 1.0);
+        approx(MathOperationsType::Asin, 1.0, FRAC_PI_2);
+        approx(MathOperationsType::Acos, 1.0, 0.0);
+        approx(MathOperationsType::Atan, 1.0, FRAC_PI_4);
+        approx(MathOperationsType::Sinh, 0.0, 0.0);
+        approx(MathOperationsType::Cosh, 0.0, 1.0);
+        approx(MathOperationsType::Tanh, 0.0, 0.0);
+        approx(MathOperationsType::Asinh, 1.0_f64.sinh(), 1.0);
+        approx(MathOperationsType::Acosh, 1.0, 0.0);
+        approx(MathOperationsType::Atanh, 0.5_f64.tanh(), 0.5);
+
+        // out of the domains
+        assert!(MathOperationsType::Asin.apply(1.5).is_nan());
+        assert!(MathOperationsType::Acosh.apply(0.5).is_nan());
+        assert!(MathOperationsType::Atanh.apply(2.0).is_nan());
+        assert_eq!(MathOperationsType::Atanh.apply(1.0), f64::INFINITY);
+        assert!(MathOperationsType::Sin.apply(f64::INFINITY).is_nan());
+    }
+
+    #[test]
+    fn test_round_to_nearest() {
+        let data = vector(&[1.234, 5.0, -1.26]);
+        assert_eq!(
+            values(round_to_nearest(&data, 0.1).unwrap()),
+            vec![1.2, 5.0, -1.3]
+        );
+        assert_eq!(
+            values(round_to_nearest(&data, 5.0).unwrap()),
+            vec![0.0, 5.0, 0.0]
+        );
+    }
+}
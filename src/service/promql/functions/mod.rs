@@ -33,17 +33,22 @@ mod irate;
 mod label_join;
 mod label_replace;
 mod last_over_time;
+mod mad_over_time;
 mod math_operations;
 mod max_over_time;
 mod min_over_time;
 mod predict_linear;
+mod present_over_time;
 mod quantile_over_time;
 mod rate;
 mod resets;
+mod scalar;
+mod sort;
 mod stddev_over_time;
 mod stdvar_over_time;
 mod sum_over_time;
 mod time_operations;
+mod timestamp;
 mod vector;
 
 pub(crate) use absent::absent;
@@ -54,7 +59,9 @@ pub(crate) use clamp::clamp;
 pub(crate) use count_over_time::count_over_time;
 pub(crate) use delta::delta;
 pub(crate) use deriv::deriv;
-pub(crate) use histogram::histogram_quantile;
+pub(crate) use histogram::{
+    add_native_histograms, histogram_quantile, native_histogram_increase, native_histogram_quantile,
+};
 pub(crate) use holt_winters::holt_winters;
 pub(crate) use idelta::idelta;
 pub(crate) use increase::increase;
@@ -62,18 +69,23 @@ pub(crate) use irate::irate;
 pub(crate) use label_join::label_join;
 pub(crate) use label_replace::label_replace;
 pub(crate) use last_over_time::last_over_time;
+pub(crate) use mad_over_time::mad_over_time;
 pub(crate) use math_operations::*;
 pub(crate) use max_over_time::max_over_time;
 pub(crate) use min_over_time::min_over_time;
 pub(crate) use predict_linear::predict_linear;
+pub(crate) use present_over_time::present_over_time;
 pub(crate) use quantile_over_time::quantile_over_time;
 pub(crate) use rate::rate;
 pub(crate) use resets::resets;
+pub(crate) use scalar::scalar;
+pub(crate) use sort::{sort, sort_desc};
 pub(crate) use stddev_over_time::stddev_over_time;
 pub(crate) use stdvar_over_time::stdvar_over_time;
 use strum::EnumString;
 pub(crate) use sum_over_time::sum_over_time;
 pub(crate) use time_operations::*;
+pub(crate) use timestamp::timestamp;
 pub(crate) use vector::vector;
 
 use super::value::LabelsExt;
@@ -85,17 +97,26 @@ pub(crate) enum Func {
     Abs,
     Absent,
     AbsentOverTime,
+    Acos,
+    Acosh,
+    Asin,
+    Asinh,
+    Atan,
+    Atanh,
     AvgOverTime,
     Ceil,
     Changes,
     Clamp,
     ClampMax,
     ClampMin,
+    Cos,
+    Cosh,
     CountOverTime,
     DayOfMonth,
     DayOfWeek,
     DayOfYear,
     DaysInMonth,
+    Deg,
     Delta,
     Deriv,
     Exp,
@@ -115,23 +136,31 @@ pub(crate) enum Func {
     Ln,
     Log10,
     Log2,
+    MadOverTime,
     MaxOverTime,
     MinOverTime,
     Minute,
     Month,
+    Pi,
     PredictLinear,
+    PresentOverTime,
     QuantileOverTime,
+    Rad,
     Rate,
     Resets,
     Round,
     Scalar,
     Sgn,
+    Sin,
+    Sinh,
     Sort,
     SortDesc,
     Sqrt,
     StddevOverTime,
     StdvarOverTime,
     SumOverTime,
+    Tan,
+    Tanh,
     Time,
     Timestamp,
     Vector,
@@ -0,0 +1,46 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use datafusion::error::Result;
+
+use crate::service::promql::value::{RangeValue, Value};
+
+/// https://prometheus.io/docs/prometheus/latest/querying/functions/#aggregation_over_time
+pub(crate) fn present_over_time(data: &Value) -> Result<Value> {
+    super::eval_idelta(data, "present_over_time", exec, false)
+}
+
+fn exec(data: &RangeValue) -> Option<f64> {
+    if data.samples.is_empty() {
+        return None;
+    }
+    Some(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::promql::value::{Labels, Sample};
+
+    #[test]
+    fn test_present_over_time() {
+        let data = RangeValue::new(
+            Labels::default(),
+            [Sample::new(1, 5.0), Sample::new(2, f64::NAN)],
+        );
+        assert_eq!(exec(&data), Some(1.0));
+        assert_eq!(exec(&RangeValue::new(Labels::default(), [])), None);
+    }
+}
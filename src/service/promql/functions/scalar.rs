@@ -0,0 +1,62 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use datafusion::error::{DataFusionError, Result};
+
+use crate::service::promql::value::Value;
+
+/// https://prometheus.io/docs/prometheus/latest/querying/functions/#scalar
+///
+/// Returns the sample value of a single-element vector as a scalar, or `NaN`
+/// if the vector does not have exactly one element.
+pub(crate) fn scalar(data: &Value) -> Result<Value> {
+    Ok(match data {
+        Value::Float(v) => Value::Float(*v),
+        Value::Vector(v) if v.len() == 1 => Value::Float(v[0].sample.value),
+        Value::Vector(_) | Value::None => Value::Float(f64::NAN),
+        _ => {
+            return Err(DataFusionError::Plan(format!(
+                "scalar: vector argument expected but got {}",
+                data.get_type()
+            )));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::promql::value::{InstantValue, Labels, Sample};
+
+    #[test]
+    fn test_scalar() {
+        let instant = InstantValue {
+            labels: Labels::default(),
+            sample: Sample::new(1, 2.0),
+        };
+        assert!(matches!(
+            scalar(&Value::Vector(vec![instant.clone()])).unwrap(),
+            Value::Float(v) if v == 2.0
+        ));
+        assert!(matches!(
+            scalar(&Value::Vector(vec![instant.clone(), instant])).unwrap(),
+            Value::Float(v) if v.is_nan()
+        ));
+        assert!(matches!(
+            scalar(&Value::None).unwrap(),
+            Value::Float(v) if v.is_nan()
+        ));
+    }
+}
@@ -0,0 +1,88 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::cmp::Ordering;
+
+use datafusion::error::{DataFusionError, Result};
+
+use crate::service::promql::value::{InstantValue, Value};
+
+/// https://prometheus.io/docs/prometheus/latest/querying/functions/#sort
+pub(crate) fn sort(data: &Value) -> Result<Value> {
+    exec(data, false)
+}
+
+/// https://prometheus.io/docs/prometheus/latest/querying/functions/#sort_desc
+pub(crate) fn sort_desc(data: &Value) -> Result<Value> {
+    exec(data, true)
+}
+
+fn exec(data: &Value, desc: bool) -> Result<Value> {
+    let data = match data {
+        Value::Vector(v) => v,
+        Value::None => return Ok(Value::None),
+        _ => {
+            return Err(DataFusionError::Plan(
+                "sort: vector argument expected".into(),
+            ));
+        }
+    };
+
+    let mut values: Vec<InstantValue> = data.clone();
+    values.sort_by(|a, b| {
+        let (a, b) = (a.sample.value, b.sample.value);
+        // NaN values always go to the end
+        match (a.is_nan(), b.is_nan()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            _ if desc => b.partial_cmp(&a).unwrap(),
+            _ => a.partial_cmp(&b).unwrap(),
+        }
+    });
+    Ok(Value::Vector(values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::promql::value::{Labels, Sample};
+
+    fn values(data: Value) -> Vec<f64> {
+        match data {
+            Value::Vector(v) => v.iter().map(|i| i.sample.value).collect(),
+            _ => panic!("vector expected"),
+        }
+    }
+
+    #[test]
+    fn test_sort() {
+        let data = Value::Vector(
+            [3.0, f64::NAN, 1.0, 2.0]
+                .iter()
+                .map(|v| InstantValue {
+                    labels: Labels::default(),
+                    sample: Sample::new(1, *v),
+                })
+                .collect(),
+        );
+        let asc = values(sort(&data).unwrap());
+        assert_eq!(asc[..3], [1.0, 2.0, 3.0]);
+        assert!(asc[3].is_nan());
+        let desc = values(sort_desc(&data).unwrap());
+        assert_eq!(desc[..3], [3.0, 2.0, 1.0]);
+        assert!(desc[3].is_nan());
+    }
+}
@@ -0,0 +1,87 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use datafusion::error::{DataFusionError, Result};
+
+use crate::service::promql::value::{InstantValue, LabelsExt, Sample, Value};
+
+/// https://prometheus.io/docs/prometheus/latest/querying/functions/#timestamp
+///
+/// `data` holds the samples at the evaluation timestamp, `timestamps` the
+/// timestamps of the selected samples, in microseconds. The latter differs
+/// from the evaluation timestamp when the argument is a vector selector.
+pub(crate) fn timestamp(data: &Value, timestamps: Option<&[i64]>) -> Result<Value> {
+    let data = match data {
+        Value::Vector(v) => v,
+        Value::None => return Ok(Value::None),
+        _ => {
+            return Err(DataFusionError::NotImplemented(format!(
+                "Unexpected input to timestamp function: {:?}",
+                data
+            )));
+        }
+    };
+
+    let out = data
+        .iter()
+        .enumerate()
+        .map(|(i, instant)| {
+            let ts = timestamps.map_or(instant.sample.timestamp, |ts| ts[i]);
+            InstantValue {
+                labels: instant.labels.without_metric_name(),
+                sample: Sample::new(instant.sample.timestamp, ts as f64 / 1_000_000.0),
+            }
+        })
+        .collect();
+    Ok(Value::Vector(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{common::meta::prom::NAME_LABEL, service::promql::value::Label};
+
+    fn vector() -> Value {
+        Value::Vector(vec![InstantValue {
+            labels: vec![
+                Arc::new(Label::new(NAME_LABEL, "up")),
+                Arc::new(Label::new("job", "api")),
+            ],
+            sample: Sample::new(3_000_000, 1.0),
+        }])
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let Value::Vector(out) = timestamp(&vector(), None).unwrap() else {
+            panic!("vector expected");
+        };
+        assert_eq!(out[0].sample.timestamp, 3_000_000);
+        assert_eq!(out[0].sample.value, 3.0);
+        assert_eq!(out[0].labels.get_value(NAME_LABEL), "");
+        assert_eq!(out[0].labels.get_value("job"), "api");
+
+        // the timestamps of the samples selected by the lookback
+        let Value::Vector(out) = timestamp(&vector(), Some(&[1_500_000])).unwrap() else {
+            panic!("vector expected");
+        };
+        assert_eq!(out[0].sample.value, 1.5);
+
+        assert!(matches!(timestamp(&Value::None, None), Ok(Value::None)));
+        assert!(timestamp(&Value::Float(1.0), None).is_err());
+    }
+}
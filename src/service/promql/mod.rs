@@ -31,6 +31,8 @@ mod exec;
 mod functions;
 
 pub mod search;
+#[cfg(test)]
+mod test_scripts;
 pub mod value;
 
 pub use engine::Engine;
@@ -39,6 +41,7 @@ pub use exec::Query;
 use crate::common::meta::stream::ScanStats;
 
 pub(crate) const DEFAULT_LOOKBACK: Duration = Duration::from_secs(300); // 5m
+pub(crate) const DEFAULT_EVAL_INTERVAL: Duration = Duration::from_secs(60); // 1m, subquery step
 pub(crate) const MINIMAL_INTERVAL: Duration = Duration::from_secs(10); // 10s
 pub(crate) const MAX_DATA_POINTS: i64 = 256; // Width of panel
 
@@ -0,0 +1,215 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::{cmp::min, collections::HashMap};
+
+use chrono::Utc;
+use once_cell::sync::Lazy;
+use promql_parser::parser;
+
+use crate::{
+    common::infra::config::{RwHashMap, CONFIG},
+    service::promql::{value::*, MetricsQueryRequest},
+};
+
+/// The result of a range query, with the samples evaluated from `start` to
+/// `end`, both included, in microseconds.
+struct CachedResult {
+    start: i64,
+    end: i64,
+    data: Vec<RangeValue>,
+    last_access: i64,
+}
+
+static RESULTS: Lazy<RwHashMap<String, CachedResult>> = Lazy::new(Default::default);
+
+/// Returns the cache key of the range query, `None` when the query should not
+/// be cached. The queries with the same step and the same start offset into the
+/// step are evaluated at the same timestamps, they share the cached samples.
+pub(crate) fn cache_key(org_id: &str, req: &MetricsQueryRequest) -> Option<String> {
+    if req.step <= 0 || req.start >= req.end {
+        return None;
+    }
+    let query = parser::parse(&req.query).ok()?.prettify();
+    // the `@ start()` and `@ end()` modifiers depend on the range of the query
+    if query.contains("start()") || query.contains("end()") {
+        return None;
+    }
+    Some(format!(
+        "{org_id}/{}/{}/{query}",
+        req.step,
+        req.start.rem_euclid(req.step)
+    ))
+}
+
+/// Returns the cached series from `start` up to the end of the cached samples
+/// or `end`, along with the last cached evaluation timestamp.
+pub(crate) fn get(key: &str, start: i64, end: i64) -> Option<(Vec<RangeValue>, i64)> {
+    let mut entry = RESULTS.get_mut(key)?;
+    if entry.start > start || entry.end < start {
+        return None;
+    }
+    entry.last_access = Utc::now().timestamp_micros();
+    let cached_end = min(entry.end, end);
+    Some((slice(&entry.data, start, cached_end), cached_end))
+}
+
+/// Caches the samples of the series from `start` to `end`, both included, the
+/// least recently used result is evicted when the cache is full.
+pub(crate) fn set(key: String, start: i64, end: i64, data: &[RangeValue]) {
+    if end < start {
+        return;
+    }
+    if !RESULTS.contains_key(&key) && RESULTS.len() >= CONFIG.limit.metrics_result_cache_max_entries
+    {
+        let lru = RESULTS
+            .iter()
+            .min_by_key(|entry| entry.last_access)
+            .map(|entry| entry.key().clone());
+        if let Some(lru) = lru {
+            RESULTS.remove(&lru);
+        }
+    }
+    RESULTS.insert(
+        key,
+        CachedResult {
+            start,
+            end,
+            data: slice(data, start, end),
+            last_access: Utc::now().timestamp_micros(),
+        },
+    );
+}
+
+/// Returns the last evaluation timestamp of the query which is not after
+/// `limit`, the samples evaluated later may still change with late ingestion.
+pub(crate) fn cacheable_end(req: &MetricsQueryRequest, limit: i64) -> i64 {
+    let end = min(req.end, limit);
+    end - (end - req.start).rem_euclid(req.step)
+}
+
+/// Appends the samples of the tail to the series of the cached samples, the
+/// tail starts after the last cached evaluation.
+pub(crate) fn merge(cached: Vec<RangeValue>, tail: Vec<RangeValue>) -> Vec<RangeValue> {
+    let mut merged: HashMap<Signature, RangeValue> = cached
+        .into_iter()
+        .map(|series| (signature(&series.labels), series))
+        .collect();
+    for series in tail {
+        match merged.get_mut(&signature(&series.labels)) {
+            Some(v) => v.samples.extend(series.samples),
+            None => {
+                merged.insert(signature(&series.labels), series);
+            }
+        }
+    }
+    merged.into_values().collect()
+}
+
+/// Keeps the samples from `start` to `end`, both included.
+fn slice(data: &[RangeValue], start: i64, end: i64) -> Vec<RangeValue> {
+    data.iter()
+        .filter_map(|series| {
+            let samples = series
+                .samples
+                .iter()
+                .filter(|s| s.timestamp >= start && s.timestamp <= end)
+                .copied()
+                .collect::<Vec<_>>();
+            (!samples.is_empty()).then(|| RangeValue::new(series.labels.clone(), samples))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn series(name: &str, timestamps: &[i64]) -> RangeValue {
+        let labels = vec![Arc::new(Label {
+            name: "__name__".to_string(),
+            value: name.to_string(),
+        })];
+        RangeValue::new(
+            labels,
+            timestamps.iter().map(|&timestamp| Sample {
+                timestamp,
+                value: timestamp as f64,
+            }),
+        )
+    }
+
+    fn request(query: &str, start: i64, end: i64, step: i64) -> MetricsQueryRequest {
+        MetricsQueryRequest {
+            query: query.to_string(),
+            start,
+            end,
+            step,
+        }
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let a = cache_key("default", &request("sum(rate(up[5m]))", 100, 1000, 10));
+        let b = cache_key("default", &request("sum( rate(up[5m]) )", 200, 1200, 10));
+        assert!(a.is_some());
+        assert_eq!(a, b);
+        // a different offset into the step evaluates other timestamps
+        let c = cache_key("default", &request("sum(rate(up[5m]))", 105, 1000, 10));
+        assert_ne!(a, c);
+        assert!(cache_key("default", &request("up @ end()", 100, 1000, 10)).is_none());
+        assert!(cache_key("default", &request("up", 100, 100, 10)).is_none());
+        assert!(cache_key("default", &request("up{", 100, 1000, 10)).is_none());
+    }
+
+    #[test]
+    fn test_cacheable_end() {
+        let req = request("up", 100, 1000, 30);
+        assert_eq!(cacheable_end(&req, 2000), 1000);
+        assert_eq!(cacheable_end(&req, 500), 490);
+        assert!(cacheable_end(&req, 50) < req.start);
+    }
+
+    #[test]
+    fn test_merge_and_slice() {
+        let cached = vec![series("a", &[10, 20]), series("b", &[20])];
+        let tail = vec![series("a", &[30]), series("c", &[30])];
+        let mut merged = merge(cached, tail);
+        merged.sort_by_key(|v| v.labels[0].value.clone());
+        let timestamps = merged
+            .iter()
+            .map(|v| v.samples.iter().map(|s| s.timestamp).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(timestamps, vec![vec![10, 20, 30], vec![20], vec![30]]);
+
+        let sliced = slice(&merged, 15, 25);
+        assert_eq!(sliced.len(), 2);
+        assert!(sliced.iter().all(|v| v.samples.len() == 1));
+    }
+
+    #[test]
+    fn test_get_and_set() {
+        let key = "test_get_and_set/10/0/up".to_string();
+        assert!(get(&key, 10, 50).is_none());
+        set(key.clone(), 10, 30, &[series("a", &[10, 20, 30, 40])]);
+        let (data, cached_end) = get(&key, 20, 50).unwrap();
+        assert_eq!(cached_end, 30);
+        assert_eq!(data[0].samples.len(), 2);
+        // the cached samples start later than the query
+        assert!(get(&key, 0, 50).is_none());
+    }
+}
@@ -19,6 +19,7 @@ use std::{
 };
 
 use ahash::AHashMap as HashMap;
+use chrono::{Duration, Utc};
 use futures::future::try_join_all;
 use tonic::{codec::CompressionEncoding, metadata::MetadataValue, transport::Channel, Request};
 use tracing::{info_span, Instrument};
@@ -45,15 +46,72 @@ use crate::{
     },
 };
 
+mod cache;
 pub mod grpc;
 
 #[tracing::instrument(skip_all, fields(org_id = org_id))]
 pub async fn search(org_id: &str, req: &MetricsQueryRequest, timeout: i64) -> Result<Value> {
+    let cache_key = if CONFIG.common.metrics_result_cache_enabled {
+        cache::cache_key(org_id, req)
+    } else {
+        None
+    };
+    match cache_key {
+        Some(key) => search_with_cache(org_id, req, timeout, key).await,
+        None => search_in_cluster(cluster_request(org_id, req, timeout)).await,
+    }
+}
+
+/// Evaluates the range query only after the cached samples and merges them,
+/// the samples within the ingestion delay window are never cached because
+/// late samples may still change them.
+async fn search_with_cache(
+    org_id: &str,
+    req: &MetricsQueryRequest,
+    timeout: i64,
+    key: String,
+) -> Result<Value> {
+    let (cached, start) = match cache::get(&key, req.start, req.end) {
+        Some((data, cached_end)) => (data, cached_end + req.step),
+        None => (vec![], req.start),
+    };
+    let mut value = if start > req.end {
+        Value::Matrix(cached)
+    } else {
+        let tail_req = MetricsQueryRequest {
+            start,
+            ..req.clone()
+        };
+        match search_in_cluster(cluster_request(org_id, &tail_req, timeout)).await? {
+            Value::Matrix(tail) => Value::Matrix(cache::merge(cached, tail)),
+            value if start == req.start => return Ok(value),
+            _ => return search_in_cluster(cluster_request(org_id, req, timeout)).await,
+        }
+    };
+
+    let delay = Duration::hours(CONFIG.limit.ingest_allowed_upto)
+        .num_microseconds()
+        .unwrap();
+    let cache_end = cache::cacheable_end(req, Utc::now().timestamp_micros() - delay);
+    if cache_end >= req.start {
+        if let Value::Matrix(data) = &value {
+            cache::set(key, req.start, cache_end, data);
+        }
+    }
+    value.sort();
+    Ok(value)
+}
+
+fn cluster_request(
+    org_id: &str,
+    req: &MetricsQueryRequest,
+    timeout: i64,
+) -> cluster_rpc::MetricsQueryRequest {
     let mut req: cluster_rpc::MetricsQueryRequest = req.to_owned().into();
     req.org_id = org_id.to_string();
     req.stype = cluster_rpc::SearchType::User as _;
     req.timeout = timeout;
-    search_in_cluster(req).await
+    req
 }
 
 #[tracing::instrument(name = "promql:search:cluster", skip_all, fields(org_id = req.org_id))]
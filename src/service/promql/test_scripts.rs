@@ -0,0 +1,414 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Runs PromQL test scripts against the engine. The scripts use the format of
+//! the upstream Prometheus `promql/testdata` files:
+//!
+//! ```text
+//! load <step>
+//!   <series> <values>
+//!
+//! eval instant at <time> <expr>
+//!   <series> <value>
+//!
+//! clear
+//! ```
+//!
+//! Values are either numbers, `_` for a missing sample, or `a+bxN` which
+//! expands to `N+1` samples starting at `a` and increasing by `b`.
+
+use std::{
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use ahash::AHashMap as HashMap;
+use async_trait::async_trait;
+use datafusion::{
+    arrow::{
+        array::{ArrayRef, Float64Array, Int64Array, StringArray},
+        datatypes::{DataType, Field, Schema},
+        record_batch::RecordBatch,
+    },
+    datasource::MemTable,
+    error::Result,
+    prelude::SessionContext,
+};
+use promql_parser::parser::{self, EvalStmt};
+
+use super::{micros, value::Value, Query, TableProvider};
+use crate::common::{
+    infra::config::CONFIG,
+    meta::{
+        prom::{HASH_LABEL, NAME_LABEL, VALUE_LABEL},
+        stream::ScanStats,
+    },
+};
+
+type Labels = Vec<(String, String)>;
+
+#[derive(Clone, Default)]
+struct MemoryProvider {
+    tables: HashMap<String, (Arc<Schema>, RecordBatch)>,
+}
+
+#[async_trait]
+impl TableProvider for MemoryProvider {
+    async fn create_context(
+        &self,
+        _org_id: &str,
+        stream_name: &str,
+        _time_range: (i64, i64),
+        _filters: &[(&str, Vec<&str>)],
+    ) -> Result<Vec<(SessionContext, Arc<Schema>, ScanStats)>> {
+        let (schema, batch) = match self.tables.get(stream_name) {
+            Some(v) => v,
+            None => return Ok(vec![]),
+        };
+        let ctx = SessionContext::new();
+        let table = MemTable::try_new(schema.clone(), vec![vec![batch.clone()]])?;
+        ctx.register_table(stream_name, Arc::new(table))?;
+        Ok(vec![(ctx, schema.clone(), ScanStats::default())])
+    }
+}
+
+impl MemoryProvider {
+    fn new(series: &[(Labels, Vec<(i64, f64)>)]) -> Self {
+        let mut metrics: HashMap<String, Vec<&(Labels, Vec<(i64, f64)>)>> = HashMap::default();
+        for s in series {
+            let name = label_value(&s.0, NAME_LABEL).expect("Missing metric name");
+            metrics.entry(name.to_string()).or_default().push(s);
+        }
+
+        let mut tables = HashMap::default();
+        for (name, series) in metrics {
+            let mut label_names = series
+                .iter()
+                .flat_map(|(labels, _)| labels.iter().map(|(k, _)| k.clone()))
+                .collect::<Vec<_>>();
+            label_names.sort();
+            label_names.dedup();
+
+            let mut fields = vec![
+                Field::new(&CONFIG.common.column_timestamp, DataType::Int64, false),
+                Field::new(HASH_LABEL, DataType::Utf8, false),
+                Field::new(VALUE_LABEL, DataType::Float64, false),
+            ];
+            fields.extend(
+                label_names
+                    .iter()
+                    .map(|k| Field::new(k, DataType::Utf8, false)),
+            );
+            let schema = Arc::new(Schema::new(fields));
+
+            let mut timestamps = vec![];
+            let mut hashes = vec![];
+            let mut values = vec![];
+            let mut label_values = vec![vec![]; label_names.len()];
+            for (labels, samples) in series {
+                let hash = format_labels(labels);
+                for (ts, value) in samples {
+                    timestamps.push(*ts);
+                    hashes.push(hash.clone());
+                    values.push(*value);
+                    for (i, k) in label_names.iter().enumerate() {
+                        label_values[i]
+                            .push(label_value(labels, k).unwrap_or_default().to_string());
+                    }
+                }
+            }
+            let mut columns: Vec<ArrayRef> = vec![
+                Arc::new(Int64Array::from(timestamps)),
+                Arc::new(StringArray::from(hashes)),
+                Arc::new(Float64Array::from(values)),
+            ];
+            columns.extend(
+                label_values
+                    .into_iter()
+                    .map(|v| Arc::new(StringArray::from(v)) as ArrayRef),
+            );
+            let batch = RecordBatch::try_new(schema.clone(), columns).unwrap();
+            tables.insert(name, (schema, batch));
+        }
+        Self { tables }
+    }
+}
+
+fn label_value<'a>(labels: &'a Labels, name: &str) -> Option<&'a str> {
+    labels
+        .iter()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.as_str())
+}
+
+/// Formats labels as `name{k="v",...}`, ignoring empty label values like
+/// Prometheus does.
+fn format_labels(labels: &Labels) -> String {
+    let mut pairs = labels
+        .iter()
+        .filter(|(k, v)| k != NAME_LABEL && !v.is_empty())
+        .map(|(k, v)| format!("{k}=\"{v}\""))
+        .collect::<Vec<_>>();
+    pairs.sort();
+    format!(
+        "{}{{{}}}",
+        label_value(labels, NAME_LABEL).unwrap_or_default(),
+        pairs.join(",")
+    )
+}
+
+/// Parses `name{k="v",...}`, `name` or `{k="v",...}`.
+fn parse_series(s: &str) -> Labels {
+    let (name, rest) = match s.find('{') {
+        Some(i) => (&s[..i], s[i + 1..].trim_end_matches('}')),
+        None => (s, ""),
+    };
+    let mut labels = Labels::new();
+    if !name.is_empty() {
+        labels.push((NAME_LABEL.to_string(), name.to_string()));
+    }
+    for pair in rest.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (k, v) = pair.split_once('=').expect("Invalid label");
+        labels.push((k.trim().to_string(), v.trim().trim_matches('"').to_string()));
+    }
+    labels
+}
+
+/// Splits a series line into its labels and the rest of the line.
+fn split_series(line: &str) -> (Labels, &str) {
+    let end = match line.find('}') {
+        Some(i) => i + 1,
+        None => line.find(char::is_whitespace).unwrap_or(line.len()),
+    };
+    (parse_series(line[..end].trim()), line[end..].trim())
+}
+
+/// Parses durations like `10s`, `5m` or `1m30s`.
+fn parse_duration(s: &str) -> Duration {
+    let mut total = Duration::ZERO;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .expect("Missing duration unit");
+        let num: u64 = rest[..digits].parse().expect("Invalid duration");
+        rest = &rest[digits..];
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let secs = match &rest[..unit_len] {
+            "ms" => {
+                total += Duration::from_millis(num);
+                rest = &rest[unit_len..];
+                continue;
+            }
+            "s" => 1,
+            "m" => 60,
+            "h" => 3600,
+            "d" => 86400,
+            "w" => 604800,
+            unit => panic!("Invalid duration unit: {unit}"),
+        };
+        total += Duration::from_secs(num * secs);
+        rest = &rest[unit_len..];
+    }
+    total
+}
+
+/// Expands the value notation of a `load` command.
+fn parse_values(s: &str) -> Vec<Option<f64>> {
+    let mut values = vec![];
+    for token in s.split_whitespace() {
+        let (head, times) = match token.rsplit_once('x') {
+            Some((head, times)) => (head, Some(times.parse::<usize>().expect("Invalid times"))),
+            None => (token, None),
+        };
+        if head == "_" {
+            values.extend(std::iter::repeat(None).take(times.unwrap_or(1)));
+            continue;
+        }
+        // the sign of the start value is not an operator
+        let (start, delta) = match head[1..].find(['+', '-']) {
+            Some(i) => {
+                let (start, delta) = head.split_at(i + 1);
+                (start, delta.trim_start_matches('+'))
+            }
+            None => (head, "0"),
+        };
+        let start: f64 = start.parse().expect("Invalid value");
+        let delta: f64 = delta.parse().expect("Invalid value");
+        match times {
+            Some(times) => {
+                values.extend((0..=times).map(|i| Some(start + delta * i as f64)));
+            }
+            None => values.push(Some(start)),
+        }
+    }
+    values
+}
+
+fn almost_equal(a: f64, b: f64) -> bool {
+    (a.is_nan() && b.is_nan()) || a == b || (a - b).abs() <= 1e-6 * a.abs().max(b.abs())
+}
+
+async fn eval_instant(provider: &MemoryProvider, expr: &str, time: Duration) -> Value {
+    let expr = parser::parse(expr).unwrap_or_else(|e| panic!("failed to parse {expr}: {e}"));
+    let mut engine = Query::new("default", provider.clone(), 30);
+    let (value, ..) = engine
+        .exec(EvalStmt {
+            expr,
+            start: UNIX_EPOCH + time,
+            end: UNIX_EPOCH + time,
+            interval: Duration::ZERO,
+            lookback_delta: Duration::ZERO,
+        })
+        .await
+        .unwrap();
+    value
+}
+
+async fn run_script(name: &str, script: &str) {
+    let mut series: Vec<(Labels, Vec<(i64, f64)>)> = vec![];
+    let mut provider = MemoryProvider::default();
+    let mut lines = script.lines().enumerate().peekable();
+    while let Some((line_no, line)) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line == "clear" {
+            series.clear();
+            provider = MemoryProvider::default();
+        } else if let Some(step) = line.strip_prefix("load ") {
+            let step = micros(parse_duration(step.trim()));
+            while let Some((_, line)) = lines.next_if(|(_, l)| !l.trim().is_empty()) {
+                let (labels, values) = split_series(line.trim());
+                let samples = parse_values(values)
+                    .into_iter()
+                    .enumerate()
+                    .filter_map(|(i, v)| v.map(|v| (step * i as i64, v)))
+                    .collect();
+                series.push((labels, samples));
+            }
+            provider = MemoryProvider::new(&series);
+        } else if let Some(cmd) = line.strip_prefix("eval instant at ") {
+            let (time, expr) = cmd.split_once(' ').expect("Missing expression");
+            let mut expected = HashMap::default();
+            let mut expected_scalar = None;
+            while let Some((_, line)) = lines.next_if(|(_, l)| !l.trim().is_empty()) {
+                let line = line.trim();
+                if let Ok(v) = line.parse::<f64>() {
+                    expected_scalar = Some(v);
+                    continue;
+                }
+                let (labels, value) = split_series(line);
+                expected.insert(format_labels(&labels), value.parse::<f64>().unwrap());
+            }
+
+            let value = eval_instant(&provider, expr, parse_duration(time)).await;
+            let location = format!("{name}:{} `{expr}`", line_no + 1);
+            match value {
+                Value::Sample(s) => {
+                    let want = expected_scalar
+                        .unwrap_or_else(|| panic!("{location}: unexpected scalar {}", s.value));
+                    assert!(
+                        almost_equal(s.value, want),
+                        "{location}: expected {want}, got {}",
+                        s.value
+                    );
+                }
+                Value::Vector(vs) => {
+                    let got = vs
+                        .iter()
+                        .map(|v| {
+                            let labels: Labels = v
+                                .labels
+                                .iter()
+                                .map(|l| (l.name.clone(), l.value.clone()))
+                                .collect();
+                            (format_labels(&labels), v.sample.value)
+                        })
+                        .collect::<HashMap<_, _>>();
+                    assert_eq!(
+                        got.len(),
+                        expected.len(),
+                        "{location}: expected {expected:?}, got {got:?}"
+                    );
+                    for (labels, want) in expected.iter() {
+                        let value = got
+                            .get(labels)
+                            .unwrap_or_else(|| panic!("{location}: missing {labels} in {got:?}"));
+                        assert!(
+                            almost_equal(*value, *want),
+                            "{location}: expected {labels} {want}, got {value}"
+                        );
+                    }
+                }
+                Value::None => {
+                    assert!(
+                        expected.is_empty() && expected_scalar.is_none(),
+                        "{location}: expected {expected:?}, got no data"
+                    );
+                }
+                v => panic!("{location}: unexpected result {:?}", v.get_type()),
+            }
+        } else {
+            panic!("{name}:{}: unknown command {line}", line_no + 1);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_subquery() {
+    run_script("subquery.test", include_str!("testdata/subquery.test")).await;
+}
+
+#[tokio::test]
+async fn test_at_modifier() {
+    run_script(
+        "at_modifier.test",
+        include_str!("testdata/at_modifier.test"),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_offset() {
+    run_script("offset.test", include_str!("testdata/offset.test")).await;
+}
+
+#[test]
+fn test_parse_values() {
+    assert_eq!(
+        parse_values("1 _ -1+2x2 5x1 _x2"),
+        vec![
+            Some(1.0),
+            None,
+            Some(-1.0),
+            Some(1.0),
+            Some(3.0),
+            Some(5.0),
+            Some(5.0),
+            None,
+            None
+        ]
+    );
+    assert_eq!(parse_duration("1m30s"), Duration::from_secs(90));
+}
+
+#[tokio::test]
+async fn test_functions() {
+    run_script("functions.test", include_str!("testdata/functions.test")).await;
+}
@@ -23,7 +23,10 @@ use serde::{
     Serialize,
 };
 
-use crate::common::{infra::config::FxIndexMap, meta::prom::NAME_LABEL};
+use crate::common::{
+    infra::config::FxIndexMap,
+    meta::prom::{NativeHistogram, NAME_LABEL},
+};
 
 // https://prometheus.io/docs/concepts/data_model/#metric-names-and-labels
 static RE_VALID_LABEL_NAME: Lazy<Regex> =
@@ -43,7 +46,8 @@ pub trait LabelsExt {
     /// Return the value of the label associated with this name of the label.
     fn get_value(&self, name: &str) -> String;
 
-    /// Set a new label -> value to this label
+    /// Set a new label -> value to this label, replacing the existing value
+    /// of the label, the labels are sorted by name after adding a new one
     fn set(&mut self, name: &str, value: &str);
 
     /// Get the values
@@ -91,10 +95,19 @@ impl LabelsExt for Labels {
     }
 
     fn set(&mut self, name: &str, value: &str) {
-        self.push(Arc::new(Label {
+        let label = Arc::new(Label {
             name: name.to_string(),
             value: value.to_string(),
-        }))
+        });
+        // the labels are not always sorted, such as the ones of a series read
+        // from the storage, so the label is looked up linearly
+        match self.iter().position(|l| l.name == name) {
+            Some(i) => self[i] = label,
+            None => {
+                self.push(label);
+                self.sort_by(|a, b| a.name.cmp(&b.name));
+            }
+        }
     }
 
     fn signature(&self) -> Signature {
@@ -273,6 +286,14 @@ impl RangeValue {
     }
 }
 
+/// The native histograms of a series, sorted by time.
+#[derive(Debug, Clone)]
+pub struct HistogramRangeValue {
+    pub labels: Labels,
+    /// Time in microseconds and the histogram
+    pub histograms: Vec<(i64, NativeHistogram)>,
+}
+
 #[derive(Debug)]
 pub(crate) enum ExtrapolationKind {
     /// Calculate the per-second average rate of increase of the time series.
@@ -694,6 +715,21 @@ mod tests {
         assert!(value.is_empty());
     }
 
+    #[test]
+    fn test_set() {
+        let mut labels: Labels = vec![
+            Arc::new(Label::new("job", "api")),
+            Arc::new(Label::new("__name__", "up")),
+            Arc::new(Label::new("instance", "a")),
+        ];
+        labels.set("instance", "b");
+        assert_eq!(labels.len(), 3);
+        assert_eq!(labels.get_value("instance"), "b");
+
+        labels.set("env", "prod");
+        assert_eq!(labels.keys(), vec!["__name__", "env", "instance", "job"]);
+    }
+
     #[test]
     fn test_keep() {
         let labels: Labels = generate_test_labels();
@@ -0,0 +1,394 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Translates the aggregations into the SQL of the search aggregations, which
+//! run over the records matching the query, and their results into buckets.
+
+use chrono::{SecondsFormat, TimeZone, Utc};
+
+use super::query::column;
+use crate::common::utils::{json, time::parse_str_to_timestamp_micros};
+
+const DEFAULT_PERCENTS: [f64; 7] = [1.0, 5.0, 25.0, 50.0, 75.0, 95.0, 99.0];
+
+#[derive(Debug, PartialEq)]
+pub enum Aggregation {
+    Terms {
+        field: String,
+        size: usize,
+        metrics: Vec<(String, Metric)>,
+    },
+    DateHistogram {
+        field: String,
+        interval: String, // such as `1 hour`
+        min_doc_count: u64,
+        metrics: Vec<(String, Metric)>,
+    },
+    Metric(Metric),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Metric {
+    Avg(String),
+    Sum(String),
+    Min(String),
+    Max(String),
+    ValueCount(String),
+    Percentiles(String, Vec<f64>),
+}
+
+impl Aggregation {
+    /// Parses the aggregation, only the metrics can be nested in the buckets.
+    pub fn parse(body: &json::Value) -> Result<Self, anyhow::Error> {
+        let (kind, options) = agg_type(body)?;
+        let field = || -> Result<String, anyhow::Error> {
+            options
+                .get("field")
+                .and_then(|v| v.as_str())
+                .map(|v| v.to_string())
+                .ok_or_else(|| anyhow::anyhow!("[{kind}] aggregation requires a field"))
+        };
+        let metrics = || -> Result<Vec<(String, Metric)>, anyhow::Error> {
+            let sub_aggs = body.get("aggs").or_else(|| body.get("aggregations"));
+            let Some(sub_aggs) = sub_aggs.and_then(|v| v.as_object()) else {
+                return Ok(vec![]);
+            };
+            sub_aggs
+                .iter()
+                .map(|(name, body)| match Aggregation::parse(body)? {
+                    Aggregation::Metric(metric) => Ok((name.clone(), metric)),
+                    _ => Err(anyhow::anyhow!(
+                        "unsupported aggregation [{name}], only metrics can be nested"
+                    )),
+                })
+                .collect()
+        };
+        match kind.as_str() {
+            "terms" => Ok(Aggregation::Terms {
+                field: field()?,
+                size: options.get("size").and_then(|v| v.as_u64()).unwrap_or(10) as usize,
+                metrics: metrics()?,
+            }),
+            "date_histogram" => {
+                let interval = ["fixed_interval", "calendar_interval", "interval"]
+                    .iter()
+                    .find_map(|name| options.get(*name).and_then(|v| v.as_str()))
+                    .ok_or_else(|| anyhow::anyhow!("[date_histogram] requires an interval"))?;
+                Ok(Aggregation::DateHistogram {
+                    field: field()?,
+                    interval: parse_interval(interval)?,
+                    min_doc_count: options
+                        .get("min_doc_count")
+                        .and_then(|v| v.as_u64())
+                        .unwrap_or_default(),
+                    metrics: metrics()?,
+                })
+            }
+            "avg" => Ok(Aggregation::Metric(Metric::Avg(field()?))),
+            "sum" => Ok(Aggregation::Metric(Metric::Sum(field()?))),
+            "min" => Ok(Aggregation::Metric(Metric::Min(field()?))),
+            "max" => Ok(Aggregation::Metric(Metric::Max(field()?))),
+            "value_count" => Ok(Aggregation::Metric(Metric::ValueCount(field()?))),
+            "percentiles" => {
+                let percents = match options.get("percents").and_then(|v| v.as_array()) {
+                    Some(percents) => percents
+                        .iter()
+                        .map(|v| {
+                            v.as_f64()
+                                .filter(|v| (0.0..=100.0).contains(v))
+                                .ok_or_else(|| anyhow::anyhow!("invalid percent {v}"))
+                        })
+                        .collect::<Result<Vec<_>, _>>()?,
+                    None => DEFAULT_PERCENTS.to_vec(),
+                };
+                Ok(Aggregation::Metric(Metric::Percentiles(field()?, percents)))
+            }
+            _ => Err(anyhow::anyhow!("unsupported aggregation [{kind}]")),
+        }
+    }
+
+    /// Returns the SQL of the aggregation over the records of the query.
+    pub fn sql(&self) -> String {
+        match self {
+            Aggregation::Terms {
+                field,
+                size,
+                metrics,
+            } => format!(
+                "SELECT {} AS zo_sql_key, COUNT(*) AS zo_sql_num{} FROM query GROUP BY zo_sql_key ORDER BY zo_sql_num DESC LIMIT {size}",
+                column(field),
+                metrics_columns(metrics)
+            ),
+            Aggregation::DateHistogram {
+                field,
+                interval,
+                metrics,
+                ..
+            } => format!(
+                "SELECT histogram({}, '{interval}') AS zo_sql_key, COUNT(*) AS zo_sql_num{} FROM query GROUP BY zo_sql_key ORDER BY zo_sql_key",
+                column(field).trim_matches('"'),
+                metrics_columns(metrics)
+            ),
+            Aggregation::Metric(metric) => {
+                format!("SELECT {} FROM query", metric.columns("m").join(", "))
+            }
+        }
+    }
+
+    /// Builds the result of the aggregation from the rows of its SQL.
+    pub fn to_response(&self, rows: &[json::Value]) -> json::Value {
+        match self {
+            Aggregation::Terms { metrics, .. } => {
+                let buckets = rows
+                    .iter()
+                    .map(|row| {
+                        let mut bucket = bucket(row, metrics);
+                        bucket.insert(
+                            "key".to_string(),
+                            row.get("zo_sql_key").cloned().unwrap_or_default(),
+                        );
+                        json::Value::Object(bucket)
+                    })
+                    .collect::<Vec<_>>();
+                json::json!({
+                    "doc_count_error_upper_bound": 0,
+                    "sum_other_doc_count": 0,
+                    "buckets": buckets,
+                })
+            }
+            Aggregation::DateHistogram {
+                min_doc_count,
+                metrics,
+                ..
+            } => {
+                let buckets = rows
+                    .iter()
+                    .filter(|row| doc_count(row) >= *min_doc_count)
+                    .filter_map(|row| {
+                        let key = match row.get("zo_sql_key")? {
+                            json::Value::Number(v) => v.as_i64()?,
+                            json::Value::String(v) => parse_str_to_timestamp_micros(v).ok()?,
+                            _ => return None,
+                        };
+                        let mut bucket = bucket(row, metrics);
+                        bucket.insert("key".to_string(), json::Value::from(key / 1000));
+                        bucket.insert(
+                            "key_as_string".to_string(),
+                            json::Value::from(
+                                Utc.timestamp_nanos(key * 1000)
+                                    .to_rfc3339_opts(SecondsFormat::Millis, true),
+                            ),
+                        );
+                        Some(json::Value::Object(bucket))
+                    })
+                    .collect::<Vec<_>>();
+                json::json!({ "buckets": buckets })
+            }
+            Aggregation::Metric(metric) => {
+                metric.to_response(rows.first().unwrap_or(&json::Value::Null), "m")
+            }
+        }
+    }
+}
+
+impl Metric {
+    /// Returns the columns of the metric, aliased from the prefix.
+    fn columns(&self, prefix: &str) -> Vec<String> {
+        let (function, field) = match self {
+            Metric::Avg(field) => ("avg", field),
+            Metric::Sum(field) => ("sum", field),
+            Metric::Min(field) => ("min", field),
+            Metric::Max(field) => ("max", field),
+            Metric::ValueCount(field) => ("count", field),
+            Metric::Percentiles(field, percents) => {
+                return percents
+                    .iter()
+                    .enumerate()
+                    .map(|(i, percent)| {
+                        format!(
+                            "approx_percentile_cont({}, {}) AS {prefix}_{i}",
+                            column(field),
+                            percent / 100.0
+                        )
+                    })
+                    .collect();
+            }
+        };
+        vec![format!("{function}({}) AS {prefix}", column(field))]
+    }
+
+    fn to_response(&self, row: &json::Value, prefix: &str) -> json::Value {
+        match self {
+            Metric::Percentiles(_, percents) => {
+                let values = percents
+                    .iter()
+                    .enumerate()
+                    .map(|(i, percent)| {
+                        (
+                            format!("{percent:?}"),
+                            row.get(format!("{prefix}_{i}"))
+                                .cloned()
+                                .unwrap_or_default(),
+                        )
+                    })
+                    .collect::<json::Map<_, _>>();
+                json::json!({ "values": values })
+            }
+            _ => json::json!({ "value": row.get(prefix).cloned().unwrap_or_default() }),
+        }
+    }
+}
+
+/// Returns the type and the options of the aggregation, besides its nested
+/// aggregations.
+fn agg_type(body: &json::Value) -> Result<(&String, &json::Value), anyhow::Error> {
+    body.as_object()
+        .and_then(|body| {
+            body.iter()
+                .find(|(k, _)| !matches!(k.as_str(), "aggs" | "aggregations" | "meta"))
+        })
+        .ok_or_else(|| anyhow::anyhow!("an aggregation requires a type, got {body}"))
+}
+
+fn metrics_columns(metrics: &[(String, Metric)]) -> String {
+    metrics
+        .iter()
+        .enumerate()
+        .flat_map(|(i, (_, metric))| metric.columns(&format!("m{i}")))
+        .map(|column| format!(", {column}"))
+        .collect()
+}
+
+fn doc_count(row: &json::Value) -> u64 {
+    row.get("zo_sql_num")
+        .and_then(|v| v.as_u64())
+        .unwrap_or_default()
+}
+
+fn bucket(row: &json::Value, metrics: &[(String, Metric)]) -> json::Map<String, json::Value> {
+    let mut bucket = json::Map::new();
+    bucket.insert("doc_count".to_string(), json::Value::from(doc_count(row)));
+    for (i, (name, metric)) in metrics.iter().enumerate() {
+        bucket.insert(name.clone(), metric.to_response(row, &format!("m{i}")));
+    }
+    bucket
+}
+
+/// Converts the interval of a date histogram, such as `5m`, `1h` or `day`, to
+/// the interval of the SQL histogram. The calendar months and years are not
+/// supported, as the buckets have a fixed duration.
+fn parse_interval(interval: &str) -> Result<String, anyhow::Error> {
+    let (amount, unit) = match interval.find(|c: char| !c.is_ascii_digit()) {
+        Some(0) => ("1", interval),
+        Some(i) => interval.split_at(i),
+        None => return Err(anyhow::anyhow!("invalid interval {interval}")),
+    };
+    let unit = match unit {
+        "ms" => "millisecond",
+        "s" | "second" => "second",
+        "m" | "minute" => "minute",
+        "h" | "hour" => "hour",
+        "d" | "day" => "day",
+        "w" | "week" => "week",
+        _ => return Err(anyhow::anyhow!("unsupported interval {interval}")),
+    };
+    Ok(format!("{amount} {unit}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_terms() {
+        let agg = Aggregation::parse(&json::json!({
+            "terms": { "field": "host.keyword", "size": 5 },
+            "aggs": { "latency": { "avg": { "field": "took" } } },
+        }))
+        .unwrap();
+        assert_eq!(
+            agg.sql(),
+            "SELECT \"host\" AS zo_sql_key, COUNT(*) AS zo_sql_num, avg(\"took\") AS m0 FROM query GROUP BY zo_sql_key ORDER BY zo_sql_num DESC LIMIT 5"
+        );
+        let resp = agg.to_response(&[
+            json::json!({ "zo_sql_key": "web-1", "zo_sql_num": 3, "m0": 12.5 }),
+            json::json!({ "zo_sql_key": "web-2", "zo_sql_num": 1, "m0": 4.0 }),
+        ]);
+        assert_eq!(
+            resp["buckets"][0],
+            json::json!({ "key": "web-1", "doc_count": 3, "latency": { "value": 12.5 } })
+        );
+        assert_eq!(resp["buckets"].as_array().unwrap().len(), 2);
+
+        // only the metrics can be nested
+        assert!(
+            Aggregation::parse(&json::json!({
+                "terms": { "field": "host" },
+                "aggs": { "by_zone": { "terms": { "field": "zone" } } },
+            }))
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_date_histogram() {
+        let agg = Aggregation::parse(&json::json!({
+            "date_histogram": { "field": "@timestamp", "fixed_interval": "30s", "min_doc_count": 1 },
+        }))
+        .unwrap();
+        assert_eq!(
+            agg.sql(),
+            "SELECT histogram(_timestamp, '30 second') AS zo_sql_key, COUNT(*) AS zo_sql_num FROM query GROUP BY zo_sql_key ORDER BY zo_sql_key"
+        );
+        let resp = agg.to_response(&[
+            json::json!({ "zo_sql_key": "2023-11-14T22:13:00", "zo_sql_num": 2 }),
+            json::json!({ "zo_sql_key": "2023-11-14T22:13:30", "zo_sql_num": 0 }),
+        ]);
+        assert_eq!(
+            resp["buckets"],
+            json::json!([{
+                "key": 1_699_999_980_000i64,
+                "key_as_string": "2023-11-14T22:13:00.000Z",
+                "doc_count": 2,
+            }])
+        );
+        assert!(
+            Aggregation::parse(&json::json!({
+                "date_histogram": { "field": "@timestamp", "calendar_interval": "1M" },
+            }))
+            .is_err()
+        );
+        assert_eq!(parse_interval("day").unwrap(), "1 day");
+    }
+
+    #[test]
+    fn test_metrics() {
+        let agg = Aggregation::parse(
+            &json::json!({ "percentiles": { "field": "took", "percents": [50, 95] } }),
+        )
+        .unwrap();
+        assert_eq!(
+            agg.sql(),
+            "SELECT approx_percentile_cont(\"took\", 0.5) AS m_0, approx_percentile_cont(\"took\", 0.95) AS m_1 FROM query"
+        );
+        assert_eq!(
+            agg.to_response(&[json::json!({ "m_0": 10, "m_1": 90 })]),
+            json::json!({ "values": { "50.0": 10, "95.0": 90 } })
+        );
+        let agg = Aggregation::parse(&json::json!({ "max": { "field": "took" } })).unwrap();
+        assert_eq!(agg.sql(), "SELECT max(\"took\") AS m FROM query");
+        assert_eq!(agg.to_response(&[]), json::json!({ "value": null }));
+    }
+}
@@ -0,0 +1,397 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Elasticsearch compatible `_search` and `_msearch`, for the tools querying
+//! Elasticsearch. The query DSL and the aggregations are translated into the
+//! SQL of a search over the logs stream named by the index.
+
+use std::collections::HashMap;
+
+use actix_web::http::StatusCode;
+use chrono::{SecondsFormat, TimeZone, Utc};
+
+use self::aggs::Aggregation;
+use crate::{
+    common::{
+        infra::{
+            config::CONFIG,
+            errors::{Error, ErrorCodes},
+        },
+        meta::{search, StreamType},
+        utils::json,
+    },
+    service::format_stream_name,
+};
+
+mod aggs;
+mod query;
+
+/// An error in the format of the Elasticsearch responses.
+#[derive(Debug)]
+pub struct EsError {
+    pub status: StatusCode,
+    pub error_type: &'static str,
+    pub reason: String,
+}
+
+impl EsError {
+    pub fn parsing(e: impl std::fmt::Display) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            error_type: "parsing_exception",
+            reason: e.to_string(),
+        }
+    }
+
+    pub fn to_json(&self) -> json::Value {
+        json::json!({
+            "error": {
+                "root_cause": [{ "type": self.error_type, "reason": self.reason }],
+                "type": self.error_type,
+                "reason": self.reason,
+            },
+            "status": self.status.as_u16(),
+        })
+    }
+}
+
+impl From<Error> for EsError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::ErrorCode(ErrorCodes::SearchStreamNotFound(stream)) => Self {
+                status: StatusCode::NOT_FOUND,
+                error_type: "index_not_found_exception",
+                reason: format!("no such index [{stream}]"),
+            },
+            Error::ErrorCode(code) => Self {
+                status: StatusCode::BAD_REQUEST,
+                error_type: "search_phase_execution_exception",
+                reason: format!("{}: {}", code.get_message(), code.get_inner_message()),
+            },
+            e => Self {
+                status: StatusCode::INTERNAL_SERVER_ERROR,
+                error_type: "exception",
+                reason: e.to_string(),
+            },
+        }
+    }
+}
+
+/// The search translated from the request body.
+#[derive(Debug)]
+struct Search {
+    index: String,
+    req: search::Request,
+    /// The aggregations by name, their SQL is keyed by position.
+    aggs: Vec<(String, Aggregation)>,
+    /// The fields returned in the hits, all when `None`.
+    source: Option<Vec<String>>,
+}
+
+pub async fn search(org_id: &str, index: &str, body: &json::Value) -> Result<json::Value, EsError> {
+    let start = std::time::Instant::now();
+    let search = translate(index, body, Utc::now().timestamp_micros()).map_err(EsError::parsing)?;
+    let res = super::search("", org_id, StreamType::Logs, &search.req).await?;
+    Ok(search.to_response(res, start.elapsed().as_millis()))
+}
+
+/// Runs the searches of the NDJSON body, the pairs of a header naming the
+/// index and of a search body. A failed search is reported in its response.
+pub async fn msearch(
+    org_id: &str,
+    default_index: Option<&str>,
+    body: &[u8],
+) -> Result<json::Value, EsError> {
+    let start = std::time::Instant::now();
+    let lines = body
+        .split(|b| *b == b'\n')
+        .filter(|line| !line.iter().all(u8::is_ascii_whitespace))
+        .map(json::from_slice::<json::Value>)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(EsError::parsing)?;
+    if lines.len() % 2 != 0 {
+        return Err(EsError::parsing(
+            "the body should have a search body after every header",
+        ));
+    }
+    let mut responses = Vec::with_capacity(lines.len() / 2);
+    for pair in lines.chunks(2) {
+        let (header, body) = (&pair[0], &pair[1]);
+        let index = match header.get("index") {
+            Some(json::Value::String(index)) => Some(index.as_str()),
+            Some(json::Value::Array(indexes)) if indexes.len() == 1 => indexes[0].as_str(),
+            _ => default_index,
+        };
+        let ret = match index {
+            Some(index) => search(org_id, index, body).await,
+            None => Err(EsError::parsing("the search requires an index")),
+        };
+        responses.push(match ret {
+            Ok(mut resp) => {
+                resp["status"] = json::Value::from(200);
+                resp
+            }
+            Err(e) => e.to_json(),
+        });
+    }
+    Ok(json::json!({
+        "took": start.elapsed().as_millis() as u64,
+        "responses": responses,
+    }))
+}
+
+fn translate(index: &str, body: &json::Value, now: i64) -> Result<Search, anyhow::Error> {
+    if index.contains(['*', ',']) {
+        return Err(anyhow::anyhow!(
+            "index patterns are not supported, the index should be a stream, got {index}"
+        ));
+    }
+    let stream_name = format_stream_name(index);
+    let mut time_range = (0, 0);
+    let condition = match body.get("query") {
+        Some(query) => query::translate(query, now, &mut time_range)?,
+        None => None,
+    };
+    let mut sql = format!("SELECT * FROM \"{stream_name}\"");
+    if let Some(condition) = condition {
+        sql.push_str(&format!(" WHERE {condition}"));
+    }
+    let order_by = sort(body.get("sort"))?;
+    if !order_by.is_empty() {
+        sql.push_str(&format!(" ORDER BY {}", order_by.join(", ")));
+    }
+
+    let mut aggs = Vec::new();
+    let mut aggs_sql = HashMap::new();
+    if let Some(body_aggs) = body.get("aggs").or_else(|| body.get("aggregations")) {
+        let body_aggs = body_aggs
+            .as_object()
+            .ok_or_else(|| anyhow::anyhow!("[aggs] should be an object"))?;
+        for (i, (name, body)) in body_aggs.iter().enumerate() {
+            let agg = Aggregation::parse(body)
+                .map_err(|e| anyhow::anyhow!("aggregation [{name}]: {e}"))?;
+            aggs_sql.insert(format!("agg{i}"), agg.sql());
+            aggs.push((name.clone(), agg));
+        }
+    }
+
+    let source = match body.get("_source") {
+        Some(json::Value::Bool(false)) => Some(vec![]),
+        Some(json::Value::String(field)) => Some(vec![field.clone()]),
+        Some(json::Value::Array(fields)) => Some(strings(fields)),
+        Some(json::Value::Object(filter)) => filter
+            .get("includes")
+            .and_then(|v| v.as_array())
+            .map(|fields| strings(fields)),
+        _ => None,
+    };
+
+    let req = search::Request {
+        query: search::Query {
+            sql,
+            from: body.get("from").and_then(|v| v.as_u64()).unwrap_or(0) as usize,
+            size: body.get("size").and_then(|v| v.as_u64()).unwrap_or(10) as usize,
+            start_time: time_range.0,
+            end_time: time_range.1,
+            sql_mode: "context".to_string(),
+            track_total_hits: true,
+            ..Default::default()
+        },
+        aggs: aggs_sql,
+        encoding: search::RequestEncoding::Empty,
+        timeout: 0,
+    };
+    Ok(Search {
+        index: index.to_string(),
+        req,
+        aggs,
+        source,
+    })
+}
+
+/// Translates the sort into the `ORDER BY` clause, ignoring the scores.
+fn sort(sort: Option<&json::Value>) -> Result<Vec<String>, anyhow::Error> {
+    let sorts = match sort {
+        None => return Ok(vec![]),
+        Some(json::Value::Array(sorts)) => sorts.iter().collect(),
+        Some(sort) => vec![sort],
+    };
+    let mut order_by = Vec::with_capacity(sorts.len());
+    for sort in sorts {
+        let (field, order) = match sort {
+            json::Value::String(sort) => match sort.split_once(':') {
+                Some((field, order)) => (field.to_string(), order.to_string()),
+                None => (sort.clone(), "asc".to_string()),
+            },
+            json::Value::Object(sort) if sort.len() == 1 => {
+                let (field, order) = sort.iter().next().unwrap();
+                let order = match order {
+                    json::Value::String(order) => order.as_str(),
+                    order => order.get("order").and_then(|v| v.as_str()).unwrap_or("asc"),
+                };
+                (field.clone(), order.to_string())
+            }
+            _ => return Err(anyhow::anyhow!("unsupported sort {sort}")),
+        };
+        if field == "_score" || field == "_doc" {
+            continue;
+        }
+        let order = if order.eq_ignore_ascii_case("desc") {
+            "DESC"
+        } else {
+            "ASC"
+        };
+        order_by.push(format!("{} {order}", query::column(&field)));
+    }
+    Ok(order_by)
+}
+
+fn strings(values: &[json::Value]) -> Vec<String> {
+    values
+        .iter()
+        .filter_map(|v| v.as_str().map(|v| v.to_string()))
+        .collect()
+}
+
+impl Search {
+    fn to_response(&self, res: search::Response, took: u128) -> json::Value {
+        let hits = res
+            .hits
+            .into_iter()
+            .enumerate()
+            .map(|(i, hit)| {
+                json::json!({
+                    "_index": self.index,
+                    "_id": (self.req.query.from + i).to_string(),
+                    "_score": null,
+                    "_source": self.source(hit),
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut resp = json::json!({
+            "took": took as u64,
+            "timed_out": false,
+            "_shards": { "total": 1, "successful": 1, "skipped": 0, "failed": 0 },
+            "hits": {
+                "total": { "value": res.total, "relation": "eq" },
+                "max_score": null,
+                "hits": hits,
+            },
+        });
+        if !self.aggs.is_empty() {
+            let aggregations = self
+                .aggs
+                .iter()
+                .enumerate()
+                .map(|(i, (name, agg))| {
+                    let rows = res
+                        .aggs
+                        .get(&format!("agg{i}"))
+                        .map_or(&[][..], |rows| rows.as_slice());
+                    (name.clone(), agg.to_response(rows))
+                })
+                .collect::<json::Map<_, _>>();
+            resp["aggregations"] = json::Value::Object(aggregations);
+        }
+        resp
+    }
+
+    /// Keeps the requested fields of the hit, and adds the `@timestamp` of the
+    /// record expected by the Elasticsearch clients.
+    fn source(&self, hit: json::Value) -> json::Value {
+        let json::Value::Object(mut hit) = hit else {
+            return hit;
+        };
+        if !hit.contains_key("@timestamp") {
+            if let Some(timestamp) = hit
+                .get(&CONFIG.common.column_timestamp)
+                .and_then(|v| v.as_i64())
+            {
+                hit.insert(
+                    "@timestamp".to_string(),
+                    json::Value::from(
+                        Utc.timestamp_nanos(timestamp * 1000)
+                            .to_rfc3339_opts(SecondsFormat::Micros, true),
+                    ),
+                );
+            }
+        }
+        if let Some(fields) = &self.source {
+            hit.retain(|k, _| {
+                fields
+                    .iter()
+                    .any(|f| f == k || query::column(f) == format!("\"{k}\""))
+            });
+        }
+        json::Value::Object(hit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translate() {
+        let body = json::json!({
+            "size": 0,
+            "query": {
+                "bool": {
+                    "filter": [
+                        { "range": { "@timestamp": { "gte": 1_700_000_000_000i64, "lte": 1_700_003_600_000i64 } } },
+                        { "query_string": { "query": "level:error" } },
+                    ]
+                }
+            },
+            "sort": [{ "@timestamp": { "order": "desc" } }, "_score"],
+            "aggs": { "per_host": { "terms": { "field": "host" } } },
+        });
+        let search = translate("app-logs", &body, 0).unwrap();
+        assert_eq!(
+            search.req.query.sql,
+            "SELECT * FROM \"app_logs\" WHERE str_match_ignore_case(\"level\", 'error') ORDER BY \"_timestamp\" DESC"
+        );
+        assert_eq!(search.req.query.start_time, 1_700_000_000_000_000);
+        assert_eq!(search.req.query.end_time, 1_700_003_600_000_001);
+        assert_eq!(search.req.query.size, 0);
+        assert_eq!(search.aggs[0].0, "per_host");
+        assert!(search.req.aggs["agg0"].starts_with("SELECT \"host\" AS zo_sql_key"));
+        assert!(translate("logs-*", &body, 0).is_err());
+    }
+
+    #[test]
+    fn test_to_response() {
+        let body = json::json!({ "_source": ["message"], "aggs": { "max_took": { "max": { "field": "took" } } } });
+        let search = translate("default", &body, 0).unwrap();
+        let mut res = search::Response::new(0, 10);
+        res.total = 7;
+        res.hits = vec![
+            json::json!({ "_timestamp": 1_700_000_000_000_000i64, "message": "ok", "took": 3 }),
+        ];
+        res.aggs
+            .insert("agg0".to_string(), vec![json::json!({ "m": 3 })]);
+        let resp = search.to_response(res, 5);
+        assert_eq!(resp["hits"]["total"]["value"], 7);
+        assert_eq!(
+            resp["hits"]["hits"][0]["_source"],
+            json::json!({ "message": "ok" })
+        );
+        assert_eq!(resp["aggregations"]["max_took"]["value"], 3);
+
+        let error = EsError::parsing("unsupported query [fuzzy]").to_json();
+        assert_eq!(error["status"], 400);
+        assert_eq!(error["error"]["type"], "parsing_exception");
+    }
+}
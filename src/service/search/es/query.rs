@@ -0,0 +1,830 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Translates the query DSL, and the Lucene syntax of `query_string`, into the
+//! conditions of the SQL `WHERE` clause. A `None` condition matches all the
+//! records.
+
+use crate::common::{
+    infra::config::CONFIG,
+    utils::{
+        flatten::format_key,
+        json,
+        time::{parse_i64_to_timestamp_micros, parse_str_to_timestamp_micros},
+    },
+};
+
+/// The start and end of the time range in microseconds, the end is exclusive
+/// and 0 leaves the bound open.
+pub type TimeRange = (i64, i64);
+
+/// Translates the query, the ranges on the timestamp which every record must
+/// match are narrowing the time range instead of adding conditions.
+pub fn translate(
+    query: &json::Value,
+    now: i64,
+    time_range: &mut TimeRange,
+) -> Result<Option<String>, anyhow::Error> {
+    condition(query, now, Some(time_range))
+}
+
+fn condition(
+    query: &json::Value,
+    now: i64,
+    time_range: Option<&mut TimeRange>,
+) -> Result<Option<String>, anyhow::Error> {
+    let (kind, body) = single_entry(query)?;
+    match kind.as_str() {
+        "match_all" => Ok(None),
+        "match_none" => Ok(Some("1 = 0".to_string())),
+        "bool" => bool_condition(body, now, time_range),
+        "term" => {
+            let (field, value) = field_value(body, "value")?;
+            Ok(Some(format!("{} = {}", column(field), literal(value)?)))
+        }
+        "terms" => {
+            let (field, values) = body
+                .as_object()
+                .and_then(|body| body.iter().find(|(k, _)| k.as_str() != "boost"))
+                .ok_or_else(|| anyhow::anyhow!("[terms] query requires a field"))?;
+            let values = values
+                .as_array()
+                .ok_or_else(|| anyhow::anyhow!("[terms] query requires an array of values"))?;
+            if values.is_empty() {
+                return Ok(Some("1 = 0".to_string()));
+            }
+            let values = values.iter().map(literal).collect::<Result<Vec<_>, _>>()?;
+            Ok(Some(format!(
+                "{} IN ({})",
+                column(field),
+                values.join(", ")
+            )))
+        }
+        "match" => {
+            let (field, value) = field_value(body, "query")?;
+            let operator = body
+                .get(field)
+                .and_then(|v| v.get("operator"))
+                .and_then(|v| v.as_str())
+                .unwrap_or("or");
+            let op = if operator.eq_ignore_ascii_case("and") {
+                " AND "
+            } else {
+                " OR "
+            };
+            let column = column(field);
+            let json::Value::String(text) = value else {
+                return Ok(Some(format!("{column} = {}", literal(value)?)));
+            };
+            Ok(join(
+                text.split_whitespace()
+                    .map(|word| text_match(&column, word))
+                    .collect(),
+                op,
+            ))
+        }
+        "match_phrase" => {
+            let (field, value) = field_value(body, "query")?;
+            let column = column(field);
+            match value {
+                json::Value::String(text) => Ok(Some(text_match(&column, text))),
+                _ => Ok(Some(format!("{column} = {}", literal(value)?))),
+            }
+        }
+        "range" => range_condition(body, now, time_range),
+        "query_string" => {
+            let query = body
+                .get("query")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("[query_string] requires a query"))?;
+            let default_field = body
+                .get("default_field")
+                .and_then(|v| v.as_str())
+                .filter(|v| *v != "*");
+            let default_and = body
+                .get("default_operator")
+                .and_then(|v| v.as_str())
+                .map_or(false, |v| v.eq_ignore_ascii_case("and"));
+            QueryString::new(query, default_field, default_and, now).parse()
+        }
+        "wildcard" => {
+            let (field, value) = field_value(body, "value")?;
+            let value = match value {
+                json::Value::String(v) => v.as_str(),
+                _ => body
+                    .get(field)
+                    .and_then(|v| v.get("wildcard"))
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("[wildcard] query requires a value"))?,
+            };
+            Ok(Some(like(&column(field), value)))
+        }
+        "prefix" => {
+            let (field, value) = field_value(body, "value")?;
+            let value = value
+                .as_str()
+                .ok_or_else(|| anyhow::anyhow!("[prefix] query requires a string value"))?;
+            Ok(Some(format!(
+                "{} LIKE {}",
+                column(field),
+                quote(&format!("{value}%"))
+            )))
+        }
+        "exists" => {
+            let field = body
+                .get("field")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("[exists] query requires a field"))?;
+            Ok(Some(format!("{} IS NOT NULL", column(field))))
+        }
+        _ => Err(anyhow::anyhow!("unsupported query [{kind}]")),
+    }
+}
+
+/// The clauses of `must` and `filter` are required, a `should` clause is
+/// required only when there are none of them, as the scores are not computed.
+fn bool_condition(
+    body: &json::Value,
+    now: i64,
+    mut time_range: Option<&mut TimeRange>,
+) -> Result<Option<String>, anyhow::Error> {
+    let clauses = |name: &str| -> Vec<&json::Value> {
+        match body.get(name) {
+            Some(json::Value::Array(v)) => v.iter().collect(),
+            Some(v) => vec![v],
+            None => vec![],
+        }
+    };
+    let mut conditions = Vec::new();
+    let must = [clauses("must"), clauses("filter")].concat();
+    for query in must.iter() {
+        if let Some(cond) = condition(query, now, time_range.as_deref_mut())? {
+            conditions.push(cond);
+        }
+    }
+
+    let should = clauses("should");
+    let minimum_should_match = match body.get("minimum_should_match") {
+        Some(json::Value::Number(v)) => v.as_u64().unwrap_or_default(),
+        Some(json::Value::String(v)) => v.parse().map_err(|_| {
+            anyhow::anyhow!("unsupported [minimum_should_match] {v}, only numbers are supported")
+        })?,
+        _ => u64::from(must.is_empty()),
+    };
+    if !should.is_empty() && minimum_should_match > 1 {
+        return Err(anyhow::anyhow!(
+            "unsupported [minimum_should_match] greater than 1"
+        ));
+    }
+    if !should.is_empty() && minimum_should_match == 1 {
+        let mut any = Vec::with_capacity(should.len());
+        for query in should {
+            match condition(query, now, None)? {
+                Some(cond) => any.push(cond),
+                // a clause matching all the records
+                None => {
+                    any.clear();
+                    break;
+                }
+            }
+        }
+        conditions.extend(join(any, " OR "));
+    }
+
+    for query in clauses("must_not") {
+        match condition(query, now, None)? {
+            Some(cond) => conditions.push(format!("NOT ({cond})")),
+            None => conditions.push("1 = 0".to_string()),
+        }
+    }
+    Ok(join(conditions, " AND "))
+}
+
+fn range_condition(
+    body: &json::Value,
+    now: i64,
+    time_range: Option<&mut TimeRange>,
+) -> Result<Option<String>, anyhow::Error> {
+    let (field, bounds) = body
+        .as_object()
+        .and_then(|body| body.iter().next())
+        .ok_or_else(|| anyhow::anyhow!("[range] query requires a field"))?;
+    let column = column(field);
+    let is_timestamp = column == format!("\"{}\"", CONFIG.common.column_timestamp);
+    let ops = [("gte", ">="), ("gt", ">"), ("lte", "<="), ("lt", "<")];
+    let mut conditions = Vec::new();
+    let mut range = (0, 0);
+    for (name, op) in ops {
+        let Some(value) = bounds.get(name).filter(|v| !v.is_null()) else {
+            continue;
+        };
+        if !is_timestamp {
+            conditions.push(format!("{column} {op} {}", literal(value)?));
+            continue;
+        }
+        let time = date_micros(value, now)?;
+        match name {
+            "gte" => range.0 = time,
+            "gt" => range.0 = time + 1,
+            "lte" => range.1 = time + 1,
+            _ => range.1 = time,
+        }
+    }
+    if !is_timestamp {
+        return Ok(join(conditions, " AND "));
+    }
+    match time_range {
+        Some(time_range) => {
+            if range.0 > 0 {
+                time_range.0 = time_range.0.max(range.0);
+            }
+            if range.1 > 0 {
+                time_range.1 = if time_range.1 > 0 {
+                    time_range.1.min(range.1)
+                } else {
+                    range.1
+                };
+            }
+            Ok(None)
+        }
+        None => {
+            if range.0 > 0 {
+                conditions.push(format!("{column} >= {}", range.0));
+            }
+            if range.1 > 0 {
+                conditions.push(format!("{column} < {}", range.1));
+            }
+            Ok(join(conditions, " AND "))
+        }
+    }
+}
+
+/// Parses a date of a range query: epoch seconds, milliseconds or
+/// microseconds, an RFC 3339 date, or the date math from `now`, such as
+/// `now-15m` or `now-1d/d`, whose rounding is ignored.
+pub fn date_micros(value: &json::Value, now: i64) -> Result<i64, anyhow::Error> {
+    match value {
+        json::Value::Number(v) => Ok(parse_i64_to_timestamp_micros(
+            v.as_i64()
+                .ok_or_else(|| anyhow::anyhow!("invalid date {v}"))?,
+        )),
+        json::Value::String(v) if v.starts_with("now") => {
+            let math = v["now".len()..].split('/').next().unwrap_or_default();
+            if math.is_empty() {
+                return Ok(now);
+            }
+            let (sign, math) = match (math.strip_prefix('-'), math.strip_prefix('+')) {
+                (Some(math), _) => (-1, math),
+                (_, Some(math)) => (1, math),
+                _ => return Err(anyhow::anyhow!("invalid date math {v}")),
+            };
+            let split = math
+                .find(|c: char| !c.is_ascii_digit())
+                .ok_or_else(|| anyhow::anyhow!("invalid date math {v}"))?;
+            let (amount, unit) = math.split_at(split);
+            let amount = amount
+                .parse::<i64>()
+                .map_err(|_| anyhow::anyhow!("invalid date math {v}"))?;
+            let seconds = match unit {
+                "s" => 1,
+                "m" => 60,
+                "h" | "H" => 3600,
+                "d" => 86400,
+                "w" => 7 * 86400,
+                "M" => 30 * 86400,
+                "y" => 365 * 86400,
+                _ => return Err(anyhow::anyhow!("invalid date math {v}")),
+            };
+            amount
+                .checked_mul(seconds * 1_000_000)
+                .and_then(|micros| now.checked_add(sign * micros))
+                .ok_or_else(|| anyhow::anyhow!("date math {v} is out of range"))
+        }
+        json::Value::String(v) => parse_str_to_timestamp_micros(v),
+        _ => Err(anyhow::anyhow!("invalid date {value}")),
+    }
+}
+
+/// The Lucene syntax of `query_string`: terms, `field:value`, quoted phrases,
+/// `*` and `?` wildcards, `[from TO to]` ranges, `AND`, `OR`, `NOT`, `+`, `-`
+/// and parentheses. The adjacent terms are joined by the default operator.
+struct QueryString<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    default_field: Option<&'a str>,
+    default_and: bool,
+    now: i64,
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl<'a> QueryString<'a> {
+    fn new(query: &str, default_field: Option<&'a str>, default_and: bool, now: i64) -> Self {
+        Self {
+            chars: query.chars().collect(),
+            pos: 0,
+            default_field,
+            default_and,
+            now,
+        }
+    }
+
+    fn parse(mut self) -> Result<Option<String>, anyhow::Error> {
+        let cond = self.or_expr()?;
+        self.skip_whitespace();
+        if self.pos < self.chars.len() {
+            return Err(anyhow::anyhow!(
+                "[query_string] unexpected input at {}",
+                self.pos
+            ));
+        }
+        Ok(cond)
+    }
+
+    fn or_expr(&mut self) -> Result<Option<String>, anyhow::Error> {
+        let mut any = vec![self.and_expr()?];
+        loop {
+            match self.peek_token() {
+                Some(Token::Or) => {
+                    self.next_token();
+                }
+                Some(Token::Close) | Some(Token::And) => break,
+                None if self.at_end() => break,
+                // the adjacent terms
+                _ if !self.default_and => {}
+                _ => break,
+            }
+            any.push(self.and_expr()?);
+        }
+        // a term matching all the records
+        if any.iter().any(|cond| cond.is_none()) {
+            return Ok(None);
+        }
+        Ok(join(any.into_iter().flatten().collect(), " OR "))
+    }
+
+    fn and_expr(&mut self) -> Result<Option<String>, anyhow::Error> {
+        let mut all = vec![self.unary()?];
+        loop {
+            match self.peek_token() {
+                Some(Token::And) => {
+                    self.next_token();
+                }
+                Some(Token::Close) | Some(Token::Or) => break,
+                None if self.at_end() => break,
+                _ if self.default_and => {}
+                _ => break,
+            }
+            all.push(self.unary()?);
+        }
+        Ok(join(all.into_iter().flatten().collect(), " AND "))
+    }
+
+    fn unary(&mut self) -> Result<Option<String>, anyhow::Error> {
+        match self.peek_token() {
+            Some(Token::Not) => {
+                self.next_token();
+                Ok(Some(match self.unary()? {
+                    Some(cond) => format!("NOT ({cond})"),
+                    None => "1 = 0".to_string(),
+                }))
+            }
+            Some(Token::Open) => {
+                self.next_token();
+                let cond = self.or_expr()?;
+                if self.next_token() != Some(Token::Close) {
+                    return Err(anyhow::anyhow!(
+                        "[query_string] missing closing parenthesis"
+                    ));
+                }
+                Ok(cond)
+            }
+            Some(token) => Err(anyhow::anyhow!("[query_string] unexpected {token:?}")),
+            None if self.at_end() => Err(anyhow::anyhow!("[query_string] unexpected end")),
+            None => self.term(),
+        }
+    }
+
+    fn term(&mut self) -> Result<Option<String>, anyhow::Error> {
+        if self.chars[self.pos] == '+' {
+            self.pos += 1; // required, as the terms joined by AND
+        }
+        let (field, value) = if self.chars.get(self.pos) == Some(&'"') {
+            (None, self.phrase()?)
+        } else {
+            let word = self.word();
+            match split_field(&word) {
+                Some((field, rest)) if rest.is_empty() => {
+                    let value = match self.chars.get(self.pos) {
+                        Some('"') => self.phrase()?,
+                        Some('[') | Some('{') => return self.range(field),
+                        _ => {
+                            return Err(anyhow::anyhow!("[query_string] missing value of {field}"));
+                        }
+                    };
+                    (Some(field), value)
+                }
+                Some((field, rest)) => (Some(field), Term::Word(rest)),
+                None => (None, Term::Word(unescape(&word))),
+            }
+        };
+        let field = field.or_else(|| self.default_field.map(|v| v.to_string()));
+        Ok(query_string_term(field.as_deref(), value))
+    }
+
+    fn phrase(&mut self) -> Result<Term, anyhow::Error> {
+        self.pos += 1; // opening quote
+        let mut phrase = String::new();
+        while let Some(c) = self.chars.get(self.pos).copied() {
+            self.pos += 1;
+            match c {
+                '\\' => {
+                    if let Some(c) = self.chars.get(self.pos) {
+                        phrase.push(*c);
+                        self.pos += 1;
+                    }
+                }
+                '"' => return Ok(Term::Phrase(phrase)),
+                c => phrase.push(c),
+            }
+        }
+        Err(anyhow::anyhow!("[query_string] missing closing quote"))
+    }
+
+    fn range(&mut self, field: String) -> Result<Option<String>, anyhow::Error> {
+        let inclusive_start = self.chars[self.pos] == '[';
+        self.pos += 1;
+        let end = self.chars[self.pos..]
+            .iter()
+            .position(|c| *c == ']' || *c == '}')
+            .ok_or_else(|| anyhow::anyhow!("[query_string] missing end of range"))?;
+        let inclusive_end = self.chars[self.pos + end] == ']';
+        let bounds = self.chars[self.pos..self.pos + end]
+            .iter()
+            .collect::<String>();
+        self.pos += end + 1;
+        let (from, to) = bounds
+            .split_once(" TO ")
+            .ok_or_else(|| anyhow::anyhow!("[query_string] invalid range {bounds}"))?;
+        let bound = |v: &str| match v.trim() {
+            "*" => json::Value::Null,
+            v => v
+                .parse::<i64>()
+                .map(json::Value::from)
+                .unwrap_or_else(|_| json::Value::from(v)),
+        };
+        let mut bounds = json::Map::new();
+        let (gt, lt) = (
+            if inclusive_start { "gte" } else { "gt" },
+            if inclusive_end { "lte" } else { "lt" },
+        );
+        bounds.insert(gt.to_string(), bound(from));
+        bounds.insert(lt.to_string(), bound(to));
+        let body = json::json!({ field: bounds });
+        range_condition(&body, self.now, None)
+    }
+
+    /// A word, up to a whitespace or a parenthesis which are not escaped.
+    fn word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(c) = self.chars.get(self.pos).copied() {
+            match c {
+                '\\' => {
+                    word.push(c);
+                    if let Some(c) = self.chars.get(self.pos + 1) {
+                        word.push(*c);
+                        self.pos += 1;
+                    }
+                }
+                c if c.is_whitespace() || c == '(' || c == ')' => break,
+                // the value of the field starts with a phrase or a range
+                '"' | '[' | '{' if word.ends_with(':') => break,
+                c => word.push(c),
+            }
+            self.pos += 1;
+        }
+        word
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .chars
+            .get(self.pos)
+            .map_or(false, |c| c.is_whitespace())
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_whitespace();
+        self.pos >= self.chars.len()
+    }
+
+    /// Returns the next operator or parenthesis without consuming it, `None`
+    /// for a term or at the end.
+    fn peek_token(&mut self) -> Option<Token> {
+        let pos = self.pos;
+        let token = self.next_token();
+        self.pos = pos;
+        token
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        self.skip_whitespace();
+        let rest = &self.chars[self.pos..];
+        let (token, len) = match rest {
+            ['(', ..] => (Token::Open, 1),
+            [')', ..] => (Token::Close, 1),
+            ['&', '&', ..] => (Token::And, 2),
+            ['|', '|', ..] => (Token::Or, 2),
+            ['!', ..] | ['-', ..] => (Token::Not, 1),
+            ['A', 'N', 'D', c, ..] if c.is_whitespace() || *c == '(' => (Token::And, 3),
+            ['O', 'R', c, ..] if c.is_whitespace() || *c == '(' => (Token::Or, 2),
+            ['N', 'O', 'T', c, ..] if c.is_whitespace() || *c == '(' => (Token::Not, 3),
+            _ => return None,
+        };
+        self.pos += len;
+        Some(token)
+    }
+}
+
+enum Term {
+    Word(String),
+    Phrase(String),
+}
+
+/// Splits `field:value` at the first colon which is not escaped.
+fn split_field(word: &str) -> Option<(String, String)> {
+    let mut escaped = false;
+    for (i, c) in word.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            ':' if !escaped && i > 0 => {
+                return Some((unescape(&word[..i]), word[i + 1..].to_string()));
+            }
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+fn unescape(word: &str) -> String {
+    let mut ret = String::with_capacity(word.len());
+    let mut escaped = false;
+    for c in word.chars() {
+        if c == '\\' && !escaped {
+            escaped = true;
+            continue;
+        }
+        escaped = false;
+        ret.push(c);
+    }
+    ret
+}
+
+fn query_string_term(field: Option<&str>, term: Term) -> Option<String> {
+    let (value, phrase) = match term {
+        Term::Word(v) => (v, false),
+        Term::Phrase(v) => (v, true),
+    };
+    match field {
+        Some("_exists_") => Some(format!("{} IS NOT NULL", column(&value))),
+        Some(field) => {
+            let column = column(field);
+            if !phrase && value == "*" {
+                Some(format!("{column} IS NOT NULL"))
+            } else if !phrase && value.contains(['*', '?']) {
+                Some(like(&column, &unescape(&value)))
+            } else if !phrase && value.parse::<f64>().is_ok_and(f64::is_finite) {
+                Some(format!("{column} = {value}"))
+            } else {
+                Some(text_match(&column, &unescape(&value)))
+            }
+        }
+        None if !phrase && value.trim_matches('*').is_empty() => None,
+        // full text search in the full text search fields
+        None => {
+            let value = if phrase {
+                value
+            } else {
+                value.trim_matches('*').to_string()
+            };
+            Some(format!(
+                "match_all_ignore_case('{}')",
+                value.replace('\'', "")
+            ))
+        }
+    }
+}
+
+/// Returns the quoted column of the field, the nested fields are flattened as
+/// at the ingestion and the `.keyword` sub-fields are the fields themselves.
+pub fn column(field: &str) -> String {
+    let field = field.strip_suffix(".keyword").unwrap_or(field);
+    let name = match field {
+        "@timestamp" | "_timestamp" => CONFIG.common.column_timestamp.clone(),
+        field => format_key(field),
+    };
+    format!("\"{name}\"")
+}
+
+fn text_match(column: &str, text: &str) -> String {
+    format!("str_match_ignore_case({column}, {})", quote(text))
+}
+
+fn like(column: &str, pattern: &str) -> String {
+    let pattern = pattern.replace('*', "%").replace('?', "_");
+    format!("{column} LIKE {}", quote(&pattern))
+}
+
+fn quote(v: &str) -> String {
+    format!("'{}'", v.replace('\'', "''"))
+}
+
+fn literal(value: &json::Value) -> Result<String, anyhow::Error> {
+    match value {
+        json::Value::String(v) => Ok(quote(v)),
+        json::Value::Number(v) => Ok(v.to_string()),
+        json::Value::Bool(v) => Ok(v.to_string()),
+        _ => Err(anyhow::anyhow!("unsupported value {value}")),
+    }
+}
+
+/// Returns the single `key: value` entry of the object.
+fn single_entry(query: &json::Value) -> Result<(&String, &json::Value), anyhow::Error> {
+    match query.as_object() {
+        Some(query) if query.len() == 1 => Ok(query.iter().next().unwrap()),
+        _ => Err(anyhow::anyhow!(
+            "a query should be an object with a single query type, got {query}"
+        )),
+    }
+}
+
+/// Returns the field and the value of `{"field": value}` or of
+/// `{"field": {"key": value}}`.
+fn field_value<'a>(
+    body: &'a json::Value,
+    key: &str,
+) -> Result<(&'a String, &'a json::Value), anyhow::Error> {
+    let (field, value) = body
+        .as_object()
+        .and_then(|body| body.iter().next())
+        .ok_or_else(|| anyhow::anyhow!("the query requires a field"))?;
+    match value {
+        json::Value::Object(options) => Ok((field, options.get(key).unwrap_or(value))),
+        value => Ok((field, value)),
+    }
+}
+
+fn join(conditions: Vec<String>, op: &str) -> Option<String> {
+    match conditions.len() {
+        0 => None,
+        1 => conditions.into_iter().next(),
+        _ => Some(
+            conditions
+                .iter()
+                .map(|cond| format!("({cond})"))
+                .collect::<Vec<_>>()
+                .join(op),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000_000_000;
+
+    fn sql(query: json::Value) -> (Option<String>, TimeRange) {
+        let mut time_range = (0, 0);
+        let cond = translate(&query, NOW, &mut time_range).unwrap();
+        (cond, time_range)
+    }
+
+    #[test]
+    fn test_translate_bool() {
+        let (cond, time_range) = sql(json::json!({
+            "bool": {
+                "must": [{ "match": { "message": { "query": "disk full", "operator": "and" } } }],
+                "filter": [
+                    { "term": { "kubernetes.namespace.keyword": "prod" } },
+                    { "range": { "@timestamp": { "gte": "now-15m", "lte": 1_700_000_000_000i64, "format": "epoch_millis" } } },
+                ],
+                "should": [{ "terms": { "level": ["error", "warn"] } }],
+                "must_not": { "exists": { "field": "trace_id" } },
+            }
+        }));
+        assert_eq!(
+            cond.unwrap(),
+            "((str_match_ignore_case(\"message\", 'disk')) AND (str_match_ignore_case(\"message\", 'full'))) AND (\"kubernetes_namespace\" = 'prod') AND (NOT (\"trace_id\" IS NOT NULL))"
+        );
+        assert_eq!(time_range, (NOW - 15 * 60 * 1_000_000, NOW + 1));
+
+        // without required clauses, a should clause is required
+        let (cond, _) = sql(json::json!({
+            "bool": { "should": [{ "term": { "code": 500 } }, { "wildcard": { "path": "/api/*" } }] }
+        }));
+        assert_eq!(
+            cond.unwrap(),
+            "(\"code\" = 500) OR (\"path\" LIKE '/api/%')"
+        );
+        // a range in a should clause is a condition
+        let (cond, time_range) = sql(json::json!({
+            "bool": { "should": [{ "range": { "_timestamp": { "gt": NOW } } }] }
+        }));
+        assert_eq!(cond.unwrap(), format!("\"_timestamp\" >= {}", NOW + 1));
+        assert_eq!(time_range, (0, 0));
+        assert_eq!(sql(json::json!({ "match_all": {} })).0, None);
+        assert!(translate(&json::json!({ "fuzzy": {} }), NOW, &mut (0, 0)).is_err());
+    }
+
+    #[test]
+    fn test_translate_query_string() {
+        let query_string = |query: &str| {
+            sql(json::json!({ "query_string": { "query": query, "analyze_wildcard": true } })).0
+        };
+        assert_eq!(query_string("*"), None);
+        assert_eq!(
+            query_string("level:error AND NOT service:\"cart api\""),
+            Some(
+                "(str_match_ignore_case(\"level\", 'error')) AND (NOT (str_match_ignore_case(\"service\", 'cart api')))"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            query_string("timeout (status:500 || status:[502 TO 504})"),
+            Some(
+                "(match_all_ignore_case('timeout')) OR ((\"status\" = 500) OR ((\"status\" >= 502) AND (\"status\" < 504)))"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            query_string("ratio:inf OR ratio:NaN"),
+            Some(
+                "(str_match_ignore_case(\"ratio\", 'inf')) OR (str_match_ignore_case(\"ratio\", 'NaN'))"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            query_string("host:web-* -_exists_:error"),
+            Some("(\"host\" LIKE 'web-%') OR (NOT (\"error\" IS NOT NULL))".to_string())
+        );
+        let cond = sql(json::json!({
+            "query_string": { "query": "a b", "default_field": "msg", "default_operator": "AND" }
+        }))
+        .0;
+        assert_eq!(
+            cond.unwrap(),
+            "(str_match_ignore_case(\"msg\", 'a')) AND (str_match_ignore_case(\"msg\", 'b'))"
+        );
+        assert!(
+            translate(
+                &json::json!({ "query_string": { "query": "(a OR b" } }),
+                NOW,
+                &mut (0, 0)
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_date_micros() {
+        assert_eq!(date_micros(&json::json!("now"), NOW).unwrap(), NOW);
+        assert_eq!(
+            date_micros(&json::json!("now-1h/h"), NOW).unwrap(),
+            NOW - 3_600_000_000
+        );
+        assert_eq!(
+            date_micros(&json::json!(1_700_000_000), NOW).unwrap(),
+            1_700_000_000_000_000
+        );
+        assert_eq!(
+            date_micros(&json::json!("2023-11-14T22:13:20Z"), NOW).unwrap(),
+            1_700_000_000_000_000
+        );
+        assert!(date_micros(&json::json!("now-1x"), NOW).is_err());
+        assert!(date_micros(&json::json!("now-9999999999999M"), NOW).is_err());
+        assert!(date_micros(&json::json!("now+9223372036854775807s"), NOW).is_err());
+    }
+}
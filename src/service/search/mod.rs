@@ -44,6 +44,7 @@ use crate::{
 };
 
 pub(crate) mod datafusion;
+pub mod es;
 pub(crate) mod grpc;
 pub(crate) mod sql;
 
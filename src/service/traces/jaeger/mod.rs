@@ -0,0 +1,798 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Jaeger compatible APIs: the ingestion of the Thrift batches sent over HTTP,
+//! and the query API read by the Jaeger UI and the Grafana Jaeger datasource.
+
+use std::{collections::HashMap, io::Error};
+
+use actix_web::{web, HttpResponse};
+use ahash::AHashMap;
+use chrono::{Duration, Utc};
+
+use super::{
+    search::{self as trace_search, get_i64, get_str, TRACES_STREAM},
+    PARENT_SPAN_ID, PARENT_TRACE_ID, REF_TYPE, SERVICE, SERVICE_NAME, UNKNOWN_SERVICE,
+};
+use crate::{
+    common::{
+        infra::{config::CONFIG, errors},
+        meta::{
+            http::HttpResponse as MetaHttpResponse,
+            search,
+            traces::{
+                Event, JaegerKeyValue, JaegerLog, JaegerProcess, JaegerReference, JaegerSpan,
+                JaegerTrace, Span, SpanRefType, TraceSearchRequest,
+            },
+            StreamType,
+        },
+        utils::json,
+    },
+    service::{db, search as search_service},
+};
+
+mod thrift;
+
+/// The stream the distinct service and operation names are written to.
+const DISTINCT_VALUES_STREAM: &str = "distinct_values";
+
+/// The services and operations are listed from the spans of this period, in
+/// days, as the Jaeger query API has no time range for them.
+const NAMES_LOOKBACK_DAYS: i64 = 7;
+
+/// Maximum number of service or operation names listed.
+const MAX_NAMES: usize = 10000;
+
+/// Number of traces found by default, as the Jaeger UI does.
+const DEFAULT_LIMIT: usize = 20;
+
+/// The columns of the span records which are not tags of the Jaeger spans.
+const SPAN_COLUMNS: [&str; 14] = [
+    "trace_id",
+    "span_id",
+    "flags",
+    "span_status",
+    "span_kind",
+    "operation_name",
+    "start_time",
+    "end_time",
+    "duration",
+    "reference_parent_span_id",
+    "reference_parent_trace_id",
+    "reference_ref_type",
+    "service_name",
+    "events",
+];
+
+pub async fn ingest(
+    org_id: &str,
+    thread_id: usize,
+    body: web::Bytes,
+    in_stream_name: Option<&str>,
+) -> Result<HttpResponse, Error> {
+    let batch = match thrift::decode_batch(&body) {
+        Ok(v) => v,
+        Err(e) => {
+            return Ok(MetaHttpResponse::bad_request(format!(
+                "Invalid Jaeger batch: {e}"
+            )));
+        }
+    };
+    super::ingest_spans(
+        org_id,
+        thread_id,
+        to_spans(batch),
+        in_stream_name,
+        "/api/org/jaeger/api/traces",
+    )
+    .await
+}
+
+/// Converts the Jaeger spans into the span records of the OTLP ingestion.
+fn to_spans(batch: thrift::Batch) -> Vec<Span> {
+    let service_name = if batch.process.service_name.is_empty() {
+        UNKNOWN_SERVICE.to_string()
+    } else {
+        batch.process.service_name
+    };
+    let mut service = AHashMap::new();
+    service.insert(
+        SERVICE_NAME.to_string(),
+        json::Value::from(service_name.clone()),
+    );
+    for tag in batch.process.tags {
+        service.insert(format!("{SERVICE}.{}", tag.key), tag_value(tag.value));
+    }
+
+    batch
+        .spans
+        .into_iter()
+        .map(|span| {
+            let trace_id = format!(
+                "{:016x}{:016x}",
+                span.trace_id_high as u64, span.trace_id_low as u64
+            );
+
+            // the span has a single parent in the OTLP model
+            let parent = span
+                .references
+                .iter()
+                .find(|r| r.ref_type == thrift::REF_TYPE_CHILD_OF)
+                .or_else(|| span.references.first())
+                .map(|r| {
+                    let ref_type = if r.ref_type == thrift::REF_TYPE_FOLLOWS_FROM {
+                        SpanRefType::FollowsFrom
+                    } else {
+                        SpanRefType::ChildOf
+                    };
+                    let trace_id = format!(
+                        "{:016x}{:016x}",
+                        r.trace_id_high as u64, r.trace_id_low as u64
+                    );
+                    (trace_id, r.span_id, ref_type)
+                })
+                .or_else(|| {
+                    (span.parent_span_id != 0)
+                        .then(|| (trace_id.clone(), span.parent_span_id, SpanRefType::ChildOf))
+                });
+            let mut reference = AHashMap::new();
+            if let Some((parent_trace_id, parent_span_id, ref_type)) = parent {
+                reference.insert(PARENT_TRACE_ID.to_string(), parent_trace_id);
+                reference.insert(
+                    PARENT_SPAN_ID.to_string(),
+                    format!("{:016x}", parent_span_id as u64),
+                );
+                reference.insert(REF_TYPE.to_string(), format!("{ref_type:?}"));
+            }
+
+            // the span kind and status are tags in the Jaeger model
+            let mut span_kind = "0";
+            let mut span_status = "UNSET";
+            let mut attributes = AHashMap::new();
+            for tag in span.tags {
+                let value = tag_value(tag.value);
+                match (tag.key.as_str(), value.as_str()) {
+                    ("span.kind", Some(kind)) => {
+                        span_kind = match kind {
+                            "internal" => "1",
+                            "server" => "2",
+                            "client" => "3",
+                            "producer" => "4",
+                            "consumer" => "5",
+                            _ => "0",
+                        };
+                    }
+                    ("otel.status_code", Some("ERROR")) => span_status = "ERROR",
+                    ("otel.status_code", Some("OK")) if span_status != "ERROR" => {
+                        span_status = "OK"
+                    }
+                    ("error", _) if value == json::Value::Bool(true) || value == "true" => {
+                        span_status = "ERROR"
+                    }
+                    _ => {
+                        attributes.insert(tag.key, value);
+                    }
+                }
+            }
+
+            let events = span
+                .logs
+                .into_iter()
+                .map(|log| {
+                    let mut name = "log".to_string();
+                    let mut attributes = AHashMap::new();
+                    for field in log.fields {
+                        match (field.key.as_str(), field.value) {
+                            ("event", thrift::TagValue::String(v)) => name = v,
+                            (_, value) => {
+                                attributes.insert(field.key, tag_value(value));
+                            }
+                        }
+                    }
+                    Event {
+                        name,
+                        _timestamp: log.timestamp.max(0) as u64 * 1000,
+                        attributes,
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            let start_time = span.start_time.max(0) as u64;
+            let duration = span.duration.max(0) as u64;
+            Span {
+                trace_id,
+                span_id: format!("{:016x}", span.span_id as u64),
+                flags: span.flags as u8,
+                span_status: span_status.to_string(),
+                span_kind: span_kind.to_string(),
+                operation_name: span.operation_name,
+                start_time: start_time * 1000,
+                end_time: (start_time + duration) * 1000,
+                duration,
+                reference,
+                service_name: service_name.clone(),
+                attributes,
+                service: service.clone(),
+                events: json::to_string(&events).unwrap(),
+            }
+        })
+        .collect()
+}
+
+fn tag_value(value: thrift::TagValue) -> json::Value {
+    match value {
+        thrift::TagValue::String(v) => json::Value::from(v),
+        thrift::TagValue::Double(v) => json::Value::from(v),
+        thrift::TagValue::Bool(v) => json::Value::from(v),
+        thrift::TagValue::Long(v) => json::Value::from(v),
+        thrift::TagValue::Binary(v) => json::Value::from(hex::encode(v)),
+    }
+}
+
+/// Lists the names of the services which sent spans recently.
+pub async fn get_services(org_id: &str) -> errors::Result<Vec<String>> {
+    get_distinct_values(org_id, "service_name", None).await
+}
+
+/// Lists the names of the operations of the service.
+pub async fn get_operations(org_id: &str, service_name: &str) -> errors::Result<Vec<String>> {
+    get_distinct_values(
+        org_id,
+        "operation_name",
+        Some(("service_name", service_name)),
+    )
+    .await
+}
+
+async fn get_distinct_values(
+    org_id: &str,
+    field: &str,
+    filter: Option<(&str, &str)>,
+) -> errors::Result<Vec<String>> {
+    let schema = db::schema::get(org_id, DISTINCT_VALUES_STREAM, StreamType::Metadata)
+        .await
+        // `db::schema::get` never fails, so it's safe to unwrap
+        .unwrap();
+    if schema.fields().is_empty() {
+        return Ok(vec![]);
+    }
+    let mut sql = format!(
+        "SELECT field_value AS zo_sql_key, SUM(count) AS zo_sql_num FROM {DISTINCT_VALUES_STREAM} WHERE stream_type='{}' AND stream_name='{TRACES_STREAM}' AND field_name='{field}'",
+        StreamType::Traces,
+    );
+    if let Some((name, value)) = filter {
+        sql = format!(
+            "{sql} AND filter_name='{name}' AND filter_value='{}'",
+            trace_search::escape_sql_string(value)
+        );
+    }
+    let end_time = Utc::now().timestamp_micros();
+    let req = search::Request {
+        query: search::Query {
+            sql: format!("{sql} GROUP BY zo_sql_key ORDER BY zo_sql_key ASC"),
+            from: 0,
+            size: MAX_NAMES,
+            start_time: end_time
+                - Duration::days(NAMES_LOOKBACK_DAYS)
+                    .num_microseconds()
+                    .unwrap(),
+            end_time,
+            sql_mode: "full".to_string(),
+            ..Default::default()
+        },
+        aggs: HashMap::new(),
+        encoding: search::RequestEncoding::Empty,
+        timeout: 0,
+    };
+    let resp = search_service::search("", org_id, StreamType::Metadata, &req)
+        .await
+        .map_err(|e| {
+            log::error!("search jaeger {field} values error: {e}");
+            e
+        })?;
+    Ok(resp
+        .hits
+        .iter()
+        .filter_map(|hit| Some(hit.get("zo_sql_key")?.as_str()?.to_string()))
+        .collect())
+}
+
+/// Loads the trace, `None` when the trace id is invalid or no span was found.
+pub async fn get_trace(
+    org_id: &str,
+    trace_id: &str,
+    start_time: i64,
+    end_time: i64,
+) -> errors::Result<Option<JaegerTrace>> {
+    // the Jaeger UI trims the leading zeros of the ids
+    let Some(trace_id) = super::normalize_id(trace_id, 32) else {
+        return Ok(None);
+    };
+    let schema = db::schema::get(org_id, TRACES_STREAM, StreamType::Traces)
+        .await
+        // `db::schema::get` never fails, so it's safe to unwrap
+        .unwrap();
+    if schema.fields().is_empty() {
+        return Ok(None);
+    }
+    let spans = trace_search::get_spans(org_id, &[trace_id.clone()], start_time, end_time).await?;
+    if spans.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(to_jaeger_trace(&trace_id, &spans)))
+}
+
+/// Finds the traces having a span matching the request, most recent first.
+pub async fn find_traces(
+    org_id: &str,
+    req: &TraceSearchRequest,
+) -> errors::Result<Vec<JaegerTrace>> {
+    let trace_ids = trace_search::find_trace_ids(org_id, req).await?;
+    if trace_ids.is_empty() {
+        return Ok(vec![]);
+    }
+    let spans = trace_search::get_spans(org_id, &trace_ids, req.start_time, req.end_time).await?;
+    let mut trace_spans = trace_search::group_by_trace(spans);
+    Ok(trace_ids
+        .into_iter()
+        .filter_map(|trace_id| {
+            let spans = trace_spans.remove(&trace_id)?;
+            Some(to_jaeger_trace(&trace_id, &spans))
+        })
+        .collect())
+}
+
+/// Builds the trace search from the parameters of the Jaeger `/api/traces`
+/// endpoint. The times are in microseconds.
+pub fn search_request(params: &[(String, String)]) -> Result<TraceSearchRequest, String> {
+    let get = |key: &str| {
+        params
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+            .filter(|v| !v.is_empty())
+    };
+    let mut req = TraceSearchRequest {
+        service_name: get("service").map(|v| v.to_string()),
+        operation_name: get("operation").map(|v| v.to_string()),
+        size: match get("limit") {
+            Some(v) => v.parse().map_err(|_| "invalid limit".to_string())?,
+            None => DEFAULT_LIMIT,
+        },
+        ..Default::default()
+    };
+
+    let mut tags = Vec::new();
+    if let Some(v) = get("tags") {
+        let v: HashMap<String, json::Value> =
+            json::from_str(v).map_err(|_| "invalid tags".to_string())?;
+        tags.extend(v.into_iter().map(|(k, v)| match v {
+            json::Value::String(v) => (k, v),
+            v => (k, v.to_string()),
+        }));
+    }
+    for (_, v) in params.iter().filter(|(k, _)| k == "tag") {
+        let (k, v) = v.split_once(':').ok_or_else(|| "invalid tag".to_string())?;
+        tags.push((k.to_string(), v.to_string()));
+    }
+    for (key, value) in tags {
+        match key.as_str() {
+            // the error tag is the span status
+            "error" => {
+                req.span_status = Some(if value == "true" { "ERROR" } else { "OK" }.to_string())
+            }
+            _ => req.attributes.push((key, value)),
+        }
+    }
+
+    for (key, duration) in [
+        ("minDuration", &mut req.min_duration),
+        ("maxDuration", &mut req.max_duration),
+    ] {
+        if let Some(v) = get(key) {
+            *duration = Some(parse_duration(v).ok_or_else(|| format!("invalid {key}"))?);
+        }
+    }
+
+    req.end_time = match get("end") {
+        Some(v) => v.parse().map_err(|_| "invalid end".to_string())?,
+        None => Utc::now().timestamp_micros(),
+    };
+    req.start_time = match get("start") {
+        Some(v) => v.parse().map_err(|_| "invalid start".to_string())?,
+        None => {
+            let lookback = get("lookback")
+                .and_then(parse_duration)
+                .unwrap_or(3600 * 1_000_000);
+            req.end_time - lookback
+        }
+    };
+    Ok(req)
+}
+
+/// Parses a duration of the Go format, eg: `1h30m`, `1.5s` or `300ms`, into
+/// microseconds.
+fn parse_duration(s: &str) -> Option<i64> {
+    let mut total = 0.0;
+    let mut rest = s.trim();
+    if rest.is_empty() {
+        return None;
+    }
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let number: f64 = rest[..number_len].parse().ok()?;
+        rest = &rest[number_len..];
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        let micros = match &rest[..unit_len] {
+            "ns" => 0.001,
+            "us" | "µs" => 1.0,
+            "ms" => 1_000.0,
+            "s" => 1_000_000.0,
+            "m" => 60_000_000.0,
+            "h" => 3_600_000_000.0,
+            _ => return None,
+        };
+        rest = &rest[unit_len..];
+        total += number * micros;
+    }
+    Some(total as i64)
+}
+
+/// Converts the span records of a trace into the Jaeger model, with a process
+/// per distinct service.
+fn to_jaeger_trace(trace_id: &str, spans: &[json::Map<String, json::Value>]) -> JaegerTrace {
+    let mut processes: Vec<JaegerProcess> = Vec::new();
+    let jaeger_spans = spans
+        .iter()
+        .map(|span| {
+            let mut process = JaegerProcess {
+                service_name: get_str(span, "service_name").to_string(),
+                tags: vec![],
+            };
+            let mut tags = Vec::new();
+            for (key, value) in span.iter() {
+                if value.is_null()
+                    || SPAN_COLUMNS.contains(&key.as_str())
+                    || *key == CONFIG.common.column_timestamp
+                {
+                    continue;
+                }
+                match key.strip_prefix("service_") {
+                    Some(key) => process.tags.push(key_value(key, value)),
+                    None => tags.push(key_value(key, value)),
+                }
+            }
+            process.tags.sort_by(|a, b| a.key.cmp(&b.key));
+            tags.sort_by(|a, b| a.key.cmp(&b.key));
+            let span_kind = match get_str(span, "span_kind") {
+                "1" => "internal",
+                "2" => "server",
+                "3" => "client",
+                "4" => "producer",
+                "5" => "consumer",
+                _ => "",
+            };
+            if !span_kind.is_empty() {
+                tags.push(key_value("span.kind", &json::Value::from(span_kind)));
+            }
+            match get_str(span, "span_status") {
+                "ERROR" => {
+                    tags.push(key_value("otel.status_code", &json::Value::from("ERROR")));
+                    tags.push(key_value("error", &json::Value::from(true)));
+                }
+                "OK" => tags.push(key_value("otel.status_code", &json::Value::from("OK"))),
+                _ => {}
+            }
+
+            let process_id = match processes.iter().position(|p| *p == process) {
+                Some(i) => i + 1,
+                None => {
+                    processes.push(process);
+                    processes.len()
+                }
+            };
+
+            let parent_span_id = get_str(span, "reference_parent_span_id");
+            let references = if parent_span_id.is_empty() {
+                vec![]
+            } else {
+                let parent_trace_id = match get_str(span, "reference_parent_trace_id") {
+                    "" => trace_id,
+                    v => v,
+                };
+                vec![JaegerReference {
+                    ref_type: if get_str(span, "reference_ref_type")
+                        == format!("{:?}", SpanRefType::FollowsFrom)
+                    {
+                        "FOLLOWS_FROM"
+                    } else {
+                        "CHILD_OF"
+                    }
+                    .to_string(),
+                    trace_id: parent_trace_id.to_string(),
+                    span_id: parent_span_id.to_string(),
+                }]
+            };
+
+            let events: Vec<json::Map<String, json::Value>> =
+                json::from_str(get_str(span, "events")).unwrap_or_default();
+            let logs = events
+                .into_iter()
+                .map(|event| {
+                    let mut fields = Vec::with_capacity(event.len());
+                    let mut timestamp = 0;
+                    for (key, value) in event.iter() {
+                        match key.as_str() {
+                            "_timestamp" => timestamp = value.as_i64().unwrap_or_default() / 1000,
+                            "name" => fields.insert(0, key_value("event", value)),
+                            _ => fields.push(key_value(key, value)),
+                        }
+                    }
+                    JaegerLog { timestamp, fields }
+                })
+                .collect();
+
+            JaegerSpan {
+                trace_id: trace_id.to_string(),
+                span_id: get_str(span, "span_id").to_string(),
+                operation_name: get_str(span, "operation_name").to_string(),
+                references,
+                flags: get_i64(span, "flags") as u32,
+                start_time: get_i64(span, "start_time") / 1000,
+                duration: get_i64(span, "duration"),
+                tags,
+                logs,
+                process_id: format!("p{process_id}"),
+                warnings: None,
+            }
+        })
+        .collect();
+
+    JaegerTrace {
+        trace_id: trace_id.to_string(),
+        spans: jaeger_spans,
+        processes: processes
+            .into_iter()
+            .enumerate()
+            .map(|(i, process)| (format!("p{}", i + 1), process))
+            .collect(),
+        warnings: None,
+    }
+}
+
+fn key_value(key: &str, value: &json::Value) -> JaegerKeyValue {
+    let value_type = match value {
+        json::Value::Bool(_) => "bool",
+        json::Value::Number(v) if v.is_f64() => "float64",
+        json::Value::Number(_) => "int64",
+        _ => "string",
+    };
+    let value = match value {
+        json::Value::Bool(_) | json::Value::Number(_) | json::Value::String(_) => value.clone(),
+        v => json::Value::from(v.to_string()),
+    };
+    JaegerKeyValue {
+        key: key.to_string(),
+        value_type: value_type.to_string(),
+        value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        thrift::{
+            decode_batch,
+            tests::{string_tag, Writer},
+        },
+        *,
+    };
+
+    #[test]
+    fn test_to_spans() {
+        let process = Writer::default()
+            .string(1, "cart")
+            .list(2, &[string_tag("hostname", "web-1")])
+            .stop();
+        let span = Writer::default()
+            .i64(1, 0x0e0e4736)
+            .i64(2, 0x4bf92f35)
+            .i64(3, 0x00f067aa0ba902b7)
+            .i64(4, 0x1a2b)
+            .string(5, "GET /cart")
+            .i32(7, 1)
+            .i64(8, 1_000_000)
+            .i64(9, 250)
+            .list(
+                10,
+                &[
+                    string_tag("span.kind", "server"),
+                    Writer::default()
+                        .string(1, "error")
+                        .i32(2, 2)
+                        .bool(5, true)
+                        .stop(),
+                    string_tag("http.method", "GET"),
+                ],
+            )
+            .list(
+                11,
+                &[Writer::default()
+                    .i64(1, 1_000_100)
+                    .list(
+                        2,
+                        &[string_tag("event", "retry"), string_tag("attempt", "2")],
+                    )
+                    .stop()],
+            )
+            .stop();
+        let batch = Writer::default()
+            .structure(1, &process)
+            .list(2, &[span])
+            .stop();
+        let spans = to_spans(decode_batch(&batch.0).unwrap());
+        let span = &spans[0];
+        assert_eq!(span.trace_id, "000000004bf92f35000000000e0e4736");
+        assert_eq!(span.span_id, "00f067aa0ba902b7");
+        assert_eq!(
+            span.reference.get(PARENT_SPAN_ID).map(|v| v.as_str()),
+            Some("0000000000001a2b")
+        );
+        assert_eq!(span.span_kind, "2");
+        assert_eq!(span.span_status, "ERROR");
+        assert_eq!(
+            (span.start_time, span.end_time),
+            (1_000_000_000, 1_000_250_000)
+        );
+        assert_eq!(span.duration, 250);
+        assert_eq!(span.service_name, "cart");
+        assert_eq!(
+            span.service.get("service.hostname"),
+            Some(&json::Value::from("web-1"))
+        );
+        assert_eq!(
+            span.attributes.keys().collect::<Vec<_>>(),
+            vec!["http.method"]
+        );
+        let events: Vec<Event> = json::from_str(&span.events).unwrap();
+        assert_eq!(events[0].name, "retry");
+        assert_eq!(events[0]._timestamp, 1_000_100_000);
+        assert_eq!(
+            events[0].attributes.get("attempt"),
+            Some(&json::Value::from("2"))
+        );
+    }
+
+    #[test]
+    fn test_to_jaeger_trace() {
+        let span = |span_id: &str, parent: &str, service: &str| {
+            let span = json::json!({
+                "_timestamp": 1000,
+                "trace_id": "4bf92f3577b34da6a3ce929d0e0e4736",
+                "span_id": span_id,
+                "reference_parent_span_id": parent,
+                "reference_ref_type": "ChildOf",
+                "service_name": service,
+                "service_k8s_namespace": "shop",
+                "operation_name": "GET /cart",
+                "span_kind": "3",
+                "span_status": "ERROR",
+                "start_time": 1_000_000_000,
+                "duration": 250,
+                "flags": 1,
+                "http_status_code": 500,
+                "events": r#"[{"name":"retry","_timestamp":1000100000,"attempt":2}]"#,
+            });
+            span.as_object().unwrap().clone()
+        };
+        let trace = to_jaeger_trace(
+            "4bf92f3577b34da6a3ce929d0e0e4736",
+            &[
+                span("a1", "", "frontend"),
+                span("b2", "a1", "cart"),
+                span("c3", "a1", "frontend"),
+            ],
+        );
+        assert_eq!(trace.processes.len(), 2);
+        assert_eq!(trace.processes["p1"].service_name, "frontend");
+        assert_eq!(trace.processes["p1"].tags[0].key, "k8s_namespace");
+        assert_eq!(
+            trace
+                .spans
+                .iter()
+                .map(|s| s.process_id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["p1", "p2", "p1"]
+        );
+        let root = &trace.spans[0];
+        assert!(root.references.is_empty());
+        assert_eq!(root.start_time, 1_000_000);
+        assert_eq!(
+            root.tags
+                .iter()
+                .map(|t| (t.key.as_str(), t.value.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("http_status_code", json::Value::from(500)),
+                ("span.kind", json::Value::from("client")),
+                ("otel.status_code", json::Value::from("ERROR")),
+                ("error", json::Value::from(true)),
+            ]
+        );
+        assert_eq!(root.logs[0].timestamp, 1_000_100);
+        assert_eq!(root.logs[0].fields[0].value, json::Value::from("retry"));
+        let child = &trace.spans[1];
+        assert_eq!(child.references[0].ref_type, "CHILD_OF");
+        assert_eq!(child.references[0].span_id, "a1");
+        assert_eq!(child.references[0].trace_id, trace.trace_id);
+    }
+
+    #[test]
+    fn test_search_request() {
+        let params = [
+            ("service", "cart"),
+            ("operation", "GET /cart"),
+            ("tags", r#"{"http.method":"GET","error":"true"}"#),
+            ("tag", "k8s.namespace:shop"),
+            ("minDuration", "1.5ms"),
+            ("maxDuration", "2s"),
+            ("limit", "5"),
+            ("start", "1000"),
+            ("end", "2000"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect::<Vec<_>>();
+        let mut req = search_request(&params).unwrap();
+        req.attributes.sort();
+        assert_eq!(
+            req,
+            TraceSearchRequest {
+                service_name: Some("cart".to_string()),
+                operation_name: Some("GET /cart".to_string()),
+                min_duration: Some(1500),
+                max_duration: Some(2_000_000),
+                span_status: Some("ERROR".to_string()),
+                attributes: vec![
+                    ("http.method".to_string(), "GET".to_string()),
+                    ("k8s.namespace".to_string(), "shop".to_string()),
+                ],
+                start_time: 1000,
+                end_time: 2000,
+                from: 0,
+                size: 5,
+            }
+        );
+
+        let req = search_request(&[("end".to_string(), "7200000000".to_string())]).unwrap();
+        assert_eq!(req.start_time, 3_600_000_000);
+        assert_eq!(req.size, DEFAULT_LIMIT);
+        assert!(search_request(&[("minDuration".to_string(), "1x".to_string())]).is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("300ms"), Some(300_000));
+        assert_eq!(parse_duration("1h30m"), Some(5_400_000_000));
+        assert_eq!(parse_duration("1.5s"), Some(1_500_000));
+        assert_eq!(parse_duration("10us"), Some(10));
+        assert_eq!(parse_duration("10"), None);
+        assert_eq!(parse_duration(""), None);
+    }
+}
@@ -0,0 +1,495 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Decoding of the Jaeger `Batch` from the Thrift binary protocol, as sent by
+//! the Jaeger clients to the `/api/traces` endpoint of the collector.
+//!
+//! See `jaeger.thrift` in the jaeger-idl repository.
+
+use anyhow::{anyhow, Result};
+
+const STOP: u8 = 0;
+const BOOL: u8 = 2;
+const BYTE: u8 = 3;
+const DOUBLE: u8 = 4;
+const I16: u8 = 6;
+const I32: u8 = 8;
+const I64: u8 = 10;
+const STRING: u8 = 11;
+const STRUCT: u8 = 12;
+const MAP: u8 = 13;
+const SET: u8 = 14;
+const LIST: u8 = 15;
+
+/// Nesting allowed when skipping unknown fields.
+const MAX_DEPTH: usize = 64;
+
+pub const REF_TYPE_CHILD_OF: i32 = 0;
+pub const REF_TYPE_FOLLOWS_FROM: i32 = 1;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Batch {
+    pub process: Process,
+    pub spans: Vec<Span>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Process {
+    pub service_name: String,
+    pub tags: Vec<Tag>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Span {
+    pub trace_id_low: i64,
+    pub trace_id_high: i64,
+    pub span_id: i64,
+    pub parent_span_id: i64,
+    pub operation_name: String,
+    pub references: Vec<SpanRef>,
+    pub flags: i32,
+    /// In microseconds.
+    pub start_time: i64,
+    /// In microseconds.
+    pub duration: i64,
+    pub tags: Vec<Tag>,
+    pub logs: Vec<Log>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SpanRef {
+    pub ref_type: i32,
+    pub trace_id_low: i64,
+    pub trace_id_high: i64,
+    pub span_id: i64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tag {
+    pub key: String,
+    pub value: TagValue,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TagValue {
+    String(String),
+    Double(f64),
+    Bool(bool),
+    Long(i64),
+    Binary(Vec<u8>),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Log {
+    /// In microseconds.
+    pub timestamp: i64,
+    pub fields: Vec<Tag>,
+}
+
+/// Decodes the batch of spans of a process.
+pub fn decode_batch(buf: &[u8]) -> Result<Batch> {
+    let mut reader = Reader { buf, pos: 0 };
+    let mut batch = Batch::default();
+    reader.read_struct(|r, id, ttype| {
+        match (id, ttype) {
+            (1, STRUCT) => batch.process = r.read_process()?,
+            (2, LIST) => batch.spans = r.read_list(STRUCT, Reader::read_span)?,
+            _ => r.skip(ttype, 0)?,
+        }
+        Ok(())
+    })?;
+    Ok(batch)
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.buf.len())
+            .ok_or_else(|| anyhow!("unexpected end of the thrift message"))?;
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_i16(&mut self) -> Result<i16> {
+        Ok(i16::from_be_bytes(self.read_bytes(2)?.try_into()?))
+    }
+
+    fn read_i32(&mut self) -> Result<i32> {
+        Ok(i32::from_be_bytes(self.read_bytes(4)?.try_into()?))
+    }
+
+    fn read_i64(&mut self) -> Result<i64> {
+        Ok(i64::from_be_bytes(self.read_bytes(8)?.try_into()?))
+    }
+
+    fn read_f64(&mut self) -> Result<f64> {
+        Ok(f64::from_bits(self.read_i64()? as u64))
+    }
+
+    fn read_len(&mut self) -> Result<usize> {
+        let len = self.read_i32()?;
+        usize::try_from(len).map_err(|_| anyhow!("negative thrift length: {len}"))
+    }
+
+    fn read_binary(&mut self) -> Result<Vec<u8>> {
+        let len = self.read_len()?;
+        Ok(self.read_bytes(len)?.to_vec())
+    }
+
+    fn read_string(&mut self) -> Result<String> {
+        Ok(String::from_utf8(self.read_binary()?)?)
+    }
+
+    /// Reads the fields of a struct until its stop field.
+    fn read_struct(
+        &mut self,
+        mut field: impl FnMut(&mut Self, i16, u8) -> Result<()>,
+    ) -> Result<()> {
+        loop {
+            let ttype = self.read_u8()?;
+            if ttype == STOP {
+                return Ok(());
+            }
+            let id = self.read_i16()?;
+            field(self, id, ttype)?;
+        }
+    }
+
+    fn read_list<T>(
+        &mut self,
+        elem_type: u8,
+        mut elem: impl FnMut(&mut Self) -> Result<T>,
+    ) -> Result<Vec<T>> {
+        let ttype = self.read_u8()?;
+        let len = self.read_len()?;
+        if ttype != elem_type {
+            return Err(anyhow!(
+                "unexpected thrift list element type: {ttype}, expected {elem_type}"
+            ));
+        }
+        // every element takes at least a byte
+        let mut list = Vec::with_capacity(len.min(self.buf.len() - self.pos));
+        for _ in 0..len {
+            list.push(elem(self)?);
+        }
+        Ok(list)
+    }
+
+    fn skip(&mut self, ttype: u8, depth: usize) -> Result<()> {
+        if depth > MAX_DEPTH {
+            return Err(anyhow!("thrift message nested too deeply"));
+        }
+        match ttype {
+            BOOL | BYTE => {
+                self.read_bytes(1)?;
+            }
+            I16 => {
+                self.read_bytes(2)?;
+            }
+            I32 => {
+                self.read_bytes(4)?;
+            }
+            DOUBLE | I64 => {
+                self.read_bytes(8)?;
+            }
+            STRING => {
+                let len = self.read_len()?;
+                self.read_bytes(len)?;
+            }
+            STRUCT => self.read_struct(|r, _, ttype| r.skip(ttype, depth + 1))?,
+            MAP => {
+                let key_type = self.read_u8()?;
+                let value_type = self.read_u8()?;
+                for _ in 0..self.read_len()? {
+                    self.skip(key_type, depth + 1)?;
+                    self.skip(value_type, depth + 1)?;
+                }
+            }
+            SET | LIST => {
+                let elem_type = self.read_u8()?;
+                for _ in 0..self.read_len()? {
+                    self.skip(elem_type, depth + 1)?;
+                }
+            }
+            _ => return Err(anyhow!("unknown thrift type: {ttype}")),
+        }
+        Ok(())
+    }
+
+    fn read_process(&mut self) -> Result<Process> {
+        let mut process = Process::default();
+        self.read_struct(|r, id, ttype| {
+            match (id, ttype) {
+                (1, STRING) => process.service_name = r.read_string()?,
+                (2, LIST) => process.tags = r.read_list(STRUCT, Reader::read_tag)?,
+                _ => r.skip(ttype, 0)?,
+            }
+            Ok(())
+        })?;
+        Ok(process)
+    }
+
+    fn read_span(&mut self) -> Result<Span> {
+        let mut span = Span::default();
+        self.read_struct(|r, id, ttype| {
+            match (id, ttype) {
+                (1, I64) => span.trace_id_low = r.read_i64()?,
+                (2, I64) => span.trace_id_high = r.read_i64()?,
+                (3, I64) => span.span_id = r.read_i64()?,
+                (4, I64) => span.parent_span_id = r.read_i64()?,
+                (5, STRING) => span.operation_name = r.read_string()?,
+                (6, LIST) => span.references = r.read_list(STRUCT, Reader::read_span_ref)?,
+                (7, I32) => span.flags = r.read_i32()?,
+                (8, I64) => span.start_time = r.read_i64()?,
+                (9, I64) => span.duration = r.read_i64()?,
+                (10, LIST) => span.tags = r.read_list(STRUCT, Reader::read_tag)?,
+                (11, LIST) => span.logs = r.read_list(STRUCT, Reader::read_log)?,
+                _ => r.skip(ttype, 0)?,
+            }
+            Ok(())
+        })?;
+        Ok(span)
+    }
+
+    fn read_span_ref(&mut self) -> Result<SpanRef> {
+        let mut span_ref = SpanRef::default();
+        self.read_struct(|r, id, ttype| {
+            match (id, ttype) {
+                (1, I32) => span_ref.ref_type = r.read_i32()?,
+                (2, I64) => span_ref.trace_id_low = r.read_i64()?,
+                (3, I64) => span_ref.trace_id_high = r.read_i64()?,
+                (4, I64) => span_ref.span_id = r.read_i64()?,
+                _ => r.skip(ttype, 0)?,
+            }
+            Ok(())
+        })?;
+        Ok(span_ref)
+    }
+
+    fn read_tag(&mut self) -> Result<Tag> {
+        let mut key = String::new();
+        let mut v_type = 0;
+        let mut v_str = None;
+        let mut v_double = None;
+        let mut v_bool = None;
+        let mut v_long = None;
+        let mut v_binary = None;
+        self.read_struct(|r, id, ttype| {
+            match (id, ttype) {
+                (1, STRING) => key = r.read_string()?,
+                (2, I32) => v_type = r.read_i32()?,
+                (3, STRING) => v_str = Some(r.read_string()?),
+                (4, DOUBLE) => v_double = Some(r.read_f64()?),
+                (5, BOOL) => v_bool = Some(r.read_u8()? != 0),
+                (6, I64) => v_long = Some(r.read_i64()?),
+                (7, STRING) => v_binary = Some(r.read_binary()?),
+                _ => r.skip(ttype, 0)?,
+            }
+            Ok(())
+        })?;
+        // the tag type enum: STRING, DOUBLE, BOOL, LONG, BINARY
+        let value = match v_type {
+            0 => TagValue::String(v_str.unwrap_or_default()),
+            1 => TagValue::Double(v_double.unwrap_or_default()),
+            2 => TagValue::Bool(v_bool.unwrap_or_default()),
+            3 => TagValue::Long(v_long.unwrap_or_default()),
+            4 => TagValue::Binary(v_binary.unwrap_or_default()),
+            _ => return Err(anyhow!("unknown jaeger tag type: {v_type}")),
+        };
+        Ok(Tag { key, value })
+    }
+
+    fn read_log(&mut self) -> Result<Log> {
+        let mut log = Log::default();
+        self.read_struct(|r, id, ttype| {
+            match (id, ttype) {
+                (1, I64) => log.timestamp = r.read_i64()?,
+                (2, LIST) => log.fields = r.read_list(STRUCT, Reader::read_tag)?,
+                _ => r.skip(ttype, 0)?,
+            }
+            Ok(())
+        })?;
+        Ok(log)
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    /// Encodes the thrift binary protocol, for the tests.
+    #[derive(Default)]
+    pub struct Writer(pub Vec<u8>);
+
+    impl Writer {
+        pub fn field(&mut self, ttype: u8, id: i16) -> &mut Self {
+            self.0.push(ttype);
+            self.0.extend(id.to_be_bytes());
+            self
+        }
+
+        pub fn i32(&mut self, id: i16, v: i32) -> &mut Self {
+            self.field(I32, id).0.extend(v.to_be_bytes());
+            self
+        }
+
+        pub fn i64(&mut self, id: i16, v: i64) -> &mut Self {
+            self.field(I64, id).0.extend(v.to_be_bytes());
+            self
+        }
+
+        pub fn string(&mut self, id: i16, v: &str) -> &mut Self {
+            self.field(STRING, id)
+                .0
+                .extend((v.len() as i32).to_be_bytes());
+            self.0.extend(v.as_bytes());
+            self
+        }
+
+        pub fn bool(&mut self, id: i16, v: bool) -> &mut Self {
+            self.field(BOOL, id).0.push(v as u8);
+            self
+        }
+
+        pub fn list(&mut self, id: i16, elems: &[Writer]) -> &mut Self {
+            self.field(LIST, id).0.push(STRUCT);
+            self.0.extend((elems.len() as i32).to_be_bytes());
+            for elem in elems {
+                self.0.extend(&elem.0);
+            }
+            self
+        }
+
+        pub fn structure(&mut self, id: i16, v: &Writer) -> &mut Self {
+            self.field(STRUCT, id).0.extend(&v.0);
+            self
+        }
+
+        pub fn stop(&mut self) -> Writer {
+            self.0.push(STOP);
+            Writer(std::mem::take(&mut self.0))
+        }
+    }
+
+    pub fn string_tag(key: &str, value: &str) -> Writer {
+        Writer::default()
+            .string(1, key)
+            .i32(2, 0)
+            .string(3, value)
+            .stop()
+    }
+
+    #[test]
+    fn test_decode_batch() {
+        let process = Writer::default()
+            .string(1, "frontend")
+            .list(2, &[string_tag("hostname", "web-1")])
+            .stop();
+        let span = Writer::default()
+            .i64(1, 2)
+            .i64(2, 1)
+            .i64(3, 3)
+            .i64(4, 0)
+            .string(5, "GET /cart")
+            .list(
+                6,
+                &[Writer::default()
+                    .i32(1, REF_TYPE_FOLLOWS_FROM)
+                    .i64(2, 2)
+                    .i64(3, 1)
+                    .i64(4, 4)
+                    .stop()],
+            )
+            .i32(7, 1)
+            .i64(8, 1_000_000)
+            .i64(9, 250)
+            .list(
+                10,
+                &[
+                    Writer::default().string(1, "error").i32(2, 2).bool(5, true).stop(),
+                    Writer::default()
+                        .string(1, "http.status_code")
+                        .i32(2, 3)
+                        .i64(6, 500)
+                        .stop(),
+                ],
+            )
+            .list(
+                11,
+                &[Writer::default()
+                    .i64(1, 1_000_100)
+                    .list(2, &[string_tag("event", "retry")])
+                    .stop()],
+            )
+            // an unknown field is skipped
+            .structure(20, &Writer::default().string(1, "ignored").stop())
+            .stop();
+        let batch = Writer::default()
+            .structure(1, &process)
+            .list(2, &[span])
+            .stop();
+
+        let batch = decode_batch(&batch.0).unwrap();
+        assert_eq!(batch.process.service_name, "frontend");
+        assert_eq!(
+            batch.process.tags,
+            vec![Tag {
+                key: "hostname".to_string(),
+                value: TagValue::String("web-1".to_string()),
+            }]
+        );
+        let span = &batch.spans[0];
+        assert_eq!(
+            (span.trace_id_high, span.trace_id_low, span.span_id),
+            (1, 2, 3)
+        );
+        assert_eq!(span.operation_name, "GET /cart");
+        assert_eq!(
+            span.references,
+            vec![SpanRef {
+                ref_type: REF_TYPE_FOLLOWS_FROM,
+                trace_id_low: 2,
+                trace_id_high: 1,
+                span_id: 4,
+            }]
+        );
+        assert_eq!((span.start_time, span.duration), (1_000_000, 250));
+        assert_eq!(span.tags[0].value, TagValue::Bool(true));
+        assert_eq!(span.tags[1].value, TagValue::Long(500));
+        assert_eq!(span.logs[0].timestamp, 1_000_100);
+        assert_eq!(span.logs[0].fields[0].key, "event");
+    }
+
+    #[test]
+    fn test_decode_batch_truncated() {
+        let batch = Writer::default()
+            .structure(1, &Writer::default().string(1, "frontend").stop())
+            .stop();
+        assert!(decode_batch(&batch.0[..batch.0.len() - 3]).is_err());
+        assert!(decode_batch(&[LIST, 0, 2, STRUCT, 0xff, 0xff, 0xff, 0xff]).is_err());
+    }
+}
@@ -41,7 +41,7 @@ use crate::{
             http::HttpResponse as MetaHttpResponse,
             stream::{PartitionTimeLevel, SchemaRecords, StreamParams},
             traces::{Event, Span, SpanRefType},
-            usage::UsageType,
+            usage::{RequestStats, UsageType},
             StreamType,
         },
         utils::{self, flatten, hasher::get_fields_key_xxh3, json},
@@ -55,13 +55,21 @@ use crate::{
     },
 };
 
+pub mod jaeger;
 pub mod otlp_http;
+pub mod search;
+pub mod service_graph;
+pub mod span_metrics;
+pub mod tail_sampling;
+pub mod zipkin;
 
 const PARENT_SPAN_ID: &str = "reference.parent_span_id";
 const PARENT_TRACE_ID: &str = "reference.parent_trace_id";
 const REF_TYPE: &str = "reference.ref_type";
 const SERVICE_NAME: &str = "service.name";
 const SERVICE: &str = "service";
+/// The service name of the spans without one, as named by OpenTelemetry.
+const UNKNOWN_SERVICE: &str = "unknown_service";
 
 pub async fn handle_trace_request(
     org_id: &str,
@@ -70,20 +78,8 @@ pub async fn handle_trace_request(
     is_grpc: bool,
     in_stream_name: Option<&str>,
 ) -> Result<HttpResponse, Error> {
-    if !cluster::is_ingester(&cluster::LOCAL_NODE_ROLE) {
-        return Ok(
-            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
-                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
-                "not an ingester".to_string(),
-            )),
-        );
-    }
-
-    if !db::file_list::BLOCKED_ORGS.is_empty() && db::file_list::BLOCKED_ORGS.contains(&org_id) {
-        return Ok(HttpResponse::Forbidden().json(MetaHttpResponse::error(
-            http::StatusCode::FORBIDDEN.into(),
-            "Quota exceeded for this organization".to_string(),
-        )));
+    if let Some(resp) = check_ingestion_allowed(org_id) {
+        return Ok(resp);
     }
     let start = std::time::Instant::now();
 
@@ -94,59 +90,11 @@ pub async fn handle_trace_request(
 
     let traces_stream_name = &traces_stream_name;
 
-    let mut runtime = crate::service::ingestion::init_functions_runtime();
-    let mut stream_alerts_map: AHashMap<String, Vec<Alert>> = AHashMap::new();
-    let mut traces_schema_map: AHashMap<String, Schema> = AHashMap::new();
-    let mut distinct_values = Vec::with_capacity(16);
-
-    let stream_schema = stream_schema_exists(
-        org_id,
-        traces_stream_name,
-        StreamType::Traces,
-        &mut traces_schema_map,
-    )
-    .await;
-
-    let mut partition_keys: Vec<String> = vec![];
-    let mut partition_time_level =
-        PartitionTimeLevel::from(CONFIG.limit.traces_file_retention.as_str());
-    if stream_schema.has_partition_keys {
-        let partition_det = crate::service::ingestion::get_stream_partition_keys(
-            traces_stream_name,
-            &traces_schema_map,
-        )
-        .await;
-        partition_keys = partition_det.partition_keys;
-        partition_time_level =
-            unwrap_partition_time_level(partition_det.partition_time_level, StreamType::Traces);
-    }
-
-    // Start get stream alerts
-    crate::service::ingestion::get_stream_alerts(
-        org_id,
-        StreamType::Traces,
-        traces_stream_name,
-        &mut stream_alerts_map,
-    )
-    .await;
-    // End get stream alert
-
-    // Start Register Transforms for stream
-    let (local_trans, stream_vrl_map) = crate::service::ingestion::register_stream_transforms(
-        org_id,
-        StreamType::Traces,
-        traces_stream_name,
-    );
-    // End Register Transforms for stream
-
-    let mut trigger: TriggerAlertData = None;
-
     let min_ts =
         (Utc::now() - Duration::hours(CONFIG.limit.ingest_allowed_upto)).timestamp_micros();
     let mut partial_success = ExportTracePartialSuccess::default();
 
-    let mut data_buf: AHashMap<String, SchemaRecords> = AHashMap::new();
-
+    let mut spans_to_write = Vec::new();
     let mut service_name: String = traces_stream_name.to_string();
     let res_spans = request.resource_spans;
     for res_span in res_spans {
@@ -240,136 +188,25 @@ pub async fn handle_trace_request(
                     events: json::to_string(&events).unwrap(),
                 };
 
-                let value: json::Value = json::to_value(local_val).unwrap();
-
-                // JSON Flattening
-                let mut value = flatten::flatten(&value).unwrap();
-
-                if !local_trans.is_empty() {
-                    value = crate::service::ingestion::apply_stream_transform(
-                        &local_trans,
-                        &value,
-                        &stream_vrl_map,
-                        traces_stream_name,
-                        &mut runtime,
-                    )
-                    .unwrap_or(value);
-                }
-                // End row based transform */
-                // get json object
-                let val_map = value.as_object_mut().unwrap();
-
-                val_map.insert(
-                    CONFIG.common.column_timestamp.clone(),
-                    json::Value::Number(timestamp.into()),
-                );
-
-                // get distinct_value item
-                for field in DISTINCT_FIELDS.iter() {
-                    if let Some(val) = val_map.get(field) {
-                        if !val.is_null() {
-                            let (filter_name, filter_value) = if field == "operation_name" {
-                                ("service_name".to_string(), service_name.clone())
-                            } else {
-                                ("".to_string(), "".to_string())
-                            };
-                            distinct_values.push(distinct_values::DvItem {
-                                stream_type: StreamType::Traces,
-                                stream_name: traces_stream_name.to_string(),
-                                field_name: field.to_string(),
-                                field_value: val.as_str().unwrap().to_string(),
-                                filter_name,
-                                filter_value,
-                            });
-                        }
-                    }
-                }
-
-                let value_str = crate::common::utils::json::to_string(&val_map).unwrap();
-
-                // check schema
-                let schema_evolution = check_for_schema(
-                    org_id,
-                    traces_stream_name,
-                    StreamType::Traces,
-                    &value_str,
-                    &mut traces_schema_map,
-                    timestamp.try_into().unwrap(),
-                    true,
-                )
-                .await;
-
-                // get hour key
-                let schema_key = get_fields_key_xxh3(&schema_evolution.schema_fields);
-
-                // get hour key
-                let mut hour_key = super::ingestion::get_wal_time_key(
-                    timestamp.try_into().unwrap(),
-                    &partition_keys,
-                    partition_time_level,
-                    val_map,
-                    Some(&schema_key),
-                );
-
-                if trigger.is_none() && !stream_alerts_map.is_empty() {
-                    // Start check for alert trigger
-                    let key = format!("{}/{}/{}", &org_id, StreamType::Traces, traces_stream_name);
-                    if let Some(alerts) = stream_alerts_map.get(&key) {
-                        let mut trigger_alerts: Vec<(Alert, Vec<json::Map<String, json::Value>>)> =
-                            Vec::new();
-                        for alert in alerts {
-                            if let Ok(Some(v)) = alert.evaluate(Some(val_map)).await {
-                                trigger_alerts.push((alert.clone(), v));
-                            }
-                        }
-                        trigger = Some(trigger_alerts);
-                    }
-                    // End check for alert trigger
-                }
-
-                if partition_keys.is_empty() {
-                    let partition_key = format!("service_name={}", service_name);
-                    hour_key.push_str(&format!("/{}", format_partition_key(&partition_key)));
-                }
-                let rec_schema = traces_schema_map.get(traces_stream_name).unwrap();
-
-                let hour_buf = data_buf.entry(hour_key).or_insert(SchemaRecords {
-                    schema: rec_schema
-                        .clone()
-                        .with_metadata(std::collections::HashMap::new()),
-                    records: vec![],
-                });
-                let loc_value: utils::json::Value =
-                    utils::json::from_slice(value_str.as_bytes()).unwrap();
-                hour_buf.records.push(loc_value);
-
                 if timestamp < min_ts.try_into().unwrap() {
+                    // written, but reported as rejected
                     partial_success.rejected_spans += 1;
+                    spans_to_write.push(local_val);
+                    continue;
+                }
+                span_metrics::record(org_id, &local_val);
+                if tail_sampling::buffer(org_id, traces_stream_name, &local_val) {
                     continue;
                 }
+                spans_to_write.push(local_val);
             }
         }
     }
 
-    let mut traces_file_name = "".to_string();
-    let mut req_stats = write_file_arrow(
-        &data_buf,
-        thread_id,
-        &StreamParams::new(org_id, traces_stream_name, StreamType::Traces),
-        &mut traces_file_name,
-        None,
-    )
-    .await;
+    let mut req_stats = write_spans(org_id, thread_id, traces_stream_name, spans_to_write).await;
     let time = start.elapsed().as_secs_f64();
     req_stats.response_time = time;
 
-    // send distinct_values
-    if !distinct_values.is_empty() {
-        if let Err(e) = distinct_values::write(org_id, distinct_values).await {
-            log::error!("Error while writing distinct values: {}", e);
-        }
-    }
-
     let ep = if is_grpc {
         "grpc/export/traces"
     } else {
@@ -405,9 +242,6 @@ pub async fn handle_trace_request(
     )
     .await;
 
-    // only one trigger per request, as it updates etcd
-    evaluate_trigger(trigger).await;
-
     let res = ExportTraceServiceResponse {
         partial_success: if partial_success.rejected_spans > 0 {
             partial_success.error_message =
@@ -427,6 +261,307 @@ pub async fn handle_trace_request(
         .body(out));
 }
 
+/// Ingests the spans converted from the Zipkin or Jaeger formats, answering
+/// as their collectors do.
+pub(crate) async fn ingest_spans(
+    org_id: &str,
+    thread_id: usize,
+    spans: Vec<Span>,
+    in_stream_name: Option<&str>,
+    ep: &str,
+) -> Result<HttpResponse, Error> {
+    if let Some(resp) = check_ingestion_allowed(org_id) {
+        return Ok(resp);
+    }
+    let start = std::time::Instant::now();
+
+    let traces_stream_name = match in_stream_name {
+        Some(name) => format_stream_name(name),
+        None => "default".to_owned(),
+    };
+    let traces_stream_name = &traces_stream_name;
+
+    let min_ts =
+        (Utc::now() - Duration::hours(CONFIG.limit.ingest_allowed_upto)).timestamp_micros();
+    let mut rejected_spans = 0;
+    let mut spans_to_write = Vec::with_capacity(spans.len());
+    for span in spans {
+        if span.start_time / 1000 < min_ts as u64 {
+            rejected_spans += 1;
+            continue;
+        }
+        span_metrics::record(org_id, &span);
+        if tail_sampling::buffer(org_id, traces_stream_name, &span) {
+            continue;
+        }
+        spans_to_write.push(span);
+    }
+
+    let mut req_stats = write_spans(org_id, thread_id, traces_stream_name, spans_to_write).await;
+    let time = start.elapsed().as_secs_f64();
+    req_stats.response_time = time;
+
+    metrics::HTTP_RESPONSE_TIME
+        .with_label_values(&[
+            ep,
+            "202",
+            org_id,
+            traces_stream_name,
+            StreamType::Traces.to_string().as_str(),
+        ])
+        .observe(time);
+    metrics::HTTP_INCOMING_REQUESTS
+        .with_label_values(&[
+            ep,
+            "202",
+            org_id,
+            traces_stream_name,
+            StreamType::Traces.to_string().as_str(),
+        ])
+        .inc();
+
+    // metric + data usage
+    report_request_usage_stats(
+        req_stats,
+        org_id,
+        traces_stream_name,
+        StreamType::Traces,
+        UsageType::Traces,
+        0,
+    )
+    .await;
+
+    if rejected_spans > 0 {
+        return Ok(HttpResponse::Accepted().json(MetaHttpResponse::message(
+            http::StatusCode::ACCEPTED.into(),
+            format!(
+                "{rejected_spans} spans were rejected due to exceeding the allowed retention period"
+            ),
+        )));
+    }
+    Ok(HttpResponse::Accepted().finish())
+}
+
+/// Formats a hexadecimal trace or span id as the OTLP ingestion does: lower
+/// case and left-padded with zeros to `len` digits.
+fn normalize_id(id: &str, len: usize) -> Option<String> {
+    if id.is_empty() || id.len() > len || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(format!("{:0>len$}", id.to_lowercase()))
+}
+
+/// Returns the response refusing the ingestion, when this node is not an
+/// ingester or the organization exceeded its quota.
+fn check_ingestion_allowed(org_id: &str) -> Option<HttpResponse> {
+    if !cluster::is_ingester(&cluster::LOCAL_NODE_ROLE) {
+        return Some(
+            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                "not an ingester".to_string(),
+            )),
+        );
+    }
+    if !db::file_list::BLOCKED_ORGS.is_empty() && db::file_list::BLOCKED_ORGS.contains(&org_id) {
+        return Some(HttpResponse::Forbidden().json(MetaHttpResponse::error(
+            http::StatusCode::FORBIDDEN.into(),
+            "Quota exceeded for this organization".to_string(),
+        )));
+    }
+    None
+}
+
+/// Writes the spans into the traces stream, applying the stream transforms and
+/// evaluating the realtime alerts of the stream.
+pub(crate) async fn write_spans(
+    org_id: &str,
+    thread_id: usize,
+    traces_stream_name: &str,
+    spans: Vec<Span>,
+) -> RequestStats {
+    if spans.is_empty() {
+        return RequestStats::default();
+    }
+
+    let mut runtime = crate::service::ingestion::init_functions_runtime();
+    let mut stream_alerts_map: AHashMap<String, Vec<Alert>> = AHashMap::new();
+    let mut traces_schema_map: AHashMap<String, Schema> = AHashMap::new();
+    let mut distinct_values = Vec::with_capacity(16);
+
+    let stream_schema = stream_schema_exists(
+        org_id,
+        traces_stream_name,
+        StreamType::Traces,
+        &mut traces_schema_map,
+    )
+    .await;
+
+    let mut partition_keys: Vec<String> = vec![];
+    let mut partition_time_level =
+        PartitionTimeLevel::from(CONFIG.limit.traces_file_retention.as_str());
+    if stream_schema.has_partition_keys {
+        let partition_det = crate::service::ingestion::get_stream_partition_keys(
+            traces_stream_name,
+            &traces_schema_map,
+        )
+        .await;
+        partition_keys = partition_det.partition_keys;
+        partition_time_level =
+            unwrap_partition_time_level(partition_det.partition_time_level, StreamType::Traces);
+    }
+
+    // Start get stream alerts
+    crate::service::ingestion::get_stream_alerts(
+        org_id,
+        StreamType::Traces,
+        traces_stream_name,
+        &mut stream_alerts_map,
+    )
+    .await;
+    // End get stream alert
+
+    // Start Register Transforms for stream
+    let (local_trans, stream_vrl_map) = crate::service::ingestion::register_stream_transforms(
+        org_id,
+        StreamType::Traces,
+        traces_stream_name,
+    );
+    // End Register Transforms for stream
+
+    let mut trigger: TriggerAlertData = None;
+
+    let mut data_buf: AHashMap<String, SchemaRecords> = AHashMap::new();
+
+    for local_val in spans {
+        let timestamp = local_val.start_time / 1000;
+        let service_name = local_val.service_name.clone();
+
+        let value: json::Value = json::to_value(local_val).unwrap();
+
+        // JSON Flattening
+        let mut value = flatten::flatten(&value).unwrap();
+
+        if !local_trans.is_empty() {
+            value = crate::service::ingestion::apply_stream_transform(
+                &local_trans,
+                &value,
+                &stream_vrl_map,
+                traces_stream_name,
+                &mut runtime,
+            )
+            .unwrap_or(value);
+        }
+        // End row based transform */
+        // get json object
+        let val_map = value.as_object_mut().unwrap();
+
+        val_map.insert(
+            CONFIG.common.column_timestamp.clone(),
+            json::Value::Number(timestamp.into()),
+        );
+
+        // get distinct_value item
+        for field in DISTINCT_FIELDS.iter() {
+            if let Some(val) = val_map.get(field) {
+                if !val.is_null() {
+                    let (filter_name, filter_value) = if field == "operation_name" {
+                        ("service_name".to_string(), service_name.clone())
+                    } else {
+                        ("".to_string(), "".to_string())
+                    };
+                    distinct_values.push(distinct_values::DvItem {
+                        stream_type: StreamType::Traces,
+                        stream_name: traces_stream_name.to_string(),
+                        field_name: field.to_string(),
+                        field_value: val.as_str().unwrap().to_string(),
+                        filter_name,
+                        filter_value,
+                    });
+                }
+            }
+        }
+
+        let value_str = crate::common::utils::json::to_string(&val_map).unwrap();
+
+        // check schema
+        let schema_evolution = check_for_schema(
+            org_id,
+            traces_stream_name,
+            StreamType::Traces,
+            &value_str,
+            &mut traces_schema_map,
+            timestamp.try_into().unwrap(),
+            true,
+        )
+        .await;
+
+        // get hour key
+        let schema_key = get_fields_key_xxh3(&schema_evolution.schema_fields);
+
+        // get hour key
+        let mut hour_key = super::ingestion::get_wal_time_key(
+            timestamp.try_into().unwrap(),
+            &partition_keys,
+            partition_time_level,
+            val_map,
+            Some(&schema_key),
+        );
+
+        if trigger.is_none() && !stream_alerts_map.is_empty() {
+            // Start check for alert trigger
+            let key = format!("{}/{}/{}", &org_id, StreamType::Traces, traces_stream_name);
+            if let Some(alerts) = stream_alerts_map.get(&key) {
+                let mut trigger_alerts: Vec<(Alert, Vec<json::Map<String, json::Value>>)> =
+                    Vec::new();
+                for alert in alerts {
+                    if let Ok(Some(v)) = alert.evaluate(Some(val_map)).await {
+                        trigger_alerts.push((alert.clone(), v));
+                    }
+                }
+                trigger = Some(trigger_alerts);
+            }
+            // End check for alert trigger
+        }
+
+        if partition_keys.is_empty() {
+            let partition_key = format!("service_name={}", service_name);
+            hour_key.push_str(&format!("/{}", format_partition_key(&partition_key)));
+        }
+        let rec_schema = traces_schema_map.get(traces_stream_name).unwrap();
+
+        let hour_buf = data_buf.entry(hour_key).or_insert(SchemaRecords {
+            schema: rec_schema
+                .clone()
+                .with_metadata(std::collections::HashMap::new()),
+            records: vec![],
+        });
+        let loc_value: utils::json::Value = utils::json::from_slice(value_str.as_bytes()).unwrap();
+        hour_buf.records.push(loc_value);
+    }
+
+    let mut traces_file_name = "".to_string();
+    let req_stats = write_file_arrow(
+        &data_buf,
+        thread_id,
+        &StreamParams::new(org_id, traces_stream_name, StreamType::Traces),
+        &mut traces_file_name,
+        None,
+    )
+    .await;
+
+    // send distinct_values
+    if !distinct_values.is_empty() {
+        if let Err(e) = distinct_values::write(org_id, distinct_values).await {
+            log::error!("Error while writing distinct values: {}", e);
+        }
+    }
+
+    // only one trigger per request, as it updates etcd
+    evaluate_trigger(trigger).await;
+
+    req_stats
+}
+
 fn get_span_status(status: Option<Status>) -> String {
     match status {
         Some(v) => match v.code() {
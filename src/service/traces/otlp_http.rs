@@ -302,6 +302,10 @@ pub async fn traces_json(
                         partial_success.rejected_spans += 1;
                         continue;
                     }
+                    super::span_metrics::record(org_id, &local_val);
+                    if super::tail_sampling::buffer(org_id, traces_stream_name, &local_val) {
+                        continue;
+                    }
 
                     let mut value: json::Value = json::to_value(local_val).unwrap();
 
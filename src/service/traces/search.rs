@@ -0,0 +1,429 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{
+    common::{
+        infra::{config::CONFIG, errors::Result},
+        meta::{
+            search,
+            traces::{RootSpan, SpanNode, TraceDetail, TraceSearchRequest, TraceSummary},
+            StreamType,
+        },
+        utils::{flatten::format_key, json},
+    },
+    service::{db, search as search_service},
+};
+
+/// The stream the traces are ingested into.
+pub const TRACES_STREAM: &str = "default";
+
+/// Number of spans loaded per page.
+const SPANS_PAGE_SIZE: usize = 1000;
+
+pub(super) const PARENT_SPAN_ID: &str = "reference_parent_span_id";
+
+/// Finds the traces having a span matching the request, most recent first,
+/// and summarizes them.
+pub async fn search_traces(org_id: &str, req: &TraceSearchRequest) -> Result<Vec<TraceSummary>> {
+    let trace_ids = find_trace_ids(org_id, req).await?;
+    if trace_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let spans = get_spans(org_id, &trace_ids, req.start_time, req.end_time).await?;
+    let mut trace_spans = group_by_trace(spans);
+    Ok(trace_ids
+        .into_iter()
+        .filter_map(|trace_id| {
+            let spans = trace_spans.remove(&trace_id)?;
+            Some(summarize(&trace_id, &spans))
+        })
+        .collect())
+}
+
+/// Finds the ids of the traces having a span matching the request, most
+/// recent first.
+pub(super) async fn find_trace_ids(org_id: &str, req: &TraceSearchRequest) -> Result<Vec<String>> {
+    let schema = db::schema::get(org_id, TRACES_STREAM, StreamType::Traces)
+        .await
+        // `db::schema::get` never fails, so it's safe to unwrap
+        .unwrap();
+    if schema.fields().is_empty() {
+        return Ok(vec![]);
+    }
+    let Some(sql_where) = search_sql_filters(req, |name| schema.field_with_name(name).is_ok())
+    else {
+        // filtering on a field that was never ingested
+        return Ok(vec![]);
+    };
+    let sql = format!(
+        "SELECT trace_id, max({}) AS zo_sql_timestamp FROM {TRACES_STREAM}",
+        CONFIG.common.column_timestamp
+    );
+    let sql = if sql_where.is_empty() {
+        format!("{sql} GROUP BY trace_id ORDER BY zo_sql_timestamp DESC")
+    } else {
+        format!(
+            "{sql} WHERE {} GROUP BY trace_id ORDER BY zo_sql_timestamp DESC",
+            sql_where.join(" AND ")
+        )
+    };
+    let hits = search(
+        org_id,
+        sql,
+        req.from,
+        req.size,
+        req.start_time,
+        req.end_time,
+    )
+    .await?
+    .hits;
+    Ok(hits
+        .iter()
+        .filter_map(|hit| Some(hit.get("trace_id")?.as_str()?.to_string()))
+        .collect())
+}
+
+/// Groups the spans by trace id, keeping their order.
+pub(super) fn group_by_trace(
+    spans: Vec<json::Map<String, json::Value>>,
+) -> HashMap<String, Vec<json::Map<String, json::Value>>> {
+    let mut trace_spans: HashMap<String, Vec<json::Map<String, json::Value>>> = HashMap::new();
+    for span in spans {
+        if let Some(trace_id) = span.get("trace_id").and_then(|v| v.as_str()) {
+            trace_spans
+                .entry(trace_id.to_string())
+                .or_default()
+                .push(span);
+        }
+    }
+    trace_spans
+}
+
+/// Loads all the spans of a trace and assembles them into trees.
+pub async fn get_trace(
+    org_id: &str,
+    trace_id: &str,
+    start_time: i64,
+    end_time: i64,
+) -> Result<Option<TraceDetail>> {
+    let schema = db::schema::get(org_id, TRACES_STREAM, StreamType::Traces)
+        .await
+        // `db::schema::get` never fails, so it's safe to unwrap
+        .unwrap();
+    if schema.fields().is_empty() {
+        return Ok(None);
+    }
+    let spans = get_spans(org_id, &[trace_id.to_string()], start_time, end_time).await?;
+    if spans.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(TraceDetail {
+        summary: summarize(trace_id, &spans),
+        spans: build_span_tree(spans),
+    }))
+}
+
+/// Loads the spans of the traces, ordered by start time.
+pub(super) async fn get_spans(
+    org_id: &str,
+    trace_ids: &[String],
+    start_time: i64,
+    end_time: i64,
+) -> Result<Vec<json::Map<String, json::Value>>> {
+    let sql = format!(
+        "SELECT * FROM {TRACES_STREAM} WHERE trace_id IN ('{}') ORDER BY start_time ASC",
+        trace_ids
+            .iter()
+            .map(|id| escape_sql_string(id))
+            .collect::<Vec<_>>()
+            .join("','")
+    );
+    let mut spans = Vec::new();
+    let mut from = 0;
+    loop {
+        let hits = search(
+            org_id,
+            sql.clone(),
+            from,
+            SPANS_PAGE_SIZE,
+            start_time,
+            end_time,
+        )
+        .await?
+        .hits;
+        let page_size = hits.len();
+        spans.extend(hits.into_iter().filter_map(|hit| match hit {
+            json::Value::Object(span) => Some(span),
+            _ => None,
+        }));
+        if page_size < SPANS_PAGE_SIZE {
+            break;
+        }
+        from += SPANS_PAGE_SIZE;
+    }
+    Ok(spans)
+}
+
+pub(super) async fn search(
+    org_id: &str,
+    sql: String,
+    from: usize,
+    size: usize,
+    start_time: i64,
+    end_time: i64,
+) -> Result<search::Response> {
+    let req = search::Request {
+        query: search::Query {
+            sql,
+            from,
+            size,
+            start_time,
+            end_time,
+            sql_mode: "full".to_string(),
+            ..Default::default()
+        },
+        aggs: HashMap::new(),
+        encoding: search::RequestEncoding::Empty,
+        timeout: 0,
+    };
+    search_service::search("", org_id, StreamType::Traces, &req)
+        .await
+        .map_err(|e| {
+            log::error!("search traces error: {e}");
+            e
+        })
+}
+
+/// Builds the SQL filters of the search request, or `None` when it filters on
+/// a field that doesn't exist.
+fn search_sql_filters(
+    req: &TraceSearchRequest,
+    has_field: impl Fn(&str) -> bool,
+) -> Option<Vec<String>> {
+    let mut filters = Vec::new();
+    if let Some(service_name) = &req.service_name {
+        filters.push(format!(
+            "service_name = '{}'",
+            escape_sql_string(service_name)
+        ));
+    }
+    if let Some(operation_name) = &req.operation_name {
+        filters.push(format!(
+            "operation_name = '{}'",
+            escape_sql_string(operation_name)
+        ));
+    }
+    if let Some(min_duration) = req.min_duration {
+        filters.push(format!("duration >= {min_duration}"));
+    }
+    if let Some(max_duration) = req.max_duration {
+        filters.push(format!("duration <= {max_duration}"));
+    }
+    if let Some(span_status) = &req.span_status {
+        filters.push(format!(
+            "span_status = '{}'",
+            escape_sql_string(&span_status.to_uppercase())
+        ));
+    }
+    for (name, value) in req.attributes.iter() {
+        let name = format_key(name);
+        if !has_field(&name) {
+            return None;
+        }
+        filters.push(format!("\"{name}\" = '{}'", escape_sql_string(value)));
+    }
+    Some(filters)
+}
+
+pub(super) fn escape_sql_string(s: &str) -> String {
+    s.replace('\'', "''")
+}
+
+fn summarize(trace_id: &str, spans: &[json::Map<String, json::Value>]) -> TraceSummary {
+    let span_ids = spans
+        .iter()
+        .filter_map(|span| span.get("span_id")?.as_str())
+        .collect::<BTreeSet<_>>();
+    let mut summary = TraceSummary {
+        trace_id: trace_id.to_string(),
+        span_count: spans.len(),
+        ..Default::default()
+    };
+    let mut services = BTreeSet::new();
+    for span in spans {
+        let start_time = get_i64(span, "start_time");
+        let end_time = get_i64(span, "end_time");
+        if summary.start_time == 0 || start_time < summary.start_time {
+            summary.start_time = start_time;
+        }
+        summary.end_time = summary.end_time.max(end_time);
+        if get_str(span, "span_status") == "ERROR" {
+            summary.error_count += 1;
+        }
+        services.insert(get_str(span, "service_name").to_string());
+        // the root is the earliest span without a parent in the trace
+        if summary.root_span.is_none() && !span_ids.contains(&get_str(span, PARENT_SPAN_ID)) {
+            summary.root_span = Some(RootSpan {
+                span_id: get_str(span, "span_id").to_string(),
+                service_name: get_str(span, "service_name").to_string(),
+                operation_name: get_str(span, "operation_name").to_string(),
+                duration: get_i64(span, "duration"),
+            });
+        }
+    }
+    summary.duration = (summary.end_time - summary.start_time) / 1000;
+    summary.services = services.into_iter().collect();
+    summary
+}
+
+/// Assembles the spans, ordered by start time, into trees. Spans whose parent
+/// is missing from the trace are roots.
+fn build_span_tree(spans: Vec<json::Map<String, json::Value>>) -> Vec<SpanNode> {
+    let span_ids = spans
+        .iter()
+        .map(|span| get_str(span, "span_id").to_string())
+        .collect::<BTreeSet<_>>();
+    let mut roots = Vec::new();
+    let mut children: HashMap<String, Vec<json::Map<String, json::Value>>> = HashMap::new();
+    for span in spans {
+        let parent_id = get_str(&span, PARENT_SPAN_ID).to_string();
+        if span_ids.contains(&parent_id) && parent_id != get_str(&span, "span_id") {
+            children.entry(parent_id).or_default().push(span);
+        } else {
+            roots.push(span);
+        }
+    }
+    roots
+        .into_iter()
+        .map(|span| attach_children(span, &mut children))
+        .collect()
+}
+
+fn attach_children(
+    span: json::Map<String, json::Value>,
+    children: &mut HashMap<String, Vec<json::Map<String, json::Value>>>,
+) -> SpanNode {
+    // removing the children guards against cycles in the parent span ids
+    let span_children = children
+        .remove(get_str(&span, "span_id"))
+        .unwrap_or_default();
+    SpanNode {
+        span,
+        children: span_children
+            .into_iter()
+            .map(|child| attach_children(child, children))
+            .collect(),
+    }
+}
+
+pub(super) fn get_str<'a>(span: &'a json::Map<String, json::Value>, key: &str) -> &'a str {
+    span.get(key).and_then(|v| v.as_str()).unwrap_or_default()
+}
+
+pub(super) fn get_i64(span: &json::Map<String, json::Value>, key: &str) -> i64 {
+    span.get(key).and_then(|v| v.as_i64()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(
+        span_id: &str,
+        parent_id: &str,
+        start_time: i64,
+        status: &str,
+    ) -> json::Map<String, json::Value> {
+        let mut span = json::json!({
+            "trace_id": "t1",
+            "span_id": span_id,
+            "service_name": format!("svc-{span_id}"),
+            "operation_name": format!("op-{span_id}"),
+            "start_time": start_time,
+            "end_time": start_time + 10_000,
+            "duration": 10,
+            "span_status": status,
+        });
+        if !parent_id.is_empty() {
+            span[PARENT_SPAN_ID] = json::Value::from(parent_id);
+        }
+        span.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_search_sql_filters() {
+        let req = TraceSearchRequest {
+            service_name: Some("cart".to_string()),
+            operation_name: Some("it's".to_string()),
+            min_duration: Some(100),
+            max_duration: Some(200),
+            span_status: Some("error".to_string()),
+            attributes: vec![("http.method".to_string(), "GET".to_string())],
+            ..Default::default()
+        };
+        assert_eq!(
+            search_sql_filters(&req, |name| name == "http_method"),
+            Some(vec![
+                "service_name = 'cart'".to_string(),
+                "operation_name = 'it''s'".to_string(),
+                "duration >= 100".to_string(),
+                "duration <= 200".to_string(),
+                "span_status = 'ERROR'".to_string(),
+                "\"http_method\" = 'GET'".to_string(),
+            ])
+        );
+        assert_eq!(search_sql_filters(&req, |_| false), None);
+    }
+
+    #[test]
+    fn test_summarize() {
+        let spans = vec![
+            span("a", "", 1_000_000, "OK"),
+            span("b", "a", 2_000_000, "ERROR"),
+            span("c", "b", 3_000_000, "UNSET"),
+        ];
+        let summary = summarize("t1", &spans);
+        assert_eq!(summary.span_count, 3);
+        assert_eq!(summary.error_count, 1);
+        assert_eq!(summary.start_time, 1_000_000);
+        assert_eq!(summary.end_time, 3_010_000);
+        assert_eq!(summary.duration, 2_010);
+        assert_eq!(summary.services, vec!["svc-a", "svc-b", "svc-c"]);
+        assert_eq!(summary.root_span.unwrap().span_id, "a");
+    }
+
+    #[test]
+    fn test_build_span_tree() {
+        let spans = vec![
+            span("a", "", 1, "OK"),
+            span("b", "a", 2, "OK"),
+            span("c", "a", 3, "OK"),
+            span("d", "b", 4, "OK"),
+            span("e", "missing", 5, "OK"),
+        ];
+        let tree = build_span_tree(spans);
+        assert_eq!(tree.len(), 2);
+        assert_eq!(get_str(&tree[0].span, "span_id"), "a");
+        assert_eq!(get_str(&tree[1].span, "span_id"), "e");
+        let children = &tree[0].children;
+        assert_eq!(children.len(), 2);
+        assert_eq!(get_str(&children[0].span, "span_id"), "b");
+        assert_eq!(get_str(&children[0].children[0].span, "span_id"), "d");
+        assert_eq!(get_str(&children[1].span, "span_id"), "c");
+    }
+}
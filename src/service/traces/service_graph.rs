@@ -0,0 +1,433 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use chrono::Utc;
+
+use super::search::{self as trace_search, PARENT_SPAN_ID, TRACES_STREAM};
+use crate::{
+    common::{
+        infra::{cluster::LOCAL_NODE_UUID, config::CONFIG},
+        meta::{
+            search,
+            traces::{ServiceGraph, ServiceGraphEdge, SERVICE_GRAPH_STREAM},
+            StreamType,
+        },
+        utils::json,
+    },
+    handler::grpc::cluster_rpc,
+    service::{db, search as search_service, usage::ingestion_service},
+};
+
+/// Number of spans loaded per page.
+const SPANS_PAGE_SIZE: usize = 10000;
+
+/// Maximum number of windows materialized per organization and run, so that a
+/// node catching up doesn't hold the job for too long.
+const MAX_WINDOWS_PER_RUN: i64 = 12;
+
+/// The span fields needed to pair the calls between services.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct SpanRef {
+    trace_id: String,
+    span_id: String,
+    parent_span_id: String,
+    service_name: String,
+    duration: i64,
+    is_error: bool,
+    timestamp: i64,
+}
+
+/// Materializes the service graph of the windows which ended since the last
+/// run, for every organization having traces. A window is only processed once
+/// the next one has ended, to leave time for its spans to be ingested.
+pub async fn run() -> Result<(), anyhow::Error> {
+    let interval = CONFIG.limit.service_graph_interval * 1_000_000;
+    if interval <= 0 {
+        return Ok(());
+    }
+    let node = db::alerts::alert_manager::get_mark("default").await;
+    if LOCAL_NODE_UUID.ne(&node) {
+        return Ok(());
+    }
+
+    let now = Utc::now().timestamp_micros();
+    let end = now - now % interval - interval;
+    for org_id in db::schema::list_organizations_from_cache() {
+        let schema = db::schema::get(&org_id, TRACES_STREAM, StreamType::Traces).await?;
+        if schema.fields().is_empty() {
+            continue;
+        }
+        let mut offset = db::service_graph::get_offset(&org_id).await;
+        if offset == 0 || offset < end - interval * MAX_WINDOWS_PER_RUN {
+            offset = end - interval;
+        }
+        while offset < end {
+            if let Err(e) = materialize(&org_id, offset, offset + interval).await {
+                log::error!(
+                    "[SERVICE GRAPH] materialize org {} window {} err: {}",
+                    org_id,
+                    offset,
+                    e
+                );
+                break;
+            }
+            offset += interval;
+            db::service_graph::set_offset(&org_id, offset).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Pairs the spans of the window with their parents and writes the edges
+/// between services to the `_service_graph` stream.
+async fn materialize(org_id: &str, start: i64, end: i64) -> Result<(), anyhow::Error> {
+    let schema = db::schema::get(org_id, TRACES_STREAM, StreamType::Traces).await?;
+    if schema.field_with_name(PARENT_SPAN_ID).is_err() {
+        // no span has a parent yet
+        return Ok(());
+    }
+
+    // parents start before their children, so that they are also looked up in
+    // the previous window
+    let spans = get_spans(org_id, start - (end - start), end).await?;
+    let edges = compute_edges(&spans, start, end);
+    if edges.is_empty() {
+        return Ok(());
+    }
+    let req = cluster_rpc::UsageRequest {
+        stream_name: SERVICE_GRAPH_STREAM.to_owned(),
+        data: Some(cluster_rpc::UsageData::from(
+            edges
+                .iter()
+                .map(json::to_value)
+                .collect::<Result<Vec<_>, _>>()?,
+        )),
+    };
+    let resp = ingestion_service::ingest(org_id, req).await?;
+    if resp.status_code != 200 {
+        return Err(anyhow::anyhow!(
+            "Error saving service graph: {}",
+            resp.message
+        ));
+    }
+    Ok(())
+}
+
+async fn get_spans(org_id: &str, start: i64, end: i64) -> Result<Vec<SpanRef>, anyhow::Error> {
+    // the pages are only consistent when ordered by a unique key
+    let sql = format!(
+        "SELECT trace_id, span_id, {PARENT_SPAN_ID}, service_name, duration, span_status, {ts} FROM {TRACES_STREAM} ORDER BY {ts}, trace_id, span_id",
+        ts = CONFIG.common.column_timestamp
+    );
+    let mut spans = Vec::new();
+    let mut from = 0;
+    loop {
+        let hits = trace_search::search(org_id, sql.clone(), from, SPANS_PAGE_SIZE, start, end)
+            .await?
+            .hits;
+        let page_size = hits.len();
+        spans.extend(hits.iter().map(|hit| {
+            let get_str = |key: &str| {
+                hit.get(key)
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            let get_i64 = |key: &str| hit.get(key).and_then(|v| v.as_i64()).unwrap_or_default();
+            SpanRef {
+                trace_id: get_str("trace_id"),
+                span_id: get_str("span_id"),
+                parent_span_id: get_str(PARENT_SPAN_ID),
+                service_name: get_str("service_name"),
+                duration: get_i64("duration"),
+                is_error: get_str("span_status") == "ERROR",
+                timestamp: get_i64(&CONFIG.common.column_timestamp),
+            }
+        }));
+        if page_size < SPANS_PAGE_SIZE {
+            break;
+        }
+        from += SPANS_PAGE_SIZE;
+    }
+    Ok(spans)
+}
+
+/// Computes the edges of the calls between services of the spans started in
+/// `[start, end)`: a call is a span whose parent belongs to another service.
+fn compute_edges(spans: &[SpanRef], start: i64, end: i64) -> Vec<ServiceGraphEdge> {
+    let services: HashMap<(&str, &str), &str> = spans
+        .iter()
+        .map(|span| {
+            (
+                (span.trace_id.as_str(), span.span_id.as_str()),
+                span.service_name.as_str(),
+            )
+        })
+        .collect();
+    let mut calls: BTreeMap<(&str, &str), (Vec<i64>, u64)> = BTreeMap::new();
+    for span in spans {
+        if span.timestamp < start || span.timestamp >= end || span.parent_span_id.is_empty() {
+            continue;
+        }
+        let Some(caller) = services.get(&(span.trace_id.as_str(), span.parent_span_id.as_str()))
+        else {
+            continue;
+        };
+        if *caller == span.service_name {
+            continue;
+        }
+        let entry = calls
+            .entry((*caller, span.service_name.as_str()))
+            .or_default();
+        entry.0.push(span.duration);
+        if span.is_error {
+            entry.1 += 1;
+        }
+    }
+    calls
+        .into_iter()
+        .map(|((caller, callee), (mut durations, error_count))| {
+            durations.sort_unstable();
+            let call_count = durations.len() as u64;
+            ServiceGraphEdge {
+                _timestamp: start,
+                caller: caller.to_string(),
+                callee: callee.to_string(),
+                call_count,
+                error_count,
+                error_rate: error_count as f64 / call_count as f64,
+                p50_latency: percentile(&durations, 0.5),
+                p95_latency: percentile(&durations, 0.95),
+            }
+        })
+        .collect()
+}
+
+/// Nearest-rank percentile of sorted values.
+fn percentile(sorted: &[i64], q: f64) -> i64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (q * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Returns the service graph over the time range, from the materialized
+/// windows which started in it.
+pub async fn get(org_id: &str, start: i64, end: i64) -> Result<ServiceGraph, anyhow::Error> {
+    let schema = db::schema::get(org_id, SERVICE_GRAPH_STREAM, StreamType::Logs).await?;
+    if schema.fields().is_empty() {
+        // no service graph has been materialized yet
+        return Ok(ServiceGraph::default());
+    }
+
+    // the pages are only consistent when ordered by a unique key, a window has
+    // one edge per caller and callee
+    let sql = format!(
+        "SELECT * FROM \"{SERVICE_GRAPH_STREAM}\" ORDER BY {}, caller, callee",
+        CONFIG.common.column_timestamp
+    );
+    let mut edges = Vec::new();
+    let mut from = 0;
+    loop {
+        let req = search::Request {
+            query: search::Query {
+                sql: sql.clone(),
+                from,
+                size: SPANS_PAGE_SIZE,
+                start_time: start,
+                end_time: end,
+                sql_mode: "full".to_string(),
+                ..Default::default()
+            },
+            aggs: HashMap::new(),
+            encoding: search::RequestEncoding::Empty,
+            timeout: 0,
+        };
+        let hits = search_service::search("", org_id, StreamType::Logs, &req)
+            .await
+            .map_err(|e| anyhow::anyhow!("Error searching service graph: {}", e))?
+            .hits;
+        let page_size = hits.len();
+        edges.extend(
+            hits.into_iter()
+                .filter_map(|hit| json::from_value::<ServiceGraphEdge>(hit).ok()),
+        );
+        if page_size < SPANS_PAGE_SIZE {
+            break;
+        }
+        from += SPANS_PAGE_SIZE;
+    }
+    Ok(merge_edges(edges, start))
+}
+
+/// Merges the edges of the windows. The latencies of an edge are the means of
+/// the windows latencies, weighted by their call counts: the windows only keep
+/// their percentiles, so this is an approximation of the percentiles over the
+/// time range, exact when it covers a single window.
+fn merge_edges(edges: Vec<ServiceGraphEdge>, start: i64) -> ServiceGraph {
+    let mut merged: BTreeMap<(String, String), (ServiceGraphEdge, f64, f64)> = BTreeMap::new();
+    for edge in edges {
+        let weight = edge.call_count as f64;
+        let entry = merged
+            .entry((edge.caller.clone(), edge.callee.clone()))
+            .or_insert_with(|| {
+                (
+                    ServiceGraphEdge {
+                        _timestamp: start,
+                        caller: edge.caller.clone(),
+                        callee: edge.callee.clone(),
+                        ..Default::default()
+                    },
+                    0.0,
+                    0.0,
+                )
+            });
+        entry.0.call_count += edge.call_count;
+        entry.0.error_count += edge.error_count;
+        entry.1 += edge.p50_latency as f64 * weight;
+        entry.2 += edge.p95_latency as f64 * weight;
+    }
+
+    let mut nodes = BTreeSet::new();
+    let edges = merged
+        .into_values()
+        .map(|(mut edge, p50, p95)| {
+            if edge.call_count > 0 {
+                let calls = edge.call_count as f64;
+                edge.error_rate = edge.error_count as f64 / calls;
+                edge.p50_latency = (p50 / calls).round() as i64;
+                edge.p95_latency = (p95 / calls).round() as i64;
+            }
+            nodes.insert(edge.caller.clone());
+            nodes.insert(edge.callee.clone());
+            edge
+        })
+        .collect();
+    ServiceGraph {
+        nodes: nodes.into_iter().collect(),
+        edges,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(
+        trace_id: &str,
+        span_id: &str,
+        parent_span_id: &str,
+        service_name: &str,
+        duration: i64,
+        is_error: bool,
+        timestamp: i64,
+    ) -> SpanRef {
+        SpanRef {
+            trace_id: trace_id.to_string(),
+            span_id: span_id.to_string(),
+            parent_span_id: parent_span_id.to_string(),
+            service_name: service_name.to_string(),
+            duration,
+            is_error,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn test_compute_edges() {
+        let spans = vec![
+            // the parent started in the previous window
+            span("t1", "a", "", "frontend", 500, false, 90),
+            span("t1", "b", "a", "cart", 100, false, 110),
+            span("t1", "c", "b", "cart", 50, false, 120),
+            span("t1", "d", "b", "redis", 10, true, 130),
+            span("t2", "a", "", "frontend", 500, false, 140),
+            span("t2", "b", "a", "cart", 300, true, 150),
+            // the parent is in another trace
+            span("t3", "x", "a", "cart", 1000, false, 160),
+            // out of the window
+            span("t4", "a", "", "frontend", 500, false, 195),
+            span("t4", "b", "a", "cart", 1000, false, 200),
+        ];
+        let edges = compute_edges(&spans, 100, 200);
+        assert_eq!(
+            edges,
+            vec![
+                ServiceGraphEdge {
+                    _timestamp: 100,
+                    caller: "cart".to_string(),
+                    callee: "redis".to_string(),
+                    call_count: 1,
+                    error_count: 1,
+                    error_rate: 1.0,
+                    p50_latency: 10,
+                    p95_latency: 10,
+                },
+                ServiceGraphEdge {
+                    _timestamp: 100,
+                    caller: "frontend".to_string(),
+                    callee: "cart".to_string(),
+                    call_count: 2,
+                    error_count: 1,
+                    error_rate: 0.5,
+                    p50_latency: 100,
+                    p95_latency: 300,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_percentile() {
+        let values = (1..=100).collect::<Vec<i64>>();
+        assert_eq!(percentile(&values, 0.5), 50);
+        assert_eq!(percentile(&values, 0.95), 95);
+        assert_eq!(percentile(&values, 0.0), 1);
+        assert_eq!(percentile(&[7], 0.95), 7);
+        assert_eq!(percentile(&[], 0.5), 0);
+    }
+
+    #[test]
+    fn test_merge_edges() {
+        let edge = |caller: &str, callee: &str, calls, errors, p50, p95| ServiceGraphEdge {
+            caller: caller.to_string(),
+            callee: callee.to_string(),
+            call_count: calls,
+            error_count: errors,
+            p50_latency: p50,
+            p95_latency: p95,
+            ..Default::default()
+        };
+        let graph = merge_edges(
+            vec![
+                edge("frontend", "cart", 3, 0, 100, 200),
+                edge("cart", "redis", 4, 1, 10, 20),
+                edge("frontend", "cart", 1, 1, 500, 600),
+            ],
+            0,
+        );
+        assert_eq!(graph.nodes, vec!["cart", "frontend", "redis"]);
+        assert_eq!(graph.edges.len(), 2);
+        assert_eq!(graph.edges[1].caller, "frontend");
+        assert_eq!(graph.edges[1].call_count, 4);
+        assert_eq!(graph.edges[1].error_count, 1);
+        assert_eq!(graph.edges[1].error_rate, 0.25);
+        assert_eq!(graph.edges[1].p50_latency, 200);
+        assert_eq!(graph.edges[1].p95_latency, 300);
+    }
+}
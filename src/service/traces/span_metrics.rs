@@ -0,0 +1,366 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Aggregates the ingested spans into request, error and duration (RED)
+//! metrics, written as Prometheus metrics.
+//!
+//! The metrics are cumulative per ingester: every ingester keeps its own
+//! series, labelled by `instance`, and writes them periodically.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::Utc;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use crate::{
+    common::{
+        infra::config::{RwHashMap, CONFIG},
+        meta::{prom::NAME_LABEL, traces::Span},
+        utils::json,
+    },
+    service::metrics::{
+        format_label_name,
+        prom::{self, prometheus},
+    },
+};
+
+pub const CALLS_METRIC: &str = "traces_spanmetrics_calls_total";
+pub const DURATION_METRIC: &str = "traces_spanmetrics_duration";
+
+/// Series which received no span for this long are no longer written, in
+/// microseconds. Matches the staleness of Prometheus.
+const SERIES_EXPIRY: i64 = 5 * 60 * 1_000_000;
+
+/// Upper bounds of the duration buckets, in seconds.
+static BUCKETS: Lazy<Vec<f64>> = Lazy::new(|| {
+    let mut buckets: Vec<f64> = CONFIG
+        .common
+        .traces_span_metrics_buckets
+        .split(',')
+        .filter_map(|v| v.trim().parse().ok())
+        .collect();
+    buckets.sort_by(|a, b| a.total_cmp(b));
+    buckets.dedup();
+    buckets
+});
+
+/// Span or resource attributes added to the labels of the series.
+static DIMENSIONS: Lazy<Vec<String>> = Lazy::new(|| {
+    CONFIG
+        .common
+        .traces_span_metrics_dimensions
+        .split(',')
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect()
+});
+
+/// The series keyed by organization and labels.
+static SERIES: Lazy<RwHashMap<(String, Vec<(String, String)>), SpanSeries>> =
+    Lazy::new(Default::default);
+
+/// The organizations whose metrics metadata was written by this node.
+static METADATA_WRITTEN: Lazy<Mutex<HashSet<String>>> = Lazy::new(Default::default);
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct SpanSeries {
+    calls: u64,
+    /// Number of spans per bucket, not cumulative. The spans above the last
+    /// bound are only counted by `calls`.
+    bucket_counts: Vec<u64>,
+    /// Sum of the durations, in seconds.
+    sum: f64,
+    /// Time of the last span, in microseconds.
+    updated_at: i64,
+}
+
+impl SpanSeries {
+    fn observe(&mut self, buckets: &[f64], duration: f64, time: i64) {
+        if self.bucket_counts.len() != buckets.len() {
+            self.bucket_counts.resize(buckets.len(), 0);
+        }
+        self.calls += 1;
+        self.sum += duration;
+        if let Some(i) = buckets.iter().position(|bound| duration <= *bound) {
+            self.bucket_counts[i] += 1;
+        }
+        self.updated_at = time;
+    }
+}
+
+/// Records the span into the metrics of the organization.
+pub fn record(org_id: &str, span: &Span) {
+    if !CONFIG.common.traces_span_metrics_enabled {
+        return;
+    }
+    let labels = span_labels(span, &DIMENSIONS);
+    let duration = span.duration as f64 / 1_000_000.0; // microseconds to seconds
+    SERIES
+        .entry((org_id.to_string(), labels))
+        .or_default()
+        .observe(&BUCKETS, duration, Utc::now().timestamp_micros());
+}
+
+/// Writes the series updated recently, and forgets the others.
+pub async fn flush() -> Result<(), anyhow::Error> {
+    let now = Utc::now().timestamp_micros();
+    SERIES.retain(|_, series| series.updated_at >= now - SERIES_EXPIRY);
+
+    let mut org_timeseries: HashMap<String, Vec<prometheus::TimeSeries>> = HashMap::new();
+    for item in SERIES.iter() {
+        let (org_id, labels) = item.key();
+        org_timeseries
+            .entry(org_id.clone())
+            .or_default()
+            .extend(to_timeseries(labels, item.value(), &BUCKETS, now / 1000));
+    }
+    for (org_id, timeseries) in org_timeseries {
+        let write_metadata = !METADATA_WRITTEN.lock().contains(&org_id);
+        let request = prometheus::WriteRequest {
+            timeseries,
+            metadata: if write_metadata { metadata() } else { vec![] },
+        };
+        if let Err(e) = prom::write_timeseries(&org_id, &request).await {
+            log::error!("[SPAN METRICS] write org {} err: {}", org_id, e);
+            continue;
+        }
+        if write_metadata {
+            METADATA_WRITTEN.lock().insert(org_id);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the labels of the span series, sorted by name.
+fn span_labels(span: &Span, dimensions: &[String]) -> Vec<(String, String)> {
+    let mut labels = vec![
+        ("service_name".to_string(), span.service_name.clone()),
+        ("span_name".to_string(), span.operation_name.clone()),
+        (
+            "span_kind".to_string(),
+            span_kind(&span.span_kind).to_string(),
+        ),
+        (
+            "status_code".to_string(),
+            format!("STATUS_CODE_{}", span.span_status),
+        ),
+        ("instance".to_string(), CONFIG.common.instance_name.clone()),
+    ];
+    for dimension in dimensions {
+        let value = span
+            .attributes
+            .get(dimension)
+            .or_else(|| span.service.get(&format!("service.{dimension}")))
+            .or_else(|| span.service.get(dimension));
+        let value = match value {
+            Some(json::Value::String(v)) => v.clone(),
+            Some(json::Value::Null) | None => continue,
+            Some(v) => v.to_string(),
+        };
+        let name = format_label_name(dimension);
+        if labels.iter().all(|(n, _)| *n != name) {
+            labels.push((name, value));
+        }
+    }
+    labels.sort();
+    labels
+}
+
+/// Names the OTLP span kind as the OpenTelemetry span metrics connector does.
+fn span_kind(kind: &str) -> &'static str {
+    match kind {
+        "1" => "SPAN_KIND_INTERNAL",
+        "2" => "SPAN_KIND_SERVER",
+        "3" => "SPAN_KIND_CLIENT",
+        "4" => "SPAN_KIND_PRODUCER",
+        "5" => "SPAN_KIND_CONSUMER",
+        _ => "SPAN_KIND_UNSPECIFIED",
+    }
+}
+
+/// Converts the series into the calls counter and the duration histogram, at
+/// `time` in milliseconds.
+fn to_timeseries(
+    labels: &[(String, String)],
+    series: &SpanSeries,
+    buckets: &[f64],
+    time: i64,
+) -> Vec<prometheus::TimeSeries> {
+    let timeseries = |name: String, extra: Option<(&str, String)>, value: f64| {
+        let mut labels = labels
+            .iter()
+            .map(|(name, value)| prometheus::Label {
+                name: name.clone(),
+                value: value.clone(),
+            })
+            .collect::<Vec<_>>();
+        labels.push(prometheus::Label {
+            name: NAME_LABEL.to_string(),
+            value: name,
+        });
+        if let Some((name, value)) = extra {
+            labels.push(prometheus::Label {
+                name: name.to_string(),
+                value,
+            });
+        }
+        prometheus::TimeSeries {
+            labels,
+            samples: vec![prometheus::Sample {
+                value,
+                timestamp: time,
+            }],
+            ..Default::default()
+        }
+    };
+
+    let mut result = Vec::with_capacity(buckets.len() + 4);
+    result.push(timeseries(
+        CALLS_METRIC.to_string(),
+        None,
+        series.calls as f64,
+    ));
+    let mut cumulative = 0;
+    for (bound, count) in buckets.iter().zip(series.bucket_counts.iter()) {
+        cumulative += count;
+        result.push(timeseries(
+            format!("{DURATION_METRIC}_bucket"),
+            Some(("le", bound.to_string())),
+            cumulative as f64,
+        ));
+    }
+    result.push(timeseries(
+        format!("{DURATION_METRIC}_bucket"),
+        Some(("le", "+Inf".to_string())),
+        series.calls as f64,
+    ));
+    result.push(timeseries(
+        format!("{DURATION_METRIC}_sum"),
+        None,
+        series.sum,
+    ));
+    result.push(timeseries(
+        format!("{DURATION_METRIC}_count"),
+        None,
+        series.calls as f64,
+    ));
+    result
+}
+
+fn metadata() -> Vec<prometheus::MetricMetadata> {
+    vec![
+        prometheus::MetricMetadata {
+            r#type: prometheus::metric_metadata::MetricType::Counter as i32,
+            metric_family_name: CALLS_METRIC.to_string(),
+            help: "Number of spans".to_string(),
+            unit: String::new(),
+        },
+        prometheus::MetricMetadata {
+            r#type: prometheus::metric_metadata::MetricType::Histogram as i32,
+            metric_family_name: DURATION_METRIC.to_string(),
+            help: "Duration of the spans".to_string(),
+            unit: "seconds".to_string(),
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use ahash::AHashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_span_labels() {
+        let span = Span {
+            trace_id: "t1".to_string(),
+            span_id: "s1".to_string(),
+            flags: 1,
+            span_status: "ERROR".to_string(),
+            span_kind: "2".to_string(),
+            operation_name: "GET /cart".to_string(),
+            start_time: 0,
+            end_time: 0,
+            duration: 0,
+            reference: AHashMap::new(),
+            service_name: "cart".to_string(),
+            attributes: AHashMap::from_iter([(
+                "http.status_code".to_string(),
+                json::Value::from(500),
+            )]),
+            service: AHashMap::from_iter([(
+                "service.k8s.namespace".to_string(),
+                json::Value::from("shop"),
+            )]),
+            events: String::new(),
+        };
+        let dimensions = ["http.status_code", "k8s.namespace", "missing"]
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>();
+        let labels = span_labels(&span, &dimensions);
+        let get = |name: &str| {
+            labels
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.as_str())
+        };
+        assert_eq!(get("service_name"), Some("cart"));
+        assert_eq!(get("span_name"), Some("GET /cart"));
+        assert_eq!(get("span_kind"), Some("SPAN_KIND_SERVER"));
+        assert_eq!(get("status_code"), Some("STATUS_CODE_ERROR"));
+        assert_eq!(get("http_status_code"), Some("500"));
+        assert_eq!(get("k8s_namespace"), Some("shop"));
+        assert_eq!(get("missing"), None);
+        assert!(labels.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn test_to_timeseries() {
+        let buckets = [0.1, 1.0];
+        let mut series = SpanSeries::default();
+        for duration in [0.0625, 0.5, 0.75, 3.0] {
+            series.observe(&buckets, duration, 1);
+        }
+        let labels = vec![("service_name".to_string(), "cart".to_string())];
+        let values = to_timeseries(&labels, &series, &buckets, 1000)
+            .into_iter()
+            .map(|ts| {
+                let name = ts
+                    .labels
+                    .iter()
+                    .filter(|l| l.name == NAME_LABEL || l.name == "le")
+                    .map(|l| l.value.clone())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                assert_eq!(ts.labels[0].value, "cart");
+                assert_eq!(ts.samples[0].timestamp, 1000);
+                (name, ts.samples[0].value)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            vec![
+                ("traces_spanmetrics_calls_total".to_string(), 4.0),
+                ("traces_spanmetrics_duration_bucket,0.1".to_string(), 1.0),
+                ("traces_spanmetrics_duration_bucket,1".to_string(), 3.0),
+                ("traces_spanmetrics_duration_bucket,+Inf".to_string(), 4.0),
+                ("traces_spanmetrics_duration_sum".to_string(), 4.3125),
+                ("traces_spanmetrics_duration_count".to_string(), 4.0),
+            ]
+        );
+    }
+}
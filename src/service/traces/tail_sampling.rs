@@ -0,0 +1,368 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Tail-based sampling of the ingested traces.
+//!
+//! The spans are buffered per trace for the decision wait, then the whole trace
+//! is kept when it matches one of the policies, or dropped. The decisions are
+//! taken per ingester, so the spans of a trace should be sent to the same
+//! ingester.
+
+use std::collections::HashMap;
+
+use chrono::Utc;
+use once_cell::sync::Lazy;
+
+use crate::{
+    common::{
+        infra::{
+            config::{RwHashMap, CONFIG},
+            metrics,
+        },
+        meta::{traces::Span, usage::UsageType, StreamType},
+        utils::json,
+    },
+    service::usage::report_request_usage_stats,
+};
+
+/// The decisions are remembered this long to apply them to the late spans, in
+/// microseconds.
+const DECISION_TTL: i64 = 5 * 60 * 1_000_000;
+
+/// Organization, stream and trace id.
+type TraceKey = (String, String, String);
+
+#[derive(Debug, Default)]
+struct PendingTrace {
+    /// Time the first span was buffered, in microseconds.
+    first_seen: i64,
+    spans: Vec<Span>,
+}
+
+static PENDING: Lazy<RwHashMap<TraceKey, PendingTrace>> = Lazy::new(Default::default);
+
+/// Whether the trace was kept, and the time of the decision in microseconds.
+static DECISIONS: Lazy<RwHashMap<TraceKey, (bool, i64)>> = Lazy::new(Default::default);
+
+static POLICIES: Lazy<Policies> = Lazy::new(Policies::from_config);
+
+#[derive(Debug, Default)]
+struct Policies {
+    keep_errors: bool,
+    /// In microseconds, 0 disables the policy.
+    min_duration: i64,
+    attributes: Vec<(String, String)>,
+    percentage: u64,
+}
+
+impl Policies {
+    fn from_config() -> Self {
+        Self {
+            keep_errors: CONFIG.common.traces_tail_sampling_keep_errors,
+            min_duration: CONFIG.common.traces_tail_sampling_min_duration * 1000,
+            attributes: CONFIG
+                .common
+                .traces_tail_sampling_attributes
+                .split(',')
+                .filter_map(|v| v.split_once('='))
+                .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+                .filter(|(k, _)| !k.is_empty())
+                .collect(),
+            percentage: CONFIG.common.traces_tail_sampling_percentage.min(100),
+        }
+    }
+
+    /// Returns the policy keeping the trace, `None` when it should be dropped.
+    fn decide(&self, trace_id: &str, spans: &[Span]) -> Option<&'static str> {
+        if self.keep_errors && spans.iter().any(is_error) {
+            return Some("error");
+        }
+        if self.min_duration > 0 {
+            let start = spans.iter().map(|s| s.start_time).min().unwrap_or_default();
+            let end = spans.iter().map(|s| s.end_time).max().unwrap_or_default();
+            // nanoseconds to microseconds
+            if end.saturating_sub(start) / 1000 > self.min_duration as u64 {
+                return Some("duration");
+            }
+        }
+        if !self.attributes.is_empty()
+            && spans.iter().any(|span| {
+                self.attributes
+                    .iter()
+                    .any(|(name, value)| attribute_matches(span, name, value))
+            })
+        {
+            return Some("attribute");
+        }
+        if self.percentage > 0 && trace_id_ratio(trace_id) % 100 < self.percentage {
+            return Some("probabilistic");
+        }
+        None
+    }
+}
+
+/// Buffers the span until the decision on its trace, returns `false` when the
+/// span should be written right away: the sampling is disabled, the buffer is
+/// full, or the trace was already kept.
+pub fn buffer(org_id: &str, stream_name: &str, span: &Span) -> bool {
+    if !CONFIG.common.traces_tail_sampling_enabled {
+        return false;
+    }
+    let key = (
+        org_id.to_string(),
+        stream_name.to_string(),
+        span.trace_id.clone(),
+    );
+    if let Some(decision) = DECISIONS.get(&key) {
+        // a late span follows the decision on its trace
+        let keep = decision.0;
+        count_spans(org_id, stream_name, keep, 1);
+        return !keep;
+    }
+    if !PENDING.contains_key(&key) && PENDING.len() >= CONFIG.limit.traces_tail_sampling_max_traces
+    {
+        // keep the trace rather than losing it without a decision
+        return false;
+    }
+    PENDING
+        .entry(key)
+        .or_insert_with(|| PendingTrace {
+            first_seen: Utc::now().timestamp_micros(),
+            spans: vec![],
+        })
+        .spans
+        .push(span.clone());
+    true
+}
+
+/// Takes the decisions on the traces buffered for the decision wait, and
+/// writes the spans of the kept ones.
+pub async fn run() -> Result<(), anyhow::Error> {
+    let now = Utc::now().timestamp_micros();
+    DECISIONS.retain(|_, (_, decided_at)| *decided_at >= now - DECISION_TTL);
+
+    let wait = CONFIG.limit.traces_tail_sampling_decision_wait * 1_000_000;
+    decide_and_write(now, now - wait).await;
+    Ok(())
+}
+
+/// Takes the decisions on all the buffered traces without waiting, so that the
+/// kept spans are written before the node stops.
+pub async fn close() -> Result<(), anyhow::Error> {
+    if PENDING.is_empty() {
+        return Ok(());
+    }
+    log::info!("[TAIL SAMPLING] flushing {} buffered traces", PENDING.len());
+    let now = Utc::now().timestamp_micros();
+    decide_and_write(now, now).await;
+    Ok(())
+}
+
+/// Takes the decisions on the traces first seen before `seen_before`, in
+/// microseconds, and writes the spans of the kept ones.
+async fn decide_and_write(now: i64, seen_before: i64) {
+    let expired = PENDING
+        .iter()
+        .filter(|trace| trace.first_seen <= seen_before)
+        .map(|trace| trace.key().clone())
+        .collect::<Vec<_>>();
+    let mut kept_spans: HashMap<(String, String), Vec<Span>> = HashMap::new();
+    for key in expired {
+        let Some((key, trace)) = PENDING.remove(&key) else {
+            continue;
+        };
+        let (org_id, stream_name, trace_id) = &key;
+        let decision = POLICIES.decide(trace_id, &trace.spans);
+        metrics::TRACES_TAIL_SAMPLING_TRACES
+            .with_label_values(&[org_id, stream_name, decision.unwrap_or("dropped")])
+            .inc();
+        count_spans(
+            org_id,
+            stream_name,
+            decision.is_some(),
+            trace.spans.len() as u64,
+        );
+        if decision.is_some() {
+            kept_spans
+                .entry((org_id.clone(), stream_name.clone()))
+                .or_default()
+                .extend(trace.spans);
+        }
+        DECISIONS.insert(key, (decision.is_some(), now));
+    }
+    metrics::TRACES_TAIL_SAMPLING_BUFFERED_TRACES
+        .with_label_values(&[])
+        .set(PENDING.len() as i64);
+
+    for ((org_id, stream_name), spans) in kept_spans {
+        let req_stats = super::write_spans(&org_id, 0, &stream_name, spans).await;
+        report_request_usage_stats(
+            req_stats,
+            &org_id,
+            &stream_name,
+            StreamType::Traces,
+            UsageType::Traces,
+            0,
+        )
+        .await;
+    }
+}
+
+fn count_spans(org_id: &str, stream_name: &str, kept: bool, count: u64) {
+    metrics::TRACES_TAIL_SAMPLING_SPANS
+        .with_label_values(&[org_id, stream_name, if kept { "kept" } else { "dropped" }])
+        .inc_by(count);
+}
+
+/// Whether the status of the span, from the OTLP protobuf or JSON encodings,
+/// is an error.
+fn is_error(span: &Span) -> bool {
+    if span.span_status == "ERROR" {
+        return true;
+    }
+    let Ok(status) = json::from_str::<json::Value>(&span.span_status) else {
+        return false;
+    };
+    match status.get("code") {
+        Some(json::Value::Number(code)) => code.as_i64() == Some(2),
+        Some(json::Value::String(code)) => code == "STATUS_CODE_ERROR",
+        _ => false,
+    }
+}
+
+fn attribute_matches(span: &Span, name: &str, value: &str) -> bool {
+    let attribute = span
+        .attributes
+        .get(name)
+        .or_else(|| span.service.get(&format!("service.{name}")))
+        .or_else(|| span.service.get(name));
+    match attribute {
+        Some(json::Value::String(v)) => v == value,
+        Some(v) => v.to_string() == value,
+        None => false,
+    }
+}
+
+/// Derives a number from the random part of the trace id, so that every node
+/// takes the same probabilistic decision.
+fn trace_id_ratio(trace_id: &str) -> u64 {
+    // not a hex id when the slice doesn't fall on a char boundary
+    let random = trace_id
+        .get(trace_id.len().saturating_sub(16)..)
+        .unwrap_or_default();
+    u64::from_str_radix(random, 16).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use ahash::AHashMap;
+
+    use super::*;
+
+    fn span(status: &str, start_time: u64, end_time: u64) -> Span {
+        Span {
+            trace_id: "4bf92f3577b34da6a3ce929d0e0e4736".to_string(),
+            span_id: "00f067aa0ba902b7".to_string(),
+            flags: 1,
+            span_status: status.to_string(),
+            span_kind: "2".to_string(),
+            operation_name: "GET /cart".to_string(),
+            start_time,
+            end_time,
+            duration: (end_time - start_time) / 1000,
+            reference: AHashMap::new(),
+            service_name: "cart".to_string(),
+            attributes: AHashMap::from_iter([(
+                "http.status_code".to_string(),
+                json::Value::from(500),
+            )]),
+            service: AHashMap::from_iter([(
+                "service.tenant".to_string(),
+                json::Value::from("acme"),
+            )]),
+            events: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_decide() {
+        let ok = span("OK", 1_000_000_000, 1_001_000_000); // 1ms
+        let trace_id = ok.trace_id.clone();
+        let none = Policies::default();
+        assert_eq!(none.decide(&trace_id, &[ok.clone()]), None);
+
+        let errors = Policies {
+            keep_errors: true,
+            ..Default::default()
+        };
+        assert_eq!(errors.decide(&trace_id, &[ok.clone()]), None);
+        let error = span("ERROR", 1_000_000_000, 1_001_000_000);
+        assert_eq!(
+            errors.decide(&trace_id, &[ok.clone(), error]),
+            Some("error")
+        );
+        let json_error = span(r#"{"code":2}"#, 1_000_000_000, 1_001_000_000);
+        assert_eq!(errors.decide(&trace_id, &[json_error]), Some("error"));
+
+        let duration = Policies {
+            min_duration: 1500,
+            ..Default::default()
+        };
+        assert_eq!(duration.decide(&trace_id, &[ok.clone()]), None);
+        let late = span("OK", 1_001_000_000, 1_002_000_000);
+        assert_eq!(
+            duration.decide(&trace_id, &[ok.clone(), late]),
+            Some("duration")
+        );
+
+        let attributes = Policies {
+            attributes: vec![("tenant".to_string(), "acme".to_string())],
+            ..Default::default()
+        };
+        assert_eq!(
+            attributes.decide(&trace_id, &[ok.clone()]),
+            Some("attribute")
+        );
+        let attributes = Policies {
+            attributes: vec![("http.status_code".to_string(), "200".to_string())],
+            ..Default::default()
+        };
+        assert_eq!(attributes.decide(&trace_id, &[ok.clone()]), None);
+
+        let all = Policies {
+            percentage: 100,
+            ..Default::default()
+        };
+        assert_eq!(all.decide(&trace_id, &[ok]), Some("probabilistic"));
+    }
+
+    #[test]
+    fn test_trace_id_ratio() {
+        assert_eq!(
+            trace_id_ratio("4bf92f3577b34da6a3ce929d0e0e4736"),
+            0xa3ce929d0e0e4736
+        );
+        assert_eq!(trace_id_ratio("0e"), 14);
+        assert_eq!(trace_id_ratio("not hex"), 0);
+        // the last 16 bytes start inside a multi-byte char
+        assert_eq!(trace_id_ratio("é0123456789abcde"), 0);
+        let kept = (0..1000u64)
+            .filter(|i| {
+                trace_id_ratio(&format!("{:032x}", i.wrapping_mul(0x9e3779b97f4a7c15))) % 100 < 10
+            })
+            .count();
+        assert!((50..150).contains(&kept));
+    }
+}
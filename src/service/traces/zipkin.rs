@@ -0,0 +1,232 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Ingestion of the spans sent to the Zipkin v2 JSON API.
+
+use std::io::Error;
+
+use actix_web::{web, HttpResponse};
+use ahash::AHashMap;
+
+use super::{PARENT_SPAN_ID, PARENT_TRACE_ID, REF_TYPE, SERVICE, SERVICE_NAME, UNKNOWN_SERVICE};
+use crate::common::{
+    meta::{
+        http::HttpResponse as MetaHttpResponse,
+        traces::{Event, Span, SpanRefType, ZipkinSpan},
+    },
+    utils::json,
+};
+
+pub async fn ingest(
+    org_id: &str,
+    thread_id: usize,
+    body: web::Bytes,
+    in_stream_name: Option<&str>,
+) -> Result<HttpResponse, Error> {
+    let zipkin_spans: Vec<ZipkinSpan> = match json::from_slice(&body) {
+        Ok(v) => v,
+        Err(e) => {
+            return Ok(MetaHttpResponse::bad_request(format!(
+                "Invalid Zipkin spans: {e}"
+            )));
+        }
+    };
+    let mut spans = Vec::with_capacity(zipkin_spans.len());
+    for span in zipkin_spans {
+        match to_span(span) {
+            Ok(span) => spans.push(span),
+            Err(e) => return Ok(MetaHttpResponse::bad_request(e)),
+        }
+    }
+    super::ingest_spans(
+        org_id,
+        thread_id,
+        spans,
+        in_stream_name,
+        "/api/org/zipkin/api/v2/spans",
+    )
+    .await
+}
+
+/// Converts the Zipkin span into the span record of the OTLP ingestion.
+fn to_span(span: ZipkinSpan) -> Result<Span, String> {
+    let trace_id = super::normalize_id(&span.trace_id, 32)
+        .ok_or_else(|| format!("Invalid trace id: {}", span.trace_id))?;
+    let span_id =
+        super::normalize_id(&span.id, 16).ok_or_else(|| format!("Invalid span id: {}", span.id))?;
+
+    let mut reference = AHashMap::new();
+    if let Some(parent_id) = span.parent_id.as_deref().filter(|v| !v.is_empty()) {
+        let parent_id = super::normalize_id(parent_id, 16)
+            .ok_or_else(|| format!("Invalid parent id: {parent_id}"))?;
+        reference.insert(PARENT_TRACE_ID.to_string(), trace_id.clone());
+        reference.insert(PARENT_SPAN_ID.to_string(), parent_id);
+        reference.insert(REF_TYPE.to_string(), format!("{:?}", SpanRefType::ChildOf));
+    }
+
+    let span_status = if span.tags.contains_key("error") {
+        "ERROR".to_string()
+    } else {
+        match span.tags.get("otel.status_code").map(|v| v.as_str()) {
+            Some("ERROR") => "ERROR".to_string(),
+            Some("OK") => "OK".to_string(),
+            _ => "UNSET".to_string(),
+        }
+    };
+    // the OTLP span kinds
+    let span_kind = match span.kind.as_deref() {
+        Some("SERVER") => "2",
+        Some("CLIENT") => "3",
+        Some("PRODUCER") => "4",
+        Some("CONSUMER") => "5",
+        _ => "1",
+    };
+
+    let local_endpoint = span.local_endpoint.unwrap_or_default();
+    let service_name = local_endpoint
+        .service_name
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| UNKNOWN_SERVICE.to_string());
+    let mut service = AHashMap::new();
+    service.insert(
+        SERVICE_NAME.to_string(),
+        json::Value::from(service_name.clone()),
+    );
+    if let Some(ip) = local_endpoint.ipv4.or(local_endpoint.ipv6) {
+        service.insert(format!("{SERVICE}.net.host.ip"), json::Value::from(ip));
+    }
+    if let Some(port) = local_endpoint.port {
+        service.insert(format!("{SERVICE}.net.host.port"), json::Value::from(port));
+    }
+
+    let mut attributes = span
+        .tags
+        .into_iter()
+        .map(|(k, v)| (k, json::Value::from(v)))
+        .collect::<AHashMap<_, _>>();
+    if let Some(remote_endpoint) = span.remote_endpoint {
+        if let Some(name) = remote_endpoint.service_name.filter(|v| !v.is_empty()) {
+            attributes.insert("peer.service".to_string(), json::Value::from(name));
+        }
+        if let Some(ip) = remote_endpoint.ipv4.or(remote_endpoint.ipv6) {
+            attributes.insert("net.peer.ip".to_string(), json::Value::from(ip));
+        }
+        if let Some(port) = remote_endpoint.port {
+            attributes.insert("net.peer.port".to_string(), json::Value::from(port));
+        }
+    }
+
+    let events = span
+        .annotations
+        .into_iter()
+        .map(|annotation| Event {
+            name: annotation.value,
+            _timestamp: annotation.timestamp * 1000,
+            attributes: AHashMap::new(),
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Span {
+        trace_id,
+        span_id,
+        flags: 1,
+        span_status,
+        span_kind: span_kind.to_string(),
+        operation_name: span.name.unwrap_or_default(),
+        start_time: span.timestamp * 1000,
+        end_time: (span.timestamp + span.duration) * 1000,
+        duration: span.duration,
+        reference,
+        service_name,
+        attributes,
+        service,
+        events: json::to_string(&events).unwrap(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_span() {
+        let spans: Vec<ZipkinSpan> = json::from_str(
+            r#"[{
+                "traceId": "5AF7183FB1D4CF5F",
+                "parentId": "6b221d5bc9e6496c",
+                "id": "352bff9a74ca9ad2",
+                "kind": "SERVER",
+                "name": "get /api",
+                "timestamp": 1556604172355737,
+                "duration": 1431,
+                "localEndpoint": {"serviceName": "backend", "ipv4": "192.168.99.1", "port": 3306},
+                "remoteEndpoint": {"ipv4": "172.19.0.2", "port": 58648},
+                "annotations": [{"timestamp": 1556604172356000, "value": "wr"}],
+                "tags": {"http.method": "GET", "error": "timeout"}
+            }]"#,
+        )
+        .unwrap();
+        let span = to_span(spans.into_iter().next().unwrap()).unwrap();
+        assert_eq!(span.trace_id, "00000000000000005af7183fb1d4cf5f");
+        assert_eq!(span.span_id, "352bff9a74ca9ad2");
+        assert_eq!(
+            span.reference.get(PARENT_SPAN_ID).map(|v| v.as_str()),
+            Some("6b221d5bc9e6496c")
+        );
+        assert_eq!(span.span_kind, "2");
+        assert_eq!(span.span_status, "ERROR");
+        assert_eq!(span.operation_name, "get /api");
+        assert_eq!(span.start_time, 1556604172355737000);
+        assert_eq!(span.end_time, 1556604172357168000);
+        assert_eq!(span.duration, 1431);
+        assert_eq!(span.service_name, "backend");
+        assert_eq!(
+            span.service.get("service.net.host.ip"),
+            Some(&json::Value::from("192.168.99.1"))
+        );
+        assert_eq!(
+            span.attributes.get("http.method"),
+            Some(&json::Value::from("GET"))
+        );
+        assert_eq!(
+            span.attributes.get("net.peer.port"),
+            Some(&json::Value::from(58648))
+        );
+        let events: Vec<Event> = json::from_str(&span.events).unwrap();
+        assert_eq!(events[0].name, "wr");
+        assert_eq!(events[0]._timestamp, 1556604172356000000);
+    }
+
+    #[test]
+    fn test_to_span_minimal() {
+        let span = to_span(ZipkinSpan {
+            trace_id: "4bf92f3577b34da6a3ce929d0e0e4736".to_string(),
+            id: "00f067aa0ba902b7".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(span.reference.is_empty());
+        assert_eq!(span.span_kind, "1");
+        assert_eq!(span.span_status, "UNSET");
+        assert_eq!(span.service_name, UNKNOWN_SERVICE);
+
+        let invalid = to_span(ZipkinSpan {
+            trace_id: "not hex".to_string(),
+            id: "00f067aa0ba902b7".to_string(),
+            ..Default::default()
+        });
+        assert!(invalid.is_err());
+    }
+}
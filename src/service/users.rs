@@ -287,6 +287,31 @@ pub async fn get_user(org_id: Option<&str>, name: &str) -> Option<User> {
     }
 }
 
+/// Returns the user of the org, or the root user, whose ingestion passcode is
+/// the given one. Without an org, the user of any org is returned, its org is
+/// the one of the passcode.
+pub fn get_user_by_passcode(org_id: Option<&str>, passcode: &str) -> Option<User> {
+    if passcode.is_empty() {
+        return None;
+    }
+    let Some(org_id) = org_id else {
+        return USERS
+            .iter()
+            .find(|item| item.value().token.eq(passcode))
+            .map(|item| item.value().clone());
+    };
+    if let Some(root) = ROOT_USER.get("root") {
+        if root.token.eq(passcode) {
+            return Some(root.value().clone());
+        }
+    }
+    let prefix = format!("{org_id}/");
+    USERS
+        .iter()
+        .find(|item| item.key().starts_with(&prefix) && item.value().token.eq(passcode))
+        .map(|item| item.value().clone())
+}
+
 pub async fn get_user_by_token(org_id: &str, token: &str) -> Option<User> {
     let root_user = USERS_RUM_TOKEN.get(&format!("{DEFAULT_ORG}/{token}"));
     if let Some(user) = root_user {